@@ -0,0 +1,113 @@
+use crate::interval::Interval;
+use crate::ray::Ray;
+use crate::vec3::Point3;
+
+// Axis-aligned bounding box, stored as one interval per axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Aabb {
+    pub(crate) x: Interval,
+    pub(crate) y: Interval,
+    pub(crate) z: Interval,
+}
+
+impl Aabb {
+    pub(crate) const EMPTY: Aabb = Aabb { x: Interval::EMPTY, y: Interval::EMPTY, z: Interval::EMPTY };
+
+    pub(crate) fn new(x: Interval, y: Interval, z: Interval) -> Self {
+        Aabb { x, y, z }.pad_to_minimums()
+    }
+
+    // Box with the two points as opposite corners, in any order.
+    pub(crate) fn from_points(a: Point3, b: Point3) -> Self {
+        Aabb::new(
+            Interval::with_bounds(a.x().min(b.x()), a.x().max(b.x())),
+            Interval::with_bounds(a.y().min(b.y()), a.y().max(b.y())),
+            Interval::with_bounds(a.z().min(b.z()), a.z().max(b.z())),
+        )
+    }
+
+    pub(crate) fn surrounding(a: &Aabb, b: &Aabb) -> Self {
+        Aabb { x: Interval::enclosing(a.x, b.x), y: Interval::enclosing(a.y, b.y), z: Interval::enclosing(a.z, b.z) }
+    }
+
+    // Grows the box to include a point.
+    pub(crate) fn include(&self, p: Point3) -> Self {
+        Aabb::surrounding(self, &Aabb::from_points(p, p))
+    }
+
+    pub(crate) fn axis_interval(&self, axis: usize) -> Interval {
+        match axis {
+            1 => self.y,
+            2 => self.z,
+            _ => self.x,
+        }
+    }
+
+    pub(crate) fn min(&self) -> Point3 {
+        Point3::new(self.x.min, self.y.min, self.z.min)
+    }
+
+    pub(crate) fn max(&self) -> Point3 {
+        Point3::new(self.x.max, self.y.max, self.z.max)
+    }
+
+    pub(crate) fn corners(&self) -> [Point3; 8] {
+        let (a, b) = (self.min(), self.max());
+        [
+            Point3::new(a.x(), a.y(), a.z()),
+            Point3::new(b.x(), a.y(), a.z()),
+            Point3::new(a.x(), b.y(), a.z()),
+            Point3::new(b.x(), b.y(), a.z()),
+            Point3::new(a.x(), a.y(), b.z()),
+            Point3::new(b.x(), a.y(), b.z()),
+            Point3::new(a.x(), b.y(), b.z()),
+            Point3::new(b.x(), b.y(), b.z()),
+        ]
+    }
+
+    // Index of the axis along which the box is largest.
+    pub(crate) fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() { 0 } else { 2 }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub(crate) fn centroid(&self, axis: usize) -> f64 {
+        let interval = self.axis_interval(axis);
+        0.5 * (interval.min + interval.max)
+    }
+
+    // Slab test: whether the ray passes through the box within `ray_t`.
+    pub(crate) fn hit(&self, ray: &Ray, mut ray_t: Interval) -> bool {
+        let origin = ray.origin();
+        let direction = ray.direction();
+
+        for axis in 0..3 {
+            let interval = self.axis_interval(axis);
+            let inverse = 1.0 / direction[axis];
+
+            let t0 = (interval.min - origin[axis]) * inverse;
+            let t1 = (interval.max - origin[axis]) * inverse;
+            let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+            ray_t.min = ray_t.min.max(near);
+            ray_t.max = ray_t.max.min(far);
+            if ray_t.max <= ray_t.min {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Gives flat boxes (of planar objects) a minimal thickness so the slab test stays robust.
+    fn pad_to_minimums(self) -> Self {
+        const DELTA: f64 = 0.0001;
+        let pad = |interval: Interval| if interval.size() < DELTA { interval.expand(DELTA) } else { interval };
+        Aabb { x: pad(self.x), y: pad(self.y), z: pad(self.z) }
+    }
+}
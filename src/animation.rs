@@ -0,0 +1,279 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::camera::Camera;
+use crate::material::MaterialTrait;
+use crate::transform::{Keyframe, Quaternion};
+use crate::vec3::{Point3, Vec3};
+
+// Transform keyframes per frame when an object's track is turned into motion, so that curved
+// paths stay curved inside the shutter interval.
+const OBJECT_STEPS_PER_FRAME: usize = 8;
+
+// How a key moves on to the next one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Interpolation {
+    Linear,
+    // Cubic Bezier whose handles follow the neighboring keys (Catmull-Rom tangents), easing in
+    // and out at the first and last keys
+    Bezier,
+}
+
+impl Interpolation {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(Interpolation::Linear),
+            "bezier" => Some(Interpolation::Bezier),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Key {
+    frame: f64,
+    value: Vec<f64>,
+    interpolation: Interpolation,
+}
+
+// Keys for one animated value, which may have several components.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Track {
+    keys: Vec<Key>,
+}
+
+impl Track {
+    fn new() -> Self {
+        Track { keys: Vec::new() }
+    }
+
+    fn add_key(&mut self, frame: f64, value: Vec<f64>, interpolation: Interpolation) {
+        let index = self.keys.partition_point(|key| key.frame <= frame);
+        self.keys.insert(index, Key { frame, value, interpolation });
+    }
+
+    // Value at `frame`; the first and last keys hold outside the keyed range.
+    pub(crate) fn evaluate(&self, frame: f64) -> Vec<f64> {
+        let last = self.keys.len() - 1;
+        if frame <= self.keys[0].frame {
+            return self.keys[0].value.clone();
+        }
+        if frame >= self.keys[last].frame {
+            return self.keys[last].value.clone();
+        }
+
+        let i = self.keys.partition_point(|key| key.frame <= frame) - 1;
+        let (k0, k1) = (&self.keys[i], &self.keys[i + 1]);
+        let span = k1.frame - k0.frame;
+        let t = (frame - k0.frame) / span;
+
+        (0..k0.value.len())
+            .map(|c| {
+                let (v0, v1) = (k0.value[c], k1.value[c]);
+                match k0.interpolation {
+                    Interpolation::Linear => v0 + t * (v1 - v0),
+                    Interpolation::Bezier => {
+                        // Handles a third of the span along the tangents; with handles evenly
+                        // spaced in time, the curve parameter is linear in the frame.
+                        let p1 = v0 + self.slope(i, c) * span / 3.0;
+                        let p2 = v1 - self.slope(i + 1, c) * span / 3.0;
+                        let s = 1.0 - t;
+                        s * s * s * v0 + 3.0 * s * s * t * p1 + 3.0 * s * t * t * p2 + t * t * t * v1
+                    }
+                }
+            })
+            .collect()
+    }
+
+    // Catmull-Rom slope per frame of component `c` at key `i`, flat at both ends.
+    fn slope(&self, i: usize, c: usize) -> f64 {
+        if i == 0 || i == self.keys.len() - 1 {
+            return 0.0;
+        }
+        let (previous, next) = (&self.keys[i - 1], &self.keys[i + 1]);
+        (next.value[c] - previous.value[c]) / (next.frame - previous.frame)
+    }
+}
+
+// What a track animates.
+#[derive(Clone, Debug, PartialEq)]
+enum Target {
+    LookFrom,
+    LookAt,
+    Vfov,
+    FocusDistance,
+    // Offset of a top-level object (by object id) from where the scene placed it
+    ObjectTranslation(usize),
+    // A parameter of the material of a top-level object (by object id)
+    Material(usize, String),
+}
+
+impl Target {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "look_from" => return Some(Target::LookFrom),
+            "look_at" => return Some(Target::LookAt),
+            "vfov" => return Some(Target::Vfov),
+            "focus_distance" => return Some(Target::FocusDistance),
+            _ => {}
+        }
+
+        let mut parts = name.splitn(3, '.');
+        if parts.next()? != "object" {
+            return None;
+        }
+        let id = parts.next()?.parse().ok()?;
+        match parts.next()? {
+            "translate" => Some(Target::ObjectTranslation(id)),
+            parameter => Some(Target::Material(id, parameter.to_string())),
+        }
+    }
+
+    fn components(&self) -> Option<usize> {
+        match self {
+            Target::LookFrom | Target::LookAt | Target::ObjectTranslation(_) => Some(3),
+            Target::Vfov | Target::FocusDistance => Some(1),
+            Target::Material(..) => None,
+        }
+    }
+}
+
+// Keyframed scene parameters over a range of frames. Keys are placed on frames, while every frame
+// is rendered in its own time, with the shutter open from `shutter_open` to `shutter_close` like
+// a still: tracks see time t of frame N as N + t, and motion over [0, 1] such as that of a moving
+// sphere plays out again in each frame.
+pub(crate) struct Animation {
+    pub(crate) first_frame: i32,
+    pub(crate) last_frame: i32,
+    pub(crate) turntable: bool, // Orbits `look_from` once around `look_at` over the frame range
+    tracks: Vec<(Target, Track)>,
+}
+
+impl Animation {
+    pub(crate) fn new(first_frame: i32, last_frame: i32) -> Self {
+        Animation { first_frame, last_frame, turntable: false, tracks: Vec::new() }
+    }
+
+    // Adds a key to the track named `name`: look_from, look_at, vfov, focus_distance,
+    // object.<id>.translate or object.<id>.<material parameter>.
+    pub(crate) fn add_key(&mut self, name: &str, frame: f64, value: Vec<f64>, interpolation: Interpolation) -> Result<(), String> {
+        let target = Target::from_name(name).ok_or_else(|| format!("unknown animated parameter '{}'", name))?;
+        if let Some(components) = target.components() {
+            if value.len() != components {
+                return Err(format!("{} takes {} values, got {}", name, components, value.len()));
+            }
+        }
+
+        let index = match self.tracks.iter().position(|(t, _)| *t == target) {
+            Some(index) => index,
+            None => {
+                self.tracks.push((target, Track::new()));
+                self.tracks.len() - 1
+            }
+        };
+        let track = &mut self.tracks[index].1;
+        if track.keys.first().is_some_and(|key| key.value.len() != value.len()) {
+            return Err(format!("keys of {} have different numbers of values", name));
+        }
+        track.add_key(frame, value, interpolation);
+        Ok(())
+    }
+
+    // Sets the camera for `frame`. Camera parameters are evaluated in the middle of the shutter
+    // interval; the shutter itself moves along with the frame.
+    pub(crate) fn apply_camera(&self, camera: &mut Camera, frame: i32, shutter: (f64, f64)) {
+        let time = frame as f64 + 0.5 * (shutter.0 + shutter.1);
+        camera.shutter_open = shutter.0;
+        camera.shutter_close = shutter.1;
+
+        for (target, track) in &self.tracks {
+            let value = track.evaluate(time);
+            match target {
+                Target::LookFrom => camera.look_from = Point3::new(value[0], value[1], value[2]),
+                Target::LookAt => camera.look_at = Point3::new(value[0], value[1], value[2]),
+                Target::Vfov => camera.vfov = value[0],
+                Target::FocusDistance => camera.focus_distance = value[0],
+                Target::ObjectTranslation(_) | Target::Material(..) => {}
+            }
+        }
+
+        if self.turntable {
+            // Rotate the view offset around the up axis through `look_at`.
+            let frames = (self.last_frame - self.first_frame + 1) as f64;
+            let angle = 360.0 * (time - self.first_frame as f64) / frames;
+            let offset = camera.look_from - camera.look_at;
+            let rotated = Quaternion::from_axis_angle(camera.vup, angle).rotate(offset);
+            camera.look_from = camera.look_at + rotated;
+        }
+    }
+
+    // Sets the animated material parameters for `frame`. `materials` holds the material of each
+    // top-level object, indexed by object id - 1.
+    pub(crate) fn apply_materials(&self, materials: &[Rc<RefCell<dyn MaterialTrait>>], frame: i32, shutter: (f64, f64)) -> Result<(), String> {
+        let time = frame as f64 + 0.5 * (shutter.0 + shutter.1);
+        for (target, track) in &self.tracks {
+            if let Target::Material(id, parameter) = target {
+                let material = id
+                    .checked_sub(1)
+                    .and_then(|index| materials.get(index))
+                    .ok_or_else(|| format!("no object with id {} (expected 1 to {})", id, materials.len()))?;
+                if !material.borrow_mut().set_parameter(parameter, &track.evaluate(time)) {
+                    return Err(format!("the material of object {} has no parameter '{}' taking these values", id, parameter));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Ids of the objects with an animated translation.
+    pub(crate) fn moving_objects(&self) -> Vec<usize> {
+        self.tracks
+            .iter()
+            .filter_map(|(target, _)| match target {
+                Target::ObjectTranslation(id) => Some(*id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Transform keyframes for `frame` of the objects with an animated translation, covering the
+    // shutter interval and the frame itself. Times are those of the frame, like the camera's
+    // shutter.
+    pub(crate) fn object_keyframes(&self, frame: i32, shutter: (f64, f64)) -> Vec<(usize, Keyframe)> {
+        let (start, end) = (shutter.0.min(0.0), shutter.1.max(1.0));
+        let steps = ((end - start) * OBJECT_STEPS_PER_FRAME as f64).ceil() as usize;
+
+        let mut keyframes = Vec::new();
+        for (target, track) in &self.tracks {
+            if let Target::ObjectTranslation(id) = target {
+                for step in 0..=steps {
+                    let time = start + (end - start) * step as f64 / steps as f64;
+                    let value = track.evaluate(frame as f64 + time);
+                    let translation = Vec3::new(value[0], value[1], value[2]);
+                    keyframes.push((*id, Keyframe::new(time, translation, Quaternion::IDENTITY, Vec3::new(1.0, 1.0, 1.0))));
+                }
+            }
+        }
+        keyframes
+    }
+}
+
+// Replaces each run of '#' in `pattern` with the frame number, zero padded to the run's length.
+// Patterns without any get '.####' before their extension, so frames do not overwrite each other.
+pub(crate) fn frame_path(pattern: &str, frame: i32) -> String {
+    if pattern.contains('#') {
+        return number_runs(pattern, frame);
+    }
+    let name_start = pattern.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    let extension = pattern[name_start..].rfind('.').filter(|&i| i > 0).map_or(pattern.len(), |i| name_start + i);
+    number_runs(&format!("{}.####{}", &pattern[..extension], &pattern[extension..]), frame)
+}
+
+fn number_runs(pattern: &str, frame: i32) -> String {
+    let Some(start) = pattern.find('#') else {
+        return pattern.to_string();
+    };
+    let length = pattern[start..].chars().take_while(|&c| c == '#').count();
+    let numbered = format!("{}{:0width$}{}", &pattern[..start], frame, &pattern[start + length..], width = length);
+    number_runs(&numbered, frame)
+}
@@ -0,0 +1,149 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::color::Color;
+use crate::vec3::{unit_vector, Point3, Vec3};
+
+// First-hit data of a single camera ray.
+#[derive(Clone, Copy)]
+pub(crate) struct FeatureSample {
+    pub(crate) depth: f64, // Along the camera's view axis, not the ray
+    pub(crate) normal: Vec3,
+    pub(crate) position: Point3,
+    pub(crate) albedo: Color,
+    pub(crate) material_id: u32,
+    pub(crate) object_id: u32,
+}
+
+// Accumulated first-hit data of one pixel.
+#[derive(Clone, Copy)]
+pub(crate) struct AovPixel {
+    pub(crate) depth: f64,
+    pub(crate) normal: Vec3,
+    pub(crate) position: Point3,
+    pub(crate) albedo: Color,
+    pub(crate) material_id: u32,
+    pub(crate) object_id: u32,
+    pub(crate) hit_count: u32,
+}
+
+// Arbitrary output variables: per-pixel first-hit data written alongside the beauty image.
+// Depth, normal, position and albedo are averaged over the samples that hit something. The ids
+// come from the first sample that hit; 0 means nothing was hit and ids of objects start at 1.
+pub(crate) struct AovBuffers {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pixels: Vec<AovPixel>,
+}
+
+impl AovBuffers {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        let empty = AovPixel {
+            depth: 0.0,
+            normal: zero,
+            position: zero,
+            albedo: zero,
+            material_id: 0,
+            object_id: 0,
+            hit_count: 0,
+        };
+        AovBuffers { width, height, pixels: vec![empty; width * height] }
+    }
+
+    pub(crate) fn add_sample(&mut self, x: usize, y: usize, sample: &FeatureSample) {
+        let pixel = &mut self.pixels[y * self.width + x];
+        if pixel.hit_count == 0 {
+            pixel.material_id = sample.material_id;
+            pixel.object_id = sample.object_id;
+        }
+        pixel.depth += sample.depth;
+        pixel.normal = pixel.normal + sample.normal;
+        pixel.position = pixel.position + sample.position;
+        pixel.albedo = pixel.albedo + sample.albedo;
+        pixel.hit_count += 1;
+    }
+
+    // Raw accumulated state of a pixel, as stored in checkpoints.
+    pub(crate) fn pixel(&self, x: usize, y: usize) -> AovPixel {
+        self.pixels[y * self.width + x]
+    }
+
+    pub(crate) fn set_pixel(&mut self, x: usize, y: usize, pixel: AovPixel) {
+        self.pixels[y * self.width + x] = pixel;
+    }
+
+    // Distance from the camera to the first hit, infinite where nothing was hit.
+    pub(crate) fn depth(&self, x: usize, y: usize) -> f64 {
+        let pixel = self.pixel(x, y);
+        if pixel.hit_count == 0 {
+            return f64::INFINITY;
+        }
+        pixel.depth / pixel.hit_count as f64
+    }
+
+    pub(crate) fn normal(&self, x: usize, y: usize) -> Vec3 {
+        let normal = self.pixel(x, y).normal;
+        if normal.near_zero() {
+            return normal;
+        }
+        unit_vector(normal)
+    }
+
+    pub(crate) fn position(&self, x: usize, y: usize) -> Point3 {
+        let pixel = self.pixel(x, y);
+        pixel.position / pixel.hit_count.max(1) as f64
+    }
+
+    pub(crate) fn albedo(&self, x: usize, y: usize) -> Color {
+        let pixel = self.pixel(x, y);
+        pixel.albedo / pixel.hit_count.max(1) as f64
+    }
+
+    pub(crate) fn material_id(&self, x: usize, y: usize) -> u32 {
+        self.pixel(x, y).material_id
+    }
+
+    pub(crate) fn object_id(&self, x: usize, y: usize) -> u32 {
+        self.pixel(x, y).object_id
+    }
+
+    // Writes every output variable as a float image named `<base>.<variable>.pfm`.
+    pub(crate) fn write(&self, base: &str) -> io::Result<()> {
+        write_pfm(&format!("{}.depth.pfm", base), self.width, self.height, 1, |x, y| [self.depth(x, y), 0.0, 0.0])?;
+        write_pfm(&format!("{}.normal.pfm", base), self.width, self.height, 3, |x, y| vec_channels(self.normal(x, y)))?;
+        write_pfm(&format!("{}.position.pfm", base), self.width, self.height, 3, |x, y| vec_channels(self.position(x, y)))?;
+        write_pfm(&format!("{}.albedo.pfm", base), self.width, self.height, 3, |x, y| vec_channels(self.albedo(x, y)))?;
+        write_pfm(&format!("{}.material_id.pfm", base), self.width, self.height, 1, |x, y| [self.material_id(x, y) as f64, 0.0, 0.0])?;
+        write_pfm(&format!("{}.object_id.pfm", base), self.width, self.height, 1, |x, y| [self.object_id(x, y) as f64, 0.0, 0.0])
+    }
+}
+
+fn vec_channels(v: Vec3) -> [f64; 3] {
+    [v.x(), v.y(), v.z()]
+}
+
+// Writes a Portable Float Map with one (grayscale) or three (color) little endian channels.
+pub(crate) fn write_pfm(
+    path: &str,
+    width: usize,
+    height: usize,
+    channels: usize,
+    pixel: impl Fn(usize, usize) -> [f64; 3],
+) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let kind = if channels == 1 { "Pf" } else { "PF" };
+    // A negative scale marks little endian data.
+    write!(out, "{}\n{} {}\n-1.0\n", kind, width, height)?;
+
+    // PFM scanlines are stored bottom to top.
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let values = pixel(x, y);
+            for value in values.iter().take(channels) {
+                out.write_all(&(*value as f32).to_le_bytes())?;
+            }
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,102 @@
+use std::io;
+
+use crate::image::Image;
+use crate::utils::{degrees_to_radians, PI};
+use crate::vec3::sample_unit_disk;
+
+// Shape of the lens opening, which defocused highlights (bokeh) take on.
+pub(crate) enum Aperture {
+    Circular,
+    // Regular polygon formed by `blades` straight diaphragm blades, rotated by `rotation` degrees
+    Polygon { blades: u32, rotation: f64 },
+    // Grayscale image spanning the square around the lens disk; brighter pixels let more light in
+    Mask(ApertureMask),
+}
+
+impl Aperture {
+    // Maps a 2D sample to a point of the aperture in [-1, 1]^2, relative to the lens radius.
+    pub(crate) fn sample(&self, u: (f64, f64)) -> (f64, f64) {
+        match self {
+            Aperture::Circular => {
+                let point = sample_unit_disk(u);
+                (point.x(), point.y())
+            }
+            Aperture::Polygon { blades, rotation } => sample_polygon(*blades.max(&3), degrees_to_radians(*rotation), u),
+            Aperture::Mask(mask) => mask.sample(u),
+        }
+    }
+}
+
+// Uniformly samples a regular polygon inscribed in the unit circle, choosing one of its triangle
+// fans with the first sample dimension and reusing the remainder of it inside the triangle.
+fn sample_polygon(blades: u32, rotation: f64, u: (f64, f64)) -> (f64, f64) {
+    let scaled = u.0 * blades as f64;
+    let blade = (scaled.floor() as u32).min(blades - 1);
+    let remainder = scaled - blade as f64;
+
+    let angle_0 = rotation + 2.0 * PI * blade as f64 / blades as f64;
+    let angle_1 = rotation + 2.0 * PI * (blade + 1) as f64 / blades as f64;
+
+    // Uniform point in the triangle (center, corner 0, corner 1).
+    let r = remainder.sqrt();
+    let b0 = r * (1.0 - u.1);
+    let b1 = r * u.1;
+    (b0 * angle_0.cos() + b1 * angle_1.cos(), b0 * angle_0.sin() + b1 * angle_1.sin())
+}
+
+// An aperture image importance sampled through its marginal (row) and conditional (column)
+// cumulative distributions.
+pub(crate) struct ApertureMask {
+    height: usize,
+    row_cdf: Vec<f64>,
+    column_cdfs: Vec<Vec<f64>>,
+}
+
+// Normalized running sum of `weights`, starting at zero; None when every weight is zero.
+fn cumulative(weights: impl Iterator<Item = f64>) -> Option<Vec<f64>> {
+    let mut cdf = vec![0.0];
+    for weight in weights {
+        cdf.push(cdf[cdf.len() - 1] + weight.max(0.0));
+    }
+    let total = cdf[cdf.len() - 1];
+    if total <= 0.0 {
+        return None;
+    }
+    Some(cdf.iter().map(|v| v / total).collect())
+}
+
+// Inverts a cumulative distribution, returning a continuous position in [0, 1).
+fn sample_cdf(cdf: &[f64], u: f64) -> f64 {
+    let bins = cdf.len() - 1;
+    let index = cdf.partition_point(|&v| v <= u).clamp(1, bins) - 1;
+    let width = cdf[index + 1] - cdf[index];
+    let offset = if width > 0.0 { (u - cdf[index]) / width } else { 0.5 };
+    (index as f64 + offset) / bins as f64
+}
+
+impl ApertureMask {
+    pub(crate) fn load(path: &str) -> io::Result<Self> {
+        let image = Image::load(path, true)?;
+        if image.width == 0 || image.height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: aperture mask is empty", path)));
+        }
+        let rows = (0..image.height).map(|y| (0..image.width).map(|x| image.gray(x, y)).collect::<Vec<f64>>()).collect::<Vec<_>>();
+
+        let row_cdf = cumulative(rows.iter().map(|row| row.iter().sum()))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{}: aperture mask is completely black", path)))?;
+        let column_cdfs = rows
+            .iter()
+            .map(|row| cumulative(row.iter().copied()).unwrap_or_else(|| vec![0.0; image.width + 1]))
+            .collect();
+
+        Ok(ApertureMask { height: image.height, row_cdf, column_cdfs })
+    }
+
+    fn sample(&self, u: (f64, f64)) -> (f64, f64) {
+        let y = sample_cdf(&self.row_cdf, u.1);
+        let row = ((y * self.height as f64) as usize).min(self.height - 1);
+        let x = sample_cdf(&self.column_cdfs[row], u.0);
+        // Image rows grow downwards while the lens' v axis points up.
+        (2.0 * x - 1.0, 1.0 - 2.0 * y)
+    }
+}
@@ -0,0 +1,177 @@
+use std::fs;
+use std::io;
+
+use crate::mesh::{invalid_data, TriangleMesh};
+use crate::vec3::{cross, unit_vector, Point3, Vec3};
+
+// Most segments along one side of a patch, however curved it is.
+const MAX_SEGMENTS: usize = 64;
+
+// Bicubic Bezier patch; `control[row][column]` with u running along the rows and v across them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct BezierPatch {
+    control: [[Point3; 4]; 4],
+}
+
+fn bernstein(t: f64) -> [f64; 4] {
+    let s = 1.0 - t;
+    [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t]
+}
+
+fn bernstein_derivative(t: f64) -> [f64; 4] {
+    let s = 1.0 - t;
+    [-3.0 * s * s, 3.0 * s * s - 6.0 * t * s, 6.0 * t * s - 3.0 * t * t, 3.0 * t * t]
+}
+
+fn curve_point(points: &[Point3; 4], t: f64) -> Point3 {
+    let b = bernstein(t);
+    b[0] * points[0] + b[1] * points[1] + b[2] * points[2] + b[3] * points[3]
+}
+
+// Segments, a power of two, for a polyline through the cubic curve to stay within `tolerance`
+// of it. The error of n segments is at most max |B''| / (8 n²), and |B''| is at most six times
+// the largest second difference of the control points. Depending only on the curve's own
+// control points, the count is the same for both patches sharing an edge.
+fn segments(points: &[Point3; 4], tolerance: f64) -> usize {
+    let second_difference = (points[0] - 2.0 * points[1] + points[2]).length().max((points[1] - 2.0 * points[2] + points[3]).length());
+    let needed = (0.75 * second_difference / tolerance).sqrt().ceil().max(1.0) as usize;
+    needed.next_power_of_two().min(MAX_SEGMENTS)
+}
+
+// Point at `t` on the polyline of `segments` pieces through the curve.
+fn polyline_point(points: &[Point3; 4], segments: usize, t: f64) -> Point3 {
+    let scaled = t * segments as f64;
+    let piece = (scaled.floor() as usize).min(segments - 1);
+    let fraction = scaled - piece as f64;
+    let start = curve_point(points, piece as f64 / segments as f64);
+    if fraction == 0.0 {
+        return start;
+    }
+    let end = curve_point(points, (piece + 1) as f64 / segments as f64);
+    start + fraction * (end - start)
+}
+
+impl BezierPatch {
+    fn row(&self, r: usize) -> [Point3; 4] {
+        self.control[r]
+    }
+
+    fn column(&self, c: usize) -> [Point3; 4] {
+        [self.control[0][c], self.control[1][c], self.control[2][c], self.control[3][c]]
+    }
+
+    fn evaluate(&self, u: f64, v: f64) -> Point3 {
+        let rows: Vec<Point3> = (0..4).map(|r| curve_point(&self.row(r), u)).collect();
+        curve_point(&[rows[0], rows[1], rows[2], rows[3]], v)
+    }
+
+    fn normal(&self, u: f64, v: f64) -> Vec3 {
+        let (bu, bv, du, dv) = (bernstein(u), bernstein(v), bernstein_derivative(u), bernstein_derivative(v));
+        let mut dp_du = Vec3::new(0.0, 0.0, 0.0);
+        let mut dp_dv = Vec3::new(0.0, 0.0, 0.0);
+        for r in 0..4 {
+            for c in 0..4 {
+                dp_du = dp_du + (du[c] * bv[r]) * self.control[r][c];
+                dp_dv = dp_dv + (bu[c] * dv[r]) * self.control[r][c];
+            }
+        }
+        let normal = cross(dp_du, dp_dv);
+        if normal.length_squared() < 1e-20 {
+            // A side collapsed to a point, like the tip of the teapot lid: step a little into
+            // the patch, where the tangents separate again.
+            if (u - 0.5).abs() > 1e-4 || (v - 0.5).abs() > 1e-4 {
+                return self.normal(u + (0.5 - u) * 1e-3, v + (0.5 - v) * 1e-3);
+            }
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        unit_vector(normal)
+    }
+
+    // Adds a grid of triangles approximating the patch to `mesh`. The grid is fine enough for
+    // the most curved row and column. Its boundary vertices are put on the polylines of the
+    // patch sides, which neighboring patches share, so no cracks open between patches.
+    fn tessellate(&self, tolerance: f64, mesh: &mut TriangleMesh) {
+        let sides = [self.row(0), self.row(3), self.column(0), self.column(3)];
+        let side_segments: Vec<usize> = sides.iter().map(|side| segments(side, tolerance)).collect();
+        let nu = (0..4).map(|r| segments(&self.row(r), tolerance)).max().unwrap_or(1);
+        let nv = (0..4).map(|c| segments(&self.column(c), tolerance)).max().unwrap_or(1);
+
+        let base = mesh.positions.len();
+        for j in 0..=nv {
+            for i in 0..=nu {
+                let (u, v) = (i as f64 / nu as f64, j as f64 / nv as f64);
+                let point = if j == 0 {
+                    polyline_point(&sides[0], side_segments[0], u)
+                } else if j == nv {
+                    polyline_point(&sides[1], side_segments[1], u)
+                } else if i == 0 {
+                    polyline_point(&sides[2], side_segments[2], v)
+                } else if i == nu {
+                    polyline_point(&sides[3], side_segments[3], v)
+                } else {
+                    self.evaluate(u, v)
+                };
+                mesh.positions.push(point);
+                mesh.normals.push(self.normal(u, v));
+                mesh.uvs.push((u, v));
+            }
+        }
+
+        let vertex = |i: usize, j: usize| base + j * (nu + 1) + i;
+        for j in 0..nv {
+            for i in 0..nu {
+                mesh.triangles.push([vertex(i, j), vertex(i + 1, j), vertex(i + 1, j + 1)]);
+                mesh.triangles.push([vertex(i, j), vertex(i + 1, j + 1), vertex(i, j + 1)]);
+            }
+        }
+    }
+}
+
+// Reads patches in the format of Newell's teapot data: the number of patches, then 16 one-based
+// control point indices per patch, the number of control points and their coordinates. Numbers
+// are separated by commas or whitespace.
+pub(crate) fn load_patches(path: &str) -> io::Result<Vec<BezierPatch>> {
+    let text = fs::read_to_string(path)?;
+    let mut tokens = text.split(|c: char| c == ',' || c.is_whitespace()).filter(|token| !token.is_empty());
+    let mut next_number = |what: &str| -> io::Result<f64> {
+        tokens
+            .next()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| invalid_data(format!("{}: expected {}", path, what)))
+    };
+
+    let patch_count = next_number("the number of patches")? as usize;
+    let mut indices = Vec::with_capacity(patch_count * 16);
+    for _ in 0..patch_count * 16 {
+        indices.push(next_number("a control point index")? as usize);
+    }
+    let point_count = next_number("the number of control points")? as usize;
+    let mut points = Vec::with_capacity(point_count);
+    for _ in 0..point_count {
+        let (x, y, z) = (next_number("a coordinate")?, next_number("a coordinate")?, next_number("a coordinate")?);
+        points.push(Point3::new(x, y, z));
+    }
+
+    indices
+        .chunks(16)
+        .map(|patch| {
+            let mut control = [[Point3::new(0.0, 0.0, 0.0); 4]; 4];
+            for (k, &index) in patch.iter().enumerate() {
+                control[k / 4][k % 4] = *index
+                    .checked_sub(1)
+                    .and_then(|i| points.get(i))
+                    .ok_or_else(|| invalid_data(format!("{}: control point index {} out of range", path, index)))?;
+            }
+            Ok(BezierPatch { control })
+        })
+        .collect()
+}
+
+// Triangles within `tolerance` of the patches, with the patches' exact normals at the vertices.
+pub(crate) fn tessellate_patches(patches: &[BezierPatch], tolerance: f64) -> TriangleMesh {
+    let mut mesh = TriangleMesh::default();
+    for patch in patches {
+        patch.tessellate(tolerance, &mut mesh);
+    }
+    mesh
+}
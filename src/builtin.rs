@@ -0,0 +1,689 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::bvh::Bvh;
+use crate::color::Color;
+use crate::csg::{Csg, CsgOperation};
+use crate::cuboid::Cuboid;
+use crate::curve::{strand_curves, Curve, CurveType};
+use crate::hair::HairMaterial;
+use crate::hittable::Hittable;
+use crate::image::Image;
+use crate::instance::Instance;
+use crate::material::{Dielectric, DiffuseLight, Isotropic, Lambertian, MaterialTrait, Metal, TexturedLambertian};
+use crate::medium::ConstantMedium;
+use crate::pbr::PbrMaterial;
+use crate::projection::Projection;
+use crate::quad::Quad;
+use crate::quadric::{Quadric, QuadricShape};
+use crate::scene::{Scene, View};
+use crate::sdf::{Sdf, SdfObject};
+use crate::sphere::Sphere;
+use crate::texture::Texture;
+use crate::transform::{AnimatedTransform, Keyframe, Quaternion};
+use crate::utils::{random_float, seed_random};
+use crate::vec3::{cross, unit_vector, Point3, Vec3};
+
+// Picture for the globes of the earth scenes, looked up in the working directory.
+const EARTH_MAP: &str = "earthmap.png";
+
+// Scenes built into the renderer, chosen by name: those of the first two books of the series,
+// each with the camera and render settings the book gives it, two for testing materials and
+// showcases of the shapes the books do not cover.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BuiltinScene {
+    Book1Cover,
+    BouncingSpheres,
+    CheckeredSpheres,
+    PerlinSpheres,
+    Earth,
+    Quads,
+    SimpleLight,
+    CornellBox,
+    CornellSmoke,
+    Book2Final,
+    MaterialBall,
+    WhiteFurnace,
+    Csg,
+    Sdf,
+    Quadrics,
+    Fur,
+}
+
+impl BuiltinScene {
+    pub(crate) const ALL: [BuiltinScene; 16] = [
+        BuiltinScene::Book1Cover,
+        BuiltinScene::BouncingSpheres,
+        BuiltinScene::CheckeredSpheres,
+        BuiltinScene::PerlinSpheres,
+        BuiltinScene::Earth,
+        BuiltinScene::Quads,
+        BuiltinScene::SimpleLight,
+        BuiltinScene::CornellBox,
+        BuiltinScene::CornellSmoke,
+        BuiltinScene::Book2Final,
+        BuiltinScene::MaterialBall,
+        BuiltinScene::WhiteFurnace,
+        BuiltinScene::Csg,
+        BuiltinScene::Sdf,
+        BuiltinScene::Quadrics,
+        BuiltinScene::Fur,
+    ];
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        BuiltinScene::ALL.into_iter().find(|scene| scene.name() == name)
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            BuiltinScene::Book1Cover => "book-1-cover",
+            BuiltinScene::BouncingSpheres => "bouncing-spheres",
+            BuiltinScene::CheckeredSpheres => "checkered-spheres",
+            BuiltinScene::PerlinSpheres => "perlin-spheres",
+            BuiltinScene::Earth => "earth",
+            BuiltinScene::Quads => "quads",
+            BuiltinScene::SimpleLight => "simple-light",
+            BuiltinScene::CornellBox => "cornell-box",
+            BuiltinScene::CornellSmoke => "cornell-smoke",
+            BuiltinScene::Book2Final => "book-2-final",
+            BuiltinScene::MaterialBall => "material-ball",
+            BuiltinScene::WhiteFurnace => "white-furnace",
+            BuiltinScene::Csg => "csg",
+            BuiltinScene::Sdf => "sdf",
+            BuiltinScene::Quadrics => "quadrics",
+            BuiltinScene::Fur => "fur",
+        }
+    }
+
+    // The scene with its camera and render settings. Random placement and noise come from
+    // `seed` alone, so the same seed always gives the same scene.
+    pub(crate) fn build(self, seed: u64) -> Scene {
+        seed_random(seed);
+        let mut scene = Scene::new();
+        match self {
+            BuiltinScene::Book1Cover => {
+                let ground = lambertian(Color::new(0.5, 0.5, 0.5));
+                scene.add(sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, &ground), ground);
+                add_small_spheres(&mut scene, false);
+                add_large_spheres(&mut scene);
+                settings(&mut scene, 1200.0, 500, 50);
+                scene.views.push(view(16.0 / 9.0, 20.0, Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), 0.6, 10.0));
+            }
+            BuiltinScene::BouncingSpheres => {
+                let checker = Texture::SolidChecker { even: Color::new(0.2, 0.3, 0.1), odd: Color::new(0.9, 0.9, 0.9), size: 0.32 };
+                let ground = textured(checker);
+                scene.add(sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, &ground), ground);
+                add_small_spheres(&mut scene, true);
+                add_large_spheres(&mut scene);
+                settings(&mut scene, 400.0, 100, 50);
+                scene.shutter = Some((0.0, 1.0));
+                scene.views.push(view(16.0 / 9.0, 20.0, Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), 0.6, 10.0));
+            }
+            BuiltinScene::CheckeredSpheres => {
+                let checker = Texture::SolidChecker { even: Color::new(0.2, 0.3, 0.1), odd: Color::new(0.9, 0.9, 0.9), size: 0.32 };
+                let material = textured(checker);
+                scene.add(sphere(Point3::new(0.0, -10.0, 0.0), 10.0, &material), material.clone());
+                scene.add(sphere(Point3::new(0.0, 10.0, 0.0), 10.0, &material), material);
+                settings(&mut scene, 400.0, 100, 50);
+                scene.views.push(view(16.0 / 9.0, 20.0, Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), 0.0, 10.0));
+            }
+            BuiltinScene::PerlinSpheres => {
+                let marble = textured(Texture::noise(seed, 4.0));
+                scene.add(sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, &marble), marble.clone());
+                scene.add(sphere(Point3::new(0.0, 2.0, 0.0), 2.0, &marble), marble);
+                settings(&mut scene, 400.0, 100, 50);
+                scene.views.push(view(16.0 / 9.0, 20.0, Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), 0.0, 10.0));
+            }
+            BuiltinScene::Earth => {
+                let earth = textured(earth_texture());
+                scene.add(sphere(Point3::new(0.0, 0.0, 0.0), 2.0, &earth), earth);
+                settings(&mut scene, 400.0, 100, 50);
+                scene.views.push(view(16.0 / 9.0, 20.0, Point3::new(0.0, 0.0, 12.0), Point3::new(0.0, 0.0, 0.0), 0.0, 10.0));
+            }
+            BuiltinScene::Quads => {
+                let quads = [
+                    (Color::new(1.0, 0.2, 0.2), Point3::new(-3.0, -2.0, 5.0), Vec3::new(0.0, 0.0, -4.0), Vec3::new(0.0, 4.0, 0.0)),
+                    (Color::new(0.2, 1.0, 0.2), Point3::new(-2.0, -2.0, 0.0), Vec3::new(4.0, 0.0, 0.0), Vec3::new(0.0, 4.0, 0.0)),
+                    (Color::new(0.2, 0.2, 1.0), Point3::new(3.0, -2.0, 1.0), Vec3::new(0.0, 0.0, 4.0), Vec3::new(0.0, 4.0, 0.0)),
+                    (Color::new(1.0, 0.5, 0.0), Point3::new(-2.0, 3.0, 1.0), Vec3::new(4.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 4.0)),
+                    (Color::new(0.2, 0.8, 0.8), Point3::new(-2.0, -3.0, 5.0), Vec3::new(4.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -4.0)),
+                ];
+                for (color, q, u, v) in quads {
+                    let material = lambertian(color);
+                    scene.add(Box::new(Quad::new(q, u, v, material.clone())), material);
+                }
+                settings(&mut scene, 400.0, 100, 50);
+                scene.views.push(view(1.0, 80.0, Point3::new(0.0, 0.0, 9.0), Point3::new(0.0, 0.0, 0.0), 0.0, 10.0));
+            }
+            BuiltinScene::SimpleLight => {
+                let marble = textured(Texture::noise(seed, 4.0));
+                scene.add(sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, &marble), marble.clone());
+                scene.add(sphere(Point3::new(0.0, 2.0, 0.0), 2.0, &marble), marble);
+                let light = diffuse_light(Color::new(4.0, 4.0, 4.0));
+                scene.add(sphere(Point3::new(0.0, 7.0, 0.0), 2.0, &light), light.clone());
+                scene.add(Box::new(Quad::new(Point3::new(3.0, 1.0, -2.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), light.clone())), light);
+                settings(&mut scene, 400.0, 100, 50);
+                scene.background = Some(Color::new(0.0, 0.0, 0.0));
+                scene.views.push(view(16.0 / 9.0, 20.0, Point3::new(26.0, 3.0, 6.0), Point3::new(0.0, 2.0, 0.0), 0.0, 10.0));
+            }
+            BuiltinScene::CornellBox => {
+                let light = (Point3::new(343.0, 554.0, 332.0), Vec3::new(-130.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -105.0), 15.0);
+                let white = add_cornell_walls(&mut scene, light);
+                let tall = Cuboid::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 330.0, 165.0), white.clone());
+                scene.add(placed(Box::new(tall), 15.0, Vec3::new(265.0, 0.0, 295.0)), white.clone());
+                let short = Cuboid::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 165.0, 165.0), white.clone());
+                scene.add(placed(Box::new(short), -18.0, Vec3::new(130.0, 0.0, 65.0)), white);
+                settings(&mut scene, 600.0, 200, 50);
+                scene.background = Some(Color::new(0.0, 0.0, 0.0));
+                scene.views.push(view(1.0, 40.0, Point3::new(278.0, 278.0, -800.0), Point3::new(278.0, 278.0, 0.0), 0.0, 10.0));
+            }
+            BuiltinScene::CornellSmoke => {
+                let light = (Point3::new(113.0, 554.0, 127.0), Vec3::new(330.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 305.0), 7.0);
+                let white = add_cornell_walls(&mut scene, light);
+                let tall = Cuboid::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 330.0, 165.0), white.clone());
+                let short = Cuboid::new(Point3::new(0.0, 0.0, 0.0), Point3::new(165.0, 165.0, 165.0), white);
+                let smoke = [
+                    (placed(Box::new(tall), 15.0, Vec3::new(265.0, 0.0, 295.0)), Color::new(0.0, 0.0, 0.0)),
+                    (placed(Box::new(short), -18.0, Vec3::new(130.0, 0.0, 65.0)), Color::new(1.0, 1.0, 1.0)),
+                ];
+                for (boundary, albedo) in smoke {
+                    let phase: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Isotropic { albedo }));
+                    scene.add(Box::new(ConstantMedium::new(boundary, 0.01, phase.clone())), phase);
+                }
+                settings(&mut scene, 600.0, 200, 50);
+                scene.background = Some(Color::new(0.0, 0.0, 0.0));
+                scene.views.push(view(1.0, 40.0, Point3::new(278.0, 278.0, -800.0), Point3::new(278.0, 278.0, 0.0), 0.0, 10.0));
+            }
+            BuiltinScene::Book2Final => add_book_2_final(&mut scene, seed),
+            BuiltinScene::MaterialBall => {
+                // A rough copper ball on a stand over a checkered floor, under the sky. The ball is
+                // object 1, so animation keys can change its material.
+                let copper: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(PbrMaterial::new(Color::new(0.95, 0.64, 0.54), 1.0, 0.3)));
+                scene.add(sphere(Point3::new(0.0, 1.2, 0.0), 1.0, &copper), copper);
+                let stand_material = lambertian(Color::new(0.1, 0.1, 0.1));
+                let mut stand = Quadric::new(QuadricShape::Cylinder { radius: 0.5 }, Point3::new(0.0, 0.0, 0.0), 0.0, 0.25, stand_material.clone());
+                stand.caps = true;
+                scene.add(Box::new(stand), stand_material);
+                let checker = Texture::Checker { even: Color::new(0.8, 0.8, 0.8), odd: Color::new(0.3, 0.3, 0.3), scale: (20.0, 20.0) };
+                let floor = textured(checker);
+                let quad = Quad::new(Point3::new(-10.0, 0.0, -10.0), Vec3::new(0.0, 0.0, 20.0), Vec3::new(20.0, 0.0, 0.0), floor.clone());
+                scene.add(Box::new(quad), floor);
+                settings(&mut scene, 400.0, 256, 50);
+                scene.views.push(view(1.0, 30.0, Point3::new(0.0, 2.5, 6.0), Point3::new(0.0, 1.0, 0.0), 0.0, 10.0));
+            }
+            BuiltinScene::WhiteFurnace => {
+                // Ideal diffuse, glass and rough metal balls, all white, in uniform white light.
+                // Materials that neither lose nor gain energy vanish against the background.
+                let diffuse = lambertian(Color::new(1.0, 1.0, 1.0));
+                let glass: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Dielectric { refraction_index: 1.5 }));
+                let metal: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(PbrMaterial::new(Color::new(1.0, 1.0, 1.0), 1.0, 0.5)));
+                for (x, material) in [(-2.5, diffuse), (0.0, glass), (2.5, metal)] {
+                    scene.add(sphere(Point3::new(x, 0.0, 0.0), 1.0, &material), material);
+                }
+                settings(&mut scene, 400.0, 64, 50);
+                scene.background = Some(Color::new(1.0, 1.0, 1.0));
+                scene.views.push(view(16.0 / 9.0, 40.0, Point3::new(0.0, 0.0, 8.0), Point3::new(0.0, 0.0, 0.0), 0.0, 10.0));
+            }
+            BuiltinScene::Csg => {
+                add_csg_shapes(&mut scene);
+                add_showcase_stage(&mut scene, Point3::new(0.0, 3.0, 10.0), Point3::new(0.0, 0.9, 0.0), 40.0);
+            }
+            BuiltinScene::Sdf => {
+                add_distance_functions(&mut scene);
+                add_showcase_stage(&mut scene, Point3::new(0.25, 3.0, 10.0), Point3::new(0.25, 0.8, 0.8), 35.0);
+            }
+            BuiltinScene::Quadrics => {
+                add_quadrics(&mut scene);
+                add_showcase_stage(&mut scene, Point3::new(-0.85, 3.0, 10.0), Point3::new(-0.85, 0.6, 0.0), 46.0);
+            }
+            BuiltinScene::Fur => {
+                add_fur_and_grass(&mut scene);
+                add_showcase_stage(&mut scene, Point3::new(2.2, 1.8, 6.0), Point3::new(2.2, 0.6, 1.0), 30.0);
+            }
+        }
+        scene
+    }
+}
+
+// The field of small random spheres of the book covers, diffuse ones bouncing up while the
+// shutter is open if `moving` is set.
+pub(crate) fn add_small_spheres(scene: &mut Scene, moving: bool) {
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = random_float();
+            let center = Point3::new(a as f64 + 0.9 * random_float(), 0.2, b as f64 + 0.9 * random_float());
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let albedo = Color::new(random_float(), random_float(), random_float());
+                    let material = lambertian(albedo);
+                    let center_1 = if moving { center + Vec3::new(0.0, random_float() * 0.5, 0.0) } else { center };
+                    scene.add(Box::new(Sphere::new(center, 0.2, material.clone(), center_1, moving)), material);
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let albedo = Color::new(random_float(), random_float(), random_float());
+                    let fuzz = random_float() * 0.5;
+                    let material: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Metal { albedo, fuzz }));
+                    scene.add(sphere(center, 0.2, &material), material);
+                } else {
+                    // glass
+                    let material: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Dielectric { refraction_index: 1.5 }));
+                    scene.add(sphere(center, 0.2, &material), material);
+                }
+            }
+        }
+    }
+}
+
+// The glass, diffuse and metal balls in the middle of the book covers.
+fn add_large_spheres(scene: &mut Scene) {
+    let glass: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Dielectric { refraction_index: 1.5 }));
+    scene.add(sphere(Point3::new(0.0, 1.0, 0.0), 1.0, &glass), glass);
+    let diffuse = lambertian(Color::new(0.4, 0.2, 0.1));
+    scene.add(sphere(Point3::new(-4.0, 1.0, 0.0), 1.0, &diffuse), diffuse);
+    let metal: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Metal { albedo: Color::new(0.7, 0.6, 0.5), fuzz: 0.0 }));
+    scene.add(sphere(Point3::new(4.0, 1.0, 0.0), 1.0, &metal), metal);
+}
+
+// The red, green and white walls of a Cornell box 555 units on a side, open towards -z, with a
+// light given as its corner, sides and strength in the ceiling. Returns the white material.
+fn add_cornell_walls(scene: &mut Scene, light: (Point3, Vec3, Vec3, f64)) -> Rc<RefCell<dyn MaterialTrait>> {
+    let red = lambertian(Color::new(0.65, 0.05, 0.05));
+    let white = lambertian(Color::new(0.73, 0.73, 0.73));
+    let green = lambertian(Color::new(0.12, 0.45, 0.15));
+    let (q, u, v, strength) = light;
+    let emitter = diffuse_light(Color::new(strength, strength, strength));
+
+    let walls = [
+        (&green, Point3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 555.0, 0.0), Vec3::new(0.0, 0.0, 555.0)),
+        (&red, Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 555.0, 0.0), Vec3::new(0.0, 0.0, 555.0)),
+        (&emitter, q, u, v),
+        (&white, Point3::new(0.0, 0.0, 0.0), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 555.0)),
+        (&white, Point3::new(555.0, 555.0, 555.0), Vec3::new(-555.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -555.0)),
+        (&white, Point3::new(0.0, 0.0, 555.0), Vec3::new(555.0, 0.0, 0.0), Vec3::new(0.0, 555.0, 0.0)),
+    ];
+    for (material, q, u, v) in walls {
+        scene.add(Box::new(Quad::new(q, u, v, material.clone())), material.clone());
+    }
+    white
+}
+
+// The final scene of the second book: a floor of boxes of random heights, a ceiling light, a
+// moving ball, glass, metal, a glass ball filled with blue haze, a globe, a marble ball and a
+// rotated cluster of small balls, all in a thin mist.
+fn add_book_2_final(scene: &mut Scene, seed: u64) {
+    let ground = lambertian(Color::new(0.48, 0.83, 0.53));
+    let mut boxes: Vec<Box<dyn Hittable>> = Vec::new();
+    for i in 0..20 {
+        for j in 0..20 {
+            let width = 100.0;
+            let corner = Point3::new(-1000.0 + i as f64 * width, 0.0, -1000.0 + j as f64 * width);
+            let height = 1.0 + 100.0 * random_float();
+            boxes.push(Box::new(Cuboid::new(corner, corner + Vec3::new(width, height, width), ground.clone())));
+        }
+    }
+    scene.add(Box::new(Bvh::new(boxes)), ground);
+
+    let light = diffuse_light(Color::new(7.0, 7.0, 7.0));
+    let ceiling = Quad::new(Point3::new(123.0, 554.0, 147.0), Vec3::new(300.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 265.0), light.clone());
+    scene.add(Box::new(ceiling), light);
+
+    let moving = lambertian(Color::new(0.7, 0.3, 0.1));
+    let center = Point3::new(400.0, 400.0, 200.0);
+    scene.add(Box::new(Sphere::new(center, 50.0, moving.clone(), center + Vec3::new(30.0, 0.0, 0.0), true)), moving);
+
+    let glass: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Dielectric { refraction_index: 1.5 }));
+    scene.add(sphere(Point3::new(260.0, 150.0, 45.0), 50.0, &glass), glass.clone());
+    let metal: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Metal { albedo: Color::new(0.8, 0.8, 0.9), fuzz: 1.0 }));
+    scene.add(sphere(Point3::new(0.0, 150.0, 145.0), 50.0, &metal), metal);
+
+    let haze: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Isotropic { albedo: Color::new(0.2, 0.4, 0.9) }));
+    scene.add(sphere(Point3::new(360.0, 150.0, 145.0), 70.0, &glass), glass.clone());
+    scene.add(Box::new(ConstantMedium::new(sphere(Point3::new(360.0, 150.0, 145.0), 70.0, &glass), 0.2, haze.clone())), haze);
+    let mist: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Isotropic { albedo: Color::new(1.0, 1.0, 1.0) }));
+    scene.add(Box::new(ConstantMedium::new(sphere(Point3::new(0.0, 0.0, 0.0), 5000.0, &glass), 0.0001, mist.clone())), mist);
+
+    let earth = textured(earth_texture());
+    scene.add(sphere(Point3::new(400.0, 200.0, 400.0), 100.0, &earth), earth);
+    let marble = textured(Texture::noise(seed, 0.2));
+    scene.add(sphere(Point3::new(220.0, 280.0, 300.0), 80.0, &marble), marble);
+
+    let white = lambertian(Color::new(0.73, 0.73, 0.73));
+    let cluster: Vec<Box<dyn Hittable>> = (0..1000)
+        .map(|_| {
+            let center = Point3::new(165.0 * random_float(), 165.0 * random_float(), 165.0 * random_float());
+            sphere(center, 10.0, &white) as Box<dyn Hittable>
+        })
+        .collect();
+    scene.add(placed(Box::new(Bvh::new(cluster)), 15.0, Vec3::new(-100.0, 270.0, 395.0)), white);
+
+    settings(scene, 800.0, 10000, 40);
+    scene.background = Some(Color::new(0.0, 0.0, 0.0));
+    scene.views.push(view(1.0, 40.0, Point3::new(478.0, 278.0, -600.0), Point3::new(278.0, 278.0, 0.0), 0.0, 10.0));
+}
+
+// A gray floor under the sky for the showcases, and the camera looking at them from `look_from`.
+fn add_showcase_stage(scene: &mut Scene, look_from: Point3, look_at: Point3, vfov: f64) {
+    let ground = lambertian(Color::new(0.5, 0.5, 0.5));
+    scene.add(sphere(Point3::new(0.0, -1000.0, 0.0), 1000.0, &ground), ground);
+    settings(scene, 400.0, 100, 50);
+    scene.views.push(view(16.0 / 9.0, vfov, look_from, look_at, 0.0, 10.0));
+}
+
+// A biconvex glass lens, a hollow glass ball and a metal cube with a ball carved out of it
+// and a smaller glass ball put in its place.
+fn add_csg_shapes(scene: &mut Scene) {
+    let csg = |operation, left, right| Box::new(Csg::new(operation, left, right).expect("the operands are closed"));
+    let glass: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Dielectric { refraction_index: 1.5 }));
+    let metal: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Metal { albedo: Color::new(0.7, 0.6, 0.5), fuzz: 0.05 }));
+    let lens = csg(
+        CsgOperation::Intersection,
+        sphere(Point3::new(2.3, 1.0, 0.0), 2.0, &glass),
+        sphere(Point3::new(5.7, 1.0, 0.0), 2.0, &glass),
+    );
+    let hollow_ball = csg(
+        CsgOperation::Difference,
+        sphere(Point3::new(0.0, 1.0, 0.0), 1.0, &glass),
+        sphere(Point3::new(0.0, 1.0, 0.0), 0.9, &glass),
+    );
+    let carved_cube = csg(
+        CsgOperation::Union,
+        csg(
+            CsgOperation::Difference,
+            Box::new(Cuboid::new(Point3::new(-4.8, 0.2, -0.8), Point3::new(-3.2, 1.8, 0.8), metal.clone())),
+            sphere(Point3::new(-4.0, 1.0, 0.0), 1.05, &metal),
+        ),
+        sphere(Point3::new(-4.0, 1.0, 0.0), 0.6, &glass),
+    );
+    for (object, material) in [(lens, &glass), (hollow_ball, &glass), (carved_cube, &metal)] {
+        scene.add(object, material.clone());
+    }
+}
+
+// A golden Mandelbulb, a Menger sponge with a corner scooped out, a twisted glass box and a
+// torus blended into a capsule on a plate with a row of cylinders carved out, next to a ball
+// dimpled by a grid of small spheres.
+fn add_distance_functions(scene: &mut Scene) {
+    let gold: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Metal { albedo: Color::new(0.8, 0.6, 0.2), fuzz: 0.1 }));
+    let stone: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Lambertian { albedo: Color::new(0.6, 0.6, 0.65) }));
+    let glass: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Dielectric { refraction_index: 1.5 }));
+    let clay: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Lambertian { albedo: Color::new(0.7, 0.3, 0.2) }));
+    let translate = |offset: Vec3, sdf: Sdf| Sdf::Translate { offset, sdf: Box::new(sdf) };
+
+    let mandelbulb = translate(
+        Vec3::new(4.0, 1.0, 0.0),
+        Sdf::Scale { factor: 0.8, sdf: Box::new(Sdf::Mandelbulb { power: 8.0, iterations: 12 }) },
+    );
+    let sponge = translate(
+        Vec3::new(0.0, 1.0, 0.0),
+        Sdf::Scale {
+            factor: 0.8,
+            sdf: Box::new(Sdf::SmoothSubtract {
+                k: 0.0,
+                a: Box::new(Sdf::MengerSponge { iterations: 4 }),
+                b: Box::new(translate(Vec3::new(1.0, 1.0, 1.0), Sdf::Sphere { radius: 0.9 })),
+            }),
+        },
+    );
+    let twisted_box = translate(
+        Vec3::new(-4.0, 1.0, 0.0),
+        Sdf::Twist {
+            rate: 1.2,
+            sdf: Box::new(Sdf::Box { half_extents: Vec3::new(0.45, 0.8, 0.45) }),
+        },
+    );
+    let blend = translate(
+        Vec3::new(2.0, 0.5, 2.2),
+        Sdf::SmoothSubtract {
+            k: 0.05,
+            a: Box::new(Sdf::SmoothUnion {
+                k: 0.1,
+                a: Box::new(Sdf::SmoothUnion {
+                    k: 0.3,
+                    a: Box::new(Sdf::Torus { major_radius: 0.5, minor_radius: 0.2 }),
+                    b: Box::new(Sdf::Capsule { a: Point3::new(0.0, -0.3, 0.0), b: Point3::new(0.0, 0.4, 0.0), radius: 0.2 }),
+                }),
+                b: Box::new(translate(
+                    Vec3::new(0.0, -0.45, 0.0),
+                    Sdf::RoundBox { half_extents: Vec3::new(0.6, 0.05, 0.6), radius: 0.03 },
+                )),
+            }),
+            b: Box::new(Sdf::Repeat {
+                period: Vec3::new(0.25, 0.0, 0.0),
+                count: [2, 0, 0],
+                sdf: Box::new(Sdf::Cylinder { radius: 0.06, half_height: 1.0 }),
+            }),
+        },
+    );
+    let dimpled_ball = translate(
+        Vec3::new(-1.5, 0.5, 2.5),
+        Sdf::SmoothSubtract {
+            k: 0.02,
+            a: Box::new(Sdf::Sphere { radius: 0.4 }),
+            b: Box::new(Sdf::Repeat {
+                period: Vec3::new(0.16, 0.16, 0.16),
+                count: [3, 3, 3],
+                sdf: Box::new(Sdf::Sphere { radius: 0.05 }),
+            }),
+        },
+    );
+
+    for (object, material) in [(mandelbulb, &gold), (sponge, &stone), (twisted_box, &glass), (blend, &clay), (dimpled_ball, &gold)] {
+        scene.add(Box::new(SdfObject::new(object, material.clone())), material.clone());
+    }
+}
+
+// Machined parts: a capped steel cylinder, a capped cone, an open hyperboloid, a
+// paraboloid dish with a quarter cut away, a glass ring and a half torus standing as an
+// arch.
+fn add_quadrics(scene: &mut Scene) {
+    let steel: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Metal { albedo: Color::new(0.7, 0.7, 0.75), fuzz: 0.15 }));
+    let paint: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Lambertian { albedo: Color::new(0.7, 0.15, 0.1) }));
+    let plaster: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Lambertian { albedo: Color::new(0.8, 0.8, 0.75) }));
+    let glass: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Dielectric { refraction_index: 1.5 }));
+
+    let mut cylinder = Quadric::new(QuadricShape::Cylinder { radius: 0.5 }, Point3::new(5.0, 0.0, 0.0), 0.0, 1.2, steel.clone());
+    cylinder.caps = true;
+    let mut cone = Quadric::new(QuadricShape::Cone { radius: 0.6, height: 1.4 }, Point3::new(3.0, 0.0, 0.2), 0.0, 1.4, paint.clone());
+    cone.caps = true;
+    let hyperboloid = Quadric::new(
+        QuadricShape::Hyperboloid { waist_radius: 0.35, slope: 0.6 },
+        Point3::new(1.0, 0.7, 0.0),
+        -0.7,
+        0.7,
+        plaster.clone(),
+    );
+    let mut dish = Quadric::new(QuadricShape::Paraboloid { radius: 0.8, height: 0.6 }, Point3::new(-1.5, 0.2, 0.0), 0.0, 0.6, steel.clone());
+    dish.phi_max = 270.0;
+    let ring = Quadric::new(
+        QuadricShape::Torus { major_radius: 0.7, minor_radius: 0.25 },
+        Point3::new(-4.0, 0.25, 0.0),
+        -0.25,
+        0.25,
+        glass.clone(),
+    );
+    let mut half_torus = Quadric::new(
+        QuadricShape::Torus { major_radius: 0.7, minor_radius: 0.15 },
+        Point3::new(-6.5, 0.0, 0.0),
+        -0.15,
+        0.15,
+        paint.clone(),
+    );
+    half_torus.phi_max = 180.0;
+    // Stand the half torus up as an arch over the ground.
+    let upright = Keyframe::new(0.0, Vec3::new(0.0, 0.0, 0.0), Quaternion::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), -90.0), Vec3::new(1.0, 1.0, 1.0));
+    let arch = Instance::new(Box::new(half_torus), AnimatedTransform::new(vec![upright]), Point3::new(-6.5, 0.0, 0.0));
+
+    let objects: Vec<(Box<dyn Hittable>, _)> = vec![
+        (Box::new(cylinder), &steel),
+        (Box::new(cone), &paint),
+        (Box::new(hyperboloid), &plaster),
+        (Box::new(dish), &steel),
+        (Box::new(ring), &glass),
+        (Box::new(arch), &paint),
+    ];
+    for (object, material) in objects {
+        scene.add(object, material.clone());
+    }
+}
+
+
+fn add_fur_and_grass(scene: &mut Scene) {
+    let center = Point3::new(4.0, 1.0, 0.0);
+    let hair: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(HairMaterial::from_melanin(1.3, 0.0, 0.3, 0.3)));
+    let skin: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Lambertian { albedo: Color::new(0.3, 0.2, 0.15) }));
+    let mut strands: Vec<Box<dyn Hittable>> = Vec::new();
+    for _ in 0..30000 {
+        // Uniform direction on the sphere; each hair leans along a random tangent and droops.
+        let z = 2.0 * random_float() - 1.0;
+        let angle = 2.0 * std::f64::consts::PI * random_float();
+        let normal = Vec3::new((1.0 - z * z).sqrt() * angle.cos(), z, (1.0 - z * z).sqrt() * angle.sin());
+        let lean = unit_vector(cross(normal, Vec3::new(random_float() - 0.5, random_float() - 0.5, random_float() - 0.5)));
+        let root = center + 0.6 * normal;
+        let points = [
+            (root, 0.006),
+            (root + 0.12 * normal + 0.04 * lean, 0.004),
+            (root + 0.2 * normal + 0.12 * lean - Vec3::new(0.0, 0.05, 0.0), 0.001),
+        ];
+        strands.extend(strand_curves(&points, CurveType::Cylinder, &hair).into_iter().map(|curve| Box::new(curve) as Box<dyn Hittable>));
+    }
+    scene.add(sphere(center, 0.6, &skin), skin);
+    scene.add(Box::new(Bvh::new(strands)), hair);
+
+    let grass: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Lambertian { albedo: Color::new(0.2, 0.5, 0.1) }));
+    let mut blades: Vec<Box<dyn Hittable>> = Vec::new();
+    for _ in 0..4000 {
+        // Blades rise from a disk, bend over to one side and twist a little on the way up.
+        let radius = 0.8 * random_float().sqrt();
+        let angle = 2.0 * std::f64::consts::PI * random_float();
+        let root = Point3::new(radius * angle.cos(), 0.0, 2.0 + radius * angle.sin());
+        let facing = 2.0 * std::f64::consts::PI * random_float();
+        let bend = Vec3::new(facing.cos(), 0.0, facing.sin());
+        let height = 0.3 + 0.3 * random_float();
+        let control = [root, root + Vec3::new(0.0, 0.5 * height, 0.0), root + Vec3::new(0.0, height, 0.0) + 0.1 * bend, root + 0.9 * height * Vec3::new(0.0, 1.0, 0.0) + 0.35 * bend];
+        let twist = facing + 0.5 * (random_float() - 0.5);
+        let kind = CurveType::Ribbon(bend, Vec3::new(twist.cos(), 0.3, twist.sin()));
+        blades.push(Box::new(Curve::new(control, (0.03, 0.0), kind, grass.clone())));
+    }
+    scene.add(Box::new(Bvh::new(blades)), grass);
+}
+
+// The map of the earth, or a grid of latitude and longitude if it cannot be loaded.
+fn earth_texture() -> Texture {
+    match Image::load(EARTH_MAP, false) {
+        Ok(image) => Texture::Image { image: Rc::new(image), scale: (1.0, 1.0), offset: (0.0, 0.0) },
+        Err(error) => {
+            eprintln!("Cannot load the earth map {}, using a checker instead: {}", EARTH_MAP, error);
+            Texture::Checker { even: Color::new(0.1, 0.2, 0.6), odd: Color::new(0.2, 0.5, 0.2), scale: (24.0, 12.0) }
+        }
+    }
+}
+
+fn lambertian(albedo: Color) -> Rc<RefCell<dyn MaterialTrait>> {
+    Rc::new(RefCell::new(Lambertian { albedo }))
+}
+
+fn textured(texture: Texture) -> Rc<RefCell<dyn MaterialTrait>> {
+    Rc::new(RefCell::new(TexturedLambertian { texture: Rc::new(texture) }))
+}
+
+// A two-sided light, as the lights of the books shine both ways.
+fn diffuse_light(emit: Color) -> Rc<RefCell<dyn MaterialTrait>> {
+    Rc::new(RefCell::new(DiffuseLight { emit, two_sided: true }))
+}
+
+fn sphere(center: Point3, radius: f64, material: &Rc<RefCell<dyn MaterialTrait>>) -> Box<Sphere> {
+    Box::new(Sphere::new(center, radius, material.clone(), center, false))
+}
+
+// The object turned by `degrees` around the y axis through the origin, then moved by `offset`.
+fn placed(object: Box<dyn Hittable>, degrees: f64, offset: Vec3) -> Box<Instance> {
+    let keyframe = Keyframe::new(0.0, offset, Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), degrees), Vec3::new(1.0, 1.0, 1.0));
+    Box::new(Instance::new(object, AnimatedTransform::new(vec![keyframe]), Point3::new(0.0, 0.0, 0.0)))
+}
+
+fn settings(scene: &mut Scene, image_width: f64, samples_per_pixel: i32, max_depth: i32) {
+    scene.image_width = Some(image_width);
+    scene.samples_per_pixel = Some(samples_per_pixel);
+    scene.max_depth = Some(max_depth);
+}
+
+fn view(aspect_ratio: f64, vfov: f64, look_from: Point3, look_at: Point3, defocus_angle: f64, focus_distance: f64) -> View {
+    View {
+        look_from,
+        look_at,
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        vfov,
+        aspect_ratio: Some(aspect_ratio),
+        projection: Projection::Perspective,
+        defocus_angle,
+        focus_distance: Some(focus_distance),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::scene_file::{load_scene, save_scene};
+
+    #[test]
+    fn every_scene_builds() {
+        for builtin in BuiltinScene::ALL {
+            let scene = builtin.build(0);
+            assert_eq!(BuiltinScene::from_name(builtin.name()), Some(builtin));
+            assert!(!scene.objects.is_empty(), "{} has no objects", builtin.name());
+            assert_eq!(scene.objects.len(), scene.materials.len(), "{} has objects without a material", builtin.name());
+            assert_eq!(scene.views.len(), 1, "{} should have one camera", builtin.name());
+            let bbox = Bvh::new(scene.objects).bounding_box();
+            assert!(bbox.x.size().is_finite() && bbox.y.size().is_finite() && bbox.z.size().is_finite(), "{} is unbounded", builtin.name());
+        }
+    }
+
+    #[test]
+    fn every_scene_saves() {
+        for builtin in BuiltinScene::ALL {
+            let scene = builtin.build(0);
+            let view = scene.views[0];
+            let camera = Camera::new(view.aspect_ratio.unwrap_or(1.0), 32.0, 1, 4, view.vfov, view.look_from, view.look_at, view.vup, 0.0, 10.0);
+            let path = std::env::temp_dir().join(format!("rtiow-{}.json", builtin.name()));
+            let path = path.to_str().unwrap();
+            save_scene(path, &scene.objects, &scene.materials, &camera, "sobol", 0).unwrap();
+            let loaded = load_scene(path);
+            std::fs::remove_file(path).unwrap();
+            assert_eq!(loaded.unwrap().objects.len(), scene.objects.len(), "{} lost objects", builtin.name());
+        }
+    }
+
+    #[test]
+    fn white_furnace_balls_vanish() {
+        let scene = BuiltinScene::WhiteFurnace.build(0);
+        let view = scene.views[0];
+        let aspect_ratio = view.aspect_ratio.unwrap();
+        let mut camera = Camera::new(aspect_ratio, 64.0, 16, scene.max_depth.unwrap(), view.vfov, view.look_from, view.look_at, view.vup, 0.0, 10.0);
+        camera.background = scene.background;
+        let film = camera.render_film(&Bvh::new(scene.objects)).unwrap();
+
+        // The camera looks down the z axis at the balls on the x axis, 8 units away. A patch of
+        // pixels around the centers of the diffuse and the glass ball has to average to the
+        // background.
+        let half_width = (view.vfov.to_radians() / 2.0).tan() * aspect_ratio;
+        let background = scene.background.unwrap();
+        for (name, x) in [("diffuse", -2.5), ("glass", 0.0)] {
+            let column = ((0.5 + x / 8.0 / half_width / 2.0) * film.width as f64) as usize;
+            let row = film.height / 2;
+            let (mut sum, mut count) = (Color::new(0.0, 0.0, 0.0), 0);
+            for y in row - 1..=row + 1 {
+                for x in column - 1..=column + 1 {
+                    sum = sum + film.pixel_sum(x, y);
+                    count += film.sample_count(x, y);
+                }
+            }
+            let average = sum / count as f64;
+            for channel in 0..3 {
+                assert!((average[channel] - background[channel]).abs() < 0.02, "the {} ball averages {:?}", name, average);
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SceneHasher};
+use crate::interval::Interval;
+use crate::json::Json;
+use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
+use crate::vec3::Point3;
+
+// Objects per leaf below which nodes are not split further.
+const LEAF_SIZE: usize = 2;
+
+// A node of the flattened hierarchy. Interior nodes keep their first child right after
+// themselves and point at the second one; leaves reference a range of `Bvh::order`.
+struct BvhNode {
+    bbox: Aabb,
+    second_child: usize,
+    first_object: usize,
+    object_count: usize,
+}
+
+// Bounding volume hierarchy over a list of objects. Hits report the object's index in the list as
+// their `object_id`, as `HittableList` does.
+pub(crate) struct Bvh {
+    objects: Vec<Box<dyn Hittable>>,
+    order: Vec<usize>,
+    nodes: Vec<BvhNode>,
+}
+
+impl Bvh {
+    pub(crate) fn new(objects: Vec<Box<dyn Hittable>>) -> Self {
+        let boxes: Vec<Aabb> = objects.iter().map(|object| object.bounding_box()).collect();
+        let mut order: Vec<usize> = (0..objects.len()).collect();
+        let mut nodes = Vec::with_capacity(2 * objects.len());
+
+        if !objects.is_empty() {
+            Self::build(&boxes, &mut order, 0, objects.len(), &mut nodes);
+        }
+
+        Bvh { objects, order, nodes }
+    }
+
+    fn build(boxes: &[Aabb], order: &mut [usize], start: usize, end: usize, nodes: &mut Vec<BvhNode>) -> usize {
+        let bbox = order[start..end].iter().fold(Aabb::EMPTY, |bbox, &i| Aabb::surrounding(&bbox, &boxes[i]));
+        let index = nodes.len();
+        nodes.push(BvhNode { bbox, second_child: 0, first_object: start, object_count: end - start });
+
+        if end - start <= LEAF_SIZE {
+            return index;
+        }
+
+        // Split at the median of the box centers along the axis where they spread the most.
+        let centers = order[start..end].iter().fold(Aabb::EMPTY, |centers, &i| {
+            let b = &boxes[i];
+            centers.include(Point3::new(b.centroid(0), b.centroid(1), b.centroid(2)))
+        });
+        let axis = centers.longest_axis();
+        order[start..end].sort_by(|&a, &b| boxes[a].centroid(axis).total_cmp(&boxes[b].centroid(axis)));
+
+        let middle = start + (end - start) / 2;
+        Self::build(boxes, order, start, middle, nodes);
+        let second_child = Self::build(boxes, order, middle, end, nodes);
+        nodes[index].second_child = second_child;
+        nodes[index].object_count = 0;
+
+        index
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, ray: &Ray, interval: Interval, record: &mut HitRecord) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = interval.max;
+        let mut stack = Vec::with_capacity(64);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bbox.hit(ray, Interval::with_bounds(interval.min, closest_so_far)) {
+                continue;
+            }
+
+            if node.object_count == 0 {
+                stack.push(node.second_child);
+                stack.push(index + 1);
+                continue;
+            }
+
+            for &object in &self.order[node.first_object..node.first_object + node.object_count] {
+                if self.objects[object].hit(ray, Interval::with_bounds(interval.min, closest_so_far), record) {
+                    hit_anything = true;
+                    record.object_id = object;
+                    closest_so_far = record.t;
+                }
+            }
+        }
+
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |node| node.bbox)
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        let objects: Vec<Json> = self.objects.iter().map(|object| object.save(scene)).collect::<Option<_>>()?;
+
+        // The whole of one mesh with one material, as `TriangleMesh::into_hittable` builds it, is
+        // written as the mesh rather than triangle by triangle.
+        let first = objects.first();
+        let whole_mesh = first.is_some_and(|first| {
+            objects.iter().enumerate().all(|(index, object)| {
+                object.get("type").and_then(Json::as_str) == Some("triangle")
+                    && object.get("index").and_then(Json::as_usize) == Some(index)
+                    && object.get("mesh") == first.get("mesh")
+                    && object.get("material") == first.get("material")
+            })
+        });
+        if let (true, Some(first)) = (whole_mesh, first) {
+            return Some(Json::object(vec![
+                ("type", "mesh".into()),
+                ("mesh", first.get("mesh")?.clone()),
+                ("material", first.get("material")?.clone()),
+            ]));
+        }
+        Some(Json::object(vec![("type", "bvh".into()), ("objects", objects.into())]))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add(self.objects.len() as u64);
+        for object in &self.objects {
+            object.hash(hasher);
+        }
+    }
+}
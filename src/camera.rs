@@ -1,13 +1,37 @@
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{self, Write};
-
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::aov::FeatureSample;
+use crate::aperture::Aperture;
+use crate::checkpoint;
 use crate::color;
-use crate::color::Color;
-use crate::hittable::{HitRecord, Hittable};
+use crate::color::{Color, PostProcess};
+use crate::denoise::{denoise, DenoiseSettings};
+use crate::exr::{Attribute, Compression, ExrImage, PixelType};
+use crate::film::Film;
+use crate::hittable::{HitRecord, Hittable, SceneHasher};
 use crate::interval::Interval;
+use crate::lens_system::LensSystem;
+use crate::light::PunctualLight;
+use crate::physical_camera::PhysicalCamera;
+use crate::png::write_png;
+use crate::projection::Projection;
+use crate::shutter::ShutterCurve;
+use crate::stereo::{Stereo, StereoMode};
+use crate::transform::AnimatedTransform;
 use crate::ray::Ray;
-use crate::utils::{degrees_to_radians, random_float};
-use crate::vec3::{cross, Point3, random_in_unit_disk, unit_vector, Vec3};
+use crate::sampler::{IndependentSampler, Sampler};
+use crate::utils::degrees_to_radians;
+use crate::vec3::{cross, dot, Point3, unit_vector, Vec3};
+
+// Sample dimensions consumed by every camera ray: pixel jitter (2D), lens (2D) and time (1D).
+const CAMERA_DIMENSIONS: u32 = 5;
+// Sample dimensions reserved for each bounce: one 1D and one 2D sample for the material.
+const BOUNCE_DIMENSIONS: u32 = 3;
 
 pub(crate) struct Camera {
     // Public
@@ -31,6 +55,44 @@ pub(crate) struct Camera {
     // Variation angle of rays through each pixel
     pub(crate) focus_distance: f64, // Distance from camera lookfrom point to plane of perfect focus
 
+    pub(crate) projection: Projection, // Mapping from image positions to ray directions
+
+    pub(crate) shutter_open: f64,
+    // Time the shutter opens; ray times are spread over the shutter interval
+    pub(crate) shutter_close: f64,
+    // Time the shutter closes
+    pub(crate) shutter_curve: ShutterCurve, // How much light the shutter lets through while opening and closing
+    pub(crate) camera_motion: Option<AnimatedTransform>, // Moves the whole camera around `look_from` over time
+    pub(crate) physical: Option<PhysicalCamera>, // Derives vfov, defocus_angle and exposure when set
+
+    pub(crate) lens_system: Option<LensSystem>, // Traces rays through a real lens prescription instead of the thin lens
+    pub(crate) aperture: Aperture,
+    // Shape of the lens opening
+    pub(crate) cat_eye: f64, // Strength of the cat's eye vignetting at the image corners, 0 disables it
+
+    pub(crate) stereo: Option<Stereo>, // Renders both eyes into one packed image when set
+
+    pub(crate) sampler: Box<dyn Sampler>, // Source of the sample values for each pixel sample
+    pub(crate) lights: Vec<PunctualLight>, // Lights without size, reached by shadow rays from every hit
+    pub(crate) background: Option<Color>, // Light arriving from every direction missing the scene; None gives the sky
+
+    pub(crate) checkpoint_file: Option<String>,
+    // File the accumulated render is periodically saved to
+    pub(crate) checkpoint_interval: f64,
+    // Seconds between two checkpoints
+    pub(crate) resume: bool, // Continue from `checkpoint_file` if it exists
+
+    pub(crate) write_aovs: bool, // Also write depth, normal, position, albedo and id images
+
+    pub(crate) exr_file: Option<String>,
+    // Linear HDR output holding the beauty pass and, with `write_aovs`, the output variables
+    pub(crate) exr_compression: Compression,
+    pub(crate) exr_pixel_type: PixelType, // Channel type of the color layers
+
+    pub(crate) output_file: String, // Display image, written as PNG for a .png path and as PPM otherwise
+    pub(crate) denoise: bool, // Also write a denoised copy of the image
+
+    pub(crate) post_process: PostProcess, // Exposure, tone mapping and white balance of the 8-bit images
 
     // Private
     image_height: i32,
@@ -50,9 +112,15 @@ pub(crate) struct Camera {
     defocus_disk_u: Vec3,
     // Defocus disk horizontal radius
     defocus_disk_v: Vec3, // Defocus disk vertical radius
+    field_of_view: f64,
+    // Vertical field of view in use, `vfov` or the physical camera's
+    lens_angle: f64,
+    // Defocus angle in use, `defocus_angle` or the physical camera's
+    physical_exposure: f64, // Exposure in EV stops derived from the physical camera
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio: f64,
         image_width: f64,
@@ -75,6 +143,29 @@ impl Camera {
             vup,
             defocus_angle,
             focus_distance,
+            projection: Projection::Perspective,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            shutter_curve: ShutterCurve::new(Vec::new()),
+            camera_motion: None,
+            physical: None,
+            lens_system: None,
+            aperture: Aperture::Circular,
+            stereo: None,
+            cat_eye: 0.0,
+            sampler: Box::new(IndependentSampler::new(0)),
+            lights: Vec::new(),
+            background: None,
+            checkpoint_file: None,
+            checkpoint_interval: 300.0,
+            resume: false,
+            write_aovs: false,
+            exr_file: None,
+            exr_compression: Compression::Zip,
+            exr_pixel_type: PixelType::Half,
+            output_file: String::from("output.ppm"),
+            denoise: false,
+            post_process: PostProcess::new(),
             u: Vec3::new(0.0, 0.0, 0.0),
             v: Vec3::new(0.0, 0.0, 0.0),
             w: Vec3::new(0.0, 0.0, 0.0),
@@ -85,33 +176,281 @@ impl Camera {
             pixel_delta_v: Vec3::new(0.0, 0.0, 0.0),
             defocus_disk_u: Vec3::new(0.0, 0.0, 0.0),
             defocus_disk_v: Vec3::new(0.0, 0.0, 0.0),
+            field_of_view: vfov,
+            lens_angle: defocus_angle,
+            physical_exposure: 0.0,
         }
     }
 
     pub(crate) fn render(&mut self, world: &dyn Hittable) -> io::Result<()> {
+        let render_start = Instant::now();
+        let film = Self::render_film(self, world)?;
+        let (width, height) = (film.width, film.height);
+        let post_process = Self::display_settings(self);
+
+        Self::write_display_image(&self.output_file, width, height, |x, y| {
+            color::display_color(film.pixel_sum(x, y), film.sample_count(x, y) as i32, &post_process)
+        })?;
+
+        // Companion files are named after the main image: <stem>.denoised.<extension> and
+        // <stem>.<variable>.pfm.
+        let output_path = Path::new(&self.output_file);
+        let extension = output_path.extension().and_then(|extension| extension.to_str()).unwrap_or("ppm");
+        let stem = output_path.with_extension("").to_string_lossy().into_owned();
+
+        let denoised = if self.denoise { Some(denoise(&film, &DenoiseSettings::default())) } else { None };
+        if let Some(denoised) = &denoised {
+            Self::write_display_image(&format!("{}.denoised.{}", stem, extension), width, height, |x, y| {
+                color::display_color(denoised[y * width + x], 1, &post_process)
+            })?;
+        }
+
+        if let Some(path) = &self.exr_file {
+            Self::write_exr(self, &film, denoised.as_deref(), path, render_start.elapsed().as_secs_f64())?;
+        } else if self.write_aovs {
+            film.aovs.write(&stem)?;
+        }
+        eprintln!("\nDone.");
+
+        Ok(())
+    }
+
+    pub(crate) fn render_film(&mut self, world: &dyn Hittable) -> io::Result<Film> {
+        // Traces every sample of the image into a film, continuing from and saving to the
+        // checkpoint file when one is set.
         Self::initialize(self);
 
-        let mut file = File::create("output.ppm")?;
-        writeln!(file, "P3\n{} {}\n255", self.image_width, self.image_height)?;
+        // Stereo renders both eyes into one packed film.
+        let (width, height) = match self.stereo {
+            Some(stereo) => stereo.packed_size(self.image_width as usize, self.image_height as usize),
+            None => (self.image_width as usize, self.image_height as usize),
+        };
+        let mut film = Film::new(width, height);
+
+        // The checkpoint file with the fingerprint of the scene it belongs to.
+        let checkpoint = self.checkpoint_file.clone().map(|path| (path, Self::scene_hash(self, world)));
+
+        if let Some((path, scene_hash)) = &checkpoint {
+            if self.resume && Path::new(path).exists() {
+                let saved = checkpoint::load(path)?;
+                if saved.sampler != self.sampler.name() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("checkpoint {} was rendered with the {} sampler, not {}", path, saved.sampler, self.sampler.name()),
+                    ));
+                }
+                if saved.scene_hash != *scene_hash || saved.film.width != width || saved.film.height != height {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("checkpoint {} was rendered from a different scene", path),
+                    ));
+                }
+                eprintln!("Resuming from {}", path);
+                self.sampler.set_seed(saved.seed);
+                film = saved.film;
+            }
+        }
+
+        // Only the output variables and the denoiser use first-hit data, so other renders skip
+        // the pass over the pixel centers and the extra ray per sample.
+        let wants_features = self.write_aovs || self.denoise;
+        let mut material_ids = if wants_features { Self::material_ids(self, world) } else { HashMap::new() };
+        let mut last_checkpoint = Instant::now();
+
+        for h in 0..height as i32 {
+            eprintln!("Scanlines remaining: {} ", height as i32 - h);
+
+            for w in 0..width as i32 {
+                let (eye, eye_x, eye_y) = match self.stereo {
+                    Some(stereo) => stereo.eye_pixel(w, h, self.image_width as i32, self.image_height),
+                    None => (0.0, w, h),
+                };
+
+                // Pixels restored from a checkpoint continue where their sample sequence stopped.
+                let first_sample = film.sample_count(w as usize, h as usize) as i32;
+
+                for sample in first_sample..self.samples_per_pixel {
+                    self.sampler.start_pixel_sample((w, h), sample as u32);
+                    // Image positions outside a fisheye's image circle see nothing.
+                    let Some((ray, weight)) = Self::get_ray(self, eye_x, eye_y, eye) else {
+                        film.add_sample(w as usize, h as usize, Color::new(0.0, 0.0, 0.0));
+                        continue;
+                    };
+                    if wants_features {
+                        if let Some(feature) = Self::feature_sample(&ray, world, -self.w, &mut material_ids) {
+                            film.aovs.add_sample(w as usize, h as usize, &feature);
+                        }
+                    }
+                    let ray_color: Color = Self::ray_color(&ray, self.max_depth, world, &self.lights, self.background, self.sampler.as_mut());
+                    film.add_sample(w as usize, h as usize, weight * ray_color);
+                }
+            }
+
+            if let Some((path, scene_hash)) = &checkpoint {
+                if last_checkpoint.elapsed().as_secs_f64() >= self.checkpoint_interval {
+                    checkpoint::save(path, *scene_hash, self.sampler.name(), self.sampler.seed(), &film)?;
+                    last_checkpoint = Instant::now();
+                }
+            }
+        }
+
+        if let Some((path, scene_hash)) = &checkpoint {
+            checkpoint::save(path, *scene_hash, self.sampler.name(), self.sampler.seed(), &film)?;
+        }
+
+        Ok(film)
+    }
+
+    pub(crate) fn display_settings(&self) -> PostProcess {
+        // Returns the post-processing of the 8-bit images, with the exposure of the physical camera.
+        PostProcess { exposure: self.post_process.exposure + self.physical_exposure, ..self.post_process }
+    }
+
+    fn write_display_image(path: &str, width: usize, height: usize, pixel: impl Fn(usize, usize) -> [u8; 3]) -> io::Result<()> {
+        // Writes 8-bit display values as a PNG when the path ends in .png, else as an ASCII PPM.
+        if path.to_ascii_lowercase().ends_with(".png") {
+            let rgb: Vec<u8> = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).flat_map(|(x, y)| pixel(x, y)).collect();
+            return write_png(path, width, height, &rgb);
+        }
+
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "P3\n{} {}\n255", width, height)?;
+        for y in 0..height {
+            for x in 0..width {
+                let [red, green, blue] = pixel(x, y);
+                writeln!(file, "{} {} {}", red, green, blue)?;
+            }
+        }
+        file.flush()
+    }
+
+    fn write_exr(&self, film: &Film, denoised: Option<&[Color]>, path: &str, render_time: f64) -> io::Result<()> {
+        let mut image = ExrImage::new(film.width, film.height, self.exr_compression);
 
-        for h in 0..self.image_height {
-            eprintln!("Scanlines remaining: {} ", self.image_height - h);
+        image.add_layer("", ["R", "G", "B"], self.exr_pixel_type, |x, y| {
+            film.pixel_sum(x, y) / film.sample_count(x, y).max(1) as f64
+        });
 
-            for w in 0..self.image_width as i32 {
-                let mut pixel_color: Color = Color::new(0.0, 0.0, 0.0);
+        if let Some(denoised) = denoised {
+            image.add_layer("denoised", ["R", "G", "B"], self.exr_pixel_type, |x, y| denoised[y * film.width + x]);
+        }
+
+        if self.write_aovs {
+            let aovs = &film.aovs;
+            image.add_channel("depth.Z", PixelType::Float, |x, y| aovs.depth(x, y));
+            image.add_layer("normal", ["X", "Y", "Z"], self.exr_pixel_type, |x, y| aovs.normal(x, y));
+            image.add_layer("position", ["X", "Y", "Z"], PixelType::Float, |x, y| aovs.position(x, y));
+            image.add_layer("albedo", ["R", "G", "B"], self.exr_pixel_type, |x, y| aovs.albedo(x, y));
+            image.add_channel("material_id.id", PixelType::Uint, |x, y| aovs.material_id(x, y) as f64);
+            image.add_channel("object_id.id", PixelType::Uint, |x, y| aovs.object_id(x, y) as f64);
+        }
 
-                for _ in 0..self.samples_per_pixel {
-                    let ray: Ray = Self::get_ray(self, w, h);
-                    let ray_color: Color = Self::ray_color(&ray, self.max_depth, world);
-                    pixel_color = pixel_color + ray_color;
+        image.set_attribute("software", Attribute::Text(String::from("rtiow")));
+        image.set_attribute("samplesPerPixel", Attribute::Int(self.samples_per_pixel));
+        image.set_attribute("renderTime", Attribute::Float(render_time as f32));
+        image.set_attribute("cameraLookFrom", Attribute::Vector(self.look_from));
+        image.set_attribute("cameraLookAt", Attribute::Vector(self.look_at));
+        image.set_attribute("cameraUp", Attribute::Vector(self.vup));
+        image.set_attribute("cameraVerticalFov", Attribute::Float(self.field_of_view as f32));
+        image.set_attribute("cameraDefocusAngle", Attribute::Float(self.lens_angle as f32));
+        image.set_attribute("cameraFocusDistance", Attribute::Float(self.focus_distance as f32));
+        image.set_attribute("shutterOpen", Attribute::Float(self.shutter_open as f32));
+        image.set_attribute("shutterClose", Attribute::Float(self.shutter_close as f32));
+        if let Some(physical) = self.physical {
+            image.set_attribute("focalLength", Attribute::Float(physical.focal_length as f32));
+            image.set_attribute("aperture", Attribute::Float(physical.f_number as f32));
+            image.set_attribute("isoSpeed", Attribute::Float(physical.iso as f32));
+            image.set_attribute("expTime", Attribute::Float(physical.shutter_seconds as f32));
+        }
+
+        image.write(path)
+    }
+
+    fn material_ids(&self, world: &dyn Hittable) -> HashMap<usize, u32> {
+        // Numbers the materials in the order the pixel centers first see them, so that ids do not
+        // depend on which pixels a resumed render still has to sample.
+        let mut material_ids = HashMap::new();
+
+        for j in 0..self.image_height {
+            for i in 0..self.image_width as i32 {
+                if let Some(ray) = Self::pinhole_ray(self, i as f64 + 0.5, j as f64 + 0.5) {
+                    Self::feature_sample(&ray, world, -self.w, &mut material_ids);
                 }
+            }
+        }
+
+        material_ids
+    }
+
+    fn feature_sample(ray: &Ray, world: &dyn Hittable, forward: Vec3, material_ids: &mut HashMap<usize, u32>) -> Option<FeatureSample> {
+        // Returns the first-hit data of a camera ray for the output variables. Depth is measured
+        // along the camera's unit `forward` axis, as a z-buffer holds it.
+        let mut hit_record = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0, false);
+        if !world.hit(ray, Interval::with_bounds(0.001, f64::INFINITY), &mut hit_record) {
+            return None;
+        }
+
+        let material_key = Rc::as_ptr(&hit_record.material_ptr) as *const () as usize;
+        let next_id = material_ids.len() as u32 + 1;
+        let material_id = *material_ids.entry(material_key).or_insert(next_id);
+        let albedo = hit_record.tinted(hit_record.material_ptr.borrow().albedo(&hit_record));
+
+        Some(FeatureSample {
+            depth: dot(hit_record.point - ray.origin(), forward),
+            normal: hit_record.normal,
+            position: hit_record.point,
+            albedo,
+            material_id,
+            object_id: hit_record.object_id as u32 + 1,
+        })
+    }
 
-                color::write_color(&mut file, pixel_color, self.samples_per_pixel)?;
+    fn scene_hash(&self, world: &dyn Hittable) -> u64 {
+        // Fingerprints the scene by walking the world, together with the camera settings that
+        // change the image.
+        let mut hasher = SceneHasher::new();
+        for value in [
+            self.image_width.to_bits(),
+            self.image_height as u64,
+            self.max_depth as u64,
+            self.field_of_view.to_bits(),
+            self.lens_angle.to_bits(),
+            self.focus_distance.to_bits(),
+            self.shutter_open.to_bits(),
+            self.shutter_close.to_bits(),
+        ] {
+            hasher.add(value);
+        }
+        if let Some(stereo) = self.stereo {
+            hasher.add(stereo.mode as u64);
+            hasher.add(stereo.layout as u64);
+            hasher.add(stereo.interpupillary_distance.to_bits());
+        }
+        for value in self.shutter_curve.values() {
+            hasher.add(value.to_bits());
+        }
+        for light in &self.lights {
+            for i in 0..3 {
+                hasher.add(light.position[i].to_bits());
+                hasher.add(light.intensity[i].to_bits());
             }
         }
-        eprintln!("\nDone.");
+        if let Some(background) = self.background {
+            (0..3).for_each(|i| hasher.add(background[i].to_bits()));
+        }
+        if let Some(motion) = &self.camera_motion {
+            for keyframe in motion.keyframes() {
+                hasher.add_keyframe(keyframe);
+            }
+        }
+        for i in 0..3 {
+            hasher.add(self.look_from[i].to_bits());
+            hasher.add(self.look_at[i].to_bits());
+            hasher.add(self.vup[i].to_bits());
+        }
 
-        Ok(())
+        world.hash(&mut hasher);
+        hasher.finish()
     }
 
     fn initialize(&mut self) {
@@ -123,12 +462,26 @@ impl Camera {
 
         self.center = self.look_from;
 
+        // A physical camera replaces the field of view, aperture and exposure settings.
+        (self.field_of_view, self.lens_angle, self.physical_exposure) = (self.vfov, self.defocus_angle, 0.0);
+        if let Some(physical) = self.physical {
+            self.field_of_view = physical.vertical_fov(self.image_width / self.image_height as f64);
+            self.lens_angle = 2.0 * (physical.aperture_radius() / self.focus_distance).atan().to_degrees();
+            self.physical_exposure = physical.exposure();
+        }
+        if let Some(lens_system) = &mut self.lens_system {
+            lens_system.focus(self.focus_distance);
+        }
+
         // Determine viewport dimensions.
         // let focal_length: f64 = (self.look_from - self.look_at).length();
-        let theta = degrees_to_radians(self.vfov);
+        let theta = degrees_to_radians(self.field_of_view);
         let height = (theta / 2.0).tan();
 
-        let viewport_height: f64 = 2.0 * height * self.focus_distance;
+        let viewport_height: f64 = match self.projection {
+            Projection::Orthographic { width } => width * self.image_height as f64 / self.image_width,
+            _ => 2.0 * height * self.focus_distance,
+        };
         let viewport_width: f64 = viewport_height * (self.image_width / self.image_height as f64);
 
         // Calculate the u,v,w unit basis vectors for the camera coordinate frame.
@@ -149,36 +502,117 @@ impl Camera {
         self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
 
         // Calculate the camera defocus disk basis vectors.
-        let defocus_radius = self.focus_distance * (degrees_to_radians(self.defocus_angle) / 2.0).tan();
+        let defocus_radius = self.focus_distance * (degrees_to_radians(self.lens_angle) / 2.0).tan();
         self.defocus_disk_u = defocus_radius * self.u;
         self.defocus_disk_v = defocus_radius * self.v;
     }
 
-    fn get_ray(&self, u: i32, v: i32) -> Ray {
-        // Get a randomly-sampled camera ray for the pixel at location i,j, originating from
-        // the camera defocus disk.
-        let pixel_center: Vec3 = self.pixel00_loc + (u as f64 * self.pixel_delta_u + v as f64 * self.pixel_delta_v);
-        let pixel_sample = pixel_center + Self::pixel_sample_square(self);
-        let ray_origin = if self.defocus_angle <= 0.0 { self.center } else { Self::defocus_disk_sample(self) };
-        let ray_direction: Vec3 = pixel_sample - ray_origin;
-        let ray_time: f64 = random_float();
-        return Ray::new(ray_origin, ray_direction, ray_time);
+    fn get_ray(&mut self, u: i32, v: i32, eye: f64) -> Option<(Ray, f64)> {
+        // Get a sampled camera ray for the pixel at location i,j, originating from
+        // the camera defocus disk of the given eye.
+        let (sample_x, sample_y) = self.sampler.get_2d();
+        let lens_sample = self.sampler.get_2d();
+        let ray_time: f64 = self.shutter_open + self.shutter_curve.sample(self.sampler.get_1d()) * (self.shutter_close - self.shutter_open);
+        Self::generate_ray(self, u as f64 + sample_x, v as f64 + sample_y, lens_sample, ray_time, eye)
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        // Returns a random point in the camera defocus disk
-        let point = random_in_unit_disk();
-        return self.center + (point[0] * self.defocus_disk_u + point[1] * self.defocus_disk_v);
+    fn pinhole_ray(&self, image_x: f64, image_y: f64) -> Option<Ray> {
+        // Returns the ray through the center of the lens for a position on the image, for probing
+        // the scene independently of the sampler.
+        Self::generate_ray(self, image_x, image_y, (0.5, 0.5), 0.5 * (self.shutter_open + self.shutter_close), 0.0).map(|(ray, _)| ray)
     }
 
-    fn pixel_sample_square(&self) -> Vec3 {
-        // Returns a random point in the square surrounding a pixel at the origin
-        let px = -0.5 + random_float();
-        let py = -0.5 + random_float();
-        return px * self.pixel_delta_u + py * self.pixel_delta_v;
+    fn generate_ray(&self, image_x: f64, image_y: f64, lens_sample: (f64, f64), time: f64, eye: f64) -> Option<(Ray, f64)> {
+        // Returns the camera ray through (image_x, image_y), measured in pixels from the upper left
+        // corner of the image, and the weight of its radiance. `eye` is -1 for the left eye of a
+        // stereo pair, 1 for the right one and 0 without stereo.
+        let (ray, weight) = Self::generate_static_ray(self, image_x, image_y, lens_sample, time, eye)?;
+
+        // Camera motion carries the ray along with the camera at the ray's time.
+        let Some(motion) = &self.camera_motion else {
+            return Some((ray, weight));
+        };
+        let keyframe = motion.at(time);
+        let origin = keyframe.apply_point(ray.origin() - self.center) + self.center;
+        Some((Ray::new(origin, keyframe.apply_vector(ray.direction()), time), weight))
     }
 
-    fn ray_color(ray: &Ray, depth: i32, world: &dyn Hittable) -> Color {
+    fn generate_static_ray(&self, image_x: f64, image_y: f64, lens_sample: (f64, f64), time: f64, eye: f64) -> Option<(Ray, f64)> {
+        // Returns the ray of `generate_ray` for the camera at rest.
+        let eye_shift = self.stereo.map_or(0.0, |stereo| eye * stereo.interpupillary_distance / 2.0);
+
+        if let Some(lens_system) = &self.lens_system {
+            let s = image_x / self.image_width;
+            let t = image_y / self.image_height as f64;
+            let (origin, direction, weight) = lens_system.generate_ray(s, t, self.image_width / self.image_height as f64, lens_sample)?;
+            // Lens space looks down +z, the camera looks down -w.
+            let world_origin = self.center + eye_shift * self.u + origin.x() * self.u + origin.y() * self.v - origin.z() * self.w;
+            let world_direction = direction.x() * self.u + direction.y() * self.v - direction.z() * self.w;
+            return Some((Ray::new(world_origin, world_direction, time), weight));
+        }
+
+        let mut image_point = self.pixel00_loc + (image_x - 0.5) * self.pixel_delta_u + (image_y - 0.5) * self.pixel_delta_v;
+        let lens_offset = Self::defocus_disk_sample(self, image_x, image_y, lens_sample)?;
+        let eye_center = self.center + eye_shift * self.u;
+
+        // Parallel eyes are whole cameras side by side; converged eyes share the focus plane.
+        let converged = matches!(self.stereo, Some(Stereo { mode: StereoMode::Converged, .. }));
+        if !converged || self.projection != Projection::Perspective {
+            image_point = image_point + eye_shift * self.u;
+        }
+
+        match self.projection {
+            Projection::Perspective => {
+                let ray_origin = if self.lens_angle <= 0.0 { eye_center } else { eye_center + lens_offset };
+                Some((Ray::new(ray_origin, image_point - ray_origin, time), 1.0))
+            }
+            Projection::Orthographic { .. } => {
+                // Rays start on the camera plane straight behind their point on the focus plane.
+                let mut ray_origin = image_point + self.focus_distance * self.w;
+                if self.lens_angle > 0.0 {
+                    ray_origin = ray_origin + lens_offset;
+                }
+                Some((Ray::new(ray_origin, image_point - ray_origin, time), 1.0))
+            }
+            _ => {
+                let s = image_x / self.image_width;
+                let t = image_y / self.image_height as f64;
+                let direction = self.projection.camera_direction(s, t, self.image_width / self.image_height as f64)?;
+                let world_direction = direction.x() * self.u + direction.y() * self.v + direction.z() * self.w;
+
+                let ray_origin = if self.projection == Projection::Equirectangular {
+                    // Omni-directional stereo: the eyes sit on a circle and each column sees the
+                    // scene from the eye pair facing it. The horizontal right vector shrinks with
+                    // the sine of the polar angle, merging the eyes at the poles.
+                    self.center + eye_shift * (-direction.z() * self.u + direction.x() * self.w)
+                } else {
+                    eye_center
+                };
+                Some((Ray::new(ray_origin, world_direction, time), 1.0))
+            }
+        }
+    }
+
+    fn defocus_disk_sample(&self, image_x: f64, image_y: f64, lens_sample: (f64, f64)) -> Option<Vec3> {
+        // Returns the offset of a sampled point in the camera aperture from the lens center, or
+        // None when the lens barrel blocks it.
+        let (x, y) = self.aperture.sample(lens_sample);
+
+        if self.cat_eye > 0.0 && self.lens_angle > 0.0 {
+            // Off axis, the opening is the intersection of the aperture with the circle of the
+            // barrel, shifted towards the image edge; defocused highlights become cat's eyes.
+            let shift_x = (2.0 * image_x / self.image_width - 1.0) * self.cat_eye;
+            let shift_y = (1.0 - 2.0 * image_y / self.image_height as f64) * self.cat_eye;
+            let (dx, dy) = (x - shift_x, y - shift_y);
+            if dx * dx + dy * dy > 1.0 {
+                return None;
+            }
+        }
+
+        Some(x * self.defocus_disk_u + y * self.defocus_disk_v)
+    }
+
+    fn ray_color(ray: &Ray, depth: i32, world: &dyn Hittable, lights: &[PunctualLight], background: Option<Color>, sampler: &mut dyn Sampler) -> Color {
         // If we've exerted the maximum depth, no more light is gathered.
         if depth <= 0 {
             return Color::new(0.0, 0.0, 0.0);
@@ -187,16 +621,39 @@ impl Camera {
         let mut hit_record: HitRecord = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0, false);
 
         if world.hit(ray, Interval::with_bounds(0.001, f64::INFINITY), &mut hit_record) {
-            // let direction = hit_record.normal + random_unit_vector();
-            // let ray: Ray = Ray::new(hit_record.point, direction);
-            let hit: Option<(Color, Ray)> = hit_record.material_ptr.borrow().scatter(ray, &hit_record);
-            if hit != None {
-                let (attenuation, scattered) = hit.unwrap();
-                return attenuation * Self::ray_color(&scattered, depth - 1, world);
+            // Every bounce starts at its own dimension so that the same bounce of different pixel
+            // samples consumes the same dimensions, whatever the previous materials drew.
+            sampler.set_dimension(CAMERA_DIMENSIONS + depth as u32 * BOUNCE_DIMENSIONS);
+            let material = hit_record.material_ptr.clone();
+            let material = material.borrow();
+            let mut color = material.emitted(&hit_record);
+
+            // Punctual lights cannot be hit by chance, so each one is checked with a shadow ray.
+            for light in lights {
+                let Some((direction, distance, light_color)) = light.illuminate(hit_record.point) else {
+                    continue;
+                };
+                let reflected = hit_record.tinted(material.evaluate(ray, &hit_record, direction));
+                if reflected.near_zero() {
+                    continue;
+                }
+                let shadow_ray = Ray::new(hit_record.point, direction, ray.time());
+                let mut blocker = HitRecord::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0), 0.0, false);
+                if !world.hit(&shadow_ray, Interval::with_bounds(0.001, distance * (1.0 - 1e-9)), &mut blocker) {
+                    color = color + reflected * light_color;
+                }
             }
-            return Color::new(0.0, 0.0, 0.0);
+
+            let hit: Option<(Color, Ray)> = material.scatter(ray, &hit_record, sampler);
+            if let Some((attenuation, scattered)) = hit {
+                color = color + hit_record.tinted(attenuation) * Self::ray_color(&scattered, depth - 1, world, lights, background, sampler);
+            }
+            return color;
         }
 
+        if let Some(background) = background {
+            return background;
+        }
         let unit_direction: Vec3 = unit_vector(ray.direction());
         const START_VALUE: f64 = 0.5;
         const END_VALUE: f64 = 1.0;
@@ -205,6 +662,45 @@ impl Camera {
         let white: Color = Color::new(1.0, 1.0, 1.0);
         let blue: Color = Color::new(0.5, 0.7, 1.0);
 
-        return (END_VALUE - delta) * white + delta * blue;
+        (END_VALUE - delta) * white + delta * blue
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtin::BuiltinScene;
+    use crate::bvh::Bvh;
+
+    // Average 8-bit value of a small render of the material ball, through the default physical
+    // camera or a plain one with the same field of view.
+    fn mean_display_value(physical: bool) -> f64 {
+        let scene = BuiltinScene::MaterialBall.build(0);
+        let view = scene.views[0];
+        let aspect_ratio = view.aspect_ratio.unwrap_or(16.0 / 9.0);
+        let vfov = PhysicalCamera::new().vertical_fov(aspect_ratio);
+        let mut camera = Camera::new(aspect_ratio, 48.0, 8, 10, vfov, view.look_from, view.look_at, view.vup, 0.0, 10.0);
+        camera.background = scene.background;
+        camera.lights = scene.lights;
+        if physical {
+            camera.physical = Some(PhysicalCamera::new());
+        }
+
+        let film = camera.render_film(&Bvh::new(scene.objects)).unwrap();
+        let post_process = camera.display_settings();
+        let mut total = 0.0;
+        for y in 0..film.height {
+            for x in 0..film.width {
+                let pixel = color::display_color(film.pixel_sum(x, y), film.sample_count(x, y) as i32, &post_process);
+                total += pixel.iter().map(|&value| value as f64).sum::<f64>();
+            }
+        }
+        total / (3 * film.width * film.height) as f64
+    }
+
+    #[test]
+    fn default_physical_camera_keeps_the_exposure() {
+        assert_eq!(PhysicalCamera::new().exposure(), 0.0);
+        let (plain, physical) = (mean_display_value(false), mean_display_value(true));
+        assert!((plain - physical).abs() < 1.0, "plain render averages {}, physical {}", plain, physical);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,146 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::aov::AovPixel;
+use crate::color::Color;
+use crate::film::Film;
+use crate::vec3::Vec3;
+
+const MAGIC: &[u8; 8] = b"RTIOWCK3";
+
+// Bytes per pixel after the header: the color sum and sample count, then the AOVs.
+const PIXEL_SIZE: u64 = 24 + 4 + 8 + 3 * 24 + 3 * 4;
+
+// Longest sampler name a checkpoint may hold.
+const MAX_SAMPLER_NAME: u32 = 64;
+
+// Everything needed to continue an interrupted render: the accumulated film, the sampler that
+// produced it with its seed and a hash identifying the scene it belongs to.
+pub(crate) struct Checkpoint {
+    pub(crate) scene_hash: u64,
+    pub(crate) sampler: String,
+    pub(crate) seed: u64,
+    pub(crate) film: Film,
+}
+
+fn write_u32(out: &mut dyn Write, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_u64(out: &mut dyn Write, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_vec3(out: &mut dyn Write, value: Vec3) -> io::Result<()> {
+    write_u64(out, value.x().to_bits())?;
+    write_u64(out, value.y().to_bits())?;
+    write_u64(out, value.z().to_bits())
+}
+
+fn read_u32(input: &mut dyn Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut dyn Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut dyn Read) -> io::Result<f64> {
+    Ok(f64::from_bits(read_u64(input)?))
+}
+
+fn read_vec3(input: &mut dyn Read) -> io::Result<Vec3> {
+    Ok(Vec3::new(read_f64(input)?, read_f64(input)?, read_f64(input)?))
+}
+
+// Saves the checkpoint next to `path` first and then moves it into place, so a render killed
+// while saving never leaves a truncated checkpoint behind.
+pub(crate) fn save(path: &str, scene_hash: u64, sampler: &str, seed: u64, film: &Film) -> io::Result<()> {
+    let temporary_path = format!("{}.tmp", path);
+    {
+        let mut out = BufWriter::new(File::create(&temporary_path)?);
+        out.write_all(MAGIC)?;
+        write_u64(&mut out, scene_hash)?;
+        write_u32(&mut out, sampler.len() as u32)?;
+        out.write_all(sampler.as_bytes())?;
+        write_u64(&mut out, seed)?;
+        write_u32(&mut out, film.width as u32)?;
+        write_u32(&mut out, film.height as u32)?;
+
+        for y in 0..film.height {
+            for x in 0..film.width {
+                write_vec3(&mut out, film.pixel_sum(x, y))?;
+                write_u32(&mut out, film.sample_count(x, y))?;
+
+                let aov = film.aovs.pixel(x, y);
+                write_u64(&mut out, aov.depth.to_bits())?;
+                write_vec3(&mut out, aov.normal)?;
+                write_vec3(&mut out, aov.position)?;
+                write_vec3(&mut out, aov.albedo)?;
+                write_u32(&mut out, aov.material_id)?;
+                write_u32(&mut out, aov.object_id)?;
+                write_u32(&mut out, aov.hit_count)?;
+            }
+        }
+        out.flush()?;
+    }
+    fs::rename(&temporary_path, path)
+}
+
+pub(crate) fn load(path: &str) -> io::Result<Checkpoint> {
+    let file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let mut input = BufReader::new(file);
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{} {}", path, message));
+
+    let mut magic = [0u8; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("is not a render checkpoint"));
+    }
+
+    let scene_hash = read_u64(&mut input)?;
+    let name_length = read_u32(&mut input)?;
+    if name_length > MAX_SAMPLER_NAME {
+        return Err(invalid("has a corrupt header"));
+    }
+    let mut name = vec![0u8; name_length as usize];
+    input.read_exact(&mut name)?;
+    let sampler = String::from_utf8(name).map_err(|_| invalid("has a corrupt header"))?;
+    let seed = read_u64(&mut input)?;
+    let width = read_u32(&mut input)? as usize;
+    let height = read_u32(&mut input)? as usize;
+
+    // The header has to agree with the size of the file before the film is allocated.
+    let header_size = (MAGIC.len() + 8 + 4 + sampler.len() + 8 + 4 + 4) as u64;
+    let pixels_size = (width as u64).checked_mul(height as u64).and_then(|pixels| pixels.checked_mul(PIXEL_SIZE));
+    if pixels_size.and_then(|size| size.checked_add(header_size)) != Some(file_size) {
+        return Err(invalid(&format!("does not hold a {} by {} film", width, height)));
+    }
+
+    let mut film = Film::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let sum: Color = read_vec3(&mut input)?;
+            let sample_count = read_u32(&mut input)?;
+            film.set_pixel(x, y, sum, sample_count);
+
+            let aov = AovPixel {
+                depth: read_f64(&mut input)?,
+                normal: read_vec3(&mut input)?,
+                position: read_vec3(&mut input)?,
+                albedo: read_vec3(&mut input)?,
+                material_id: read_u32(&mut input)?,
+                object_id: read_u32(&mut input)?,
+                hit_count: read_u32(&mut input)?,
+            };
+            film.aovs.set_pixel(x, y, aov);
+        }
+    }
+
+    Ok(Checkpoint { scene_hash, sampler, seed, film })
+}
@@ -1,39 +1,233 @@
 use crate::interval::Interval;
-use crate::vec3::Vec3;
+use crate::vec3::{dot, Vec3};
 
 pub(crate) type Color = Vec3;
 
-pub(crate) fn linear_to_gamma(color: f64) -> f64 {
-    color.sqrt()
+// Rows of the linear sRGB (D65) to CIE XYZ matrix and its inverse.
+const SRGB_TO_XYZ: [Vec3; 3] = [
+    Vec3::new(0.4123908, 0.3575843, 0.1804808),
+    Vec3::new(0.2126390, 0.7151687, 0.0721923),
+    Vec3::new(0.0193308, 0.1191948, 0.9505322),
+];
+const XYZ_TO_SRGB: [Vec3; 3] = [
+    Vec3::new(3.2409699, -1.5373832, -0.4986108),
+    Vec3::new(-0.9692436, 1.8759675, 0.0415551),
+    Vec3::new(0.0556301, -0.2039770, 1.0569715),
+];
+
+// Bradford cone response matrix used for chromatic adaptation, and its inverse.
+const BRADFORD: [Vec3; 3] = [
+    Vec3::new(0.8951, 0.2664, -0.1614),
+    Vec3::new(-0.7502, 1.7135, 0.0367),
+    Vec3::new(0.0389, -0.0685, 1.0296),
+];
+const BRADFORD_INVERSE: [Vec3; 3] = [
+    Vec3::new(0.9869929, -0.1470543, 0.1599627),
+    Vec3::new(0.4323053, 0.5183603, 0.0492912),
+    Vec3::new(-0.0085287, 0.0400428, 0.9684867),
+];
+
+// Input and output matrices of Stephen Hill's fit of the ACES reference rendering transform.
+const ACES_INPUT: [Vec3; 3] = [
+    Vec3::new(0.59719, 0.35458, 0.04823),
+    Vec3::new(0.07600, 0.90834, 0.01566),
+    Vec3::new(0.02840, 0.13383, 0.83777),
+];
+const ACES_OUTPUT: [Vec3; 3] = [
+    Vec3::new(1.60475, -0.53108, -0.07367),
+    Vec3::new(-0.10208, 1.10813, -0.00605),
+    Vec3::new(-0.00327, -0.07276, 1.07602),
+];
+
+// AgX inset and outset matrices, which desaturate bright colors before the log curve.
+const AGX_INSET: [Vec3; 3] = [
+    Vec3::new(0.842479062253094, 0.0784335999999992, 0.0792237451477643),
+    Vec3::new(0.0423282422610123, 0.878468636469772, 0.0791661274605434),
+    Vec3::new(0.0423756549057051, 0.0784336, 0.879142973793104),
+];
+const AGX_OUTSET: [Vec3; 3] = [
+    Vec3::new(1.19687900512017, -0.0980208811401368, -0.0990297440797205),
+    Vec3::new(-0.0528968517574562, 1.15190312990417, -0.0989611768448433),
+    Vec3::new(-0.0529716355144438, -0.0980434501171241, 1.15107367264116),
+];
+const AGX_MIN_EV: f64 = -12.47393;
+const AGX_MAX_EV: f64 = 4.026069;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ToneMapper {
+    // Hard clip at 1
+    Clamp,
+    // Reinhard's operator on luminance, keeping hue
+    Reinhard,
+    // Filmic curve of the ACES reference rendering and output transforms
+    AcesFilmic,
+    // Log encoded filmic curve in the style of Blender's AgX
+    Agx,
+}
+
+impl ToneMapper {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "clamp" => Some(ToneMapper::Clamp),
+            "reinhard" => Some(ToneMapper::Reinhard),
+            "aces" => Some(ToneMapper::AcesFilmic),
+            "agx" => Some(ToneMapper::Agx),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ToneMapper::Clamp => "clamp",
+            ToneMapper::Reinhard => "reinhard",
+            ToneMapper::AcesFilmic => "aces",
+            ToneMapper::Agx => "agx",
+        }
+    }
+
+    // Maps linear scene radiance to linear display values in [0, 1].
+    fn apply(&self, color: Color) -> Color {
+        match self {
+            ToneMapper::Clamp => color,
+            ToneMapper::Reinhard => {
+                let luminance = luminance(color);
+                if luminance <= 0.0 {
+                    return color;
+                }
+                color * (1.0 / (1.0 + luminance))
+            }
+            ToneMapper::AcesFilmic => {
+                let v = multiply(&ACES_INPUT, color);
+                let fitted = Color::new(aces_curve(v.x()), aces_curve(v.y()), aces_curve(v.z()));
+                multiply(&ACES_OUTPUT, fitted)
+            }
+            ToneMapper::Agx => {
+                let v = multiply(&AGX_INSET, color);
+                let encode = |x: f64| (x.max(1e-10).log2().clamp(AGX_MIN_EV, AGX_MAX_EV) - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+                let curved = Color::new(agx_curve(encode(v.x())), agx_curve(encode(v.y())), agx_curve(encode(v.z())));
+                // The curve produces display encoded values; bring them back to linear.
+                let v = multiply(&AGX_OUTSET, curved);
+                Color::new(v.x().max(0.0).powf(2.2), v.y().max(0.0).powf(2.2), v.z().max(0.0).powf(2.2))
+            }
+        }
+    }
+}
+
+// The post-processing stage that turns the float framebuffer into display values: white balance,
+// exposure, tone mapping and the sRGB transfer function, in that order.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PostProcess {
+    pub(crate) exposure: f64,
+    // Exposure adjustment in EV stops; +1 doubles the brightness
+    pub(crate) tone_mapper: ToneMapper,
+    pub(crate) white_balance: Option<f64>, // Color temperature in Kelvin that is mapped to white
+}
+
+impl PostProcess {
+    pub(crate) fn new() -> Self {
+        PostProcess { exposure: 0.0, tone_mapper: ToneMapper::Clamp, white_balance: None }
+    }
+
+    // Returns sRGB encoded values in [0, 1] for a linear pixel value.
+    pub(crate) fn apply(&self, linear: Color) -> Color {
+        let mut color = linear;
+        if let Some(temperature) = self.white_balance {
+            color = white_balance(color, temperature);
+        }
+        color = color * 2f64.powf(self.exposure);
+        color = self.tone_mapper.apply(color);
+
+        let unit = Interval::with_bounds(0.0, 1.0);
+        Color::new(
+            linear_to_srgb(unit.clamp(color.x())),
+            linear_to_srgb(unit.clamp(color.y())),
+            linear_to_srgb(unit.clamp(color.z())),
+        )
+    }
+}
+
+fn multiply(matrix: &[Vec3; 3], v: Vec3) -> Vec3 {
+    Vec3::new(dot(matrix[0], v), dot(matrix[1], v), dot(matrix[2], v))
+}
+
+pub(crate) fn luminance(color: Color) -> f64 {
+    dot(SRGB_TO_XYZ[1], color)
+}
+
+fn aces_curve(v: f64) -> f64 {
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    a / b
+}
+
+// Polynomial approximation of the AgX base contrast curve.
+fn agx_curve(x: f64) -> f64 {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
 }
 
-pub(crate) fn write_color(out: &mut dyn std::io::Write, pixel_color: Color, samples_per_pixel: i32) -> std::io::Result<()> {
-    let mut red = pixel_color.x();
-    let mut green = pixel_color.y();
-    let mut blue = pixel_color.z();
+// CIE xy chromaticity of a blackbody at `temperature` Kelvin (Kang et al. 2002).
+fn planckian_chromaticity(temperature: f64) -> (f64, f64) {
+    let t = temperature.clamp(1667.0, 25000.0);
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000.0 {
+        -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t3 + 2.1070379e6 / t2 + 0.2226347e3 / t + 0.240390
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.1063814 * x3 - 1.34811020 * x2 + 2.18555832 * x - 0.20219683
+    } else if t <= 4000.0 {
+        -0.9549476 * x3 - 1.37418593 * x2 + 2.09137015 * x - 0.16748867
+    } else {
+        3.0817580 * x3 - 5.87338670 * x2 + 3.75112997 * x - 0.37001483
+    };
+    (x, y)
+}
 
+// Chromatic adaptation (von Kries in Bradford space) from a light of the given color temperature
+// to the D65 white point of sRGB, so that light appears white.
+fn white_balance(color: Color, temperature: f64) -> Color {
+    let (x, y) = planckian_chromaticity(temperature);
+    let source_white = Vec3::new(x / y, 1.0, (1.0 - x - y) / y);
+    let target_white = Vec3::new(0.95047, 1.0, 1.08883);
+
+    let source_cone = multiply(&BRADFORD, source_white);
+    let target_cone = multiply(&BRADFORD, target_white);
+    let gain = Vec3::new(
+        target_cone.x() / source_cone.x(),
+        target_cone.y() / source_cone.y(),
+        target_cone.z() / source_cone.z(),
+    );
+
+    let cone = multiply(&BRADFORD, multiply(&SRGB_TO_XYZ, color)) * gain;
+    multiply(&XYZ_TO_SRGB, multiply(&BRADFORD_INVERSE, cone))
+}
+
+// The piecewise sRGB opto-electronic transfer function (IEC 61966-2-1).
+pub(crate) fn linear_to_srgb(color: f64) -> f64 {
+    if color <= 0.0031308 {
+        12.92 * color
+    } else {
+        1.055 * color.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Develops an accumulated pixel into 8-bit display values.
+pub(crate) fn display_color(pixel_color: Color, samples_per_pixel: i32, post_process: &PostProcess) -> [u8; 3] {
     // Divide the color by the number of samples
-    let scale = 1.0 / samples_per_pixel as f64;
-    red = red * scale;
-    green = green * scale;
-    blue = blue * scale;
+    let scale = 1.0 / samples_per_pixel.max(1) as f64;
 
-    // Apply the linear to gamma transform
-    red = linear_to_gamma(red);
-    green = linear_to_gamma(green);
-    blue = linear_to_gamma(blue);
+    // Develop the linear value into display values
+    let display = post_process.apply(pixel_color * scale);
 
     let intensity: Interval = Interval::with_bounds(0.0, 0.999);
 
-    red = 256.0 * intensity.clamp(red);
-    green = 256.0 * intensity.clamp(green);
-    blue = 256.0 * intensity.clamp(blue);
-
-    writeln!(
-        out,
-        "{} {} {}",
-        red,
-        green,
-        blue
-    )
+    let red = (256.0 * intensity.clamp(display.x())) as u8;
+    let green = (256.0 * intensity.clamp(display.y())) as u8;
+    let blue = (256.0 * intensity.clamp(display.z())) as u8;
+
+    [red, green, blue]
 }
@@ -0,0 +1,158 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SceneHasher, Span};
+use crate::interval::Interval;
+use crate::json::Json;
+use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
+use crate::vec3::{Point3, Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CsgOperation {
+    Union,
+    Intersection,
+    // The left object with the right one carved out
+    Difference,
+}
+
+impl CsgOperation {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            CsgOperation::Union => "union",
+            CsgOperation::Intersection => "intersection",
+            CsgOperation::Difference => "difference",
+        }
+    }
+
+    fn contains(&self, inside_left: bool, inside_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => inside_left || inside_right,
+            CsgOperation::Intersection => inside_left && inside_right,
+            CsgOperation::Difference => inside_left && !inside_right,
+        }
+    }
+}
+
+// Constructive solid geometry: a boolean combination of two closed objects (ones that report
+// `spans`), itself closed so that nodes nest. Surfaces keep the material of the object they come
+// from.
+pub(crate) struct Csg {
+    operation: CsgOperation,
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl Csg {
+    // Fails if either operand is not closed, as it would leave the node with no inside at all.
+    pub(crate) fn new(operation: CsgOperation, left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Result<Self, String> {
+        // Objects without spans have none for any ray, so a single probe tells.
+        let probe = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        for (side, operand) in [("left", &left), ("right", &right)] {
+            if operand.spans(&probe).is_none() {
+                return Err(format!("the {} operand of a CSG {} is not a closed object", side, operation.name()));
+            }
+        }
+
+        let (left_box, right_box) = (left.bounding_box(), right.bounding_box());
+        let bbox = match operation {
+            CsgOperation::Union => Aabb::surrounding(&left_box, &right_box),
+            CsgOperation::Intersection => Aabb::new(
+                Interval::with_bounds(left_box.x.min.max(right_box.x.min), left_box.x.max.min(right_box.x.max)),
+                Interval::with_bounds(left_box.y.min.max(right_box.y.min), left_box.y.max.min(right_box.y.max)),
+                Interval::with_bounds(left_box.z.min.max(right_box.z.min), left_box.z.max.min(right_box.z.max)),
+            ),
+            CsgOperation::Difference => left_box,
+        };
+        Ok(Csg { operation, left, right, bbox })
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, interval: Interval, record: &mut HitRecord) -> bool {
+        if !self.bbox.hit(ray, interval) {
+            return false;
+        }
+        let Some(spans) = self.spans(ray) else {
+            return false;
+        };
+
+        let first = spans.iter().flat_map(|span| [&span.enter, &span.exit]).find(|hit| interval.surrounds(hit.t));
+        let Some(hit) = first else {
+            return false;
+        };
+
+        *record = hit.clone();
+        let outward_normal = record.normal;
+        record.set_face_normal(ray, outward_normal);
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        // Walk the boundaries of both operands in order, tracking whether the ray is inside each,
+        // and keep the boundaries where the combined inside state changes.
+        let left = self.left.spans(ray)?;
+        let right = self.right.spans(ray)?;
+
+        let mut events: Vec<(&HitRecord, bool, bool)> = Vec::with_capacity(2 * (left.len() + right.len()));
+        for (spans, is_left) in [(&left, true), (&right, false)] {
+            for span in spans {
+                events.push((&span.enter, is_left, true));
+                events.push((&span.exit, is_left, false));
+            }
+        }
+        events.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+        let (mut inside_left, mut inside_right) = (false, false);
+        let mut inside = false;
+        let mut enter: Option<HitRecord> = None;
+        let mut result = Vec::new();
+
+        for (hit, is_left, entering) in events {
+            if is_left {
+                inside_left = entering;
+            } else {
+                inside_right = entering;
+            }
+            let now_inside = self.operation.contains(inside_left, inside_right);
+            if now_inside == inside {
+                continue;
+            }
+            inside = now_inside;
+
+            // Carved surfaces face into the removed object.
+            let mut boundary = hit.clone();
+            if !is_left && self.operation == CsgOperation::Difference {
+                boundary.normal = -boundary.normal;
+            }
+
+            if inside {
+                enter = Some(boundary);
+            } else if let Some(enter) = enter.take() {
+                if boundary.t > enter.t {
+                    result.push(Span { enter, exit: boundary });
+                }
+            }
+        }
+
+        Some(result)
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![
+            ("type", "csg".into()),
+            ("operation", self.operation.name().into()),
+            ("left", self.left.save(scene)?),
+            ("right", self.right.save(scene)?),
+        ]))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add(self.operation as u64);
+        self.left.hash(hasher);
+        self.right.hash(hasher);
+    }
+}
@@ -0,0 +1,118 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SceneHasher, Span};
+use crate::interval::Interval;
+use crate::json::Json;
+use crate::material::MaterialTrait;
+use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
+use crate::vec3::{Point3, Vec3};
+
+// A solid axis-aligned box. Rotated or moving boxes are made with an `Instance`.
+pub(crate) struct Cuboid {
+    min: Point3,
+    max: Point3,
+    material_ptr: Rc<RefCell<dyn MaterialTrait>>,
+}
+
+impl Cuboid {
+    // Box with the two points as opposite corners, in any order.
+    pub(crate) fn new(a: Point3, b: Point3, material_ptr: Rc<RefCell<dyn MaterialTrait>>) -> Self {
+        let min = Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        Cuboid { min, max, material_ptr }
+    }
+
+    // Parameters where the line of the ray enters and leaves the box, with the axes of the faces
+    // it crosses there, or None if it misses.
+    fn crossings(&self, ray: &Ray) -> Option<((f64, usize), (f64, usize))> {
+        let mut near = (-f64::INFINITY, 0);
+        let mut far = (f64::INFINITY, 0);
+
+        for axis in 0..3 {
+            let origin = ray.origin()[axis];
+            let direction = ray.direction()[axis];
+            if direction == 0.0 {
+                if origin < self.min[axis] || origin > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+
+            let t0 = (self.min[axis] - origin) / direction;
+            let t1 = (self.max[axis] - origin) / direction;
+            let (t_enter, t_exit) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            if t_enter > near.0 {
+                near = (t_enter, axis);
+            }
+            if t_exit < far.0 {
+                far = (t_exit, axis);
+            }
+        }
+
+        if near.0 >= far.0 {
+            return None;
+        }
+        Some((near, far))
+    }
+
+    fn record(&self, ray: &Ray, t: f64, axis: usize) -> HitRecord {
+        let point = ray.at(t);
+        // The outward normal of the crossed face points away from the box center.
+        let center = 0.5 * (self.min[axis] + self.max[axis]);
+        let mut outward_normal = Vec3::new(0.0, 0.0, 0.0);
+        outward_normal[axis] = if point[axis] > center { 1.0 } else { -1.0 };
+
+        let mut record = HitRecord::new(point, outward_normal, t, false);
+        record.set_material(&self.material_ptr);
+        record
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, ray_t: Interval, record: &mut HitRecord) -> bool {
+        let Some((near, far)) = self.crossings(ray) else {
+            return false;
+        };
+
+        let (t, axis) = if ray_t.surrounds(near.0) {
+            near
+        } else if ray_t.surrounds(far.0) {
+            far
+        } else {
+            return false;
+        };
+
+        *record = self.record(ray, t, axis);
+        let outward_normal = record.normal;
+        record.set_face_normal(ray, outward_normal);
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::from_points(self.min, self.max)
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        Some(match self.crossings(ray) {
+            Some((near, far)) => vec![Span { enter: self.record(ray, near.0, near.1), exit: self.record(ray, far.0, far.1) }],
+            None => Vec::new(),
+        })
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![
+            ("type", "cuboid".into()),
+            ("min", self.min.into()),
+            ("max", self.max.into()),
+            ("material", scene.material(&self.material_ptr)),
+        ]))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add_box(&self.bounding_box());
+        hasher.add_material(&self.material_ptr);
+    }
+}
@@ -0,0 +1,305 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SceneHasher};
+use crate::interval::Interval;
+use crate::json::Json;
+use crate::material::MaterialTrait;
+use crate::mesh::invalid_data;
+use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
+use crate::vec3::{cross, dot, unit_vector, Point3, Vec3};
+
+// Deepest subdivision of a curve during intersection.
+const MAX_DEPTH: i32 = 10;
+
+// How the width of a curve is turned into a surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CurveType {
+    // A strip that always faces the ray, for fine hair and fur seen from afar
+    Flat,
+    // A strip facing the ray but shaded with the normals of a round tube
+    Cylinder,
+    // A strip with its normal turning from the first to the second vector along the curve, like
+    // a blade of grass; it thins out when seen edge-on
+    Ribbon(Vec3, Vec3),
+}
+
+impl CurveType {
+    // The types that need no normals.
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "flat" => Some(CurveType::Flat),
+            "cylinder" => Some(CurveType::Cylinder),
+            _ => None,
+        }
+    }
+}
+
+// Cubic Bezier curve swept by a width that changes linearly from one end to the other. Hits give
+// u along the curve and v across it, with the tangent along u.
+pub(crate) struct Curve {
+    control: [Point3; 4],
+    widths: (f64, f64),
+    kind: CurveType,
+    material_ptr: Rc<RefCell<dyn MaterialTrait>>,
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    (1.0 - t) * a + t * b
+}
+
+fn bezier_point(cp: &[Point3; 4], u: f64) -> Point3 {
+    let s = 1.0 - u;
+    s * s * s * cp[0] + 3.0 * s * s * u * cp[1] + 3.0 * s * u * u * cp[2] + u * u * u * cp[3]
+}
+
+fn bezier_derivative(cp: &[Point3; 4], u: f64) -> Vec3 {
+    let s = 1.0 - u;
+    3.0 * (s * s * (cp[1] - cp[0]) + 2.0 * s * u * (cp[2] - cp[1]) + u * u * (cp[3] - cp[2]))
+}
+
+// Splits the curve at its middle into two halves (de Casteljau).
+fn split(cp: &[Point3; 4]) -> ([Point3; 4], [Point3; 4]) {
+    let m01 = 0.5 * (cp[0] + cp[1]);
+    let m12 = 0.5 * (cp[1] + cp[2]);
+    let m23 = 0.5 * (cp[2] + cp[3]);
+    let a = 0.5 * (m01 + m12);
+    let b = 0.5 * (m12 + m23);
+    let middle = 0.5 * (a + b);
+    ([cp[0], m01, a, middle], [middle, b, m23, cp[3]])
+}
+
+// A ray-space hit: distance along the unit ray direction and the curve coordinates.
+struct CurveHit {
+    z: f64,
+    u: f64,
+    v: f64,
+}
+
+// State of the search for the nearest hit along one ray, in ray space.
+struct Search {
+    direction: Vec3, // Unit ray direction in world space
+    z_min: f64,
+    z_max: f64,
+    hit: Option<CurveHit>,
+}
+
+impl Curve {
+    pub(crate) fn new(control: [Point3; 4], widths: (f64, f64), kind: CurveType, material_ptr: Rc<RefCell<dyn MaterialTrait>>) -> Self {
+        Curve { control, widths, kind, material_ptr }
+    }
+
+    fn ribbon_normal(&self, u: f64) -> Option<Vec3> {
+        let CurveType::Ribbon(n0, n1) = self.kind else {
+            return None;
+        };
+        let (n0, n1) = (unit_vector(n0), unit_vector(n1));
+        let angle = dot(n0, n1).clamp(-1.0, 1.0).acos();
+        if angle < 1e-6 {
+            return Some(n0);
+        }
+        Some((((1.0 - u) * angle).sin() * n0 + (u * angle).sin() * n1) / angle.sin())
+    }
+
+    // Intersects the part of the curve from `u0` to `u1`, given by `cp` in ray space (the ray
+    // starts at the origin and runs along +z), keeping the nearest hit of the search. Halves are
+    // tested until `depth` runs out and the remaining piece is nearly straight.
+    fn intersect_piece(&self, cp: &[Point3; 4], u0: f64, u1: f64, depth: i32, search: &mut Search) {
+        // Reject pieces whose widened bounds miss the ray.
+        let half_width = 0.5 * lerp(u0, self.widths.0, self.widths.1).max(lerp(u1, self.widths.0, self.widths.1));
+        let bounds = cp.iter().fold(Aabb::EMPTY, |bounds, &p| bounds.include(p));
+        if bounds.x.min - half_width > 0.0
+            || bounds.x.max + half_width < 0.0
+            || bounds.y.min - half_width > 0.0
+            || bounds.y.max + half_width < 0.0
+            || bounds.z.min - half_width > search.z_max
+            || bounds.z.max + half_width < search.z_min
+        {
+            return;
+        }
+
+        if depth > 0 {
+            let (first, second) = split(cp);
+            let middle = 0.5 * (u0 + u1);
+            self.intersect_piece(&first, u0, middle, depth - 1, search);
+            self.intersect_piece(&second, middle, u1, depth - 1, search);
+            return;
+        }
+
+        // Treat the piece as the segment between its ends. The ray must pass between the planes
+        // through the ends perpendicular to the curve there, so neighboring pieces do not both
+        // report a hit.
+        if (cp[1].y() - cp[0].y()) * -cp[0].y() + cp[0].x() * (cp[0].x() - cp[1].x()) < 0.0 {
+            return;
+        }
+        if (cp[2].y() - cp[3].y()) * -cp[3].y() + cp[3].x() * (cp[3].x() - cp[2].x()) < 0.0 {
+            return;
+        }
+        let segment = Vec3::new(cp[3].x() - cp[0].x(), cp[3].y() - cp[0].y(), 0.0);
+        let denominator = segment.length_squared();
+        if denominator == 0.0 {
+            return;
+        }
+        // Closest point of the segment to the ray, as a fraction of the piece
+        let w = (-(cp[0].x() * segment.x() + cp[0].y() * segment.y()) / denominator).clamp(0.0, 1.0);
+        let u = lerp(w, u0, u1).clamp(u0, u1);
+
+        let mut hit_width = lerp(u, self.widths.0, self.widths.1);
+        if let Some(normal) = self.ribbon_normal(u) {
+            hit_width *= dot(normal, search.direction).abs();
+        }
+
+        let point = bezier_point(cp, w);
+        let distance_squared = point.x() * point.x() + point.y() * point.y();
+        if distance_squared > 0.25 * hit_width * hit_width || point.z() < search.z_min || point.z() > search.z_max {
+            return;
+        }
+
+        // Offset across the curve: v = 0.5 on the center line, increasing towards the side
+        // given by the direction back along the ray crossed with the tangent.
+        let tangent = bezier_derivative(cp, w);
+        let side = tangent.y() * point.x() - tangent.x() * point.y();
+        let offset = distance_squared.sqrt() / hit_width;
+        let v = if side > 0.0 { 0.5 - offset } else { 0.5 + offset };
+
+        search.z_max = point.z();
+        search.hit = Some(CurveHit { z: point.z(), u, v });
+    }
+}
+
+impl Hittable for Curve {
+    fn hit(&self, ray: &Ray, ray_t: Interval, record: &mut HitRecord) -> bool {
+        // Move the control points into a space where the ray starts at the origin and runs along
+        // +z, with the curve's chord mostly along x, which keeps the bounds of its pieces tight.
+        let length = ray.direction().length();
+        let z_axis = ray.direction() / length;
+        let mut across = cross(z_axis, self.control[3] - self.control[0]);
+        if across.length_squared() < 1e-20 {
+            let helper = if z_axis.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+            across = cross(z_axis, helper);
+        }
+        let y_axis = unit_vector(across);
+        let x_axis = cross(y_axis, z_axis);
+        let to_ray = |p: Point3| {
+            let q = p - ray.origin();
+            Point3::new(dot(q, x_axis), dot(q, y_axis), dot(q, z_axis))
+        };
+        let cp = [to_ray(self.control[0]), to_ray(self.control[1]), to_ray(self.control[2]), to_ray(self.control[3])];
+
+        // Enough halvings for the pieces to be within a twentieth of the width of straight.
+        let mut curvature: f64 = 0.0;
+        for i in 0..2 {
+            let second = cp[i] - 2.0 * cp[i + 1] + cp[i + 2];
+            curvature = curvature.max(second.x().abs()).max(second.y().abs()).max(second.z().abs());
+        }
+        let epsilon = 0.05 * self.widths.0.max(self.widths.1);
+        let depth = if curvature > 0.0 && epsilon > 0.0 {
+            ((std::f64::consts::SQRT_2 * 6.0 * curvature / (8.0 * epsilon)).log2() * 0.5).round().clamp(0.0, MAX_DEPTH as f64) as i32
+        } else {
+            0
+        };
+
+        let mut search = Search { direction: z_axis, z_min: ray_t.min * length, z_max: ray_t.max * length, hit: None };
+        self.intersect_piece(&cp, 0.0, 1.0, depth, &mut search);
+        let Some(hit) = search.hit else {
+            return false;
+        };
+
+        let t = hit.z / length;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+        record.t = t;
+        record.point = ray.at(t);
+        let tangent = unit_vector(bezier_derivative(&self.control, hit.u));
+        // Facing normal: back along the ray, square to the curve
+        let facing = unit_vector(-z_axis + dot(z_axis, tangent) * tangent);
+        let outward_normal = match self.kind {
+            CurveType::Flat => facing,
+            CurveType::Cylinder => {
+                let h = 2.0 * hit.v - 1.0;
+                let side = cross(facing, tangent);
+                (1.0 - h * h).max(0.0).sqrt() * facing + h * side
+            }
+            CurveType::Ribbon(..) => unit_vector(self.ribbon_normal(hit.u).unwrap_or(facing)),
+        };
+        record.set_face_normal(ray, outward_normal);
+        record.set_material(&self.material_ptr);
+        (record.u, record.v) = (hit.u, hit.v);
+        record.tangent = tangent;
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let half_width = 0.5 * self.widths.0.max(self.widths.1);
+        let bounds = self.control.iter().fold(Aabb::EMPTY, |bounds, &p| bounds.include(p));
+        let r = Vec3::new(half_width, half_width, half_width);
+        Aabb::from_points(bounds.min() - r, bounds.max() + r)
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        let control: Vec<f64> = self.control.iter().flat_map(|point| [point.x(), point.y(), point.z()]).collect();
+        let mut members = vec![("type", "curve".into()), ("control", control.into()), ("widths", vec![self.widths.0, self.widths.1].into())];
+        match self.kind {
+            CurveType::Flat => members.push(("kind", "flat".into())),
+            CurveType::Cylinder => members.push(("kind", "cylinder".into())),
+            CurveType::Ribbon(n0, n1) => {
+                members.push(("kind", "ribbon".into()));
+                members.push(("normals", vec![n0.x(), n0.y(), n0.z(), n1.x(), n1.y(), n1.z()].into()));
+            }
+        }
+        members.push(("material", scene.material(&self.material_ptr)));
+        Some(Json::object(members))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add_box(&self.bounding_box());
+        hasher.add_material(&self.material_ptr);
+    }
+}
+
+// Reads strands, one per line: groups of x, y, z and width for each point the strand passes
+// through, separated by whitespace. Lines starting with '#' are comments.
+pub(crate) fn load_strands(path: &str) -> io::Result<Vec<Vec<(Point3, f64)>>> {
+    let text = fs::read_to_string(path)?;
+    let mut strands = Vec::new();
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let values: Vec<f64> = line
+            .split_whitespace()
+            .map(|field| field.parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid_data(format!("{}:{}: expected numbers", path, line_number + 1)))?;
+        if !values.len().is_multiple_of(4) || values.len() < 8 {
+            return Err(invalid_data(format!("{}:{}: expected at least two points of x, y, z and width", path, line_number + 1)));
+        }
+        strands.push(values.chunks(4).map(|v| (Point3::new(v[0], v[1], v[2]), v[3])).collect());
+    }
+
+    Ok(strands)
+}
+
+// Bezier curves passing through the points of a strand, with Catmull-Rom tangents.
+pub(crate) fn strand_curves(points: &[(Point3, f64)], kind: CurveType, material_ptr: &Rc<RefCell<dyn MaterialTrait>>) -> Vec<Curve> {
+    let last = points.len() - 1;
+    let point = |i: isize| points[i.clamp(0, last as isize) as usize].0;
+    (0..last as isize)
+        .map(|i| {
+            let control = [
+                point(i),
+                point(i) + (point(i + 1) - point(i - 1)) / 6.0,
+                point(i + 1) - (point(i + 2) - point(i)) / 6.0,
+                point(i + 1),
+            ];
+            Curve::new(control, (points[i as usize].1, points[i as usize + 1].1), kind, Rc::clone(material_ptr))
+        })
+        .collect()
+}
@@ -0,0 +1,130 @@
+use crate::color::Color;
+use crate::film::Film;
+use crate::vec3::{dot, Vec3};
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al., "Edge-Avoiding À-Trous Wavelet Transform
+// for fast Global Illumination Filtering"). Each pass blurs with a 5x5 B3-spline kernel whose taps
+// are spread 2^pass pixels apart; neighbours only contribute where color, albedo, normal and depth
+// agree, so edges and texture survive while the Monte Carlo noise is averaged away.
+
+const PASSES: usize = 5;
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Per-pixel guide data taken from the output variables.
+#[derive(Clone, Copy)]
+struct Feature {
+    albedo: Color,
+    normal: Vec3,
+    depth: f64,
+    hit: bool,
+}
+
+pub(crate) struct DenoiseSettings {
+    pub(crate) color_sigma: f64,
+    pub(crate) albedo_sigma: f64,
+    pub(crate) normal_power: f64,
+    pub(crate) depth_sigma: f64,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings { color_sigma: 0.6, albedo_sigma: 0.1, normal_power: 64.0, depth_sigma: 0.05 }
+    }
+}
+
+// Returns the denoised, linear color of every pixel in scanline order.
+pub(crate) fn denoise(film: &Film, settings: &DenoiseSettings) -> Vec<Color> {
+    let width = film.width;
+    let height = film.height;
+
+    let mut features = Vec::with_capacity(width * height);
+    let mut image = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let hit = film.aovs.pixel(x, y).hit_count > 0;
+            let albedo = film.aovs.albedo(x, y);
+            features.push(Feature { albedo, normal: film.aovs.normal(x, y), depth: film.aovs.depth(x, y), hit });
+
+            // Filter illumination rather than color so the albedo's texture is not blurred.
+            let color = film.pixel_sum(x, y) / film.sample_count(x, y).max(1) as f64;
+            image.push(if hit { demodulate(color, albedo) } else { color });
+        }
+    }
+
+    for pass in 0..PASSES {
+        let step = 1i64 << pass;
+        // Later passes see less noise, so they may be stricter about color differences.
+        let color_sigma = settings.color_sigma / (1u64 << pass) as f64;
+        let mut filtered = Vec::with_capacity(width * height);
+
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let center = (y as usize) * width + x as usize;
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                let mut weight_sum = 0.0;
+
+                for (j, kernel_y) in KERNEL.iter().enumerate() {
+                    let sample_y = y + (j as i64 - 2) * step;
+                    if sample_y < 0 || sample_y >= height as i64 {
+                        continue;
+                    }
+                    for (i, kernel_x) in KERNEL.iter().enumerate() {
+                        let sample_x = x + (i as i64 - 2) * step;
+                        if sample_x < 0 || sample_x >= width as i64 {
+                            continue;
+                        }
+                        let neighbour = (sample_y as usize) * width + sample_x as usize;
+                        let weight = kernel_x
+                            * kernel_y
+                            * edge_weight(&features[center], &features[neighbour], settings)
+                            * color_weight(image[center], image[neighbour], color_sigma);
+                        sum = sum + weight * image[neighbour];
+                        weight_sum += weight;
+                    }
+                }
+
+                filtered.push(if weight_sum > 0.0 { sum / weight_sum } else { image[center] });
+            }
+        }
+        image = filtered;
+    }
+
+    image
+        .iter()
+        .zip(&features)
+        .map(|(&color, feature)| if feature.hit { color * floor_albedo(feature.albedo) } else { color })
+        .collect()
+}
+
+// Keeps black surfaces from dividing by zero when their illumination is demodulated.
+fn floor_albedo(albedo: Color) -> Color {
+    const MIN_ALBEDO: f64 = 1e-3;
+    Color::new(albedo.x().max(MIN_ALBEDO), albedo.y().max(MIN_ALBEDO), albedo.z().max(MIN_ALBEDO))
+}
+
+fn demodulate(color: Color, albedo: Color) -> Color {
+    let albedo = floor_albedo(albedo);
+    Color::new(color.x() / albedo.x(), color.y() / albedo.y(), color.z() / albedo.z())
+}
+
+fn color_weight(center: Color, neighbour: Color, sigma: f64) -> f64 {
+    let difference = center - neighbour;
+    (-difference.length_squared() / (sigma * sigma)).exp()
+}
+
+fn edge_weight(center: &Feature, neighbour: &Feature, settings: &DenoiseSettings) -> f64 {
+    if center.hit != neighbour.hit {
+        return 0.0;
+    }
+    if !center.hit {
+        return 1.0;
+    }
+
+    let albedo_difference = (center.albedo - neighbour.albedo).length_squared();
+    let albedo_weight = (-albedo_difference / (settings.albedo_sigma * settings.albedo_sigma)).exp();
+    let normal_weight = dot(center.normal, neighbour.normal).max(0.0).powf(settings.normal_power);
+    let relative_depth = (center.depth - neighbour.depth).abs() / center.depth.max(1e-6);
+    let depth_weight = (-relative_depth / settings.depth_sigma).exp();
+
+    albedo_weight * normal_weight * depth_weight
+}
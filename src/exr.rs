@@ -0,0 +1,340 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::deflate::zlib_compress;
+use crate::vec3::Vec3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Compression {
+    None,
+    Rle,
+    // Zlib, one scanline per block
+    Zips,
+    // Zlib, sixteen scanlines per block
+    Zip,
+}
+
+impl Compression {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Compression::None),
+            "rle" => Some(Compression::Rle),
+            "zips" => Some(Compression::Zips),
+            "zip" => Some(Compression::Zip),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Rle => 1,
+            Compression::Zips => 2,
+            Compression::Zip => 3,
+        }
+    }
+
+    fn scanlines_per_block(&self) -> usize {
+        match self {
+            Compression::Zip => 16,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PixelType {
+    Uint,
+    Half,
+    Float,
+}
+
+impl PixelType {
+    fn id(&self) -> i32 {
+        match self {
+            PixelType::Uint => 0,
+            PixelType::Half => 1,
+            PixelType::Float => 2,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            PixelType::Half => 2,
+            _ => 4,
+        }
+    }
+}
+
+// Header attribute values; covers what the renderer records as metadata.
+pub(crate) enum Attribute {
+    Int(i32),
+    Float(f32),
+    Text(String),
+    Vector(Vec3),
+}
+
+struct Channel {
+    name: String,
+    pixel_type: PixelType,
+    values: Vec<f64>,
+}
+
+// A single part, scanline OpenEXR image. Channels named `layer.channel` form layers.
+pub(crate) struct ExrImage {
+    width: usize,
+    height: usize,
+    compression: Compression,
+    channels: Vec<Channel>,
+    attributes: Vec<(String, Attribute)>,
+}
+
+impl ExrImage {
+    pub(crate) fn new(width: usize, height: usize, compression: Compression) -> Self {
+        ExrImage { width, height, compression, channels: Vec::new(), attributes: Vec::new() }
+    }
+
+    // Adds a channel whose values are given by `pixel` for every pixel, in scanline order.
+    pub(crate) fn add_channel(&mut self, name: &str, pixel_type: PixelType, pixel: impl Fn(usize, usize) -> f64) {
+        let mut values = Vec::with_capacity(self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                values.push(pixel(x, y));
+            }
+        }
+        self.channels.push(Channel { name: name.to_string(), pixel_type, values });
+    }
+
+    // Adds the three channels of a vector valued layer, such as `albedo.R`, `albedo.G`, `albedo.B`.
+    pub(crate) fn add_layer(
+        &mut self,
+        layer: &str,
+        names: [&str; 3],
+        pixel_type: PixelType,
+        pixel: impl Fn(usize, usize) -> Vec3,
+    ) {
+        for (i, name) in names.iter().enumerate() {
+            let channel_name = if layer.is_empty() { name.to_string() } else { format!("{}.{}", layer, name) };
+            self.add_channel(&channel_name, pixel_type, |x, y| pixel(x, y)[i]);
+        }
+    }
+
+    pub(crate) fn set_attribute(&mut self, name: &str, value: Attribute) {
+        self.attributes.retain(|(existing, _)| existing != name);
+        self.attributes.push((name.to_string(), value));
+    }
+
+    pub(crate) fn write(&mut self, path: &str) -> io::Result<()> {
+        // Readers expect the channel list, and so the pixel data, in alphabetical order.
+        self.channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let header = self.header();
+        let lines_per_block = self.compression.scanlines_per_block();
+        let block_count = self.height.div_ceil(lines_per_block);
+
+        let mut blocks = Vec::with_capacity(block_count);
+        for block in 0..block_count {
+            let first_line = block * lines_per_block;
+            let last_line = (first_line + lines_per_block).min(self.height);
+            blocks.push((first_line, self.compress(&self.block_data(first_line, last_line))));
+        }
+
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&header)?;
+
+        // Offset table: absolute file position of every block.
+        let mut offset = (header.len() + 8 * block_count) as u64;
+        for (_, data) in &blocks {
+            out.write_all(&offset.to_le_bytes())?;
+            offset += 8 + data.len() as u64;
+        }
+
+        for (first_line, data) in &blocks {
+            out.write_all(&(*first_line as i32).to_le_bytes())?;
+            out.write_all(&(data.len() as i32).to_le_bytes())?;
+            out.write_all(data)?;
+        }
+        out.flush()
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header: Vec<u8> = vec![0x76, 0x2f, 0x31, 0x01];
+        let long_names = self.channels.iter().any(|c| c.name.len() > 31) || self.attributes.iter().any(|(n, _)| n.len() > 31);
+        let version: u32 = 2 | if long_names { 0x400 } else { 0 };
+        header.extend(version.to_le_bytes());
+
+        let mut channel_list = Vec::new();
+        for channel in &self.channels {
+            channel_list.extend(channel.name.as_bytes());
+            channel_list.push(0);
+            channel_list.extend(channel.pixel_type.id().to_le_bytes());
+            // pLinear and reserved bytes, then x and y sampling.
+            channel_list.extend([0, 0, 0, 0]);
+            channel_list.extend(1i32.to_le_bytes());
+            channel_list.extend(1i32.to_le_bytes());
+        }
+        channel_list.push(0);
+
+        let mut window = Vec::new();
+        for value in [0, 0, self.width as i32 - 1, self.height as i32 - 1] {
+            window.extend(value.to_le_bytes());
+        }
+
+        write_attribute(&mut header, "channels", "chlist", &channel_list);
+        write_attribute(&mut header, "compression", "compression", &[self.compression.id()]);
+        write_attribute(&mut header, "dataWindow", "box2i", &window);
+        write_attribute(&mut header, "displayWindow", "box2i", &window);
+        write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        write_attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+        write_attribute(&mut header, "screenWindowCenter", "v2f", &[0u8; 8]);
+        write_attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+
+        for (name, value) in &self.attributes {
+            match value {
+                Attribute::Int(v) => write_attribute(&mut header, name, "int", &v.to_le_bytes()),
+                Attribute::Float(v) => write_attribute(&mut header, name, "float", &v.to_le_bytes()),
+                Attribute::Text(v) => write_attribute(&mut header, name, "string", v.as_bytes()),
+                Attribute::Vector(v) => {
+                    let mut bytes = Vec::new();
+                    for i in 0..3 {
+                        bytes.extend((v[i] as f32).to_le_bytes());
+                    }
+                    write_attribute(&mut header, name, "v3f", &bytes);
+                }
+            }
+        }
+        header.push(0);
+        header
+    }
+
+    // Uncompressed pixel data of the scanlines [first_line, last_line): every line holds all of
+    // its pixels for the first channel, then for the second one, and so on.
+    fn block_data(&self, first_line: usize, last_line: usize) -> Vec<u8> {
+        let bytes_per_pixel: usize = self.channels.iter().map(|c| c.pixel_type.size()).sum();
+        let mut data = Vec::with_capacity((last_line - first_line) * self.width * bytes_per_pixel);
+
+        for y in first_line..last_line {
+            for channel in &self.channels {
+                for value in &channel.values[y * self.width..(y + 1) * self.width] {
+                    match channel.pixel_type {
+                        PixelType::Uint => data.extend((value.max(0.0) as u32).to_le_bytes()),
+                        PixelType::Half => data.extend(f32_to_half(*value as f32).to_le_bytes()),
+                        PixelType::Float => data.extend((*value as f32).to_le_bytes()),
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let compressed = match self.compression {
+            Compression::None => return data.to_vec(),
+            Compression::Rle => run_length_encode(&predict(&interleave(data))),
+            Compression::Zips | Compression::Zip => zlib_compress(&predict(&interleave(data))),
+        };
+
+        // Blocks that do not shrink are stored uncompressed, which readers detect by their size.
+        if compressed.len() >= data.len() {
+            return data.to_vec();
+        }
+        compressed
+    }
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend(name.as_bytes());
+    header.push(0);
+    header.extend(kind.as_bytes());
+    header.push(0);
+    header.extend((value.len() as i32).to_le_bytes());
+    header.extend(value);
+}
+
+// Splits the bytes into the even and odd halves, grouping similar bytes of consecutive values.
+fn interleave(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    out.extend(data.iter().step_by(2));
+    out.extend(data.iter().skip(1).step_by(2));
+    out
+}
+
+// Replaces every byte with its difference to the previous one.
+fn predict(data: &[u8]) -> Vec<u8> {
+    let mut out = data.to_vec();
+    for i in (1..out.len()).rev() {
+        out[i] = data[i].wrapping_sub(data[i - 1]).wrapping_add(128);
+    }
+    out
+}
+
+// OpenEXR's run length encoding: a non-negative count `n` repeats the next byte n + 1 times,
+// a negative count `-n` is followed by n literal bytes.
+fn run_length_encode(data: &[u8]) -> Vec<u8> {
+    const MIN_RUN: usize = 3;
+    const MAX_RUN: usize = 127;
+
+    let mut out = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let mut end = start + 1;
+        while end < data.len() && data[end] == data[start] && end - start - 1 < MAX_RUN {
+            end += 1;
+        }
+
+        if end - start >= MIN_RUN {
+            out.push((end - start - 1) as u8);
+            out.push(data[start]);
+        } else {
+            while end < data.len()
+                && (end + 1 >= data.len() || data[end] != data[end + 1] || end + 2 >= data.len() || data[end + 1] != data[end + 2])
+                && end - start < MAX_RUN
+            {
+                end += 1;
+            }
+            out.push((start as isize - end as isize) as u8);
+            out.extend(&data[start..end]);
+        }
+        start = end;
+    }
+    out
+}
+
+// Converts to IEEE 754 half precision, rounding to nearest even.
+pub(crate) fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7fffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 31 {
+        return sign | 0x7c00;
+    }
+
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        let full = mantissa | 0x800000;
+        let shift = (14 - half_exponent) as u32;
+        let mut half = full >> shift;
+        let round = 1 << (shift - 1);
+        if full & round != 0 && (full & (round - 1) != 0 || half & 1 != 0) {
+            half += 1;
+        }
+        return sign | half as u16;
+    }
+
+    let mut half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    if mantissa & 0x1000 != 0 && (mantissa & 0xfff != 0 || half & 1 != 0) {
+        // A carry out of the mantissa correctly bumps the exponent.
+        half += 1;
+    }
+    sign | half as u16
+}
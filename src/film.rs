@@ -0,0 +1,45 @@
+use crate::aov::AovBuffers;
+use crate::color::Color;
+
+// Accumulated radiance of every pixel, kept in floating point until the image is written.
+pub(crate) struct Film {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pixels: Vec<Color>,
+    sample_counts: Vec<u32>,
+    pub(crate) aovs: AovBuffers,
+}
+
+impl Film {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Film {
+            width,
+            height,
+            pixels: vec![Color::new(0.0, 0.0, 0.0); width * height],
+            sample_counts: vec![0; width * height],
+            aovs: AovBuffers::new(width, height),
+        }
+    }
+
+    pub(crate) fn add_sample(&mut self, x: usize, y: usize, color: Color) {
+        let index = y * self.width + x;
+        self.pixels[index] = self.pixels[index] + color;
+        self.sample_counts[index] += 1;
+    }
+
+    // Sum of all samples taken for the pixel.
+    pub(crate) fn pixel_sum(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub(crate) fn sample_count(&self, x: usize, y: usize) -> u32 {
+        self.sample_counts[y * self.width + x]
+    }
+
+    // Restores a pixel from previously saved state.
+    pub(crate) fn set_pixel(&mut self, x: usize, y: usize, sum: Color, sample_count: u32) {
+        let index = y * self.width + x;
+        self.pixels[index] = sum;
+        self.sample_counts[index] = sample_count;
+    }
+}
@@ -0,0 +1,482 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::image::Image;
+use crate::json::{base64_decode, Json};
+use crate::light::{LightKind, PunctualLight};
+use crate::material::MaterialTrait;
+use crate::mesh::{invalid_data, TriangleMesh};
+use crate::pbr::PbrMaterial;
+use crate::projection::Projection;
+use crate::scene::{Scene, View};
+use crate::transform::Matrix;
+use crate::utils::PI;
+use crate::vec3::{unit_vector, Point3, Vec3};
+
+// Extensions whose absence would change what the file describes, so files requiring them are
+// rejected rather than rendered wrongly.
+const SUPPORTED_REQUIRED_EXTENSIONS: [&str; 2] = ["KHR_lights_punctual", "KHR_materials_emissive_strength"];
+
+// Turns %xx escapes of a relative URI back into the characters of the file name.
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' { uri.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) } else { None };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+struct Loader<'a> {
+    json: Json,
+    directory: &'a Path,
+    buffers: Vec<Vec<u8>>,
+    images: HashMap<(usize, bool), Option<Rc<Image>>>,
+    materials: HashMap<usize, Rc<RefCell<dyn MaterialTrait>>>,
+    scene: Scene,
+}
+
+impl Loader<'_> {
+    fn array(&self, name: &str) -> &[Json] {
+        self.json.get(name).map_or(&[], Json::elements)
+    }
+
+    fn item(&self, name: &str, index: usize) -> Result<&Json, String> {
+        self.array(name).get(index).ok_or_else(|| format!("{} {} does not exist", name, index))
+    }
+
+    fn load_uri(&self, uri: &str) -> Result<Vec<u8>, String> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let (_, encoded) = data.split_once(";base64,").ok_or_else(|| String::from("only base64 data URIs are supported"))?;
+            return base64_decode(encoded);
+        }
+        let path = self.directory.join(percent_decode(uri));
+        fs::read(&path).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    fn buffer_view(&self, index: usize) -> Result<&[u8], String> {
+        let view = self.item("bufferViews", index)?;
+        let buffer_index = view.get("buffer").and_then(Json::as_usize).ok_or("buffer view without a buffer")?;
+        let buffer = self.buffers.get(buffer_index).ok_or_else(|| format!("buffer {} does not exist", buffer_index))?;
+        let offset = view.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+        let length = view.get("byteLength").and_then(Json::as_usize).ok_or("buffer view without a byteLength")?;
+        buffer.get(offset..offset + length).ok_or_else(|| format!("buffer view {} runs past the end of buffer {}", index, buffer_index))
+    }
+
+    // Reads `count` elements of `components` values of `component_type` from `data`, `stride`
+    // bytes apart (tightly packed for 0).
+    fn read_values(data: &[u8], component_type: usize, components: usize, count: usize, stride: usize, normalized: bool) -> Result<Vec<f64>, String> {
+        let size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(format!("unknown component type {}", component_type)),
+        };
+        let stride = if stride == 0 { size * components } else { stride };
+        if count > 0 && (count - 1) * stride + size * components > data.len() {
+            return Err(String::from("accessor runs past the end of its buffer view"));
+        }
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let at = element * stride + component * size;
+                let bytes = &data[at..at + size];
+                let value = match component_type {
+                    5120 => bytes[0] as i8 as f64,
+                    5121 => bytes[0] as f64,
+                    5122 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    5123 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    5125 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                    _ => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+                };
+                values.push(match (normalized, component_type) {
+                    (true, 5120) => (value / 127.0).max(-1.0),
+                    (true, 5121) => value / 255.0,
+                    (true, 5122) => (value / 32767.0).max(-1.0),
+                    (true, 5123) => value / 65535.0,
+                    _ => value,
+                });
+            }
+        }
+        Ok(values)
+    }
+
+    // The values of an accessor, flattened, with the number of values per element.
+    fn accessor(&self, index: usize) -> Result<(Vec<f64>, usize), String> {
+        let accessor = self.item("accessors", index)?;
+        let error = |what: &str| format!("accessor {}: {}", index, what);
+        let component_type = accessor.get("componentType").and_then(Json::as_usize).ok_or_else(|| error("no componentType"))?;
+        let count = accessor.get("count").and_then(Json::as_usize).ok_or_else(|| error("no count"))?;
+        let components = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => return Err(error("unknown type")),
+        };
+        let normalized = matches!(accessor.get("normalized"), Some(Json::Bool(true)));
+
+        let mut values = match accessor.get("bufferView").and_then(Json::as_usize) {
+            Some(view) => {
+                let stride = self.item("bufferViews", view)?.get("byteStride").and_then(Json::as_usize).unwrap_or(0);
+                let offset = accessor.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+                let data = self.buffer_view(view)?.get(offset..).ok_or_else(|| error("byteOffset past the end of its buffer view"))?;
+                Loader::read_values(data, component_type, components, count, stride, normalized).map_err(|message| error(&message))?
+            }
+            None => vec![0.0; count * components],
+        };
+
+        // Sparse accessors replace some elements of the base values.
+        if let Some(sparse) = accessor.get("sparse") {
+            let sparse_count = sparse.get("count").and_then(Json::as_usize).ok_or_else(|| error("sparse without a count"))?;
+            let part = |name: &str| -> Result<&[u8], String> {
+                let part = sparse.get(name).ok_or_else(|| error(&format!("sparse without {}", name)))?;
+                let view = part.get("bufferView").and_then(Json::as_usize).ok_or_else(|| error("sparse part without a bufferView"))?;
+                let offset = part.get("byteOffset").and_then(Json::as_usize).unwrap_or(0);
+                self.buffer_view(view)?.get(offset..).ok_or_else(|| error("sparse byteOffset past the end of its buffer view"))
+            };
+            let index_type = sparse.get("indices").and_then(|indices| indices.get("componentType")).and_then(Json::as_usize).unwrap_or(5125);
+            let indices = Loader::read_values(part("indices")?, index_type, 1, sparse_count, 0, false)?;
+            let replacements = Loader::read_values(part("values")?, component_type, components, sparse_count, 0, normalized)?;
+            for (i, &element) in indices.iter().enumerate() {
+                let element = element as usize;
+                if element >= count {
+                    return Err(error("sparse index out of range"));
+                }
+                values[element * components..(element + 1) * components].copy_from_slice(&replacements[i * components..(i + 1) * components]);
+            }
+        }
+        Ok((values, components))
+    }
+
+    // The image of a texture, decoded once per color encoding. Images that cannot be decoded are
+    // reported and left out, leaving the plain factors.
+    fn texture(&mut self, info: Option<&Json>, linear: bool) -> Result<Option<Rc<Image>>, String> {
+        let Some(texture) = info.and_then(|info| info.get("index")).and_then(Json::as_usize) else {
+            return Ok(None);
+        };
+        let Some(source) = self.item("textures", texture)?.get("source").and_then(Json::as_usize) else {
+            return Ok(None);
+        };
+        if let Some(image) = self.images.get(&(source, linear)) {
+            return Ok(image.clone());
+        }
+
+        let image = self.item("images", source)?;
+        let data = match (image.get("uri").and_then(Json::as_str), image.get("bufferView").and_then(Json::as_usize)) {
+            (Some(uri), _) => self.load_uri(uri)?,
+            (None, Some(view)) => self.buffer_view(view)?.to_vec(),
+            (None, None) => return Err(format!("image {} has neither a uri nor a bufferView", source)),
+        };
+        let decoded = match Image::decode(&data, linear) {
+            Ok(decoded) => Some(Rc::new(decoded)),
+            Err(message) => {
+                eprintln!("Skipping image {}: {}", source, message);
+                None
+            }
+        };
+        self.images.insert((source, linear), decoded.clone());
+        Ok(decoded)
+    }
+
+    fn material(&mut self, index: Option<usize>) -> Result<Rc<RefCell<dyn MaterialTrait>>, String> {
+        // Primitives without a material are plain white and rough.
+        let key = index.unwrap_or(usize::MAX);
+        if let Some(material) = self.materials.get(&key) {
+            return Ok(material.clone());
+        }
+        let Some(index) = index else {
+            let material: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(PbrMaterial::new(Color::new(1.0, 1.0, 1.0), 0.0, 1.0)));
+            self.materials.insert(key, material.clone());
+            return Ok(material);
+        };
+
+        let json = self.item("materials", index)?.clone();
+        let pbr = json.get("pbrMetallicRoughness");
+        let factor = |name: &str, default: f64| pbr.and_then(|pbr| pbr.get(name)).and_then(Json::as_f64).unwrap_or(default);
+        let base_color = pbr.and_then(|pbr| pbr.get("baseColorFactor")).and_then(Json::numbers).unwrap_or_else(|| vec![1.0; 4]);
+        let mut material = PbrMaterial::new(
+            Color::new(base_color[0], base_color[1], base_color[2]),
+            factor("metallicFactor", 1.0),
+            factor("roughnessFactor", 1.0),
+        );
+        material.base_color_texture = self.texture(pbr.and_then(|pbr| pbr.get("baseColorTexture")), false)?;
+        material.metallic_roughness_texture = self.texture(pbr.and_then(|pbr| pbr.get("metallicRoughnessTexture")), true)?;
+
+        let emissive = json.get("emissiveFactor").and_then(Json::numbers).unwrap_or_else(|| vec![0.0; 3]);
+        let strength = json
+            .get("extensions")
+            .and_then(|extensions| extensions.get("KHR_materials_emissive_strength"))
+            .and_then(|extension| extension.get("emissiveStrength"))
+            .and_then(Json::as_f64)
+            .unwrap_or(1.0);
+        material.emissive = strength * Color::new(emissive[0], emissive[1], emissive[2]);
+        material.emissive_texture = self.texture(json.get("emissiveTexture"), false)?;
+
+        let material: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(material));
+        self.materials.insert(key, material.clone());
+        Ok(material)
+    }
+
+    fn primitive(&mut self, primitive: &Json, transform: &Matrix) -> Result<(), String> {
+        let mode = primitive.get("mode").and_then(Json::as_usize).unwrap_or(4);
+        if mode < 4 {
+            eprintln!("Skipping a primitive of points or lines");
+            return Ok(());
+        }
+        let attributes = primitive.get("attributes").ok_or("primitive without attributes")?;
+        let attribute = |name: &str| attributes.get(name).and_then(Json::as_usize);
+
+        let (positions, _) = self.accessor(attribute("POSITION").ok_or("primitive without POSITION")?)?;
+        let mut mesh = TriangleMesh {
+            positions: positions.chunks_exact(3).map(|p| transform.point(Vec3::new(p[0], p[1], p[2]))).collect(),
+            ..TriangleMesh::default()
+        };
+        if let Some(index) = attribute("NORMAL") {
+            mesh.normals = self.accessor(index)?.0.chunks_exact(3).map(|n| transform.normal(Vec3::new(n[0], n[1], n[2]))).collect();
+        }
+        if let Some(index) = attribute("TEXCOORD_0") {
+            mesh.uvs = self.accessor(index)?.0.chunks_exact(2).map(|uv| (uv[0], uv[1])).collect();
+        }
+        if let Some(index) = attribute("COLOR_0") {
+            let (colors, components) = self.accessor(index)?;
+            mesh.colors = colors.chunks_exact(components).map(|c| Color::new(c[0], c[1], c[2])).collect();
+        }
+
+        let vertex_count = mesh.positions.len();
+        let indices: Vec<usize> = match primitive.get("indices").and_then(Json::as_usize) {
+            Some(index) => self.accessor(index)?.0.iter().map(|&i| i as usize).collect(),
+            None => (0..vertex_count).collect(),
+        };
+        if indices.iter().any(|&i| i >= vertex_count) {
+            return Err(String::from("primitive with a vertex index out of range"));
+        }
+        mesh.triangles = match mode {
+            4 => indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect(),
+            // Strips alternate their winding so every triangle faces the same way.
+            5 => (2..indices.len())
+                .map(|i| if i % 2 == 0 { [indices[i - 2], indices[i - 1], indices[i]] } else { [indices[i - 1], indices[i - 2], indices[i]] })
+                .collect(),
+            6 => (2..indices.len()).map(|i| [indices[0], indices[i - 1], indices[i]]).collect(),
+            _ => return Err(format!("unknown primitive mode {}", mode)),
+        };
+        // A mirroring transform turns the winding around.
+        if transform.determinant() < 0.0 {
+            for triangle in &mut mesh.triangles {
+                triangle.swap(1, 2);
+            }
+        }
+        if mesh.triangles.is_empty() {
+            return Ok(());
+        }
+
+        let material = self.material(primitive.get("material").and_then(Json::as_usize))?;
+        self.scene.add(Box::new(mesh.into_hittable(material.clone())), material);
+        Ok(())
+    }
+
+    fn camera(&mut self, index: usize, transform: &Matrix) -> Result<(), String> {
+        let camera = self.item("cameras", index)?;
+        let look_from = transform.point(Point3::new(0.0, 0.0, 0.0));
+        let forward = unit_vector(transform.vector(Vec3::new(0.0, 0.0, -1.0)));
+        let up = unit_vector(transform.vector(Vec3::new(0.0, 1.0, 0.0)));
+        let mut scene_camera = View {
+            look_from,
+            look_at: look_from + forward,
+            vup: up,
+            vfov: 45.0,
+            aspect_ratio: None,
+            projection: Projection::Perspective,
+            defocus_angle: 0.0,
+            focus_distance: None,
+        };
+
+        match camera.get("type").and_then(Json::as_str) {
+            Some("perspective") => {
+                let perspective = camera.get("perspective").ok_or("perspective camera without its settings")?;
+                let yfov = perspective.get("yfov").and_then(Json::as_f64).ok_or("perspective camera without yfov")?;
+                scene_camera.vfov = yfov * 180.0 / PI;
+                scene_camera.aspect_ratio = perspective.get("aspectRatio").and_then(Json::as_f64);
+            }
+            Some("orthographic") => {
+                let orthographic = camera.get("orthographic").ok_or("orthographic camera without its settings")?;
+                let magnification = |name: &str| orthographic.get(name).and_then(Json::as_f64).ok_or(format!("orthographic camera without {}", name));
+                let (xmag, ymag) = (magnification("xmag")?, magnification("ymag")?);
+                // The magnifications are half the size of the view, scaled like the node.
+                let scale = transform.vector(Vec3::new(1.0, 0.0, 0.0)).length();
+                scene_camera.projection = Projection::Orthographic { width: 2.0 * xmag * scale };
+                scene_camera.aspect_ratio = Some(xmag / ymag);
+            }
+            _ => return Err(format!("camera {} has an unknown type", index)),
+        }
+        self.scene.views.push(scene_camera);
+        Ok(())
+    }
+
+    fn light(&mut self, index: usize, transform: &Matrix) -> Result<(), String> {
+        let lights = self.json.get("extensions").and_then(|extensions| extensions.get("KHR_lights_punctual")).and_then(|lights| lights.get("lights"));
+        let light = lights.map_or(&[] as &[Json], Json::elements).get(index).ok_or_else(|| format!("light {} does not exist", index))?;
+        let color = match light.get("color").and_then(Json::numbers) {
+            Some(values) if values.len() == 3 => values,
+            Some(_) => return Err(format!("light {} has a color without 3 values", index)),
+            None => vec![1.0; 3],
+        };
+        let intensity = light.get("intensity").and_then(Json::as_f64).unwrap_or(1.0);
+        let direction = unit_vector(transform.vector(Vec3::new(0.0, 0.0, -1.0)));
+        let kind = match light.get("type").and_then(Json::as_str) {
+            Some("point") => LightKind::Point,
+            Some("directional") => LightKind::Directional { direction },
+            Some("spot") => {
+                let spot = light.get("spot");
+                let angle = |name: &str, default: f64| spot.and_then(|spot| spot.get(name)).and_then(Json::as_f64).unwrap_or(default);
+                LightKind::Spot { direction, inner_angle: angle("innerConeAngle", 0.0), outer_angle: angle("outerConeAngle", PI / 4.0) }
+            }
+            _ => return Err(format!("light {} has an unknown type", index)),
+        };
+        self.scene.lights.push(PunctualLight {
+            kind,
+            position: transform.point(Point3::new(0.0, 0.0, 0.0)),
+            intensity: intensity * Color::new(color[0], color[1], color[2]),
+            range: light.get("range").and_then(Json::as_f64),
+        });
+        Ok(())
+    }
+
+    fn node(&mut self, index: usize, parent: &Matrix, depth: usize) -> Result<(), String> {
+        if depth > 256 {
+            return Err(String::from("node hierarchy too deep or cyclic"));
+        }
+        let node = self.item("nodes", index)?.clone();
+        let local = match node.get("matrix").and_then(Json::numbers) {
+            Some(values) if values.len() == 16 => Matrix::from_columns(&values),
+            Some(_) => return Err(format!("node {} has a matrix without 16 values", index)),
+            None => Matrix::from_trs(
+                &node.get("translation").and_then(Json::numbers).unwrap_or_else(|| vec![0.0; 3]),
+                &node.get("rotation").and_then(Json::numbers).unwrap_or_else(|| vec![0.0, 0.0, 0.0, 1.0]),
+                &node.get("scale").and_then(Json::numbers).unwrap_or_else(|| vec![1.0; 3]),
+            ),
+        };
+        let transform = parent.multiply(&local);
+
+        if let Some(mesh) = node.get("mesh").and_then(Json::as_usize) {
+            let primitives = self.item("meshes", mesh)?.get("primitives").map_or(Vec::new(), |primitives| primitives.elements().to_vec());
+            for (number, primitive) in primitives.iter().enumerate() {
+                self.primitive(primitive, &transform).map_err(|message| format!("mesh {} primitive {}: {}", mesh, number, message))?;
+            }
+        }
+        if let Some(camera) = node.get("camera").and_then(Json::as_usize) {
+            self.camera(camera, &transform)?;
+        }
+        let light = node.get("extensions").and_then(|extensions| extensions.get("KHR_lights_punctual")).and_then(|extension| extension.get("light"));
+        if let Some(light) = light.and_then(Json::as_usize) {
+            self.light(light, &transform)?;
+        }
+        for child in node.get("children").map_or(&[] as &[Json], Json::elements) {
+            let child = child.as_usize().ok_or_else(|| format!("node {} has an invalid child", index))?;
+            self.node(child, &transform, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+// Splits a binary .glb file into its JSON text and its binary buffer, if any.
+fn split_glb(data: &[u8]) -> Result<(String, Option<Vec<u8>>), String> {
+    let word = |at: usize| data.get(at..at + 4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize);
+    if word(4) != Some(2) {
+        return Err(String::from("only version 2 of the binary format is supported"));
+    }
+    let mut position = 12;
+    let (mut json, mut binary) = (None, None);
+    while let (Some(length), Some(kind)) = (word(position), word(position + 4)) {
+        let chunk = data.get(position + 8..position + 8 + length).ok_or("truncated chunk")?;
+        match kind {
+            0x4e4f534a => json = Some(String::from_utf8_lossy(chunk).into_owned()),
+            0x004e4942 => binary = Some(chunk.to_vec()),
+            _ => {}
+        }
+        position += 8 + length;
+    }
+    Ok((json.ok_or("no JSON chunk")?, binary))
+}
+
+// Loads the default scene of a glTF 2.0 file, .gltf with its buffers and images beside it or
+// embedded as data URIs, or binary .glb.
+pub(crate) fn load_gltf(path: &str) -> io::Result<Scene> {
+    let data = fs::read(path)?;
+    let directory = Path::new(path).parent().unwrap_or(Path::new("."));
+    parse_gltf(&data, directory).map_err(|message| invalid_data(format!("{}: {}", path, message)))
+}
+
+fn parse_gltf(data: &[u8], directory: &Path) -> Result<Scene, String> {
+    let (text, binary) = if data.starts_with(b"glTF") { split_glb(data)? } else { (String::from_utf8_lossy(data).into_owned(), None) };
+    let json = Json::parse(&text)?;
+
+    let version = json.get("asset").and_then(|asset| asset.get("version")).and_then(Json::as_str).unwrap_or("");
+    if !version.starts_with("2.") {
+        return Err(format!("unsupported glTF version '{}'", version));
+    }
+    for extension in json.get("extensionsRequired").map_or(&[] as &[Json], Json::elements) {
+        let name = extension.as_str().unwrap_or("");
+        if !SUPPORTED_REQUIRED_EXTENSIONS.contains(&name) {
+            return Err(format!("requires the unsupported extension {}", name));
+        }
+    }
+
+    let mut loader = Loader {
+        json,
+        directory,
+        buffers: Vec::new(),
+        images: HashMap::new(),
+        materials: HashMap::new(),
+        scene: Scene::new(),
+    };
+    let mut binary = binary;
+    for (index, buffer) in loader.array("buffers").to_vec().iter().enumerate() {
+        // In a .glb file the first buffer without a uri is the binary chunk.
+        let bytes = match buffer.get("uri").and_then(Json::as_str) {
+            Some(uri) => loader.load_uri(uri)?,
+            None => binary.take().ok_or_else(|| format!("buffer {} has no uri", index))?,
+        };
+        let length = buffer.get("byteLength").and_then(Json::as_usize).unwrap_or(0);
+        if bytes.len() < length {
+            return Err(format!("buffer {} holds {} bytes instead of {}", index, bytes.len(), length));
+        }
+        loader.buffers.push(bytes);
+    }
+
+    // The default scene, else the first one, else every node without a parent.
+    let scene = loader.json.get("scene").and_then(Json::as_usize).unwrap_or(0);
+    let roots: Vec<usize> = match loader.array("scenes").get(scene) {
+        Some(scene) => scene.get("nodes").map_or(&[] as &[Json], Json::elements).iter().filter_map(Json::as_usize).collect(),
+        None => {
+            let children: Vec<usize> = loader
+                .array("nodes")
+                .iter()
+                .flat_map(|node| node.get("children").map_or(&[] as &[Json], Json::elements).iter().filter_map(Json::as_usize))
+                .collect();
+            (0..loader.array("nodes").len()).filter(|node| !children.contains(node)).collect()
+        }
+    };
+    for root in roots {
+        loader.node(root, &Matrix::IDENTITY, 0)?;
+    }
+    Ok(loader.scene)
+}
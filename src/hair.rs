@@ -0,0 +1,319 @@
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::json::Json;
+use crate::material::MaterialTrait;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene_file::SceneWriter;
+use crate::utils::{degrees_to_radians, PI};
+use crate::vec3::{cross, dot, unit_vector};
+
+// Scattering lobes followed explicitly: R, TT and TRT; longer paths are lumped into one more.
+const P_MAX: usize = 3;
+
+// Absorption coefficients of the two melanin pigments per unit concentration.
+const EUMELANIN_SIGMA_A: Color = Color::new(0.419, 0.697, 1.37);
+const PHEOMELANIN_SIGMA_A: Color = Color::new(0.187, 0.4, 1.05);
+
+// Scattering from hair fibers after d'Eon et al. and Chiang et al., as in pbrt: light reflects
+// off the cuticle (R), passes through the fiber (TT) or reflects once inside it (TRT), with a
+// longitudinal and an azimuthal spread per lobe. Needs hits that carry the fiber tangent and the
+// offset across the fiber in v, as curves do.
+pub(crate) struct HairMaterial {
+    sigma_a: Color, // Absorption inside the fiber, per fiber diameter
+    eta: f64,
+    // Longitudinal variances per lobe, from the longitudinal roughness
+    v: [f64; P_MAX + 1],
+    // Logistic scale of the azimuthal spread, from the azimuthal roughness
+    s: f64,
+    // Sines and cosines of the cuticle scale tilt, doubled k times for k = 0, 1, 2
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+    // The parameters given to `new`, kept for saving
+    beta_m: f64,
+    beta_n: f64,
+    alpha: f64,
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+fn safe_asin(x: f64) -> f64 {
+    x.clamp(-1.0, 1.0).asin()
+}
+
+fn exp_color(c: Color) -> Color {
+    Color::new(c.x().exp(), c.y().exp(), c.z().exp())
+}
+
+fn average(c: Color) -> f64 {
+    (c.x() + c.y() + c.z()) / 3.0
+}
+
+// Modified Bessel function of the first kind, order 0, from its series.
+fn bessel_i0(x: f64) -> f64 {
+    let mut value = 0.0;
+    let mut x2i = 1.0;
+    let mut factorial: f64 = 1.0;
+    let mut four_i = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            factorial *= i as f64;
+        }
+        value += x2i / (four_i * factorial * factorial);
+        x2i *= x * x;
+        four_i *= 4.0;
+    }
+    value
+}
+
+fn log_bessel_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        x + 0.5 * (-(2.0 * PI).ln() + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+// Fresnel reflectance of a dielectric entered from air.
+fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_theta_i < 0.0 { (-cos_theta_i, 1.0 / eta) } else { (cos_theta_i, eta) };
+    let sin_t = safe_sqrt(1.0 - cos_i * cos_i) / eta;
+    if sin_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = safe_sqrt(1.0 - sin_t * sin_t);
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// Longitudinal scattering of a lobe with variance `v`.
+fn longitudinal(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, v: f64) -> f64 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        // Evaluated in logarithms, where the terms would overflow on their own.
+        (log_bessel_i0(a) - b - 1.0 / v + std::f64::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        ((-b).exp() * bessel_i0(a)) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+// Attenuation of each lobe: Fresnel reflection and transmission and absorption `t` per pass.
+fn attenuation(cos_theta_o: f64, eta: f64, h: f64, t: Color) -> [Color; P_MAX + 1] {
+    let cos_gamma_o = safe_sqrt(1.0 - h * h);
+    let f = fresnel_dielectric(cos_theta_o * cos_gamma_o, eta);
+    let r = Color::new(f, f, f);
+    let tt = (1.0 - f) * (1.0 - f) * t;
+    let trt = f * tt * t;
+    let rest = Color::new(
+        trt.x() * f * t.x() / (1.0 - t.x() * f),
+        trt.y() * f * t.y() / (1.0 - t.y() * f),
+        trt.z() * f * t.z() / (1.0 - t.z() * f),
+    );
+    [r, tt, trt, rest]
+}
+
+// Azimuthal deflection of lobe p for the entry and refracted offsets.
+fn deflection(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    2.0 * p as f64 * gamma_t - 2.0 * gamma_o + p as f64 * PI
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let x = x.abs();
+    (-x / s).exp() / (s * (1.0 + (-x / s).exp()).powi(2))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+fn trimmed_logistic(x: f64, s: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(PI, s) - logistic_cdf(-PI, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64) -> f64 {
+    let k = logistic_cdf(PI, s) - logistic_cdf(-PI, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(-PI, s)) - 1.0).ln();
+    x.clamp(-PI, PI)
+}
+
+// Azimuthal scattering of lobe p at the difference `phi` between the azimuths.
+fn azimuthal(phi: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64) -> f64 {
+    let mut dphi = phi - deflection(p, gamma_o, gamma_t);
+    while dphi > PI {
+        dphi -= 2.0 * PI;
+    }
+    while dphi < -PI {
+        dphi += 2.0 * PI;
+    }
+    trimmed_logistic(dphi, s)
+}
+
+impl HairMaterial {
+    // `beta_m` and `beta_n` are the longitudinal and azimuthal roughness in [0, 1]; `alpha` tilts
+    // the cuticle scales, in degrees.
+    pub(crate) fn new(sigma_a: Color, eta: f64, beta_m: f64, beta_n: f64, alpha: f64) -> Self {
+        let v0 = (0.726 * beta_m + 0.812 * beta_m * beta_m + 3.7 * beta_m.powi(20)).powi(2);
+        let v = [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0];
+        let s = (PI / 8.0).sqrt() * (0.265 * beta_n + 1.194 * beta_n * beta_n + 5.372 * beta_n.powi(22));
+
+        let mut sin_2k_alpha = [degrees_to_radians(alpha).sin(), 0.0, 0.0];
+        let mut cos_2k_alpha = [safe_sqrt(1.0 - sin_2k_alpha[0] * sin_2k_alpha[0]), 0.0, 0.0];
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1].powi(2) - sin_2k_alpha[i - 1].powi(2);
+        }
+
+        HairMaterial { sigma_a, eta, v, s, sin_2k_alpha, cos_2k_alpha, beta_m, beta_n, alpha }
+    }
+
+    // Absorption from the concentrations of the dark (eumelanin) and red (pheomelanin) pigments;
+    // about 0.3 is blonde, 1.3 brown and 8 black.
+    pub(crate) fn from_melanin(eumelanin: f64, pheomelanin: f64, beta_m: f64, beta_n: f64) -> Self {
+        let sigma_a = eumelanin * EUMELANIN_SIGMA_A + pheomelanin * PHEOMELANIN_SIGMA_A;
+        HairMaterial::new(sigma_a, 1.55, beta_m, beta_n, 2.0)
+    }
+
+    // Absorption giving roughly `color` for the multiply scattered look of a head of hair.
+    pub(crate) fn from_color(color: Color, beta_m: f64, beta_n: f64) -> Self {
+        let denominator = 5.969 - 0.215 * beta_n + 2.532 * beta_n.powi(2) - 10.73 * beta_n.powi(3) + 5.574 * beta_n.powi(4) + 0.245 * beta_n.powi(5);
+        let channel = |c: f64| (c.max(1e-4).ln() / denominator).powi(2);
+        let sigma_a = Color::new(channel(color.x()), channel(color.y()), channel(color.z()));
+        HairMaterial::new(sigma_a, 1.55, beta_m, beta_n, 2.0)
+    }
+
+    // The outgoing angle rotated by the tilt of the scales for lobe p.
+    fn tilted(&self, p: usize, sin_theta_o: f64, cos_theta_o: f64) -> (f64, f64) {
+        let (sin_tilt, cos_tilt, sign) = match p {
+            0 => (self.sin_2k_alpha[1], self.cos_2k_alpha[1], -1.0),
+            1 => (self.sin_2k_alpha[0], self.cos_2k_alpha[0], 1.0),
+            2 => (self.sin_2k_alpha[2], self.cos_2k_alpha[2], 1.0),
+            _ => return (sin_theta_o, cos_theta_o),
+        };
+        (sin_theta_o * cos_tilt + sign * cos_theta_o * sin_tilt, cos_theta_o * cos_tilt - sign * sin_theta_o * sin_tilt)
+    }
+
+    // Absorption along the refracted path through the fiber.
+    fn transmittance(&self, sin_theta_o: f64, cos_theta_o: f64, h: f64) -> Color {
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sin_theta_t * sin_theta_t);
+        let eta_p = (self.eta * self.eta - sin_theta_o * sin_theta_o).sqrt() / cos_theta_o;
+        let sin_gamma_t = h / eta_p;
+        let cos_gamma_t = safe_sqrt(1.0 - sin_gamma_t * sin_gamma_t);
+        exp_color(-(2.0 * cos_gamma_t / cos_theta_t) * self.sigma_a)
+    }
+
+    fn gamma_t(&self, sin_theta_o: f64, cos_theta_o: f64, h: f64) -> f64 {
+        let eta_p = (self.eta * self.eta - sin_theta_o * sin_theta_o).sqrt() / cos_theta_o;
+        safe_asin(h / eta_p)
+    }
+
+    // Probability of choosing each lobe, in proportion to its attenuation.
+    fn lobe_pdf(&self, sin_theta_o: f64, cos_theta_o: f64, h: f64) -> [f64; P_MAX + 1] {
+        let a = attenuation(cos_theta_o, self.eta, h, self.transmittance(sin_theta_o, cos_theta_o, h));
+        let total: f64 = a.iter().map(|c| average(*c)).sum();
+        let mut pdf = [0.0; P_MAX + 1];
+        for (p, value) in pdf.iter_mut().enumerate() {
+            *value = if total > 0.0 { average(a[p]) / total } else { 1.0 / (P_MAX + 1) as f64 };
+        }
+        pdf
+    }
+
+    // Scattering times the cosine, and the density of sampling it, for directions given by the
+    // sines, cosines and azimuths of their angles to the fiber.
+    fn evaluate(&self, (sin_theta_o, cos_theta_o, phi_o): (f64, f64, f64), (sin_theta_i, cos_theta_i, phi_i): (f64, f64, f64), h: f64) -> (Color, f64) {
+        let gamma_o = safe_asin(h);
+        let gamma_t = self.gamma_t(sin_theta_o, cos_theta_o, h);
+        let a = attenuation(cos_theta_o, self.eta, h, self.transmittance(sin_theta_o, cos_theta_o, h));
+        let lobe_pdf = self.lobe_pdf(sin_theta_o, cos_theta_o, h);
+        let phi = phi_i - phi_o;
+
+        let mut value = Color::new(0.0, 0.0, 0.0);
+        let mut pdf = 0.0;
+        for p in 0..P_MAX {
+            let (sin_op, cos_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+            let m = longitudinal(cos_theta_i, cos_op.abs(), sin_theta_i, sin_op, self.v[p]);
+            let n = azimuthal(phi, p, self.s, gamma_o, gamma_t);
+            value = value + (m * n) * a[p];
+            pdf += m * n * lobe_pdf[p];
+        }
+        let m = longitudinal(cos_theta_i, cos_theta_o, sin_theta_i, sin_theta_o, self.v[P_MAX]);
+        value = value + (m / (2.0 * PI)) * a[P_MAX];
+        pdf += m * lobe_pdf[P_MAX] / (2.0 * PI);
+        (value, pdf)
+    }
+}
+
+impl MaterialTrait for HairMaterial {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        // Fiber frame: x along the fiber, z towards the viewer square to it and y across, the
+        // direction in which the offset h grows.
+        let x = hit_record.tangent;
+        if x.length_squared() == 0.0 {
+            return None;
+        }
+        let x = unit_vector(x);
+        let wo = -unit_vector(ray_in.direction());
+        let facing = wo - dot(wo, x) * x;
+        let z = if facing.length_squared() > 1e-12 { unit_vector(facing) } else { hit_record.normal };
+        let y = cross(z, x);
+        let h = (2.0 * hit_record.v - 1.0).clamp(-1.0, 1.0);
+
+        let sin_theta_o = dot(wo, x).clamp(-1.0, 1.0);
+        let cos_theta_o = safe_sqrt(1.0 - sin_theta_o * sin_theta_o);
+        let phi_o = dot(wo, z).atan2(dot(wo, y));
+
+        // Pick a lobe with the 1D sample, then reuse what is left of it for the azimuth.
+        let lobe_pdf = self.lobe_pdf(sin_theta_o, cos_theta_o, h);
+        let mut u = sampler.get_1d();
+        let mut p = 0;
+        while p < P_MAX && u >= lobe_pdf[p] {
+            u -= lobe_pdf[p];
+            p += 1;
+        }
+        let u_phi = (u / lobe_pdf[p].max(1e-12)).clamp(0.0, 1.0 - 1e-12);
+
+        // Longitudinal angle around the tilted reflection of the outgoing one
+        let (sin_op, cos_op) = self.tilted(p, sin_theta_o, cos_theta_o);
+        let (u0, u1) = sampler.get_2d();
+        let u0 = u0.max(1e-5);
+        let cos_theta = 1.0 + self.v[p] * (u0 + (1.0 - u0) * (-2.0 / self.v[p]).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+        let sin_theta_i = -cos_theta * sin_op + sin_theta * (2.0 * PI * u1).cos() * cos_op;
+        let cos_theta_i = safe_sqrt(1.0 - sin_theta_i * sin_theta_i);
+
+        let gamma_o = safe_asin(h);
+        let gamma_t = self.gamma_t(sin_theta_o, cos_theta_o, h);
+        let dphi = if p < P_MAX {
+            deflection(p, gamma_o, gamma_t) + sample_trimmed_logistic(u_phi, self.s)
+        } else {
+            2.0 * PI * u_phi
+        };
+        let phi_i = phi_o + dphi;
+
+        let (value, pdf) = self.evaluate((sin_theta_o, cos_theta_o, phi_o), (sin_theta_i, cos_theta_i, phi_i), h);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let wi = sin_theta_i * x + cos_theta_i * phi_i.cos() * y + cos_theta_i * phi_i.sin() * z;
+        Some(((1.0 / pdf) * value, Ray::new(hit_record.point, wi, ray_in.time())))
+    }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        exp_color(-1.0 * self.sigma_a)
+    }
+
+    fn save(&self, _scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![
+            ("type", "hair".into()),
+            ("sigma_a", self.sigma_a.into()),
+            ("eta", self.eta.into()),
+            ("beta_m", self.beta_m.into()),
+            ("beta_n", self.beta_n.into()),
+            ("alpha", self.alpha.into()),
+        ]))
+    }
+}
@@ -0,0 +1,289 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SceneHasher};
+use crate::image::Image;
+use crate::interval::Interval;
+use crate::json::Json;
+use crate::material::MaterialTrait;
+use crate::noise::Perlin;
+use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
+use crate::vec3::{cross, dot, unit_vector, Point3, Vec3};
+
+// Octaves of noise in generated terrain.
+const TERRAIN_OCTAVES: u32 = 7;
+
+// Terrain over a rectangle of the xz plane, from a grid of height samples. Each grid cell is split
+// into two triangles; normals are interpolated from the samples' normals.
+pub(crate) struct Heightfield {
+    heights: Vec<f64>, // World heights, row by row along z
+    normals: Vec<Vec3>,
+    columns: usize,
+    rows: usize,
+    corner: Point3, // Lowest corner of the rectangle at height 0
+    cell_x: f64,
+    cell_z: f64,
+    cell_ranges: Vec<Interval>, // Lowest and highest sample of each cell
+    material_ptr: Rc<RefCell<dyn MaterialTrait>>,
+    bbox: Aabb,
+}
+
+impl Heightfield {
+    // `samples` holds `columns` × `rows` values in [0, 1], row by row along z. The grid spans
+    // `size.x()` by `size.z()` around `center`, rising up to `size.y()` above it. Grids need at
+    // least 2 × 2 samples to have a cell.
+    pub(crate) fn new(
+        samples: &[f64],
+        columns: usize,
+        rows: usize,
+        center: Point3,
+        size: Vec3,
+        material_ptr: Rc<RefCell<dyn MaterialTrait>>,
+    ) -> Result<Self, String> {
+        check_grid(columns, rows)?;
+        let corner = center - Vec3::new(size.x() / 2.0, 0.0, size.z() / 2.0);
+        let heights: Vec<f64> = samples.iter().map(|s| center.y() + s * size.y()).collect();
+        let cell_x = size.x() / (columns - 1) as f64;
+        let cell_z = size.z() / (rows - 1) as f64;
+        Heightfield::from_heights(heights, columns, rows, corner, (cell_x, cell_z), material_ptr)
+    }
+
+    // A grid of world heights whose first sample is over `corner`, with samples `cell_size.0`
+    // apart along x and `cell_size.1` along z.
+    pub(crate) fn from_heights(
+        heights: Vec<f64>,
+        columns: usize,
+        rows: usize,
+        corner: Point3,
+        cell_size: (f64, f64),
+        material_ptr: Rc<RefCell<dyn MaterialTrait>>,
+    ) -> Result<Self, String> {
+        check_grid(columns, rows)?;
+        if heights.len() != columns * rows {
+            return Err(format!("a heightfield of {} by {} samples needs {} heights, not {}", columns, rows, columns * rows, heights.len()));
+        }
+        let (cell_x, cell_z) = cell_size;
+
+        // Normals from central differences, one-sided at the edges.
+        let height = |i: usize, j: usize| heights[j * columns + i];
+        let mut normals = Vec::with_capacity(heights.len());
+        for j in 0..rows {
+            for i in 0..columns {
+                let (left, right) = (i.saturating_sub(1), (i + 1).min(columns - 1));
+                let (back, front) = (j.saturating_sub(1), (j + 1).min(rows - 1));
+                let slope_x = (height(right, j) - height(left, j)) / ((right - left) as f64 * cell_x);
+                let slope_z = (height(i, front) - height(i, back)) / ((front - back) as f64 * cell_z);
+                normals.push(unit_vector(Vec3::new(-slope_x, 1.0, -slope_z)));
+            }
+        }
+
+        let mut cell_ranges = Vec::with_capacity((columns - 1) * (rows - 1));
+        for j in 0..rows - 1 {
+            for i in 0..columns - 1 {
+                let corners = [height(i, j), height(i + 1, j), height(i, j + 1), height(i + 1, j + 1)];
+                let low = corners.iter().copied().fold(f64::INFINITY, f64::min);
+                let high = corners.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                cell_ranges.push(Interval::with_bounds(low, high));
+            }
+        }
+
+        let low = heights.iter().copied().fold(f64::INFINITY, f64::min);
+        let high = heights.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let far = Point3::new(corner.x() + (columns - 1) as f64 * cell_x, high, corner.z() + (rows - 1) as f64 * cell_z);
+        let bbox = Aabb::from_points(Point3::new(corner.x(), low, corner.z()), far);
+
+        Ok(Heightfield { heights, normals, columns, rows, corner, cell_x, cell_z, cell_ranges, material_ptr, bbox })
+    }
+
+    // Heights from the brightness of a grayscale image, with the top row of the image at the
+    // far (-z) side.
+    pub(crate) fn from_image(
+        image: &Image,
+        center: Point3,
+        size: Vec3,
+        material_ptr: Rc<RefCell<dyn MaterialTrait>>,
+    ) -> Result<Self, String> {
+        let samples: Vec<f64> = (0..image.height).flat_map(|y| (0..image.width).map(move |x| image.gray(x, y))).collect();
+        Heightfield::new(&samples, image.width, image.height, center, size, material_ptr)
+    }
+
+    // Rolling hills from fractal noise on a `resolution` × `resolution` grid, stretched to fill
+    // [0, 1]. `features` is about the number of hills across the terrain.
+    pub(crate) fn from_noise(
+        seed: u64,
+        resolution: usize,
+        features: f64,
+        center: Point3,
+        size: Vec3,
+        material_ptr: Rc<RefCell<dyn MaterialTrait>>,
+    ) -> Result<Self, String> {
+        check_grid(resolution, resolution)?;
+        let perlin = Perlin::new(seed);
+        let step = features / (resolution - 1) as f64;
+        let mut samples: Vec<f64> = (0..resolution)
+            .flat_map(|j| (0..resolution).map(move |i| (i, j)))
+            .map(|(i, j)| perlin.fbm(Point3::new(i as f64 * step, 0.5, j as f64 * step), TERRAIN_OCTAVES))
+            .collect();
+        let low = samples.iter().copied().fold(f64::INFINITY, f64::min);
+        let high = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        for sample in &mut samples {
+            *sample = if high > low { (*sample - low) / (high - low) } else { 0.0 };
+        }
+        Heightfield::new(&samples, resolution, resolution, center, size, material_ptr)
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Point3 {
+        Point3::new(self.corner.x() + i as f64 * self.cell_x, self.heights[j * self.columns + i], self.corner.z() + j as f64 * self.cell_z)
+    }
+
+    // Nearest hit on the two triangles of cell (i, j) within `ray_t`: the distance, the
+    // interpolated normal and the outward normal of the triangle.
+    fn hit_cell(&self, ray: &Ray, ray_t: Interval, i: usize, j: usize) -> Option<(f64, Vec3, Vec3)> {
+        let index = |i: usize, j: usize| j * self.columns + i;
+        let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+        let mut nearest: Option<(f64, Vec3, Vec3)> = None;
+
+        for [a, b, c] in [[0, 3, 1], [1, 3, 2]] {
+            let (p0, p1, p2) = (self.vertex(corners[a].0, corners[a].1), self.vertex(corners[b].0, corners[b].1), self.vertex(corners[c].0, corners[c].1));
+            let edge_1 = p1 - p0;
+            let edge_2 = p2 - p0;
+            let p = cross(ray.direction(), edge_2);
+            let determinant = dot(edge_1, p);
+            if determinant.abs() < 1e-12 {
+                continue;
+            }
+            let inverse = 1.0 / determinant;
+            let s = ray.origin() - p0;
+            let b1 = dot(s, p) * inverse;
+            let q = cross(s, edge_1);
+            let b2 = dot(ray.direction(), q) * inverse;
+            if b1 < 0.0 || b2 < 0.0 || b1 + b2 > 1.0 {
+                continue;
+            }
+            let t = dot(edge_2, q) * inverse;
+            let limit = nearest.map_or(ray_t.max, |(t, _, _)| t);
+            if !Interval::with_bounds(ray_t.min, limit).surrounds(t) {
+                continue;
+            }
+            let n = |k: usize| self.normals[index(corners[k].0, corners[k].1)];
+            let shading = (1.0 - b1 - b2) * n(a) + b1 * n(b) + b2 * n(c);
+            // Both triangles wind so that the cross product points up.
+            nearest = Some((t, unit_vector(shading), unit_vector(cross(edge_1, edge_2))));
+        }
+        nearest
+    }
+}
+
+fn check_grid(columns: usize, rows: usize) -> Result<(), String> {
+    if columns < 2 || rows < 2 {
+        return Err(format!("a heightfield needs at least 2 by 2 samples, not {} by {}", columns, rows));
+    }
+    Ok(())
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, ray: &Ray, ray_t: Interval, record: &mut HitRecord) -> bool {
+        // Clip the ray to the bounds, then step through the cells it crosses in order (a 2D DDA),
+        // testing only cells whose height range the ray passes through.
+        let origin = ray.origin();
+        let direction = ray.direction();
+        let mut span = ray_t;
+        for axis in 0..3 {
+            let interval = self.bbox.axis_interval(axis);
+            let inverse = 1.0 / direction[axis];
+            let t0 = (interval.min - origin[axis]) * inverse;
+            let t1 = (interval.max - origin[axis]) * inverse;
+            span.min = span.min.max(t0.min(t1));
+            span.max = span.max.min(t0.max(t1));
+        }
+        if span.max < span.min {
+            return false;
+        }
+
+        let entry = ray.at(span.min);
+        let cells = (self.columns - 1, self.rows - 1);
+        let grid_x = (entry.x() - self.corner.x()) / self.cell_x;
+        let grid_z = (entry.z() - self.corner.z()) / self.cell_z;
+        let mut i = (grid_x.floor().max(0.0) as usize).min(cells.0 - 1);
+        let mut j = (grid_z.floor().max(0.0) as usize).min(cells.1 - 1);
+
+        // Per axis: step direction, ray distance across one cell and distance to the next line.
+        let setup = |cell: usize, d: f64, o: f64, start: f64, size: f64| -> (i64, f64, f64) {
+            if d > 0.0 {
+                (1, size / d, (start + (cell + 1) as f64 * size - o) / d)
+            } else if d < 0.0 {
+                (-1, -size / d, (start + cell as f64 * size - o) / d)
+            } else {
+                (0, f64::INFINITY, f64::INFINITY)
+            }
+        };
+        let (step_x, delta_x, mut next_x) = setup(i, direction.x(), origin.x(), self.corner.x(), self.cell_x);
+        let (step_z, delta_z, mut next_z) = setup(j, direction.z(), origin.z(), self.corner.z(), self.cell_z);
+
+        let mut cell_start = span.min;
+        loop {
+            let cell_end = next_x.min(next_z).min(span.max);
+            let y_in = origin.y() + cell_start * direction.y();
+            let y_out = origin.y() + cell_end * direction.y();
+            let range = self.cell_ranges[j * cells.0 + i];
+            if y_in.min(y_out) <= range.max && y_in.max(y_out) >= range.min {
+                if let Some((t, shading, geometric)) = self.hit_cell(ray, ray_t, i, j) {
+                    record.t = t;
+                    record.point = ray.at(t);
+                    record.set_face_normal(ray, geometric);
+                    record.normal = if record.front_face { shading } else { -shading };
+                    record.set_material(&self.material_ptr);
+                    record.u = (record.point.x() - self.corner.x()) / (self.cell_x * cells.0 as f64);
+                    record.v = (record.point.z() - self.corner.z()) / (self.cell_z * cells.1 as f64);
+                    return true;
+                }
+            }
+
+            if cell_end >= span.max {
+                return false;
+            }
+            if next_x < next_z {
+                let moved = i as i64 + step_x;
+                if moved < 0 || moved >= cells.0 as i64 {
+                    return false;
+                }
+                i = moved as usize;
+                cell_start = next_x;
+                next_x += delta_x;
+            } else {
+                let moved = j as i64 + step_z;
+                if moved < 0 || moved >= cells.1 as i64 {
+                    return false;
+                }
+                j = moved as usize;
+                cell_start = next_z;
+                next_z += delta_z;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![
+            ("type", "heightfield".into()),
+            ("columns", self.columns.into()),
+            ("rows", self.rows.into()),
+            ("corner", self.corner.into()),
+            ("cell_size", vec![self.cell_x, self.cell_z].into()),
+            ("heights", self.heights.clone().into()),
+            ("material", scene.material(&self.material_ptr)),
+        ]))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add(self.columns as u64);
+        hasher.add(self.rows as u64);
+        hasher.add_box(&self.bbox);
+        hasher.add_material(&self.material_ptr);
+    }
+}
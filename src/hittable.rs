@@ -1,10 +1,16 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
+use crate::aabb::Aabb;
 use crate::color::Color;
 use crate::interval::Interval;
+use crate::json::Json;
 use crate::material::{Lambertian, MaterialTrait};
 use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
+use crate::transform::Keyframe;
+use crate::utils::mix_bits;
 use crate::vec3::{dot, Point3, Vec3};
 
 #[derive(Clone)]
@@ -13,15 +19,36 @@ pub(crate) struct HitRecord {
     pub(crate) normal: Vec3,
     pub(crate) material_ptr: Rc<RefCell<dyn MaterialTrait>>,
     pub(crate) t: f64,
-    pub(crate) front_face: bool
+    pub(crate) front_face: bool,
+    pub(crate) u: f64, // Surface coordinates of the hit, each in [0, 1]
+    pub(crate) v: f64,
+    pub(crate) tangent: Vec3, // Direction of increasing u, where the surface defines one (zero otherwise)
+    pub(crate) object_id: usize, // Index of the object in the top-level `HittableList` or `Bvh`
+    pub(crate) tint: Option<Color>, // Color of the point or vertices hit, multiplying what the material reflects
 }
 
 impl HitRecord {
     pub(crate) fn new(point: Point3, normal: Vec3, t: f64, front_face: bool) -> Self {
         let material_ground = Rc::new(RefCell::new(Lambertian { albedo: Color::new(0.8, 0.8, 0.0)}));
 
-        HitRecord { point, normal, t, front_face, material_ptr: material_ground }
+        HitRecord { point, normal, t, front_face, u: 0.0, v: 0.0, tangent: Vec3::new(0.0, 0.0, 0.0), material_ptr: material_ground, object_id: 0, tint: None }
     }
+
+    // Sets the material of the hit, without a tint, surface coordinates or a tangent. Records are
+    // reused from object to object, so primitives call this before filling in what they know about
+    // the surface.
+    pub(crate) fn set_material(&mut self, material_ptr: &Rc<RefCell<dyn MaterialTrait>>) {
+        self.material_ptr = Rc::clone(material_ptr);
+        self.tint = None;
+        (self.u, self.v) = (0.0, 0.0);
+        self.tangent = Vec3::new(0.0, 0.0, 0.0);
+    }
+
+    // `color` reflected by the material, multiplied by the tint of the hit.
+    pub(crate) fn tinted(&self, color: Color) -> Color {
+        self.tint.map_or(color, |tint| tint * color)
+    }
+
     pub(crate) fn set_face_normal(&mut self, ray: &Ray, outward_normal: Vec3) {
         // Sets the hit record normal vector
         // NOTE: the parameter `outward_normal` is assumed to have unit length.
@@ -30,6 +57,79 @@ impl HitRecord {
 
 }
 
+// A stretch of a ray inside a closed object. Both records hold the outward surface normal,
+// whatever side the ray comes from, and `front_face` is not set.
+#[derive(Clone)]
+pub(crate) struct Span {
+    pub(crate) enter: HitRecord,
+    pub(crate) exit: HitRecord,
+}
+
 pub(crate) trait Hittable {
     fn hit(&self, ray: &Ray, interval: Interval, record: &mut HitRecord) -> bool;
+
+    // Box enclosing the object over the whole time it moves.
+    fn bounding_box(&self) -> Aabb;
+
+    // Every span of the whole line of the ray (negative `t` included) inside the object, in
+    // order. Only closed objects can report them, which lets them take part in CSG; the others
+    // return None.
+    fn spans(&self, _ray: &Ray) -> Option<Vec<Span>> {
+        None
+    }
+
+    // Description of the object for a scene file, with its materials and meshes added to
+    // `scene`. Objects that cannot be written down return None.
+    fn save(&self, _scene: &mut SceneWriter) -> Option<Json> {
+        None
+    }
+
+    // Adds the object to a fingerprint of the world: its bounds and materials, and the objects it
+    // holds.
+    fn hash(&self, hasher: &mut SceneHasher);
+}
+
+// Running fingerprint of a world, to tell whether a checkpoint belongs to it. Materials count by
+// the order they are first met, which is the same in every run that builds the same scene.
+pub(crate) struct SceneHasher {
+    hash: u64,
+    material_ids: HashMap<*const (), usize>,
+}
+
+impl SceneHasher {
+    pub(crate) fn new() -> Self {
+        SceneHasher { hash: 0x9e3779b97f4a7c15, material_ids: HashMap::new() }
+    }
+
+    pub(crate) fn add(&mut self, value: u64) {
+        self.hash = mix_bits(self.hash ^ value).wrapping_add(0x9e3779b97f4a7c15);
+    }
+
+    pub(crate) fn add_box(&mut self, bbox: &Aabb) {
+        for interval in [bbox.x, bbox.y, bbox.z] {
+            self.add(interval.min.to_bits());
+            self.add(interval.max.to_bits());
+        }
+    }
+
+    pub(crate) fn add_keyframe(&mut self, keyframe: &Keyframe) {
+        self.add(keyframe.time.to_bits());
+        for i in 0..3 {
+            self.add(keyframe.translation[i].to_bits());
+            self.add(keyframe.scale[i].to_bits());
+        }
+        for value in keyframe.rotation.to_array() {
+            self.add(value.to_bits());
+        }
+    }
+
+    pub(crate) fn add_material(&mut self, material: &Rc<RefCell<dyn MaterialTrait>>) {
+        let next = self.material_ids.len();
+        let id = *self.material_ids.entry(Rc::as_ptr(material) as *const ()).or_insert(next);
+        self.add(id as u64);
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.hash
+    }
 }
\ No newline at end of file
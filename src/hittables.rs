@@ -1,22 +1,32 @@
-use crate::hittable::{HitRecord, Hittable};
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SceneHasher, Span};
 use crate::interval::Interval;
+use crate::json::Json;
 use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
 use crate::vec3::{Point3, Vec3};
 
 pub(crate) struct HittableList {
     objects: Vec<Box<dyn Hittable>>,
+    bbox: Aabb,
 }
 
 impl HittableList {
     // Constructs a new, empty `HittableList`.
     pub(crate) fn new() -> Self {
-        HittableList { objects: Vec::new() }
+        HittableList { objects: Vec::new(), bbox: Aabb::EMPTY }
     }
 
     // Adds an object to the list.
     pub(crate) fn add(&mut self, object: Box<dyn Hittable>) {
+        self.bbox = Aabb::surrounding(&self.bbox, &object.bounding_box());
         self.objects.push(object);
     }
+
+    // Takes the objects out of the list, keeping their order.
+    pub(crate) fn into_objects(self) -> Vec<Box<dyn Hittable>> {
+        self.objects
+    }
 }
 
 impl Hittable for HittableList {
@@ -25,9 +35,10 @@ impl Hittable for HittableList {
         let mut hit_anything = false;
         let mut closest_so_far = interval.max;
 
-        for object in &self.objects {
+        for (index, object) in self.objects.iter().enumerate() {
             if object.hit(ray, Interval::with_bounds(interval.min, closest_so_far), &mut temp_record) {
                 hit_anything = true;
+                temp_record.object_id = index;
                 closest_so_far = temp_record.t;
                 *record = temp_record.clone();
             }
@@ -35,4 +46,54 @@ impl Hittable for HittableList {
 
         hit_anything
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        // The list is the union of its objects, which may overlap: count how many objects the
+        // ray is inside and keep the stretches where that count is not zero.
+        let mut events: Vec<(HitRecord, bool)> = Vec::new();
+        for object in &self.objects {
+            for span in object.spans(ray)? {
+                events.push((span.enter, true));
+                events.push((span.exit, false));
+            }
+        }
+        events.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+        let mut depth = 0;
+        let mut enter: Option<HitRecord> = None;
+        let mut result = Vec::new();
+        for (hit, entering) in events {
+            if entering {
+                depth += 1;
+                if depth == 1 {
+                    enter = Some(hit);
+                }
+            } else {
+                depth -= 1;
+                if depth == 0 {
+                    if let Some(enter) = enter.take() {
+                        result.push(Span { enter, exit: hit });
+                    }
+                }
+            }
+        }
+
+        Some(result)
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        let objects: Vec<Json> = self.objects.iter().map(|object| object.save(scene)).collect::<Option<_>>()?;
+        Some(Json::object(vec![("type", "list".into()), ("objects", objects.into())]))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add(self.objects.len() as u64);
+        for object in &self.objects {
+            object.hash(hasher);
+        }
+    }
 }
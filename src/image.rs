@@ -0,0 +1,185 @@
+use std::fs;
+use std::io;
+
+use crate::color::Color;
+use crate::png::read_png;
+
+// A decoded image with linear float channels, stored in scanline order from the top row.
+pub(crate) struct Image {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pixels: Vec<Color>,
+    pub(crate) source: Option<(String, bool)>, // File and `linear` flag it was loaded with, if any
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Image {
+    // Loads a PNG, PPM/PGM (binary or ASCII) or PFM image. Integer formats are treated as sRGB
+    // encoded and converted to linear values unless `linear` is set, as for masks and height data.
+    pub(crate) fn load(path: &str, linear: bool) -> io::Result<Image> {
+        let data = fs::read(path)?;
+        let mut image = Image::decode(&data, linear).map_err(|message| invalid_data(format!("{}: {}", path, message)))?;
+        image.source = Some((path.to_string(), linear));
+        Ok(image)
+    }
+
+    // Decodes an image file held in memory, as `load` does.
+    pub(crate) fn decode(data: &[u8], linear: bool) -> Result<Image, String> {
+        if data.len() < 2 {
+            return Err(String::from("file too short for an image"));
+        }
+        match &data[0..2] {
+            b"P2" | b"P3" | b"P5" | b"P6" => parse_pnm(data, linear),
+            b"PF" | b"Pf" => parse_pfm(data),
+            [0x89, b'P'] => {
+                let (width, height, rgba) = read_png(data)?;
+                let decode = |v: f64| if linear { v } else { srgb_to_linear(v) };
+                let pixels = rgba.iter().map(|p| Color::new(decode(p[0]), decode(p[1]), decode(p[2]))).collect();
+                Ok(Image { width, height, pixels, source: None })
+            }
+            [0xff, 0xd8] => Err(String::from("JPEG images are not supported")),
+            _ => Err(String::from("unsupported image format")),
+        }
+    }
+
+    // An image from `width` × `height` linear pixels in scanline order.
+    pub(crate) fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Result<Image, String> {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            return Err(format!("{} pixels do not make a {} by {} image", pixels.len(), width, height));
+        }
+        Ok(Image { width, height, pixels, source: None })
+    }
+
+    pub(crate) fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub(crate) fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y.min(self.height - 1) * self.width + x.min(self.width - 1)]
+    }
+
+    // Bilinear lookup at texture coordinates (u, v), with v = 0 at the top row and the image
+    // repeating outside [0, 1].
+    pub(crate) fn sample(&self, u: f64, v: f64) -> Color {
+        let x = u.rem_euclid(1.0) * self.width as f64 - 0.5;
+        let y = v.rem_euclid(1.0) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let wrap = |value: f64, size: usize| (value as i64).rem_euclid(size as i64) as usize;
+        let texel = |dx: f64, dy: f64| self.pixels[wrap(y0 + dy, self.height) * self.width + wrap(x0 + dx, self.width)];
+        (1.0 - fy) * ((1.0 - fx) * texel(0.0, 0.0) + fx * texel(1.0, 0.0)) + fy * ((1.0 - fx) * texel(0.0, 1.0) + fx * texel(1.0, 1.0))
+    }
+
+    // Average of the channels, for images used as masks or height maps.
+    pub(crate) fn gray(&self, x: usize, y: usize) -> f64 {
+        let color = self.pixel(x, y);
+        (color.x() + color.y() + color.z()) / 3.0
+    }
+}
+
+pub(crate) fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Splits the whitespace separated header fields of a PNM/PFM file, skipping comments. Returns
+// the fields and the offset right after the single whitespace that ends the last one.
+fn header_fields(data: &[u8], count: usize) -> Result<(Vec<String>, usize), String> {
+    let mut fields = Vec::new();
+    let mut position = 0;
+
+    while fields.len() < count {
+        while position < data.len() && data[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if position < data.len() && data[position] == b'#' {
+            while position < data.len() && data[position] != b'\n' {
+                position += 1;
+            }
+            continue;
+        }
+        let start = position;
+        while position < data.len() && !data[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err(String::from("truncated header"));
+        }
+        fields.push(String::from_utf8_lossy(&data[start..position]).into_owned());
+    }
+
+    Ok((fields, position + 1))
+}
+
+fn parse_number<T: std::str::FromStr>(field: &str) -> Result<T, String> {
+    field.parse().map_err(|_| format!("invalid number '{}'", field))
+}
+
+fn parse_pnm(data: &[u8], linear: bool) -> Result<Image, String> {
+    let kind = &data[0..2];
+    let (fields, body) = header_fields(data, 4)?;
+    let width: usize = parse_number(&fields[1])?;
+    let height: usize = parse_number(&fields[2])?;
+    let max_value: f64 = parse_number(&fields[3])?;
+    let channels = if kind == b"P2" || kind == b"P5" { 1 } else { 3 };
+    let count = width * height * channels;
+
+    let values: Vec<f64> = if kind == b"P2" || kind == b"P3" {
+        let text = String::from_utf8_lossy(&data[body.min(data.len())..]);
+        text.split_whitespace().take(count).map(parse_number::<f64>).collect::<Result<_, _>>()?
+    } else if max_value < 256.0 {
+        data[body.min(data.len())..].iter().take(count).map(|&v| v as f64).collect()
+    } else {
+        data[body.min(data.len())..].chunks_exact(2).take(count).map(|v| u16::from_be_bytes([v[0], v[1]]) as f64).collect()
+    };
+    if values.len() < count {
+        return Err(String::from("truncated pixel data"));
+    }
+
+    let decode = |v: f64| {
+        let normalized = v / max_value;
+        if linear { normalized } else { srgb_to_linear(normalized) }
+    };
+    let pixels = values
+        .chunks_exact(channels)
+        .map(|p| if channels == 1 { Color::new(decode(p[0]), decode(p[0]), decode(p[0])) } else { Color::new(decode(p[0]), decode(p[1]), decode(p[2])) })
+        .collect();
+
+    Ok(Image { width, height, pixels, source: None })
+}
+
+fn parse_pfm(data: &[u8]) -> Result<Image, String> {
+    let (fields, body) = header_fields(data, 4)?;
+    let channels = if fields[0] == "PF" { 3 } else { 1 };
+    let width: usize = parse_number(&fields[1])?;
+    let height: usize = parse_number(&fields[2])?;
+    let scale: f64 = parse_number(&fields[3])?;
+    let little_endian = scale < 0.0;
+
+    let floats: Vec<f64> = data[body.min(data.len())..]
+        .chunks_exact(4)
+        .take(width * height * channels)
+        .map(|b| {
+            let bytes = [b[0], b[1], b[2], b[3]];
+            (if little_endian { f32::from_le_bytes(bytes) } else { f32::from_be_bytes(bytes) }) as f64
+        })
+        .collect();
+    if floats.len() < width * height * channels {
+        return Err(String::from("truncated pixel data"));
+    }
+
+    // PFM stores the bottom row first.
+    let mut pixels = vec![Color::new(0.0, 0.0, 0.0); width * height];
+    for (i, p) in floats.chunks_exact(channels).enumerate() {
+        let (x, y) = (i % width, height - 1 - i / width);
+        pixels[y * width + x] = if channels == 1 { Color::new(p[0], p[0], p[0]) } else { Color::new(p[0], p[1], p[2]) };
+    }
+
+    Ok(Image { width, height, pixels, source: None })
+}
@@ -0,0 +1,94 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SceneHasher, Span};
+use crate::interval::Interval;
+use crate::json::Json;
+use crate::ray::Ray;
+use crate::scene_file::{keyframes_json, SceneWriter};
+use crate::transform::AnimatedTransform;
+use crate::vec3::{unit_vector, Point3};
+
+// An object placed by a transform that may change over time, applied around `pivot`. Rays are
+// moved into the object's space at their own time, which gives motion blur for any object.
+pub(crate) struct Instance {
+    object: Box<dyn Hittable>,
+    transform: Rc<RefCell<AnimatedTransform>>,
+    pivot: Point3,
+    bbox: Aabb,
+}
+
+impl Instance {
+    pub(crate) fn new(object: Box<dyn Hittable>, transform: AnimatedTransform, pivot: Point3) -> Self {
+        let transforms = [transform.clone()];
+        Self::shared(object, Rc::new(RefCell::new(transform)), &transforms, pivot)
+    }
+
+    // An instance whose transform is swapped between renders through `transform`, such as once
+    // per frame of an animation. The bounding box covers the object under each of `transforms`,
+    // which has to hold every transform it will be given.
+    pub(crate) fn shared(object: Box<dyn Hittable>, transform: Rc<RefCell<AnimatedTransform>>, transforms: &[AnimatedTransform], pivot: Point3) -> Self {
+        let object_box = object.bounding_box();
+        let local_box = Aabb::from_points(object_box.min() - pivot, object_box.max() - pivot);
+        let moved = transforms.iter().fold(Aabb::EMPTY, |bounds, transform| Aabb::surrounding(&bounds, &transform.motion_bounds(&local_box)));
+        let bbox = Aabb::from_points(moved.min() + pivot, moved.max() + pivot);
+        Instance { object, transform, pivot, bbox }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, interval: Interval, record: &mut HitRecord) -> bool {
+        let keyframe = self.transform.borrow().at(ray.time());
+        let origin = keyframe.inverse_point(ray.origin() - self.pivot) + self.pivot;
+        let local_ray = Ray::new(origin, keyframe.inverse_vector(ray.direction()), ray.time());
+
+        // The ray parameter is unchanged by the affine transform, so `t` carries over as is.
+        if !self.object.hit(&local_ray, interval, record) {
+            return false;
+        }
+
+        record.point = keyframe.apply_point(record.point - self.pivot) + self.pivot;
+        record.normal = unit_vector(keyframe.apply_normal(record.normal));
+        record.tangent = keyframe.apply_vector(record.tangent);
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let keyframe = self.transform.borrow().at(ray.time());
+        let origin = keyframe.inverse_point(ray.origin() - self.pivot) + self.pivot;
+        let local_ray = Ray::new(origin, keyframe.inverse_vector(ray.direction()), ray.time());
+
+        let to_world = |record: &mut HitRecord| {
+            record.point = keyframe.apply_point(record.point - self.pivot) + self.pivot;
+            record.normal = unit_vector(keyframe.apply_normal(record.normal));
+        };
+        let mut spans = self.object.spans(&local_ray)?;
+        for span in &mut spans {
+            to_world(&mut span.enter);
+            to_world(&mut span.exit);
+        }
+        Some(spans)
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![
+            ("type", "instance".into()),
+            ("keyframes", keyframes_json(self.transform.borrow().keyframes())),
+            ("pivot", self.pivot.into()),
+            ("object", self.object.save(scene)?),
+        ]))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        for keyframe in self.transform.borrow().keyframes() {
+            hasher.add_keyframe(keyframe);
+        }
+        (0..3).for_each(|i| hasher.add(self.pivot[i].to_bits()));
+        self.object.hash(hasher);
+    }
+}
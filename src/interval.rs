@@ -5,9 +5,25 @@ pub(crate) struct Interval {
 }
 
 impl Interval {
+    pub(crate) const EMPTY: Interval = Interval { min: f64::INFINITY, max: -f64::INFINITY };
+
     pub fn with_bounds(min: f64, max: f64) -> Self {
         Self { min, max }
     }
+
+    // Smallest interval containing both intervals.
+    pub(crate) fn enclosing(a: Interval, b: Interval) -> Self {
+        Self { min: a.min.min(b.min), max: a.max.max(b.max) }
+    }
+
+    pub(crate) fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    pub(crate) fn expand(&self, delta: f64) -> Self {
+        Self { min: self.min - delta / 2.0, max: self.max + delta / 2.0 }
+    }
+
     pub(crate) fn surrounds(&self, x: f64) -> bool {
         self.min < x && x < self.max
     }
@@ -20,6 +36,6 @@ impl Interval {
         if x > self.max {
            return self.max
         }
-        return x
+        x
     }
 }
@@ -0,0 +1,338 @@
+use std::fs;
+use std::io;
+
+use crate::ray::Ray;
+use crate::vec3::{dot, refract, unit_vector, Point3, Vec3};
+
+// Radial bins of the exit pupil bounds, and rays traced to find the bounds of each one.
+const PUPIL_BINS: usize = 32;
+const PUPIL_SAMPLES: usize = 16384;
+
+// One spherical interface (or the aperture stop) of a lens prescription, in meters.
+#[derive(Clone, Copy, Debug)]
+struct LensElement {
+    curvature_radius: f64,
+    thickness: f64,
+    eta: f64,
+    aperture_radius: f64,
+}
+
+// A camera lens described by a real prescription, traced element by element in the manner of
+// pbrt's realistic camera. Lens space has the film at z = 0 and the elements along -z, in meters.
+pub(crate) struct LensSystem {
+    elements: Vec<LensElement>,
+    film_diagonal: f64,
+    meters_per_unit: f64,
+    // Bounds of the rear element area that rays from each radial film bin pass through.
+    exit_pupil_bounds: Vec<[f64; 4]>,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl LensSystem {
+    // Loads a lens data file: one element per line with the curvature radius, thickness, index of
+    // refraction and aperture diameter in millimeters, from the front element towards the film.
+    // A zero radius marks the aperture stop, whose diameter `aperture_diameter` (mm) may reduce.
+    pub(crate) fn load(path: &str, film_diagonal: f64, aperture_diameter: Option<f64>, meters_per_unit: f64) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut elements = Vec::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values: Vec<f64> = line
+                .split_whitespace()
+                .map(|field| field.parse::<f64>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid_data(format!("{}:{}: expected numbers", path, line_number + 1)))?;
+            if values.len() != 4 {
+                return Err(invalid_data(format!("{}:{}: expected radius, thickness, eta and aperture", path, line_number + 1)));
+            }
+
+            let mut diameter = values[3];
+            if values[0] == 0.0 {
+                if let Some(stop) = aperture_diameter {
+                    if stop > diameter {
+                        eprintln!("Warning: aperture diameter {} is larger than the stop of {}, using {}", stop, path, diameter);
+                    } else {
+                        diameter = stop;
+                    }
+                }
+            }
+            elements.push(LensElement {
+                curvature_radius: values[0] * 0.001,
+                thickness: values[1] * 0.001,
+                eta: values[2],
+                aperture_radius: diameter * 0.001 / 2.0,
+            });
+        }
+
+        if elements.is_empty() {
+            return Err(invalid_data(format!("{}: no lens elements", path)));
+        }
+
+        Ok(LensSystem { elements, film_diagonal: film_diagonal * 0.001, meters_per_unit, exit_pupil_bounds: Vec::new() })
+    }
+
+    // Moves the film so the plane `focus_distance` world units away is in focus and updates the
+    // exit pupil bounds for the new position.
+    pub(crate) fn focus(&mut self, focus_distance: f64) {
+        let focus_distance = focus_distance * self.meters_per_unit;
+        if let Some(film_distance) = self.focus_thick_lens(focus_distance) {
+            self.elements.last_mut().unwrap().thickness = film_distance;
+        } else {
+            eprintln!("Warning: cannot focus the lens system at {} m", focus_distance);
+        }
+
+        self.exit_pupil_bounds = (0..PUPIL_BINS)
+            .map(|bin| {
+                let radius = self.film_diagonal / 2.0;
+                self.bound_exit_pupil(bin as f64 / PUPIL_BINS as f64 * radius, (bin + 1) as f64 / PUPIL_BINS as f64 * radius)
+            })
+            .collect();
+    }
+
+    // Film size in meters for an image of the given aspect ratio.
+    fn film_extent(&self, aspect_ratio: f64) -> (f64, f64) {
+        let height = self.film_diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        (height * aspect_ratio, height)
+    }
+
+    fn lens_rear_z(&self) -> f64 {
+        self.elements.last().unwrap().thickness
+    }
+
+    fn lens_front_z(&self) -> f64 {
+        self.elements.iter().map(|e| e.thickness).sum()
+    }
+
+    fn rear_element_radius(&self) -> f64 {
+        self.elements.last().unwrap().aperture_radius
+    }
+
+    // Generates a camera space ray (x right, y up, z forward, meters) for the image position
+    // (s, t) in [0, 1]^2 and a lens sample, with its radiometric weight. None means the lens
+    // barrel or an aperture blocked the ray.
+    pub(crate) fn generate_ray(&self, s: f64, t: f64, aspect_ratio: f64, lens_sample: (f64, f64)) -> Option<(Point3, Vec3, f64)> {
+        let (width, height) = self.film_extent(aspect_ratio);
+        // The lens flips the image, so film positions are mirrored horizontally here and
+        // vertically by the downward image axis.
+        let film_point = Point3::new(-(s - 0.5) * width, (t - 0.5) * height, 0.0);
+
+        let (rear_point, bounds_area) = self.sample_exit_pupil(film_point.x(), film_point.y(), lens_sample);
+        let ray = Ray::new(film_point, rear_point - film_point, 0.0);
+        let (origin, direction) = self.trace_from_film(&ray)?;
+
+        // cos^4 falloff, normalized by the exit pupil of the image center.
+        let cos_theta = unit_vector(ray.direction()).z();
+        let center_bounds = self.exit_pupil_bounds[0];
+        let center_area = (center_bounds[2] - center_bounds[0]) * (center_bounds[3] - center_bounds[1]);
+        let weight = cos_theta.powi(4) * bounds_area / center_area;
+
+        Some((origin / self.meters_per_unit, direction, weight))
+    }
+
+    fn sample_exit_pupil(&self, film_x: f64, film_y: f64, lens_sample: (f64, f64)) -> (Point3, f64) {
+        let film_radius = (film_x * film_x + film_y * film_y).sqrt();
+        let bin = ((film_radius / (self.film_diagonal / 2.0) * PUPIL_BINS as f64) as usize).min(PUPIL_BINS - 1);
+        let bounds = self.exit_pupil_bounds[bin];
+
+        let x = bounds[0] + lens_sample.0 * (bounds[2] - bounds[0]);
+        let y = bounds[1] + lens_sample.1 * (bounds[3] - bounds[1]);
+        let area = (bounds[2] - bounds[0]) * (bounds[3] - bounds[1]);
+
+        // Bounds are computed along +x; rotate them to the film point's direction.
+        let (sin_theta, cos_theta) = if film_radius != 0.0 { (film_y / film_radius, film_x / film_radius) } else { (0.0, 1.0) };
+        (Point3::new(cos_theta * x - sin_theta * y, sin_theta * x + cos_theta * y, self.lens_rear_z()), area)
+    }
+
+    fn bound_exit_pupil(&self, film_x0: f64, film_x1: f64) -> [f64; 4] {
+        let rear_radius = self.rear_element_radius();
+        let projected = [-1.5 * rear_radius, -1.5 * rear_radius, 1.5 * rear_radius, 1.5 * rear_radius];
+        let mut bounds = [f64::INFINITY, f64::INFINITY, -f64::INFINITY, -f64::INFINITY];
+        let mut exiting = 0;
+
+        for i in 0..PUPIL_SAMPLES {
+            let film_point = Point3::new(film_x0 + (i as f64 + 0.5) / PUPIL_SAMPLES as f64 * (film_x1 - film_x0), 0.0, 0.0);
+            let (u0, u1) = (radical_inverse(2, i as u64), radical_inverse(3, i as u64));
+            let rear_x = projected[0] + u0 * (projected[2] - projected[0]);
+            let rear_y = projected[1] + u1 * (projected[3] - projected[1]);
+
+            let inside = rear_x >= bounds[0] && rear_x <= bounds[2] && rear_y >= bounds[1] && rear_y <= bounds[3];
+            let rear_point = Point3::new(rear_x, rear_y, self.lens_rear_z());
+            if inside || self.trace_from_film(&Ray::new(film_point, rear_point - film_point, 0.0)).is_some() {
+                bounds = [bounds[0].min(rear_x), bounds[1].min(rear_y), bounds[2].max(rear_x), bounds[3].max(rear_y)];
+                exiting += 1;
+            }
+        }
+
+        if exiting == 0 {
+            return projected;
+        }
+        // Grow by the sample spacing so thin slivers of the pupil are not lost.
+        let margin = 2.0 * (3.0 * rear_radius * 2f64.sqrt()) / (PUPIL_SAMPLES as f64).sqrt();
+        [bounds[0] - margin, bounds[1] - margin, bounds[2] + margin, bounds[3] + margin]
+    }
+
+    // Traces a camera space ray leaving the film through the elements, from the rear one to the
+    // front one. Returns the camera space origin and direction of the ray leaving the lens.
+    fn trace_from_film(&self, ray: &Ray) -> Option<(Point3, Vec3)> {
+        let mut origin = flip_z(ray.origin());
+        let mut direction = flip_z(ray.direction());
+        let mut element_z = 0.0;
+
+        for i in (0..self.elements.len()).rev() {
+            let element = self.elements[i];
+            element_z -= element.thickness;
+
+            let eta_incident = element.eta;
+            let eta_transmitted = if i > 0 && self.elements[i - 1].eta != 0.0 { self.elements[i - 1].eta } else { 1.0 };
+            (origin, direction) = interact(&element, element_z, origin, direction, eta_incident, eta_transmitted)?;
+        }
+
+        Some((flip_z(origin), flip_z(direction)))
+    }
+
+    // Traces a camera space ray entering the front element through to the film side.
+    fn trace_from_scene(&self, ray: &Ray) -> Option<(Point3, Vec3)> {
+        let mut origin = flip_z(ray.origin());
+        let mut direction = flip_z(ray.direction());
+        let mut element_z = -self.lens_front_z();
+
+        for (i, element) in self.elements.iter().enumerate() {
+            let eta_incident = if i == 0 || self.elements[i - 1].eta == 0.0 { 1.0 } else { self.elements[i - 1].eta };
+            let eta_transmitted = if element.eta != 0.0 { element.eta } else { 1.0 };
+            (origin, direction) = interact(element, element_z, origin, direction, eta_incident, eta_transmitted)?;
+            element_z += element.thickness;
+        }
+
+        Some((flip_z(origin), flip_z(direction)))
+    }
+
+    // Computes the film distance that focuses at `focus_distance` meters with a thick lens
+    // approximation derived from the cardinal points of the system.
+    fn focus_thick_lens(&self, focus_distance: f64) -> Option<f64> {
+        let x = 0.001 * self.film_diagonal;
+
+        let scene_ray = Ray::new(Point3::new(x, 0.0, self.lens_front_z() + 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let (film_origin, film_direction) = self.trace_from_scene(&scene_ray)?;
+        let (principal_0, focal_0) = cardinal_points(&scene_ray, film_origin, film_direction)?;
+
+        let film_ray = Ray::new(Point3::new(x, 0.0, self.lens_rear_z() - 1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let (scene_origin, scene_direction) = self.trace_from_film(&film_ray)?;
+        let (principal_1, _) = cardinal_points(&film_ray, scene_origin, scene_direction)?;
+
+        let focal_length = focal_0 - principal_0;
+        let z = -focus_distance;
+        let c = (principal_1 - z - principal_0) * (principal_1 - z - 4.0 * focal_length - principal_0);
+        if c < 0.0 {
+            return None;
+        }
+        let delta = 0.5 * (principal_1 - z + principal_0 - c.sqrt());
+        Some(self.lens_rear_z() + delta)
+    }
+}
+
+fn flip_z(v: Vec3) -> Vec3 {
+    Vec3::new(v.x(), v.y(), -v.z())
+}
+
+// Returns the z of the principal plane and of the focal point for a ray parallel to the axis
+// and the same ray after passing through the lens.
+fn cardinal_points(ray_in: &Ray, out_origin: Point3, out_direction: Vec3) -> Option<(f64, f64)> {
+    if out_direction.x() == 0.0 {
+        return None;
+    }
+    let t_focus = -out_origin.x() / out_direction.x();
+    let focal_z = -(out_origin + t_focus * out_direction).z();
+    let t_principal = (ray_in.origin().x() - out_origin.x()) / out_direction.x();
+    let principal_z = -(out_origin + t_principal * out_direction).z();
+    Some((principal_z, focal_z))
+}
+
+// Intersects a lens space ray with one interface, checks the element's aperture and refracts.
+fn interact(
+    element: &LensElement,
+    element_z: f64,
+    origin: Point3,
+    direction: Vec3,
+    eta_incident: f64,
+    eta_transmitted: f64,
+) -> Option<(Point3, Vec3)> {
+    let is_stop = element.curvature_radius == 0.0;
+    let (t, normal) = if is_stop {
+        if direction.z() == 0.0 {
+            return None;
+        }
+        ((element_z - origin.z()) / direction.z(), Vec3::new(0.0, 0.0, 0.0))
+    } else {
+        intersect_spherical_element(element.curvature_radius, element_z + element.curvature_radius, origin, direction)?
+    };
+    if t < 0.0 {
+        return None;
+    }
+
+    let hit = origin + t * direction;
+    if hit.x() * hit.x() + hit.y() * hit.y() > element.aperture_radius * element.aperture_radius {
+        return None;
+    }
+    if is_stop {
+        return Some((hit, direction));
+    }
+
+    // Refract, failing on total internal reflection.
+    let unit_direction = unit_vector(direction);
+    let eta = eta_incident / eta_transmitted;
+    let cos_theta = dot(-unit_direction, normal).min(1.0);
+    if eta * eta * (1.0 - cos_theta * cos_theta) >= 1.0 {
+        return None;
+    }
+    Some((hit, refract(unit_direction, normal, eta)))
+}
+
+// Intersects a ray with the sphere of an element; the normal faces the incoming ray.
+fn intersect_spherical_element(radius: f64, z_center: f64, origin: Point3, direction: Vec3) -> Option<(f64, Vec3)> {
+    let o = origin - Vec3::new(0.0, 0.0, z_center);
+    let a = direction.length_squared();
+    let b = 2.0 * dot(direction, o);
+    let c = o.length_squared() - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    let q = if b < 0.0 { -0.5 * (b - root) } else { -0.5 * (b + root) };
+    let (mut t0, mut t1) = (q / a, c / q);
+    if t0 > t1 {
+        std::mem::swap(&mut t0, &mut t1);
+    }
+
+    let use_closer = (direction.z() > 0.0) ^ (radius < 0.0);
+    let t = if use_closer { t0 } else { t1 };
+    if t < 0.0 {
+        return None;
+    }
+
+    let mut normal = unit_vector(o + t * direction);
+    if dot(normal, -direction) < 0.0 {
+        normal = -normal;
+    }
+    Some((t, normal))
+}
+
+fn radical_inverse(base: u64, mut index: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed = 0u64;
+    while index > 0 {
+        let next = index / base;
+        reversed = reversed * base + (index - next * base);
+        inv_base_m *= inv_base;
+        index = next;
+    }
+    reversed as f64 * inv_base_m
+}
@@ -0,0 +1,60 @@
+use crate::color::Color;
+use crate::vec3::{dot, unit_vector, Point3, Vec3};
+
+// Shape of the light given off by a punctual light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum LightKind {
+    // The same in every direction
+    Point,
+    // A cone around `direction`, full inside `inner_angle` and fading out to `outer_angle`
+    // (radians from the axis)
+    Spot { direction: Vec3, inner_angle: f64, outer_angle: f64 },
+    // Parallel light travelling along `direction`, as from the sun
+    Directional { direction: Vec3 },
+}
+
+// A light with no size, as in glTF's KHR_lights_punctual. Rays can never hit it, so it reaches
+// the scene only through the shadow rays traced towards it from each hit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PunctualLight {
+    pub(crate) kind: LightKind,
+    pub(crate) position: Point3,
+    // Color times intensity: candela for point and spot lights, lux for directional ones
+    pub(crate) intensity: Color,
+    // Distance at which the light has faded out completely, if any
+    pub(crate) range: Option<f64>,
+}
+
+impl PunctualLight {
+    // Unit direction from `point` towards the light, the distance to it and the light arriving
+    // there, or None when the point is outside the light's reach.
+    pub(crate) fn illuminate(&self, point: Point3) -> Option<(Vec3, f64, Color)> {
+        if let LightKind::Directional { direction } = self.kind {
+            return Some((-unit_vector(direction), f64::INFINITY, self.intensity));
+        }
+
+        let offset = self.position - point;
+        let distance_squared = offset.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let towards_light = offset / distance;
+
+        // Smooth fade to zero at the range, as the glTF specification suggests.
+        let mut falloff = 1.0 / distance_squared;
+        if let Some(range) = self.range {
+            falloff *= (1.0 - (distance / range).powi(4)).clamp(0.0, 1.0);
+        }
+        if let LightKind::Spot { direction, inner_angle, outer_angle } = self.kind {
+            let cosine = dot(-towards_light, unit_vector(direction));
+            let (cos_inner, cos_outer) = (inner_angle.cos(), outer_angle.cos());
+            let t = ((cosine - cos_outer) / (cos_inner - cos_outer).max(1e-4)).clamp(0.0, 1.0);
+            falloff *= t * t;
+        }
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some((towards_light, distance, falloff * self.intensity))
+    }
+}
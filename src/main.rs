@@ -4,11 +4,36 @@ use std::rc::Rc;
 use hittables::HittableList;
 use vec3::Point3;
 
+use crate::animation::{frame_path, Animation, Interpolation};
+use crate::aperture::{Aperture, ApertureMask};
+use crate::bezier::{load_patches, tessellate_patches};
+use crate::builtin::{add_small_spheres, BuiltinScene};
+use crate::bvh::Bvh;
 use crate::camera::Camera;
-use crate::color::Color;
-use crate::material::{Dielectric, Lambertian, Metal};
+use crate::color::{Color, ToneMapper};
+use crate::curve::{load_strands, strand_curves, CurveType};
+use crate::exr::{Compression, PixelType};
+use crate::gltf::load_gltf;
+use crate::hair::HairMaterial;
+use crate::heightfield::Heightfield;
+use crate::image::Image;
+use crate::instance::Instance;
+use crate::material::Lambertian;
+use crate::mesh::{PolygonMesh, TriangleMesh};
+use crate::lens_system::LensSystem;
+use crate::pbrt::load_pbrt;
+use crate::physical_camera::PhysicalCamera;
+use crate::point_cloud::{PointCloud, PointData, PointShape};
+use crate::subdivision::catmull_clark;
+use crate::stereo::{Stereo, StereoLayout, StereoMode};
+use crate::projection::Projection;
+use crate::sampler::sampler_from_name;
+use crate::scene::Scene;
+use crate::scene_file::{export_obj, load_scene, save_scene};
+use crate::shutter::ShutterCurve;
 use crate::sphere::Sphere;
-use crate::utils::random_float;
+use crate::transform::{AnimatedTransform, Keyframe, Quaternion};
+use crate::utils::{hash_values, seed_random};
 use crate::vec3::Vec3;
 
 mod vec3;
@@ -21,71 +46,710 @@ mod utils;
 mod interval;
 mod camera;
 mod material;
+mod sampler;
+mod film;
+mod checkpoint;
+mod aov;
+mod deflate;
+mod exr;
+mod denoise;
+mod projection;
+mod physical_camera;
+mod image;
+mod aperture;
+mod lens_system;
+mod stereo;
+mod aabb;
+mod bvh;
+mod transform;
+mod instance;
+mod shutter;
+mod animation;
+mod png;
+mod cuboid;
+mod csg;
+mod sdf;
+mod quadric;
+mod mesh;
+mod bezier;
+mod subdivision;
+mod noise;
+mod heightfield;
+mod curve;
+mod hair;
+mod ply;
+mod point_cloud;
+mod json;
+mod light;
+mod pbr;
+mod gltf;
+mod scene;
+mod texture;
+mod pbrt;
+mod scene_file;
+mod quad;
+mod medium;
+mod builtin;
 
 fn main() {
+    // Options
+    let mut sampler_name: Option<String> = None;
+    let mut image_width: Option<f64> = None;
+    let mut aspect_ratio: Option<f64> = None;
+    let mut samples_per_pixel: Option<i32> = None;
+    let mut seed: Option<u64> = None;
+    let mut checkpoint_file: Option<String> = None;
+    let mut checkpoint_interval: f64 = 300.0;
+    let mut resume = false;
+    let mut write_aovs = false;
+    let mut exr_file: Option<String> = None;
+    let mut exr_compression = Compression::Zip;
+    let mut exr_pixel_type = PixelType::Half;
+    let mut denoise = false;
+    let mut exposure: Option<f64> = None;
+    let mut tone_mapper: Option<ToneMapper> = None;
+    let mut white_balance: Option<f64> = None;
+    let mut projection_name = String::from("perspective");
+    let mut fisheye_fov: f64 = 180.0;
+    let mut ortho_width: f64 = 10.0;
+    let mut shutter: (Option<f64>, Option<f64>) = (None, None);
+    let mut physical: Option<PhysicalCamera> = None;
+    let mut aperture_blades: u32 = 0;
+    let mut aperture_rotation: f64 = 0.0;
+    let mut aperture_mask: Option<String> = None;
+    let mut cat_eye: Option<f64> = None;
+    let mut lens_file: Option<String> = None;
+    let mut film_diagonal: f64 = 35.0;
+    let mut lens_aperture: Option<f64> = None;
+    let mut meters_per_unit: Option<f64> = None;
+    let mut stereo_mode: Option<StereoMode> = None;
+    let mut stereo_layout = StereoLayout::SideBySide;
+    let mut interpupillary_distance: f64 = 0.064;
+    let mut shutter_curve: Vec<f64> = Vec::new();
+    let mut camera_keyframes: Vec<Keyframe> = Vec::new();
+    let mut object_keyframes: Vec<(usize, Keyframe)> = Vec::new();
+    let mut output_file: Option<String> = None;
+    let mut frames: Option<(i32, i32)> = None;
+    let mut animation_keys: Vec<(String, f64, Vec<f64>, Interpolation)> = Vec::new();
+    let mut turntable = false;
+    let mut patch_files: Vec<String> = Vec::new();
+    let mut patch_tolerance = 0.005;
+    let mut mesh_files: Vec<String> = Vec::new();
+    let mut subdivision_levels: u32 = 0;
+    let mut heightfield_source: Option<String> = None;
+    let mut heightfield_size = Vec3::new(40.0, 3.0, 40.0);
+    let mut heightfield_resolution: usize = 512;
+    let mut strand_files: Vec<String> = Vec::new();
+    let mut curve_type = CurveType::Cylinder;
+    let mut hair_melanin = (1.3, 0.0);
+    let mut hair_color: Option<Color> = None;
+    let mut hair_roughness = (0.3, 0.3);
+    let mut point_cloud_files: Vec<String> = Vec::new();
+    let mut point_radius = 0.01;
+    let mut point_shape = PointShape::Sphere;
+    let mut gltf_file: Option<String> = None;
+    let mut gltf_camera: usize = 0;
+    let mut pbrt_file: Option<String> = None;
+    let mut scene_file: Option<String> = None;
+    let mut builtin: Option<BuiltinScene> = None;
+    let mut save_scene_file: Option<String> = None;
+    let mut obj_file: Option<String> = None;
+    let mut model_placement = Keyframe::new(0.0, Vec3::new(0.0, 0.0, 0.0), Quaternion::IDENTITY, Vec3::new(1.0, 1.0, 1.0));
+    let mut args = std::env::args().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sampler" => sampler_name = Some(args.next().unwrap_or_default()),
+            "--width" => image_width = Some(parse_option(&arg, args.next())),
+            "--aspect-ratio" => aspect_ratio = Some(parse_option(&arg, args.next())),
+            "--samples" => samples_per_pixel = Some(parse_option(&arg, args.next())),
+            "--seed" => seed = Some(parse_option(&arg, args.next())),
+            "--checkpoint" => checkpoint_file = args.next(),
+            "--checkpoint-interval" => checkpoint_interval = parse_option(&arg, args.next()),
+            "--resume" => resume = true,
+            "--aovs" => write_aovs = true,
+            "--exr" => exr_file = args.next(),
+            "--exr-compression" => {
+                let name = args.next().unwrap_or_default();
+                exr_compression = Compression::from_name(&name).unwrap_or_else(|| {
+                    eprintln!("Unknown EXR compression: {} (expected none, rle, zips or zip)", name);
+                    std::process::exit(2);
+                });
+            }
+            "--exr-float" => exr_pixel_type = PixelType::Float,
+            "--denoise" => denoise = true,
+            "--exposure" => exposure = Some(parse_option(&arg, args.next())),
+            "--tonemap" => {
+                let name = args.next().unwrap_or_default();
+                tone_mapper = Some(ToneMapper::from_name(&name).unwrap_or_else(|| {
+                    eprintln!("Unknown tone mapper: {} (expected clamp, reinhard, aces or agx)", name);
+                    std::process::exit(2);
+                }));
+            }
+            "--white-balance" => white_balance = Some(parse_option(&arg, args.next())),
+            "--projection" => projection_name = args.next().unwrap_or_default(),
+            "--fisheye-fov" => fisheye_fov = parse_option(&arg, args.next()),
+            "--ortho-width" => ortho_width = parse_option(&arg, args.next()),
+            "--shutter-open" => shutter.0 = Some(parse_option(&arg, args.next())),
+            "--shutter-close" => shutter.1 = Some(parse_option(&arg, args.next())),
+            "--shutter-curve" => shutter_curve = parse_list(&arg, args.next(), None),
+            "--camera-keyframe" => camera_keyframes.push(parse_keyframe(&parse_list(&arg, args.next(), Some(7)))),
+            "--object-keyframe" => {
+                let values = parse_list(&arg, args.next(), Some(8));
+                object_keyframes.push((values[0] as usize, parse_keyframe(&values[1..])));
+            }
+            "--sensor-width" => physical.get_or_insert_with(PhysicalCamera::new).sensor_width = parse_option(&arg, args.next()),
+            "--sensor-height" => physical.get_or_insert_with(PhysicalCamera::new).sensor_height = parse_option(&arg, args.next()),
+            "--focal-length" => physical.get_or_insert_with(PhysicalCamera::new).focal_length = parse_option(&arg, args.next()),
+            "--f-number" => physical.get_or_insert_with(PhysicalCamera::new).f_number = parse_option(&arg, args.next()),
+            "--iso" => physical.get_or_insert_with(PhysicalCamera::new).iso = parse_option(&arg, args.next()),
+            "--shutter-seconds" => physical.get_or_insert_with(PhysicalCamera::new).shutter_seconds = parse_option(&arg, args.next()),
+            "--aperture-blades" => aperture_blades = parse_option(&arg, args.next()),
+            "--aperture-rotation" => aperture_rotation = parse_option(&arg, args.next()),
+            "--aperture-mask" => aperture_mask = args.next(),
+            "--cat-eye" => cat_eye = Some(parse_option(&arg, args.next())),
+            "--lens-file" => lens_file = args.next(),
+            "--film-diagonal" => film_diagonal = parse_option(&arg, args.next()),
+            "--lens-aperture" => lens_aperture = Some(parse_option(&arg, args.next())),
+            "--meters-per-unit" => meters_per_unit = Some(parse_option(&arg, args.next())),
+            "--stereo" => {
+                let name = args.next().unwrap_or_default();
+                stereo_mode = Some(StereoMode::from_name(&name).unwrap_or_else(|| {
+                    eprintln!("Unknown stereo mode: {} (expected parallel or converged)", name);
+                    std::process::exit(2);
+                }));
+            }
+            "--stereo-layout" => {
+                let name = args.next().unwrap_or_default();
+                stereo_layout = StereoLayout::from_name(&name).unwrap_or_else(|| {
+                    eprintln!("Unknown stereo layout: {} (expected side-by-side or top-bottom)", name);
+                    std::process::exit(2);
+                });
+            }
+            "--output" => output_file = args.next(),
+            "--frames" => {
+                let range = args.next().unwrap_or_default();
+                let parsed = range.split_once('-').and_then(|(first, last)| Some((first.parse().ok()?, last.parse().ok()?)));
+                frames = Some(parsed.unwrap_or_else(|| {
+                    eprintln!("Missing or invalid value for {} (expected FIRST-LAST)", arg);
+                    std::process::exit(2);
+                }));
+            }
+            "--key" => {
+                // --key NAME FRAME VALUE[,VALUE...] [linear|bezier]
+                let name = args.next().unwrap_or_default();
+                let frame: f64 = parse_option(&arg, args.next());
+                let value = parse_list(&arg, args.next(), None);
+                let interpolation = match args.peek().and_then(|next| Interpolation::from_name(next)) {
+                    Some(interpolation) => {
+                        args.next();
+                        interpolation
+                    }
+                    None => Interpolation::Linear,
+                };
+                animation_keys.push((name, frame, value, interpolation));
+            }
+            "--turntable" => turntable = true,
+            "--patches" => patch_files.push(parse_option(&arg, args.next())),
+            "--patch-tolerance" => patch_tolerance = parse_option(&arg, args.next()),
+            "--mesh" => mesh_files.push(parse_option(&arg, args.next())),
+            "--subdivide" => subdivision_levels = parse_option(&arg, args.next()),
+            "--heightfield" => heightfield_source = args.next(),
+            "--heightfield-size" => {
+                let values = parse_list(&arg, args.next(), Some(3));
+                heightfield_size = Vec3::new(values[0], values[1], values[2]);
+            }
+            "--heightfield-resolution" => heightfield_resolution = parse_option(&arg, args.next()),
+            "--strands" => strand_files.push(parse_option(&arg, args.next())),
+            "--curve-type" => {
+                let name = args.next().unwrap_or_default();
+                curve_type = CurveType::from_name(&name).unwrap_or_else(|| {
+                    eprintln!("Unknown curve type: {} (expected flat or cylinder)", name);
+                    std::process::exit(2);
+                });
+            }
+            "--hair-melanin" => {
+                let values = parse_list(&arg, args.next(), None);
+                if values.is_empty() || values.len() > 2 {
+                    eprintln!("Missing or invalid value for {}", arg);
+                    std::process::exit(2);
+                }
+                hair_melanin = (values[0], values.get(1).copied().unwrap_or(0.0));
+            }
+            "--hair-color" => {
+                let values = parse_list(&arg, args.next(), Some(3));
+                hair_color = Some(Color::new(values[0], values[1], values[2]));
+            }
+            "--hair-roughness" => {
+                let values = parse_list(&arg, args.next(), Some(2));
+                hair_roughness = (values[0], values[1]);
+            }
+            "--point-cloud" => point_cloud_files.push(parse_option(&arg, args.next())),
+            "--point-radius" => point_radius = parse_option(&arg, args.next()),
+            "--point-shape" => {
+                let name = args.next().unwrap_or_default();
+                point_shape = PointShape::from_name(&name).unwrap_or_else(|| {
+                    eprintln!("Unknown point shape: {} (expected sphere or disk)", name);
+                    std::process::exit(2);
+                });
+            }
+            "--gltf" => gltf_file = args.next(),
+            "--gltf-camera" => gltf_camera = parse_option(&arg, args.next()),
+            "--pbrt" => pbrt_file = args.next(),
+            "--scene" => scene_file = args.next(),
+            "--builtin" => {
+                let name = args.next().unwrap_or_default();
+                builtin = Some(BuiltinScene::from_name(&name).unwrap_or_else(|| {
+                    let names: Vec<&str> = BuiltinScene::ALL.iter().map(|scene| scene.name()).collect();
+                    eprintln!("Unknown built-in scene: {} (expected {})", name, names.join(", "));
+                    std::process::exit(2);
+                }));
+            }
+            "--save-scene" => save_scene_file = args.next(),
+            "--export-obj" => obj_file = args.next(),
+            "--model-placement" => {
+                // tx, ty, tz, rotation around x, y, z in degrees and a uniform scale
+                let values = parse_list(&arg, args.next(), Some(7));
+                let mut keyframe = parse_keyframe(&[&[0.0], &values[..6]].concat());
+                keyframe.scale = Vec3::new(values[6], values[6], values[6]);
+                model_placement = keyframe;
+            }
+            "--ipd" => interpupillary_distance = parse_option(&arg, args.next()),
+            "--exposure-compensation" => {
+                physical.get_or_insert_with(PhysicalCamera::new).exposure_compensation = parse_option(&arg, args.next())
+            }
+            _ => {
+                eprintln!("Unknown argument: {}", arg);
+                std::process::exit(2);
+            }
+        }
+    }
+
     // World
+    seed_random(seed.unwrap_or(0));
     let mut world: HittableList = HittableList::new();
+    // Material of each top-level object, for animation by object id.
+    let mut materials: Vec<Rc<RefCell<dyn material::MaterialTrait>>> = Vec::new();
+
+    let import_count = [&gltf_file, &pbrt_file, &scene_file].iter().filter(|file| file.is_some()).count() + usize::from(builtin.is_some());
+    if import_count > 1 {
+        eprintln!("Only one of --gltf, --pbrt, --scene and --builtin can be given");
+        std::process::exit(2);
+    }
+
+    // The book's cover with its diffuse spheres bouncing, unless a whole scene is imported.
+    if import_count == 0 {
+        let mut cover = Scene::new();
+        let material_ground: Rc<RefCell<dyn material::MaterialTrait>> = Rc::new(RefCell::new(Lambertian { albedo: Color::new(0.5, 0.5, 0.5) }));
+        cover.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, material_ground.clone(), Point3::new(0.0, 0.0, 0.0), false)), material_ground);
+        add_small_spheres(&mut cover, true);
+        materials.extend(cover.materials);
+        for object in cover.objects {
+            world.add(object);
+        }
+    }
 
-    let material_ground = Rc::new(RefCell::new(Lambertian { albedo: Color::new(0.5, 0.5, 0.5) }));
-
-    world.add(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, material_ground, Point3::new(0.0, 0.0, 0.0), false)));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_float();
-            let center = Point3::new(a as f64 + 0.9 * random_float(), 0.2, b as f64 + 0.9 * random_float());
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                let material: Rc<RefCell<dyn material::MaterialTrait>>;
-
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let albedo = Color::new(random_float(), random_float(), random_float());
-                    material = Rc::new(RefCell::new(Lambertian { albedo }));
-                    let center_1 = center + Vec3::new(0.0, random_float() * 0.5, 0.0);
-                    world.add(Box::new(Sphere::new(center, 0.2, material, center_1, true)));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let albedo = Color::new(random_float(), random_float(), random_float());
-                    let fuzz = random_float() * 0.5;
-                    material = Rc::new(RefCell::new(Metal { albedo, fuzz }));
-                    world.add(Box::new(Sphere::new(center, 0.2, material, Point3::new(0.0, 0.0, 0.0), false)));
-                } else {
-                    // glass
-                    material = Rc::new(RefCell::new(Dielectric { refraction_index: 1.5 }));
-                    world.add(Box::new(Sphere::new(center, 0.2, material, Point3::new(0.0, 0.0, 0.0), false)));
+    // Imported models: Bezier patches are tessellated and OBJ meshes subdivided into smooth
+    // triangle meshes, all placed by `--model-placement`. PLY and STL meshes are used as they are;
+    // models with vertex colors get a white surface for the colors to tint.
+    let model_material: Rc<RefCell<dyn material::MaterialTrait>> = Rc::new(RefCell::new(Lambertian { albedo: Color::new(0.75, 0.75, 0.7) }));
+    let white: Rc<RefCell<dyn material::MaterialTrait>> = Rc::new(RefCell::new(Lambertian { albedo: Color::new(1.0, 1.0, 1.0) }));
+    let mut models = Vec::new();
+    for path in &patch_files {
+        match load_patches(path) {
+            Ok(patches) => models.push(tessellate_patches(&patches, patch_tolerance)),
+            Err(error) => {
+                eprintln!("Cannot load patches: {}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+    for path in &mesh_files {
+        let extension = path.rsplit('.').next().unwrap_or_default().to_ascii_lowercase();
+        let loaded = match extension.as_str() {
+            "ply" => TriangleMesh::load_ply(path),
+            "stl" => TriangleMesh::load_stl(path),
+            _ => PolygonMesh::load_obj(path).map(|mesh| {
+                let mut triangles = catmull_clark(&mesh, subdivision_levels).triangulate();
+                if subdivision_levels > 0 {
+                    triangles.compute_smooth_normals();
                 }
+                triangles
+            }),
+        };
+        match loaded {
+            Ok(triangles) => models.push(triangles),
+            Err(error) => {
+                eprintln!("Cannot load mesh: {}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+    for mut model in models {
+        model.transform(&model_placement);
+        let material = if model.colors.is_empty() { model_material.clone() } else { white.clone() };
+        materials.push(material.clone());
+        world.add(Box::new(model.into_hittable(material)));
+    }
+
+    // Terrain from a grayscale image, or from fractal noise when the source is "noise".
+    if let Some(source) = &heightfield_source {
+        let soil: Rc<RefCell<dyn material::MaterialTrait>> = Rc::new(RefCell::new(Lambertian { albedo: Color::new(0.35, 0.45, 0.25) }));
+        let center = Point3::new(0.0, 0.0, 0.0);
+        let terrain = if source == "noise" {
+            Heightfield::from_noise(seed.unwrap_or(0), heightfield_resolution, 6.0, center, heightfield_size, soil.clone())
+        } else {
+            Image::load(source, true)
+                .map_err(|error| error.to_string())
+                .and_then(|image| Heightfield::from_image(&image, center, heightfield_size, soil.clone()).map_err(|error| format!("{}: {}", source, error)))
+        };
+        let terrain = terrain.unwrap_or_else(|error| {
+            eprintln!("Cannot build heightfield: {}", error);
+            std::process::exit(1);
+        });
+        materials.push(soil);
+        world.add(Box::new(terrain));
+    }
+
+    // Hair from strand files.
+    let hair: Rc<RefCell<dyn material::MaterialTrait>> = match hair_color {
+        Some(color) => Rc::new(RefCell::new(HairMaterial::from_color(color, hair_roughness.0, hair_roughness.1))),
+        None => Rc::new(RefCell::new(HairMaterial::from_melanin(hair_melanin.0, hair_melanin.1, hair_roughness.0, hair_roughness.1))),
+    };
+    for path in &strand_files {
+        match load_strands(path) {
+            Ok(strands) => {
+                let curves: Vec<Box<dyn hittable::Hittable>> = strands
+                    .iter()
+                    .flat_map(|strand| strand_curves(strand, curve_type, &hair))
+                    .map(|curve| Box::new(curve) as Box<dyn hittable::Hittable>)
+                    .collect();
+                materials.push(hair.clone());
+                world.add(Box::new(Bvh::new(curves)));
+            }
+            Err(error) => {
+                eprintln!("Cannot load strands: {}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+    // Point clouds, placed like the models, with each point's color tinting a white surface.
+    for path in &point_cloud_files {
+        match PointData::load_ply(path) {
+            Ok(mut points) => {
+                points.transform(&model_placement);
+                let radius = point_radius * model_placement.scale.x().abs();
+                materials.push(white.clone());
+                world.add(Box::new(PointCloud::new(points, radius, point_shape, white.clone())));
+            }
+            Err(error) => {
+                eprintln!("Cannot load point cloud: {}", error);
+                std::process::exit(1);
             }
         }
     }
 
+    // A glTF, PBRT, saved or built-in scene with its materials, lights and cameras, the first
+    // camera (or the one chosen with --gltf-camera) giving the view. Render settings from the
+    // scene apply unless given on the command line.
+    let imported = match (&gltf_file, &pbrt_file, &scene_file, builtin) {
+        (Some(path), _, _, _) => Some((path.as_str(), "glTF", load_gltf(path))),
+        (_, Some(path), _, _) => Some((path.as_str(), "PBRT", load_pbrt(path))),
+        (_, _, Some(path), _) => Some((path.as_str(), "saved", load_scene(path))),
+        (_, _, _, Some(builtin)) => Some((builtin.name(), "built-in", Ok(builtin.build(seed.unwrap_or(0))))),
+        _ => None,
+    };
+    let mut lights = Vec::new();
+    let mut scene_camera = None;
+    let mut background = None;
+    let mut scene_max_depth = None;
+    let mut scene_aperture = None;
+    let mut scene_stereo = None;
+    if let Some((path, format, result)) = imported {
+        match result {
+            Ok(scene) => {
+                if !scene.views.is_empty() && gltf_camera >= scene.views.len() {
+                    eprintln!("No camera {} in {} (expected 0 to {})", gltf_camera, path, scene.views.len() - 1);
+                    std::process::exit(2);
+                }
+                scene_camera = scene.views.get(gltf_camera).copied();
+                lights = scene.lights;
+                background = scene.background;
+                scene_max_depth = scene.max_depth;
+                image_width = image_width.or(scene.image_width);
+                samples_per_pixel = samples_per_pixel.or(scene.samples_per_pixel);
+                sampler_name = sampler_name.or(scene.sampler_name);
+                seed = seed.or(scene.seed);
+                if let Some((open, close)) = scene.shutter {
+                    shutter = (shutter.0.or(Some(open)), shutter.1.or(Some(close)));
+                }
+                if shutter_curve.is_empty() {
+                    shutter_curve = scene.shutter_curve;
+                }
+                if camera_keyframes.is_empty() {
+                    camera_keyframes = scene.camera_keyframes;
+                }
+                if let Some(post_process) = scene.post_process {
+                    exposure = exposure.or(Some(post_process.exposure));
+                    tone_mapper = tone_mapper.or(Some(post_process.tone_mapper));
+                    white_balance = white_balance.or(post_process.white_balance);
+                }
+                // Physical settings on the command line replace the scene's as a whole.
+                physical = physical.or(scene.physical);
+                cat_eye = cat_eye.or(scene.cat_eye);
+                scene_aperture = scene.aperture;
+                scene_stereo = scene.stereo;
+                materials.extend(scene.materials);
+                for object in scene.objects {
+                    world.add(object);
+                }
+            }
+            Err(error) => {
+                eprintln!("Cannot load {} scene: {}", format, error);
+                std::process::exit(1);
+            }
+        }
+    }
+    let image_width = image_width.unwrap_or(400.0);
+    let samples_per_pixel = samples_per_pixel.unwrap_or(100);
+    let sampler_name = sampler_name.unwrap_or_else(|| String::from("sobol"));
+    let seed = seed.unwrap_or(0);
+    let shutter = (shutter.0.unwrap_or(0.0), shutter.1.unwrap_or(1.0));
 
     // Image
-    const ASPECT_RATIO: f64 = 16.0 / 9.0;
-    const IMAGE_WIDTH: f64 = 400f64;
-    const SAMPLES_PER_PIXEL: i32 = 100;
     const MAX_DEPTH: i32 = 50;
-    const VFOV: f64 = 20.0;
+    let mut vfov: f64 = 20.0;
 
-    const DEFOCUS_ANGLE: f64 = 1.0;
-    const FOCUS_DISTANCE: f64 = 10.0;
+    let mut defocus_angle: f64 = 1.0;
+    let mut focus_distance: f64 = 10.0;
 
-    let look_from: Point3 = Point3::new(13.0, 2.0, 3.0);
-    let look_at: Point3 = Point3::new(0.0, 0.0, 0.0);
-    let vup: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+    let mut look_from: Point3 = Point3::new(13.0, 2.0, 3.0);
+    let mut look_at: Point3 = Point3::new(0.0, 0.0, 0.0);
+    let mut vup: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+
+    // An imported camera replaces the view and its focus; --aspect-ratio and --projection still
+    // override it.
+    if let Some(view) = scene_camera {
+        (look_from, look_at, vup, vfov) = (view.look_from, view.look_at, view.vup, view.vfov);
+        defocus_angle = view.defocus_angle;
+        focus_distance = view.focus_distance.unwrap_or(focus_distance);
+        aspect_ratio = aspect_ratio.or(view.aspect_ratio);
+    }
 
     // Camera
     let mut camera = Camera::new(
-        ASPECT_RATIO, IMAGE_WIDTH,
-        SAMPLES_PER_PIXEL,
-        MAX_DEPTH,
-        VFOV,
+        aspect_ratio.unwrap_or(16.0 / 9.0), image_width,
+        samples_per_pixel,
+        scene_max_depth.unwrap_or(MAX_DEPTH),
+        vfov,
         look_from,
         look_at,
         vup,
-        DEFOCUS_ANGLE,
-        FOCUS_DISTANCE,
+        defocus_angle,
+        focus_distance,
     );
+    camera.checkpoint_file = checkpoint_file;
+    camera.checkpoint_interval = checkpoint_interval;
+    camera.resume = resume;
+    camera.write_aovs = write_aovs;
+    camera.exr_file = exr_file;
+    camera.exr_compression = exr_compression;
+    camera.exr_pixel_type = exr_pixel_type;
+    camera.denoise = denoise;
+    camera.post_process.exposure = exposure.unwrap_or(0.0);
+    camera.post_process.tone_mapper = tone_mapper.unwrap_or(ToneMapper::Clamp);
+    camera.post_process.white_balance = white_balance;
+    camera.shutter_open = shutter.0;
+    camera.shutter_close = shutter.1;
+    camera.shutter_curve = ShutterCurve::new(shutter_curve);
+    if !camera_keyframes.is_empty() {
+        camera.camera_motion = Some(AnimatedTransform::new(camera_keyframes));
+    }
+    camera.physical = physical.map(|physical| PhysicalCamera { meters_per_unit: meters_per_unit.unwrap_or(physical.meters_per_unit), ..physical });
+    camera.cat_eye = cat_eye.unwrap_or(0.0);
+    camera.stereo = stereo_mode.map(|mode| Stereo { mode, layout: stereo_layout, interpupillary_distance }).or(scene_stereo);
+    if let Some(path) = aperture_mask {
+        match ApertureMask::load(&path) {
+            Ok(mask) => camera.aperture = Aperture::Mask(mask),
+            Err(error) => {
+                eprintln!("Cannot load aperture mask: {}", error);
+                std::process::exit(1);
+            }
+        }
+    } else if aperture_blades > 0 {
+        camera.aperture = Aperture::Polygon { blades: aperture_blades, rotation: aperture_rotation };
+    } else if let Some(aperture) = scene_aperture {
+        camera.aperture = aperture;
+    }
+    if let Some(path) = lens_file {
+        match LensSystem::load(&path, film_diagonal, lens_aperture, meters_per_unit.unwrap_or(1.0)) {
+            Ok(lens_system) => camera.lens_system = Some(lens_system),
+            Err(error) => {
+                eprintln!("Cannot load lens file: {}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+    camera.projection = Projection::from_name(&projection_name, fisheye_fov, ortho_width).unwrap_or_else(|| {
+        eprintln!(
+            "Unknown projection: {} (expected perspective, orthographic, fisheye, equisolid or equirectangular)",
+            projection_name
+        );
+        std::process::exit(2);
+    });
+    if let Some(view) = scene_camera {
+        if projection_name == "perspective" {
+            camera.projection = view.projection;
+        }
+    }
+    camera.lights = lights;
+    camera.background = background;
+
+    match sampler_from_name(&sampler_name, samples_per_pixel, seed) {
+        Some(sampler) => camera.sampler = sampler,
+        None => {
+            eprintln!("Unknown sampler: {} (expected independent, stratified, halton or sobol)", sampler_name);
+            std::process::exit(2);
+        }
+    }
+
+    // Animation: keyed parameters over a frame range, rendered to numbered frames.
+    let animation = if frames.is_some() || turntable || !animation_keys.is_empty() {
+        let (first_frame, last_frame) = frames.unwrap_or((1, 1));
+        let mut animation = Animation::new(first_frame, last_frame);
+        animation.turntable = turntable;
+        for (name, frame, value, interpolation) in animation_keys {
+            if let Err(error) = animation.add_key(&name, frame, value, interpolation) {
+                eprintln!("Invalid animation key: {}", error);
+                std::process::exit(2);
+            }
+        }
+        Some(animation)
+    } else {
+        None
+    };
+
+    // Objects given keyframes move around the center of their bounding box. Ids are those of the
+    // object_id output, which start at 1. Objects with an animated translation get a transform
+    // for every frame, swapped in as the frames are rendered.
+    let mut objects = world.into_objects();
+    let moving = animation.as_ref().map_or(Vec::new(), |animation| animation.moving_objects());
+    let mut frame_transforms: Vec<(Rc<RefCell<AnimatedTransform>>, Vec<AnimatedTransform>)> = Vec::new();
+    for id in 1..=objects.len() {
+        let keyframes: Vec<Keyframe> = object_keyframes.iter().filter(|(object, _)| *object == id).map(|(_, keyframe)| *keyframe).collect();
+        let animation = animation.as_ref().filter(|_| moving.contains(&id));
+        if keyframes.is_empty() && animation.is_none() {
+            continue;
+        }
+        let object = objects.remove(id - 1);
+        let bbox = object.bounding_box();
+        let pivot = 0.5 * (bbox.min() + bbox.max());
+        let instance = match animation {
+            Some(animation) => {
+                let transforms: Vec<AnimatedTransform> = (animation.first_frame..=animation.last_frame)
+                    .map(|frame| {
+                        let track = animation.object_keyframes(frame, shutter).into_iter().filter(|(object, _)| *object == id).map(|(_, keyframe)| keyframe);
+                        AnimatedTransform::new(keyframes.iter().copied().chain(track).collect())
+                    })
+                    .collect();
+                let transform = Rc::new(RefCell::new(transforms[0].clone()));
+                let instance = Instance::shared(object, Rc::clone(&transform), &transforms, pivot);
+                frame_transforms.push((transform, transforms));
+                instance
+            }
+            None => Instance::new(object, AnimatedTransform::new(keyframes), pivot),
+        };
+        objects.insert(id - 1, Box::new(instance));
+    }
+    let unknown_id = object_keyframes.iter().map(|(id, _)| *id).chain(moving.iter().copied()).find(|&id| id == 0 || id > objects.len());
+    if let Some(id) = unknown_id {
+        eprintln!("No object with id {} (expected 1 to {})", id, objects.len());
+        std::process::exit(2);
+    }
+
+    // The finished scene, to reload with --scene or to open in other tools.
+    if let Some(path) = &save_scene_file {
+        if let Err(error) = save_scene(path, &objects, &materials, &camera, &sampler_name, seed) {
+            eprintln!("Cannot save scene: {}", error);
+            std::process::exit(1);
+        }
+    }
+    if let Some(path) = &obj_file {
+        if let Err(error) = export_obj(path, &objects, &materials) {
+            eprintln!("Cannot export OBJ: {}", error);
+            std::process::exit(1);
+        }
+    }
+    let world = Bvh::new(objects);
+
+    let Some(animation) = animation else {
+        camera.output_file = output_file.unwrap_or_else(|| String::from("output.ppm"));
+        if let Err(error) = camera.render(&world) {
+            eprintln!("Render failed: {}", error);
+            std::process::exit(1);
+        }
+        return;
+    };
+
+    // Each frame starts from the scene's camera and gets its own sample pattern; '#' in the output
+    // paths is replaced by the frame number.
+    let output_pattern = output_file.unwrap_or_else(|| String::from("frame_####.png"));
+    let checkpoint_pattern = camera.checkpoint_file.clone();
+    let exr_pattern = camera.exr_file.clone();
+    for frame in animation.first_frame..=animation.last_frame {
+        eprintln!("Frame {}", frame);
+        camera.look_from = look_from;
+        camera.look_at = look_at;
+        camera.vfov = vfov;
+        camera.focus_distance = focus_distance;
+        animation.apply_camera(&mut camera, frame, shutter);
+        for (transform, transforms) in &frame_transforms {
+            *transform.borrow_mut() = transforms[(frame - animation.first_frame) as usize].clone();
+        }
+        if let Err(error) = animation.apply_materials(&materials, frame, shutter) {
+            eprintln!("Invalid animation key: {}", error);
+            std::process::exit(2);
+        }
+
+        camera.output_file = frame_path(&output_pattern, frame);
+        camera.checkpoint_file = checkpoint_pattern.as_deref().map(|pattern| frame_path(pattern, frame));
+        camera.exr_file = exr_pattern.as_deref().map(|pattern| frame_path(pattern, frame));
+        camera.sampler.set_seed(hash_values(&[seed, frame as u64]));
 
-    let _ = camera.render(&world);
+        if let Err(error) = camera.render(&world) {
+            eprintln!("Render failed: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Parses a comma separated list of numbers, optionally of an exact length.
+fn parse_list(name: &str, value: Option<String>, length: Option<usize>) -> Vec<f64> {
+    let values: Option<Vec<f64>> = value.as_deref().and_then(|text| text.split(',').map(|v| v.trim().parse().ok()).collect());
+    match values {
+        Some(values) if length.is_none_or(|length| values.len() == length) => values,
+        _ => {
+            eprintln!("Missing or invalid value for {}", name);
+            std::process::exit(2);
+        }
+    }
+}
+
+// Builds a keyframe from time, translation x, y, z and rotation around x, y, z in degrees.
+fn parse_keyframe(values: &[f64]) -> Keyframe {
+    Keyframe::new(
+        values[0],
+        Vec3::new(values[1], values[2], values[3]),
+        Quaternion::from_euler(values[4], values[5], values[6]),
+        Vec3::new(1.0, 1.0, 1.0),
+    )
+}
+
+fn parse_option<T: std::str::FromStr>(name: &str, value: Option<String>) -> T {
+    match value.as_deref().map(str::parse) {
+        Some(Ok(parsed)) => parsed,
+        _ => {
+            eprintln!("Missing or invalid value for {}", name);
+            std::process::exit(2);
+        }
+    }
 }
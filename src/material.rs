@@ -1,11 +1,53 @@
+use std::rc::Rc;
+
 use crate::color::Color;
 use crate::hittable::HitRecord;
+use crate::json::Json;
 use crate::ray::Ray;
-use crate::utils::random_float;
-use crate::vec3::{dot, random_unit_vector, reflect, refract, unit_vector};
+use crate::sampler::Sampler;
+use crate::scene_file::SceneWriter;
+use crate::texture::Texture;
+use crate::utils::PI;
+use crate::vec3::{dot, reflect, refract, sample_unit_vector, unit_vector, Vec3};
 
 pub(crate) trait MaterialTrait  {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray)>;
+    // Every scatter draws at most one 1D and one 2D sample from `sampler`.
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)>;
+
+    // Surface color at the hit, as written to the albedo output variable.
+    fn albedo(&self, hit_record: &HitRecord) -> Color;
+
+    // Light given off at the hit towards the incoming ray.
+    fn emitted(&self, _hit_record: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Fraction of the light arriving from the unit `direction` that leaves along the reversed
+    // incoming ray, cosine included, for lighting by punctual lights. Materials that only
+    // reflect in a few exact directions return black.
+    fn evaluate(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Sets a named parameter for animation. Returns false if the material has no such parameter
+    // or it takes a different number of values.
+    fn set_parameter(&mut self, _name: &str, _value: &[f64]) -> bool {
+        false
+    }
+
+    // Description of the material for a scene file, or None if it cannot be written down.
+    fn save(&self, _scene: &mut SceneWriter) -> Option<Json> {
+        None
+    }
+}
+
+fn set_color(color: &mut Color, value: &[f64]) -> bool {
+    match value {
+        [r, g, b] => *color = Color::new(*r, *g, *b),
+        [gray] => *color = Color::new(*gray, *gray, *gray),
+        _ => return false,
+    }
+    true
 }
 
 pub struct Lambertian {
@@ -13,8 +55,8 @@ pub struct Lambertian {
 }
 
 impl MaterialTrait for Lambertian {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction = rec.normal + random_unit_vector();
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let mut scatter_direction = rec.normal + sample_unit_vector(sampler.get_2d());
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
@@ -22,6 +64,52 @@ impl MaterialTrait for Lambertian {
         let attenuation = self.albedo;
         Some((attenuation, scattered))
     }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn evaluate(&self, _ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Color {
+        dot(rec.normal, direction).max(0.0) / PI * self.albedo
+    }
+
+    fn set_parameter(&mut self, name: &str, value: &[f64]) -> bool {
+        match name {
+            "albedo" => set_color(&mut self.albedo, value),
+            _ => false,
+        }
+    }
+
+    fn save(&self, _scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![("type", "lambertian".into()), ("albedo", self.albedo.into())]))
+    }
+}
+
+// A Lambertian surface whose color comes from a texture.
+pub(crate) struct TexturedLambertian {
+    pub(crate) texture: Rc<Texture>,
+}
+
+impl MaterialTrait for TexturedLambertian {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let mut scatter_direction = rec.normal + sample_unit_vector(sampler.get_2d());
+        if scatter_direction.near_zero() {
+            scatter_direction = rec.normal;
+        }
+        Some((self.albedo(rec), Ray::new(rec.point, scatter_direction, ray_in.time())))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.texture.value(rec.u, rec.v, rec.point)
+    }
+
+    fn evaluate(&self, _ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Color {
+        dot(rec.normal, direction).max(0.0) / PI * self.albedo(rec)
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![("type", "textured_lambertian".into()), ("texture", self.texture.save(scene))]))
+    }
 }
 
 pub struct Metal {
@@ -30,12 +118,29 @@ pub struct Metal {
 }
 
 impl MaterialTrait for Metal {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
         let reflected = reflect(unit_vector(ray_in.direction()), hit_record.normal);
-        let scattered = Ray::new(hit_record.point, reflected + self.fuzz * random_unit_vector(), ray_in.time());
+        let scattered = Ray::new(hit_record.point, reflected + self.fuzz * sample_unit_vector(sampler.get_2d()), ray_in.time());
         let attenuation = self.albedo;
         Some((attenuation, scattered))
     }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn set_parameter(&mut self, name: &str, value: &[f64]) -> bool {
+        match (name, value) {
+            ("albedo", _) => return set_color(&mut self.albedo, value),
+            ("fuzz", [fuzz]) => self.fuzz = *fuzz,
+            _ => return false,
+        }
+        true
+    }
+
+    fn save(&self, _scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![("type", "metal".into()), ("albedo", self.albedo.into()), ("fuzz", self.fuzz.into())]))
+    }
 }
 
 pub struct Dielectric {
@@ -43,7 +148,7 @@ pub struct Dielectric {
 }
 
 impl MaterialTrait for Dielectric {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
         let refraction_ratio = if hit_record.front_face {
             1.0 / self.refraction_index
         } else {
@@ -56,18 +161,99 @@ impl MaterialTrait for Dielectric {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction;
-
-        if cannot_refract || reflectance(cos_theta, refraction_ratio) > random_float() {
-            direction = reflect(unit_direction, hit_record.normal);
+        let direction = if cannot_refract || reflectance(cos_theta, refraction_ratio) > sampler.get_1d() {
+            reflect(unit_direction, hit_record.normal)
         } else {
-            direction = refract(unit_direction, hit_record.normal, refraction_ratio);
-        }
+            refract(unit_direction, hit_record.normal, refraction_ratio)
+        };
 
         let scattered = Ray::new(hit_record.point, direction, ray_in.time());
 
         Some((Color::new(1.0, 1.0, 1.0), scattered))
     }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    fn set_parameter(&mut self, name: &str, value: &[f64]) -> bool {
+        match (name, value) {
+            ("refraction_index", [index]) => self.refraction_index = *index,
+            _ => return false,
+        }
+        true
+    }
+
+    fn save(&self, _scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![("type", "dielectric".into()), ("refraction_index", self.refraction_index.into())]))
+    }
+}
+
+// A surface that gives off light and reflects none, the emitter of an area light. One-sided
+// lights shine only on the side their normal faces.
+pub(crate) struct DiffuseLight {
+    pub(crate) emit: Color,
+    pub(crate) two_sided: bool,
+}
+
+impl MaterialTrait for DiffuseLight {
+    fn scatter(&self, _ray_in: &Ray, _hit_record: &HitRecord, _sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        self.emit
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        if hit_record.front_face || self.two_sided {
+            self.emit
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: &[f64]) -> bool {
+        match name {
+            "emit" => set_color(&mut self.emit, value),
+            _ => false,
+        }
+    }
+
+    fn save(&self, _scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![("type", "diffuse_light".into()), ("emit", self.emit.into()), ("two_sided", self.two_sided.into())]))
+    }
+}
+
+// Scatters equally in every direction: the phase function of smoke and fog in a
+// `ConstantMedium`.
+pub(crate) struct Isotropic {
+    pub(crate) albedo: Color,
+}
+
+impl MaterialTrait for Isotropic {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        Some((self.albedo, Ray::new(hit_record.point, sample_unit_vector(sampler.get_2d()), ray_in.time())))
+    }
+
+    fn albedo(&self, _hit_record: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn evaluate(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> Color {
+        self.albedo / (4.0 * PI)
+    }
+
+    fn set_parameter(&mut self, name: &str, value: &[f64]) -> bool {
+        match name {
+            "albedo" => set_color(&mut self.albedo, value),
+            _ => false,
+        }
+    }
+
+    fn save(&self, _scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![("type", "isotropic".into()), ("albedo", self.albedo.into())]))
+    }
 }
 
 fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SceneHasher};
+use crate::interval::Interval;
+use crate::json::Json;
+use crate::material::MaterialTrait;
+use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
+use crate::utils::hash_values;
+use crate::vec3::Vec3;
+
+// Smoke, fog or mist of constant density filling a closed boundary (one that reports `spans`).
+// A ray scatters inside after an exponentially distributed distance, off the phase function,
+// usually an `Isotropic` material. Hits take no sampler, so the distance is drawn from a hash of
+// the ray, which keeps renders repeatable.
+pub(crate) struct ConstantMedium {
+    boundary: Box<dyn Hittable>,
+    density: f64,
+    phase_function: Rc<RefCell<dyn MaterialTrait>>,
+}
+
+impl ConstantMedium {
+    pub(crate) fn new(boundary: Box<dyn Hittable>, density: f64, phase_function: Rc<RefCell<dyn MaterialTrait>>) -> Self {
+        ConstantMedium { boundary, density, phase_function }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, interval: Interval, record: &mut HitRecord) -> bool {
+        if !self.boundary.bounding_box().hit(ray, interval) {
+            return false;
+        }
+        let Some(spans) = self.boundary.spans(ray) else {
+            return false;
+        };
+
+        let (origin, direction) = (ray.origin(), ray.direction());
+        let hash = hash_values(&[
+            origin.x().to_bits(),
+            origin.y().to_bits(),
+            origin.z().to_bits(),
+            direction.x().to_bits(),
+            direction.y().to_bits(),
+            direction.z().to_bits(),
+            ray.time().to_bits(),
+        ]);
+        let uniform = 1.0 - (hash >> 11) as f64 / (1u64 << 53) as f64;
+        // The distance is memoryless, so what is left of it carries over from one span to the next.
+        let mut remaining = -uniform.ln() / self.density;
+        let length = direction.length();
+        for span in spans {
+            let (enter, exit) = (span.enter.t.max(interval.min), span.exit.t.min(interval.max));
+            if enter >= exit {
+                continue;
+            }
+            let inside = (exit - enter) * length;
+            if remaining >= inside {
+                remaining -= inside;
+                continue;
+            }
+
+            let t = enter + remaining / length;
+            // Any normal does for a phase function.
+            *record = HitRecord::new(ray.at(t), Vec3::new(1.0, 0.0, 0.0), t, true);
+            record.set_material(&self.phase_function);
+            return true;
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![
+            ("type", "medium".into()),
+            ("boundary", self.boundary.save(scene)?),
+            ("density", self.density.into()),
+            ("material", scene.material(&self.phase_function)),
+        ]))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add(self.density.to_bits());
+        hasher.add_material(&self.phase_function);
+        self.boundary.hash(hasher);
+    }
+}
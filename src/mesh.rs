@@ -0,0 +1,329 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::color::Color;
+use crate::hittable::{HitRecord, Hittable, SceneHasher};
+use crate::interval::Interval;
+use crate::json::Json;
+use crate::material::MaterialTrait;
+use crate::ply::Ply;
+use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
+use crate::transform::Keyframe;
+use crate::vec3::{cross, dot, unit_vector, Point3, Vec3};
+
+pub(crate) fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Triangles sharing vertices. Normals, surface coordinates and colors are per vertex and
+// interpolated across each triangle; any of them may be empty. Colors tint the material.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TriangleMesh {
+    pub(crate) positions: Vec<Point3>,
+    pub(crate) normals: Vec<Vec3>,
+    pub(crate) uvs: Vec<(f64, f64)>,
+    pub(crate) colors: Vec<Color>,
+    pub(crate) triangles: Vec<[usize; 3]>,
+}
+
+impl TriangleMesh {
+    // Sets each vertex normal to the average of the normals of the triangles around it, weighted by
+    // their areas, which smooths over the facets.
+    pub(crate) fn compute_smooth_normals(&mut self) {
+        let mut normals = vec![Vec3::new(0.0, 0.0, 0.0); self.positions.len()];
+        for &[a, b, c] in &self.triangles {
+            // Twice the area along the face normal
+            let face = cross(self.positions[b] - self.positions[a], self.positions[c] - self.positions[a]);
+            for vertex in [a, b, c] {
+                normals[vertex] = normals[vertex] + face;
+            }
+        }
+        self.normals = normals
+            .into_iter()
+            .map(|n| if n.length_squared() > 0.0 { unit_vector(n) } else { n })
+            .collect();
+    }
+
+    // Bakes a transform into the vertices.
+    pub(crate) fn transform(&mut self, keyframe: &Keyframe) {
+        for p in &mut self.positions {
+            *p = keyframe.apply_point(*p);
+        }
+        for n in &mut self.normals {
+            if n.length_squared() > 0.0 {
+                *n = unit_vector(keyframe.apply_normal(*n));
+            }
+        }
+    }
+
+    // Reads a PLY mesh: vertices with x, y and z, and optionally normals (nx, ny and nz), surface
+    // coordinates (u and v, s and t, or texture_u and texture_v) and colors, and faces with
+    // vertex_indices (or vertex_index) lists. Faces with more than three vertices are split into
+    // fans.
+    pub(crate) fn load_ply(path: &str) -> io::Result<Self> {
+        let ply = Ply::load(path)?;
+        let error = |what: &str| invalid_data(format!("{}: {}", path, what));
+        let vertices = ply.element("vertex").ok_or_else(|| error("no vertex element"))?;
+        let mut mesh = TriangleMesh {
+            positions: vertices.vectors(["x", "y", "z"]).ok_or_else(|| error("vertices have no x, y and z"))?,
+            normals: vertices.vectors(["nx", "ny", "nz"]).unwrap_or_default(),
+            colors: vertices.colors().unwrap_or_default(),
+            ..TriangleMesh::default()
+        };
+        if let Some((u, v)) = [("u", "v"), ("s", "t"), ("texture_u", "texture_v")]
+            .iter()
+            .find_map(|&(u, v)| Some((vertices.scalar(u)?, vertices.scalar(v)?)))
+        {
+            mesh.uvs = u.iter().copied().zip(v.iter().copied()).collect();
+        }
+
+        let faces = ply.element("face").ok_or_else(|| error("no face element"))?;
+        let faces = faces.lists("vertex_indices").or_else(|| faces.lists("vertex_index")).ok_or_else(|| error("faces have no vertex_indices"))?;
+        for (number, face) in faces.iter().enumerate() {
+            if face.len() < 3 {
+                return Err(error(&format!("face {} has fewer than three vertices", number)));
+            }
+            if let Some(&index) = face.iter().find(|&&index| index < 0.0 || index as usize >= mesh.positions.len()) {
+                return Err(error(&format!("face {} has vertex index {} out of range", number, index)));
+            }
+            mesh.triangles.extend((1..face.len() - 1).map(|i| [face[0] as usize, face[i] as usize, face[i + 1] as usize]));
+        }
+        Ok(mesh)
+    }
+
+    // Reads an STL file, ASCII or binary. STL stores each triangle on its own, so vertices at the
+    // same position are merged; the facet normals are left out since they are those of the faces.
+    pub(crate) fn load_stl(path: &str) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let mut mesh = TriangleMesh::default();
+        let mut indices: HashMap<[u64; 3], usize> = HashMap::new();
+        let mut vertex = |p: Point3| {
+            // Adding zero turns -0 into 0, so both merge.
+            let key = [(p.x() + 0.0).to_bits(), (p.y() + 0.0).to_bits(), (p.z() + 0.0).to_bits()];
+            *indices.entry(key).or_insert_with(|| {
+                mesh.positions.push(p);
+                mesh.positions.len() - 1
+            })
+        };
+        let mut triangles = Vec::new();
+
+        // A binary file is an 80 byte header, a triangle count and 50 bytes per triangle. Its
+        // header may start with "solid" too, so the size decides.
+        let count = data.get(80..84).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize);
+        if count.is_some_and(|count| data.len() == 84 + 50 * count) {
+            for record in data[84..].chunks_exact(50) {
+                let float = |i: usize| f32::from_le_bytes(record[4 * i..4 * i + 4].try_into().unwrap()) as f64;
+                // The first three floats are the facet normal.
+                let corner = |k: usize| Point3::new(float(3 + 3 * k), float(4 + 3 * k), float(5 + 3 * k));
+                triangles.push([vertex(corner(0)), vertex(corner(1)), vertex(corner(2))]);
+            }
+        } else if data.starts_with(b"solid") {
+            let text = String::from_utf8_lossy(&data);
+            let mut corners = Vec::with_capacity(3);
+            for (line_number, line) in text.lines().enumerate() {
+                let error = |what: &str| invalid_data(format!("{}:{}: {}", path, line_number + 1, what));
+                let mut fields = line.split_whitespace();
+                match fields.next() {
+                    Some("vertex") => {
+                        let values: Vec<f64> = fields
+                            .map(|field| field.parse::<f64>())
+                            .collect::<Result<_, _>>()
+                            .map_err(|_| error("expected vertex coordinates"))?;
+                        if values.len() != 3 {
+                            return Err(error("expected three vertex coordinates"));
+                        }
+                        corners.push(vertex(Point3::new(values[0], values[1], values[2])));
+                    }
+                    Some("endloop") => {
+                        if corners.len() != 3 {
+                            return Err(error("facets need exactly three vertices"));
+                        }
+                        triangles.push([corners[0], corners[1], corners[2]]);
+                        corners.clear();
+                    }
+                    _ => {}
+                }
+            }
+            if !corners.is_empty() {
+                return Err(invalid_data(format!("{}: last facet is not closed", path)));
+            }
+        } else {
+            let size = count.map_or(String::from("too short"), |count| format!("{} bytes where {} triangles need {}", data.len(), count, 84 + 50 * count));
+            return Err(invalid_data(format!("{}: neither ASCII STL nor binary STL ({})", path, size)));
+        }
+
+        // Skip facets that collapsed to a line or a point.
+        mesh.triangles = triangles.into_iter().filter(|&[a, b, c]| a != b && b != c && a != c).collect();
+        Ok(mesh)
+    }
+
+    // Turns the mesh into a hierarchy of triangles that all use `material_ptr`.
+    pub(crate) fn into_hittable(self, material_ptr: Rc<RefCell<dyn MaterialTrait>>) -> Bvh {
+        TriangleMesh::shared_hittable(&Rc::new(self), material_ptr)
+    }
+
+    // The same for a mesh that other objects may also use.
+    pub(crate) fn shared_hittable(mesh: &Rc<TriangleMesh>, material_ptr: Rc<RefCell<dyn MaterialTrait>>) -> Bvh {
+        let triangles: Vec<Box<dyn Hittable>> = (0..mesh.triangles.len())
+            .map(|index| Box::new(Triangle::new(Rc::clone(mesh), index, Rc::clone(&material_ptr))) as Box<dyn Hittable>)
+            .collect();
+        Bvh::new(triangles)
+    }
+}
+
+// One triangle of a mesh.
+pub(crate) struct Triangle {
+    mesh: Rc<TriangleMesh>,
+    index: usize,
+    material_ptr: Rc<RefCell<dyn MaterialTrait>>,
+}
+
+impl Triangle {
+    pub(crate) fn new(mesh: Rc<TriangleMesh>, index: usize, material_ptr: Rc<RefCell<dyn MaterialTrait>>) -> Self {
+        Triangle { mesh, index, material_ptr }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, ray_t: Interval, record: &mut HitRecord) -> bool {
+        // Möller-Trumbore: solve for the distance and barycentric coordinates at once.
+        let [a, b, c] = self.mesh.triangles[self.index];
+        let (p0, p1, p2) = (self.mesh.positions[a], self.mesh.positions[b], self.mesh.positions[c]);
+        let edge_1 = p1 - p0;
+        let edge_2 = p2 - p0;
+        let p = cross(ray.direction(), edge_2);
+        let determinant = dot(edge_1, p);
+        if determinant.abs() < 1e-12 {
+            return false;
+        }
+        let inverse = 1.0 / determinant;
+        let s = ray.origin() - p0;
+        let b1 = dot(s, p) * inverse;
+        if !(0.0..=1.0).contains(&b1) {
+            return false;
+        }
+        let q = cross(s, edge_1);
+        let b2 = dot(ray.direction(), q) * inverse;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return false;
+        }
+        let t = dot(edge_2, q) * inverse;
+        if !ray_t.surrounds(t) {
+            return false;
+        }
+        let b0 = 1.0 - b1 - b2;
+
+        record.t = t;
+        record.point = ray.at(t);
+        // The geometric normal decides which side was hit; the interpolated normal is turned to
+        // the same side so shading never looks through the surface.
+        let geometric = unit_vector(cross(edge_1, edge_2));
+        record.set_face_normal(ray, geometric);
+        if !self.mesh.normals.is_empty() {
+            let shading = b0 * self.mesh.normals[a] + b1 * self.mesh.normals[b] + b2 * self.mesh.normals[c];
+            if shading.length_squared() > 0.0 {
+                let shading = unit_vector(shading);
+                record.normal = if dot(shading, record.normal) < 0.0 { -shading } else { shading };
+            }
+        }
+        record.set_material(&self.material_ptr);
+        (record.u, record.v) = if self.mesh.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let (uv0, uv1, uv2) = (self.mesh.uvs[a], self.mesh.uvs[b], self.mesh.uvs[c]);
+            (b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0, b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1)
+        };
+        if !self.mesh.colors.is_empty() {
+            record.tint = Some(b0 * self.mesh.colors[a] + b1 * self.mesh.colors[b] + b2 * self.mesh.colors[c]);
+        }
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [a, b, c] = self.mesh.triangles[self.index];
+        Aabb::from_points(self.mesh.positions[a], self.mesh.positions[b]).include(self.mesh.positions[c])
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![
+            ("type", "triangle".into()),
+            ("mesh", scene.mesh(&self.mesh)),
+            ("index", self.index.into()),
+            ("material", scene.material(&self.material_ptr)),
+        ]))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add_box(&self.bounding_box());
+        hasher.add_material(&self.material_ptr);
+    }
+}
+
+// Faces with any number of vertices, as read from a modeling package.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PolygonMesh {
+    pub(crate) positions: Vec<Point3>,
+    pub(crate) faces: Vec<Vec<usize>>,
+}
+
+impl PolygonMesh {
+    // Reads the vertices and faces of a Wavefront OBJ file; other statements are skipped.
+    pub(crate) fn load_obj(path: &str) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut mesh = PolygonMesh::default();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let error = |what: &str| invalid_data(format!("{}:{}: {}", path, line_number + 1, what));
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("v") => {
+                    let values: Vec<f64> = fields
+                        .take(3)
+                        .map(|field| field.parse::<f64>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| error("expected vertex coordinates"))?;
+                    if values.len() != 3 {
+                        return Err(error("expected three vertex coordinates"));
+                    }
+                    mesh.positions.push(Point3::new(values[0], values[1], values[2]));
+                }
+                Some("f") => {
+                    // Vertices are v, v/vt, v//vn or v/vt/vn; negative indices count back from
+                    // the last vertex read.
+                    let mut face = Vec::new();
+                    for field in fields {
+                        let index: i64 = field.split('/').next().unwrap_or("").parse().map_err(|_| error("expected a vertex index"))?;
+                        let resolved = if index < 0 { mesh.positions.len() as i64 + index } else { index - 1 };
+                        if resolved < 0 || resolved >= mesh.positions.len() as i64 {
+                            return Err(error("vertex index out of range"));
+                        }
+                        face.push(resolved as usize);
+                    }
+                    if face.len() < 3 {
+                        return Err(error("faces need at least three vertices"));
+                    }
+                    mesh.faces.push(face);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(mesh)
+    }
+
+    // Splits each face into a fan of triangles around its first vertex.
+    pub(crate) fn triangulate(&self) -> TriangleMesh {
+        let triangles = self
+            .faces
+            .iter()
+            .flat_map(|face| (1..face.len() - 1).map(move |i| [face[0], face[i], face[i + 1]]))
+            .collect();
+        TriangleMesh { positions: self.positions.clone(), triangles, ..TriangleMesh::default() }
+    }
+}
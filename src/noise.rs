@@ -0,0 +1,86 @@
+use crate::utils::mix_bits;
+use crate::vec3::{dot, unit_vector, Point3, Vec3};
+
+const POINT_COUNT: usize = 256;
+
+// Perlin gradient noise: random unit vectors on the integer lattice, blended smoothly in
+// between. The lattice is shuffled from `seed` alone, so it does not disturb the random
+// sequence that places the rest of the scene.
+pub(crate) struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub(crate) fn new(seed: u64) -> Self {
+        let mut state = mix_bits(seed ^ 0x5eed);
+        let mut next = move || {
+            state = mix_bits(state.wrapping_add(0x9e3779b97f4a7c15));
+            (state >> 11) as f64 / (1u64 << 53) as f64
+        };
+
+        let gradients = (0..POINT_COUNT)
+            .map(|_| loop {
+                let v = Vec3::new(2.0 * next() - 1.0, 2.0 * next() - 1.0, 2.0 * next() - 1.0);
+                if v.length_squared() > 1e-6 && v.length_squared() <= 1.0 {
+                    break unit_vector(v);
+                }
+            })
+            .collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            for i in (1..POINT_COUNT).rev() {
+                let target = ((next() * (i + 1) as f64) as usize).min(i);
+                p.swap(i, target);
+            }
+            p
+        };
+        let (perm_x, perm_y, perm_z) = (permutation(), permutation(), permutation());
+
+        Perlin { gradients, perm_x, perm_y, perm_z }
+    }
+
+    // Noise in about [-1, 1], zero at the lattice points.
+    pub(crate) fn noise(&self, p: Point3) -> f64 {
+        let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (u, v, w) = (p.x() - fx, p.y() - fy, p.z() - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        // Hermite smoothing hides the lattice.
+        let (uu, vv, ww) = (u * u * (3.0 - 2.0 * u), v * v * (3.0 - 2.0 * v), w * w * (3.0 - 2.0 * w));
+        let mut accumulated = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let mask = POINT_COUNT as i64 - 1;
+                    let index = self.perm_x[((i + di) & mask) as usize]
+                        ^ self.perm_y[((j + dj) & mask) as usize]
+                        ^ self.perm_z[((k + dk) & mask) as usize];
+                    let weight = Vec3::new(u - di as f64, v - dj as f64, w - dk as f64);
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    accumulated += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * dot(self.gradients[index], weight);
+                }
+            }
+        }
+        accumulated
+    }
+
+    // Sum of `octaves` layers of noise, each at twice the frequency and half the amplitude of
+    // the one before.
+    pub(crate) fn fbm(&self, p: Point3, octaves: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut point = p;
+        let mut amplitude = 1.0;
+        for _ in 0..octaves {
+            sum += amplitude * self.noise(point);
+            point = 2.0 * point;
+            amplitude *= 0.5;
+        }
+        sum
+    }
+}
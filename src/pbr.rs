@@ -0,0 +1,191 @@
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::image::Image;
+use crate::json::Json;
+use crate::material::MaterialTrait;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::scene_file::SceneWriter;
+use crate::utils::PI;
+use crate::vec3::{cross, dot, sample_unit_vector, unit_vector, Vec3};
+
+// Smallest GGX roughness, which keeps perfectly smooth surfaces from dividing by zero.
+const MIN_ALPHA: f64 = 1e-3;
+
+// The metallic-roughness material of glTF: a diffuse base under a GGX specular layer whose
+// reflectance goes from 4% for dielectrics to the base color for metals. Textures are looked up
+// with the hit's surface coordinates and multiply the factors.
+pub(crate) struct PbrMaterial {
+    pub(crate) base_color: Color,
+    pub(crate) base_color_texture: Option<Rc<Image>>,
+    pub(crate) metallic: f64,
+    pub(crate) roughness: f64,
+    // Roughness in the green channel and metalness in the blue one
+    pub(crate) metallic_roughness_texture: Option<Rc<Image>>,
+    pub(crate) emissive: Color,
+    pub(crate) emissive_texture: Option<Rc<Image>>,
+}
+
+// Properties of the material at one hit.
+struct Surface {
+    base_color: Color,
+    metallic: f64,
+    alpha: f64, // GGX roughness, the square of the perceptual roughness
+}
+
+fn luminance(color: Color) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+fn schlick(f0: Color, cosine: f64) -> Color {
+    let weight = (1.0 - cosine).clamp(0.0, 1.0).powi(5);
+    f0 + weight * (Color::new(1.0, 1.0, 1.0) - f0)
+}
+
+// Share of the microfacets seen from a direction at `cosine` to the normal (Smith, GGX).
+fn smith_g1(cosine: f64, alpha: f64) -> f64 {
+    let alpha_squared = alpha * alpha;
+    2.0 * cosine / (cosine + (alpha_squared + (1.0 - alpha_squared) * cosine * cosine).sqrt())
+}
+
+impl PbrMaterial {
+    pub(crate) fn new(base_color: Color, metallic: f64, roughness: f64) -> Self {
+        PbrMaterial {
+            base_color,
+            base_color_texture: None,
+            metallic,
+            roughness,
+            metallic_roughness_texture: None,
+            emissive: Color::new(0.0, 0.0, 0.0),
+            emissive_texture: None,
+        }
+    }
+
+    fn surface(&self, hit_record: &HitRecord) -> Surface {
+        let lookup = |texture: &Option<Rc<Image>>| texture.as_ref().map(|image| image.sample(hit_record.u, hit_record.v));
+        let base_color = lookup(&self.base_color_texture).map_or(self.base_color, |texel| texel * self.base_color);
+        let (metallic, roughness) = match lookup(&self.metallic_roughness_texture) {
+            Some(texel) => (texel.z() * self.metallic, texel.y() * self.roughness),
+            None => (self.metallic, self.roughness),
+        };
+        Surface { base_color, metallic: metallic.clamp(0.0, 1.0), alpha: (roughness * roughness).max(MIN_ALPHA) }
+    }
+
+    // Reflectance of the specular layer head-on.
+    fn f0(surface: &Surface) -> Color {
+        (1.0 - surface.metallic) * Color::new(0.04, 0.04, 0.04) + surface.metallic * surface.base_color
+    }
+
+    // The diffuse base, without the 1/pi; light reflected by the specular layer never reaches it.
+    fn diffuse(surface: &Surface, cos_view: f64) -> Color {
+        (1.0 - surface.metallic) * surface.base_color * (Color::new(1.0, 1.0, 1.0) - schlick(PbrMaterial::f0(surface), cos_view))
+    }
+
+    // Probability of sampling the specular layer rather than the diffuse base.
+    fn specular_probability(surface: &Surface, cos_view: f64) -> f64 {
+        let specular = luminance(schlick(PbrMaterial::f0(surface), cos_view));
+        let diffuse = luminance(PbrMaterial::diffuse(surface, cos_view));
+        if specular + diffuse <= 0.0 {
+            return 1.0;
+        }
+        (specular / (specular + diffuse)).clamp(0.1, 1.0)
+    }
+}
+
+impl MaterialTrait for PbrMaterial {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Color, Ray)> {
+        let surface = self.surface(hit_record);
+        let normal = hit_record.normal;
+        let view = -unit_vector(ray_in.direction());
+        let cos_view = dot(normal, view).max(1e-4);
+        let specular_probability = PbrMaterial::specular_probability(&surface, cos_view);
+        let choice = sampler.get_1d();
+        let u = sampler.get_2d();
+
+        if choice >= specular_probability {
+            let mut direction = normal + sample_unit_vector(u);
+            if direction.near_zero() {
+                direction = normal;
+            }
+            let weight = PbrMaterial::diffuse(&surface, cos_view) / (1.0 - specular_probability);
+            return Some((weight, Ray::new(hit_record.point, direction, ray_in.time())));
+        }
+
+        // Microfacet normal drawn in proportion to its distribution times its cosine.
+        let alpha_squared = surface.alpha * surface.alpha;
+        let cos_theta = ((1.0 - u.0) / (1.0 + (alpha_squared - 1.0) * u.0)).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        let helper = if normal.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let tangent = unit_vector(cross(helper, normal));
+        let bitangent = cross(normal, tangent);
+        let half = sin_theta * phi.cos() * tangent + sin_theta * phi.sin() * bitangent + cos_theta * normal;
+
+        let cos_half_view = dot(view, half);
+        let direction = 2.0 * cos_half_view * half - view;
+        let cos_light = dot(normal, direction);
+        if cos_light <= 0.0 || cos_half_view <= 0.0 {
+            return None;
+        }
+        let shadowing = smith_g1(cos_view, surface.alpha) * smith_g1(cos_light, surface.alpha);
+        let weight = shadowing * cos_half_view / (cos_view * cos_theta) / specular_probability * schlick(PbrMaterial::f0(&surface), cos_half_view);
+        Some((weight, Ray::new(hit_record.point, direction, ray_in.time())))
+    }
+
+    fn albedo(&self, hit_record: &HitRecord) -> Color {
+        self.surface(hit_record).base_color
+    }
+
+    fn emitted(&self, hit_record: &HitRecord) -> Color {
+        match &self.emissive_texture {
+            Some(image) => image.sample(hit_record.u, hit_record.v) * self.emissive,
+            None => self.emissive,
+        }
+    }
+
+    fn evaluate(&self, ray_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Color {
+        let normal = hit_record.normal;
+        let view = -unit_vector(ray_in.direction());
+        let (cos_view, cos_light) = (dot(normal, view).max(1e-4), dot(normal, direction));
+        if cos_light <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let surface = self.surface(hit_record);
+        let half = unit_vector(view + direction);
+        let cos_half = dot(normal, half).max(0.0);
+        let alpha_squared = surface.alpha * surface.alpha;
+        let denominator = cos_half * cos_half * (alpha_squared - 1.0) + 1.0;
+        let distribution = alpha_squared / (PI * denominator * denominator);
+        let shadowing = smith_g1(cos_view, surface.alpha) * smith_g1(cos_light, surface.alpha);
+        let fresnel = schlick(PbrMaterial::f0(&surface), dot(view, half));
+        let specular = distribution * shadowing / (4.0 * cos_view) * fresnel;
+        specular + cos_light / PI * PbrMaterial::diffuse(&surface, cos_view)
+    }
+
+    fn set_parameter(&mut self, name: &str, value: &[f64]) -> bool {
+        match (name, value) {
+            ("base_color", [r, g, b]) => self.base_color = Color::new(*r, *g, *b),
+            ("metallic", [metallic]) => self.metallic = *metallic,
+            ("roughness", [roughness]) => self.roughness = *roughness,
+            ("emissive", [r, g, b]) => self.emissive = Color::new(*r, *g, *b),
+            _ => return false,
+        }
+        true
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        let mut image = |texture: &Option<Rc<Image>>| texture.as_ref().map_or(Json::Null, |image| scene.image(image));
+        Some(Json::object(vec![
+            ("type", "pbr".into()),
+            ("base_color", self.base_color.into()),
+            ("base_color_texture", image(&self.base_color_texture)),
+            ("metallic", self.metallic.into()),
+            ("roughness", self.roughness.into()),
+            ("metallic_roughness_texture", image(&self.metallic_roughness_texture)),
+            ("emissive", self.emissive.into()),
+            ("emissive_texture", image(&self.emissive_texture)),
+        ]))
+    }
+}
@@ -0,0 +1,1040 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::image::Image;
+use crate::light::{LightKind, PunctualLight};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, MaterialTrait, Metal, TexturedLambertian};
+use crate::mesh::{invalid_data, TriangleMesh};
+use crate::pbr::PbrMaterial;
+use crate::projection::Projection;
+use crate::scene::{Scene, View};
+use crate::sphere::Sphere;
+use crate::texture::Texture;
+use crate::transform::Matrix;
+use crate::utils::{degrees_to_radians, PI};
+use crate::vec3::{cross, dot, unit_vector, Point3, Vec3};
+
+// PBRT's camera space is left-handed, this renderer's right-handed. Unless the camera transform
+// mirrors already, the world is mirrored along x so the image comes out the same way round.
+const MIRROR: Matrix = Matrix([[-1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+
+// Refractive indices of PBRT's named glass spectra, at the sodium d line.
+const GLASSES: [(&str, f64); 7] = [
+    ("glass-BK7", 1.5168),
+    ("glass-BAF10", 1.6700),
+    ("glass-FK51A", 1.4866),
+    ("glass-LASF9", 1.8503),
+    ("glass-F5", 1.6034),
+    ("glass-F10", 1.7280),
+    ("glass-F11", 1.6209),
+];
+
+// Complex refractive indices (eta, k) of PBRT's named metal spectra at red, green and blue.
+const METALS: [(&str, [f64; 3], [f64; 3]); 5] = [
+    ("Ag", [0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+    ("Al", [1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+    ("Au", [0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+    ("Cu", [0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+    ("CuZn", [0.444, 0.527, 1.094], [3.695, 2.765, 1.829]),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Number(f64),
+    Open,
+    Close,
+}
+
+// A token with the file and line it was read from.
+struct Located {
+    token: Token,
+    file: usize,
+    line: usize,
+}
+
+// Splits a scene file into tokens, reading included files in their place. Relative paths are
+// resolved against the directory of the main file, as PBRT does.
+fn tokenize(path: &Path, directory: &Path, files: &mut Vec<String>, tokens: &mut Vec<Located>) -> Result<(), String> {
+    if files.len() > 256 {
+        return Err(String::from("too many included files"));
+    }
+    let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    let file = files.len();
+    files.push(path.display().to_string());
+    let bytes = text.as_bytes();
+    let (mut position, mut line) = (0, 1);
+    while position < bytes.len() {
+        let start = position;
+        let token = match bytes[position] {
+            b'\n' => {
+                line += 1;
+                position += 1;
+                continue;
+            }
+            b'#' => {
+                while position < bytes.len() && bytes[position] != b'\n' {
+                    position += 1;
+                }
+                continue;
+            }
+            byte if byte.is_ascii_whitespace() => {
+                position += 1;
+                continue;
+            }
+            b'[' => {
+                position += 1;
+                Token::Open
+            }
+            b']' => {
+                position += 1;
+                Token::Close
+            }
+            b'"' => {
+                let mut value = String::new();
+                position += 1;
+                loop {
+                    match bytes.get(position) {
+                        None | Some(b'\n') => return Err(format!("{}:{}: unterminated string", files[file], line)),
+                        Some(b'"') => break,
+                        Some(b'\\') if position + 1 < bytes.len() => {
+                            position += 1;
+                            value.push(match bytes[position] {
+                                b'n' => '\n',
+                                b't' => '\t',
+                                other => other as char,
+                            });
+                        }
+                        Some(_) => {
+                            // Copies a whole character, which may take several bytes.
+                            let character = text[position..].chars().next().unwrap();
+                            value.push(character);
+                            position += character.len_utf8() - 1;
+                        }
+                    }
+                    position += 1;
+                }
+                position += 1;
+                Token::Text(value)
+            }
+            _ => {
+                while position < bytes.len() && !bytes[position].is_ascii_whitespace() && !b"[]\"#".contains(&bytes[position]) {
+                    position += 1;
+                }
+                let word = &text[start..position];
+                match word.parse::<f64>() {
+                    Ok(number) => Token::Number(number),
+                    Err(_) => Token::Word(word.to_string()),
+                }
+            }
+        };
+
+        // Include and Import splice another file in.
+        if let Token::Text(name) = &token {
+            if let Some(Located { token: Token::Word(directive), .. }) = tokens.last() {
+                if directive == "Include" || directive == "Import" {
+                    tokens.pop();
+                    tokenize(&directory.join(name), directory, files, tokens).map_err(|message| format!("{}:{}: {}", files[file], line, message))?;
+                    continue;
+                }
+            }
+        }
+        tokens.push(Located { token, file, line });
+    }
+    Ok(())
+}
+
+// Value of a parameter. Booleans are kept as their text.
+#[derive(Clone, Debug)]
+enum Value {
+    Number(f64),
+    Text(String),
+}
+
+// A parameter as written, "type name" followed by one value or a bracketed list.
+#[derive(Clone, Debug)]
+struct Param {
+    kind: String,
+    name: String,
+    values: Vec<Value>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Params(Vec<Param>);
+
+impl Params {
+    fn get(&self, name: &str) -> Option<&Param> {
+        self.0.iter().find(|param| param.name == name)
+    }
+
+    fn numbers(&self, name: &str) -> Option<Vec<f64>> {
+        let param = self.get(name)?;
+        Some(param.values.iter().filter_map(|value| if let Value::Number(number) = value { Some(*number) } else { None }).collect())
+    }
+
+    fn float(&self, name: &str) -> Option<f64> {
+        self.numbers(name)?.first().copied()
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        match self.get(name)?.values.first()? {
+            Value::Text(text) => Some(text),
+            Value::Number(_) => None,
+        }
+    }
+
+    fn bool(&self, name: &str) -> Option<bool> {
+        self.string(name).map(|text| text == "true")
+    }
+
+    fn point(&self, name: &str, default: Point3) -> Point3 {
+        match self.numbers(name).as_deref() {
+            Some([x, y, z]) => Point3::new(*x, *y, *z),
+            _ => default,
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Located>,
+    files: Vec<String>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|located| &located.token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).map(|located| located.token.clone());
+        self.position += 1;
+        token
+    }
+
+    // File and line of the last token read.
+    fn location(&self) -> String {
+        match self.tokens.get(self.position.saturating_sub(1)).or(self.tokens.last()) {
+            Some(located) => format!("{}:{}", self.files[located.file], located.line),
+            None => self.files.first().cloned().unwrap_or_default(),
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{}: {}", self.location(), message)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Text(text)) => Ok(text),
+            _ => Err(self.error("expected a quoted string")),
+        }
+    }
+
+    // `count` numbers, bracketed or not.
+    fn numbers(&mut self, count: usize) -> Result<Vec<f64>, String> {
+        let bracketed = self.peek() == Some(&Token::Open);
+        if bracketed {
+            self.position += 1;
+        }
+        let mut numbers = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.next() {
+                Some(Token::Number(number)) => numbers.push(number),
+                _ => return Err(self.error(&format!("expected {} numbers", count))),
+            }
+        }
+        if bracketed && self.next() != Some(Token::Close) {
+            return Err(self.error("expected ]"));
+        }
+        Ok(numbers)
+    }
+
+    fn params(&mut self) -> Result<Params, String> {
+        let mut params = Vec::new();
+        while let Some(Token::Text(declaration)) = self.peek() {
+            let parts: Vec<&str> = declaration.split_whitespace().collect();
+            let [kind, name] = parts[..] else {
+                return Err(self.error(&format!("invalid parameter declaration \"{}\"", declaration)));
+            };
+            let (kind, name) = (kind.to_string(), name.to_string());
+            self.position += 1;
+            let value = |token: Token| match token {
+                Token::Number(number) => Some(Value::Number(number)),
+                Token::Text(text) | Token::Word(text) => Some(Value::Text(text)),
+                _ => None,
+            };
+            let mut values = Vec::new();
+            match self.next() {
+                Some(Token::Open) => loop {
+                    match self.next() {
+                        Some(Token::Close) => break,
+                        Some(token) => values.push(value(token).ok_or_else(|| self.error("expected ]"))?),
+                        None => return Err(self.error("expected ]")),
+                    }
+                },
+                Some(token) => values.push(value(token).ok_or_else(|| self.error(&format!("parameter \"{}\" without a value", name)))?),
+                None => return Err(self.error(&format!("parameter \"{}\" without a value", name))),
+            }
+            params.push(Param { kind, name, values });
+        }
+        Ok(Params(params))
+    }
+}
+
+// The attributes shapes are created with.
+#[derive(Clone)]
+struct GraphicsState {
+    transform: Matrix,
+    // None for "interface" materials, whose shapes only bound media and are not rendered
+    material: Option<Rc<RefCell<dyn MaterialTrait>>>,
+    area_light: Option<Rc<RefCell<dyn MaterialTrait>>>,
+    reverse_orientation: bool,
+}
+
+// A shape inside an object definition, created again for every instance.
+struct ShapeCall {
+    name: String,
+    params: Params,
+    state: GraphicsState,
+    location: String,
+}
+
+struct Loader {
+    directory: PathBuf,
+    scene: Scene,
+    state: GraphicsState,
+    // Saved states, and whether each was saved by TransformBegin rather than AttributeBegin
+    stack: Vec<(GraphicsState, bool)>,
+    in_world: bool,
+    world_mirror: Matrix, // MIRROR or the identity, applied to the whole world
+    motion_end: bool, // After ActiveTransform EndTime, whose transforms are ignored
+    coordinate_systems: HashMap<String, Matrix>,
+    named_materials: HashMap<String, Option<Rc<RefCell<dyn MaterialTrait>>>>,
+    textures: HashMap<String, Rc<Texture>>,
+    float_textures: HashSet<String>,
+    objects: HashMap<String, Vec<ShapeCall>>,
+    object: Option<(String, Vec<ShapeCall>)>, // Definition in progress
+    camera: Option<(String, Params, Matrix)>, // Type, parameters and camera to world transform
+    film: Params,
+    sampler: Option<(String, Params)>,
+    warned: HashSet<String>,
+}
+
+// Refractive index and extinction coefficient of a named metal.
+fn metal(name: &str) -> Option<(Color, Color)> {
+    let &(_, eta, k) = METALS.iter().find(|(metal, _, _)| *metal == name)?;
+    Some((Color::new(eta[0], eta[1], eta[2]), Color::new(k[0], k[1], k[2])))
+}
+
+fn gray(value: f64) -> Color {
+    Color::new(value, value, value)
+}
+
+// Reflectance at normal incidence of a conductor with complex refractive index eta + ik.
+fn conductor_reflectance(eta: Color, k: Color) -> Color {
+    let channel = |i: usize| {
+        let (n, k) = (eta[i], k[i]);
+        ((n - 1.0) * (n - 1.0) + k * k) / ((n + 1.0) * (n + 1.0) + k * k)
+    };
+    Color::new(channel(0), channel(1), channel(2))
+}
+
+// Color of a black body at `kelvin`, scaled so its largest channel is one (after Tanner Helland's
+// fit of the CIE data).
+fn blackbody(kelvin: f64) -> Color {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let red = if t <= 66.0 { 255.0 } else { 329.698727446 * (t - 60.0).powf(-0.1332047592) };
+    let green = if t <= 66.0 { 99.4708025861 * t.ln() - 161.1195681661 } else { 288.1221695283 * (t - 60.0).powf(-0.0755148492) };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (t - 10.0).ln() - 305.0447927307
+    };
+    // The fit gives sRGB encoded values.
+    let channel = |value: f64| crate::image::srgb_to_linear((value / 255.0).clamp(0.0, 1.0));
+    let color = Color::new(channel(red), channel(green), channel(blue));
+    color / color.x().max(color.y()).max(color.z())
+}
+
+impl Loader {
+    fn warn(&mut self, location: &str, message: String) {
+        // Each problem is reported once, however often the file repeats it.
+        if self.warned.insert(message.clone()) {
+            eprintln!("{}: {}", location, message);
+        }
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.directory.join(name).display().to_string()
+    }
+
+    fn default_material() -> Rc<RefCell<dyn MaterialTrait>> {
+        Rc::new(RefCell::new(Lambertian { albedo: gray(0.5) }))
+    }
+
+    // Value of a spectrum parameter given as RGB, a named or sampled spectrum, a black body or a
+    // single number. None when absent or given as a texture.
+    fn color(&mut self, params: &Params, name: &str, location: &str) -> Option<Color> {
+        let param = params.get(name)?;
+        let numbers = params.numbers(name).unwrap_or_default();
+        match (param.kind.as_str(), &numbers[..]) {
+            ("rgb" | "color", [r, g, b]) => Some(Color::new(*r, *g, *b)),
+            ("float", [value]) => Some(gray(*value)),
+            ("blackbody", [kelvin]) => Some(blackbody(*kelvin)),
+            ("blackbody", [kelvin, scale]) => Some(*scale * blackbody(*kelvin)),
+            ("spectrum", []) => {
+                let spectrum = params.string(name).unwrap_or("").to_string();
+                let metal = spectrum.strip_prefix("metal-").and_then(|rest| {
+                    let (name, part) = rest.rsplit_once('-')?;
+                    let (eta, k) = metal(name)?;
+                    match part {
+                        "eta" => Some(eta),
+                        "k" => Some(k),
+                        _ => None,
+                    }
+                });
+                let glass = GLASSES.iter().find(|(glass, _)| *glass == spectrum).map(|&(_, eta)| gray(eta));
+                let color = metal.or(glass);
+                if color.is_none() {
+                    self.warn(location, format!("unknown named spectrum \"{}\" ignored", spectrum));
+                }
+                color
+            }
+            // Wavelength and value pairs, averaged to a gray.
+            ("spectrum", pairs) if pairs.len() >= 2 && pairs.len() % 2 == 0 => {
+                let values: Vec<f64> = pairs.chunks_exact(2).map(|pair| pair[1]).collect();
+                Some(gray(values.iter().sum::<f64>() / values.len() as f64))
+            }
+            ("texture", _) => None,
+            (kind, _) => {
+                self.warn(location, format!("invalid value for \"{} {}\" ignored", kind, name));
+                None
+            }
+        }
+    }
+
+    // A color that may also be given as a texture, like the reflectance of a diffuse material.
+    fn texture(&mut self, params: &Params, name: &str, default: Color, location: &str) -> Rc<Texture> {
+        if params.get(name).is_some_and(|param| param.kind == "texture") {
+            let texture_name = params.string(name).unwrap_or("").to_string();
+            if let Some(texture) = self.textures.get(&texture_name) {
+                return Rc::clone(texture);
+            }
+            let message = if self.float_textures.contains(&texture_name) {
+                format!("float texture \"{}\" cannot give a color, ignored", texture_name)
+            } else {
+                format!("unknown texture \"{}\" ignored", texture_name)
+            };
+            self.warn(location, message);
+        }
+        Rc::new(Texture::Solid(self.color(params, name, location).unwrap_or(default)))
+    }
+
+    // A color for materials that cannot take textures; a texture is reduced to its value in the
+    // middle of the surface.
+    fn solid_color(&mut self, params: &Params, name: &str, default: Color, kind: &str, location: &str) -> Color {
+        let texture = self.texture(params, name, default, location);
+        if !matches!(*texture, Texture::Solid(_)) {
+            self.warn(location, format!("textures on \"{}\" materials are not supported, using a solid color", kind));
+        }
+        texture.value(0.5, 0.5, Point3::new(0.0, 0.0, 0.0))
+    }
+
+    // A roughness as this renderer's perceptual roughness, whose square is the GGX alpha. PBRT
+    // takes the square root of remapped roughnesses to get alpha.
+    fn roughness(&mut self, params: &Params, default: f64, location: &str) -> f64 {
+        for name in ["roughness", "uroughness", "vroughness"] {
+            if params.get(name).is_some_and(|param| param.kind == "texture") {
+                self.warn(location, String::from("roughness textures are not supported, using the default roughness"));
+            }
+        }
+        let roughness = match (params.float("uroughness"), params.float("vroughness")) {
+            (Some(u), Some(v)) => (u + v) / 2.0,
+            _ => params.float("roughness").unwrap_or(default),
+        };
+        if params.bool("remaproughness").unwrap_or(true) {
+            roughness.max(0.0).powf(0.25)
+        } else {
+            roughness.max(0.0).sqrt()
+        }
+    }
+
+    fn material(&mut self, kind: &str, params: &Params, location: &str) -> Option<Rc<RefCell<dyn MaterialTrait>>> {
+        let material: Rc<RefCell<dyn MaterialTrait>> = match kind {
+            "" | "none" | "interface" => return None,
+            "diffuse" | "matte" => {
+                let name = if kind == "matte" { "Kd" } else { "reflectance" };
+                let texture = self.texture(params, name, gray(0.5), location);
+                match *texture {
+                    Texture::Solid(albedo) => Rc::new(RefCell::new(Lambertian { albedo })),
+                    _ => Rc::new(RefCell::new(TexturedLambertian { texture })),
+                }
+            }
+            "conductor" | "metal" => {
+                let reflectance = if params.get("reflectance").is_some() {
+                    self.solid_color(params, "reflectance", gray(1.0), kind, location)
+                } else {
+                    // Copper unless told otherwise, as in PBRT.
+                    let (eta, k) = metal("Cu").unwrap();
+                    let eta = self.color(params, "eta", location).unwrap_or(eta);
+                    let k = self.color(params, "k", location).unwrap_or(k);
+                    conductor_reflectance(eta, k)
+                };
+                let roughness = self.roughness(params, if kind == "metal" { 0.01 } else { 0.0 }, location);
+                Rc::new(RefCell::new(PbrMaterial::new(reflectance, 1.0, roughness)))
+            }
+            "dielectric" | "glass" | "thindielectric" => {
+                if kind == "thindielectric" {
+                    self.warn(location, String::from("thin dielectrics are rendered as solid glass"));
+                }
+                let eta = ["eta", "index"].iter().find_map(|name| self.color(params, name, location)).map_or(1.5, |eta| (eta.x() + eta.y() + eta.z()) / 3.0);
+                if self.roughness(params, 0.0, location) > 0.0 {
+                    self.warn(location, String::from("rough dielectrics are rendered smooth"));
+                }
+                Rc::new(RefCell::new(Dielectric { refraction_index: eta }))
+            }
+            "mirror" => Rc::new(RefCell::new(Metal { albedo: self.solid_color(params, "Kr", gray(0.9), kind, location), fuzz: 0.0 })),
+            "coateddiffuse" | "plastic" | "substrate" => {
+                let (name, default_color, default_roughness) = match kind {
+                    "coateddiffuse" => ("reflectance", 0.5, 0.0),
+                    "plastic" => ("Kd", 0.25, 0.1),
+                    _ => ("Kd", 0.5, 0.1),
+                };
+                let base = self.solid_color(params, name, gray(default_color), kind, location);
+                let roughness = self.roughness(params, default_roughness, location);
+                Rc::new(RefCell::new(PbrMaterial::new(base, 0.0, roughness)))
+            }
+            "mix" => {
+                // The first of the two materials.
+                let first = match params.get("materials") {
+                    Some(_) => params.string("materials").map(str::to_string),
+                    None => params.string("namedmaterial1").map(str::to_string),
+                };
+                self.warn(location, String::from("mix materials are rendered with their first material"));
+                return match first.and_then(|name| self.named_materials.get(&name).cloned()) {
+                    Some(material) => material,
+                    None => Some(Loader::default_material()),
+                };
+            }
+            _ => {
+                self.warn(location, format!("unsupported material \"{}\" rendered as diffuse", kind));
+                let name = if params.get("reflectance").is_some() { "reflectance" } else { "Kd" };
+                Rc::new(RefCell::new(Lambertian { albedo: self.solid_color(params, name, gray(0.5), kind, location) }))
+            }
+        };
+        Some(material)
+    }
+
+    fn define_texture(&mut self, name: String, kind: &str, class: &str, params: &Params, location: &str) {
+        if kind == "float" {
+            self.float_textures.insert(name);
+            return;
+        }
+        let scale = (params.float("uscale").unwrap_or(1.0), params.float("vscale").unwrap_or(1.0));
+        let offset = (params.float("udelta").unwrap_or(0.0), params.float("vdelta").unwrap_or(0.0));
+        if params.string("mapping").is_some_and(|mapping| mapping != "uv") {
+            self.warn(location, String::from("only uv texture mappings are supported"));
+        }
+        let texture = match class {
+            "imagemap" => {
+                let filename = params.string("filename").unwrap_or("").to_string();
+                let linear = params.string("encoding") == Some("linear") || params.bool("gamma") == Some(false);
+                if params.float("scale").is_some_and(|scale| scale != 1.0) {
+                    self.warn(location, String::from("image texture scales are ignored"));
+                }
+                match Image::load(&self.path(&filename), linear) {
+                    Ok(image) => Texture::Image { image: Rc::new(image), scale, offset },
+                    Err(error) => {
+                        self.warn(location, format!("cannot load texture image: {}; using gray", error));
+                        Texture::Solid(gray(0.5))
+                    }
+                }
+            }
+            "checkerboard" => {
+                if params.float("dimension").is_some_and(|dimension| dimension != 2.0) {
+                    self.warn(location, String::from("3D checkerboards are rendered as 2D ones"));
+                }
+                let even = self.texture(params, "tex1", gray(1.0), location).value(0.5, 0.5, Point3::new(0.0, 0.0, 0.0));
+                let odd = self.texture(params, "tex2", gray(0.0), location).value(0.5, 0.5, Point3::new(0.0, 0.0, 0.0));
+                Texture::Checker { even, odd, scale }
+            }
+            "constant" => Texture::Solid(self.color(params, "value", location).unwrap_or(gray(1.0))),
+            _ => {
+                self.warn(location, format!("unsupported texture \"{}\" rendered gray", class));
+                Texture::Solid(gray(0.5))
+            }
+        };
+        self.textures.insert(name, Rc::new(texture));
+    }
+
+    fn light(&mut self, kind: &str, params: &Params, location: &str) {
+        let transform = self.state.transform;
+        let scale = self.color(params, "scale", location).unwrap_or(gray(1.0));
+        if params.get("power").is_some() || params.get("illuminance").is_some() {
+            self.warn(location, String::from("light power and illuminance are ignored"));
+        }
+        let from = transform.point(params.point("from", Point3::new(0.0, 0.0, 0.0)));
+        let direction = transform.vector(params.point("to", Point3::new(0.0, 0.0, 1.0)) - params.point("from", Point3::new(0.0, 0.0, 0.0)));
+        let (kind, intensity) = match kind {
+            "point" => (LightKind::Point, self.color(params, "I", location).unwrap_or(gray(1.0))),
+            "spot" => {
+                let outer = params.float("coneangle").unwrap_or(30.0);
+                let inner = outer - params.float("conedelta").unwrap_or(5.0);
+                let kind = LightKind::Spot { direction, inner_angle: degrees_to_radians(inner.max(0.0)), outer_angle: degrees_to_radians(outer) };
+                (kind, self.color(params, "I", location).unwrap_or(gray(1.0)))
+            }
+            "distant" => (LightKind::Directional { direction }, self.color(params, "L", location).unwrap_or(gray(1.0))),
+            "infinite" => {
+                if params.get("mapname").is_some() || params.get("filename").is_some() {
+                    self.warn(location, String::from("environment maps are not supported, using uniform light"));
+                }
+                let radiance = self.color(params, "L", location).unwrap_or(gray(1.0));
+                self.scene.background = Some(scale * radiance);
+                return;
+            }
+            _ => {
+                self.warn(location, format!("unsupported light \"{}\" skipped", kind));
+                return;
+            }
+        };
+        self.scene.lights.push(PunctualLight { kind, position: from, intensity: scale * intensity, range: None });
+    }
+
+    // Transforms a mesh into the world and adds it. Triangles are turned to face the way PBRT
+    // would have them face, which decides the lit side of area lights and the inside of glass.
+    fn add_mesh(&mut self, mut mesh: TriangleMesh, state: &GraphicsState, material: Rc<RefCell<dyn MaterialTrait>>) {
+        let transform = state.transform;
+        for p in &mut mesh.positions {
+            *p = transform.point(*p);
+        }
+        for n in &mut mesh.normals {
+            *n = transform.normal(*n);
+        }
+        if mesh.normals.len() == mesh.positions.len() {
+            // With normals, each face is turned to the side they point to.
+            for triangle in &mut mesh.triangles {
+                let [a, b, c] = *triangle;
+                let face = cross(mesh.positions[b] - mesh.positions[a], mesh.positions[c] - mesh.positions[a]);
+                if dot(face, mesh.normals[a] + mesh.normals[b] + mesh.normals[c]) < 0.0 {
+                    triangle.swap(1, 2);
+                }
+            }
+        } else {
+            mesh.normals.clear();
+            // Otherwise PBRT goes by the winding, turned around by ReverseOrientation and by
+            // transforms that change handedness, the mirror into this renderer's world included.
+            if state.reverse_orientation == (transform.determinant() > 0.0) {
+                for triangle in &mut mesh.triangles {
+                    triangle.swap(1, 2);
+                }
+            }
+        }
+        if !mesh.triangles.is_empty() {
+            self.scene.add(Box::new(mesh.into_hittable(Rc::clone(&material))), material);
+        }
+    }
+
+    fn shape(&mut self, name: &str, params: &Params, state: &GraphicsState, location: &str) -> Result<(), String> {
+        let Some(material) = state.area_light.clone().or(state.material.clone()) else {
+            return Ok(());
+        };
+        match name {
+            "sphere" => {
+                if ["zmin", "zmax", "phimax"].iter().any(|name| params.get(name).is_some()) {
+                    self.warn(location, String::from("partial spheres are rendered whole"));
+                }
+                let transform = state.transform;
+                let scales: Vec<f64> = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)]
+                    .iter()
+                    .map(|&axis| transform.vector(axis).length())
+                    .collect();
+                let scale = transform.determinant().abs().cbrt();
+                if scales.iter().any(|&s| (s - scale).abs() > 1e-6 * scale) {
+                    self.warn(location, String::from("spheres with uneven scales are rendered round"));
+                }
+                let center = transform.point(Point3::new(0.0, 0.0, 0.0));
+                let radius = params.float("radius").unwrap_or(1.0) * scale;
+                self.scene.add(Box::new(Sphere::new(center, radius, Rc::clone(&material), center, false)), material);
+            }
+            "trianglemesh" | "loopsubdiv" => {
+                if name == "loopsubdiv" {
+                    self.warn(location, String::from("subdivision surfaces are rendered without subdividing"));
+                }
+                let points = params.numbers("P").ok_or_else(|| format!("{}: {} without \"P\"", location, name))?;
+                let mut mesh = TriangleMesh { positions: points.chunks_exact(3).map(|p| Point3::new(p[0], p[1], p[2])).collect(), ..TriangleMesh::default() };
+                let indices = match params.numbers("indices") {
+                    Some(indices) => indices,
+                    None if mesh.positions.len() == 3 => vec![0.0, 1.0, 2.0],
+                    None => return Err(format!("{}: {} without \"indices\"", location, name)),
+                };
+                if let Some(&index) = indices.iter().find(|&&index| index < 0.0 || index as usize >= mesh.positions.len()) {
+                    return Err(format!("{}: {} has vertex index {} out of range", location, name, index));
+                }
+                mesh.triangles = indices.chunks_exact(3).map(|t| [t[0] as usize, t[1] as usize, t[2] as usize]).collect();
+                if let Some(normals) = params.numbers("N") {
+                    mesh.normals = normals.chunks_exact(3).map(|n| Vec3::new(n[0], n[1], n[2])).collect();
+                }
+                if let Some(uvs) = params.numbers("uv").or_else(|| params.numbers("st")) {
+                    mesh.uvs = uvs.chunks_exact(2).map(|uv| (uv[0], uv[1])).collect();
+                    if mesh.uvs.len() != mesh.positions.len() {
+                        mesh.uvs.clear();
+                    }
+                }
+                self.add_mesh(mesh, state, material);
+            }
+            "plymesh" => {
+                let filename = params.string("filename").ok_or_else(|| format!("{}: plymesh without \"filename\"", location))?;
+                let path = self.path(filename);
+                let mesh = TriangleMesh::load_ply(&path).map_err(|error| match error.kind() {
+                    io::ErrorKind::InvalidData => format!("{}: {}", location, error),
+                    _ => format!("{}: {}: {}", location, path, error),
+                })?;
+                self.add_mesh(mesh, state, material);
+            }
+            _ => self.warn(location, format!("unsupported shape \"{}\" skipped", name)),
+        }
+        Ok(())
+    }
+
+    // Sets the current transform, unless it is the end-of-shutter transform of a moving object.
+    fn set_transform(&mut self, transform: Matrix, location: &str) {
+        if self.motion_end {
+            self.warn(location, String::from("moving transforms are rendered at the shutter opening"));
+            return;
+        }
+        self.state.transform = transform;
+    }
+
+    // Transform equal to PBRT's identity: the mirror into this renderer's world, once in it.
+    fn base(&self) -> Matrix {
+        if self.in_world { self.world_mirror } else { Matrix::IDENTITY }
+    }
+
+    fn directive(&mut self, parser: &mut Parser, directive: &str) -> Result<(), String> {
+        let location = parser.location();
+        let current = self.state.transform;
+        match directive {
+            "Identity" => self.set_transform(self.base(), &location),
+            "Translate" => {
+                let v = parser.numbers(3)?;
+                self.set_transform(current.multiply(&Matrix::translation(Vec3::new(v[0], v[1], v[2]))), &location);
+            }
+            "Scale" => {
+                let v = parser.numbers(3)?;
+                self.set_transform(current.multiply(&Matrix::scaling(Vec3::new(v[0], v[1], v[2]))), &location);
+            }
+            "Rotate" => {
+                let v = parser.numbers(4)?;
+                self.set_transform(current.multiply(&Matrix::rotation(Vec3::new(v[1], v[2], v[3]), v[0])), &location);
+            }
+            "LookAt" => {
+                let v = parser.numbers(9)?;
+                let (eye, target, up) = (Point3::new(v[0], v[1], v[2]), Point3::new(v[3], v[4], v[5]), Vec3::new(v[6], v[7], v[8]));
+                let forward = unit_vector(target - eye);
+                let right = cross(unit_vector(up), forward);
+                if right.length_squared() < 1e-12 {
+                    return Err(format!("{}: LookAt with the up vector along the view direction", location));
+                }
+                let right = unit_vector(right);
+                let columns = [right, cross(forward, right), forward, eye];
+                let values: Vec<f64> = columns.iter().enumerate().flat_map(|(i, c)| [c.x(), c.y(), c.z(), if i == 3 { 1.0 } else { 0.0 }]).collect();
+                let camera_to_world = Matrix::from_columns(&values);
+                self.set_transform(current.multiply(&camera_to_world.inverse().unwrap_or(Matrix::IDENTITY)), &location);
+            }
+            "Transform" => {
+                let values = parser.numbers(16)?;
+                self.set_transform(self.base().multiply(&Matrix::from_columns(&values)), &location);
+            }
+            "ConcatTransform" => {
+                let values = parser.numbers(16)?;
+                self.set_transform(current.multiply(&Matrix::from_columns(&values)), &location);
+            }
+            "CoordinateSystem" => {
+                let name = parser.string()?;
+                self.coordinate_systems.insert(name, current);
+            }
+            "CoordSysTransform" => {
+                let name = parser.string()?;
+                match self.coordinate_systems.get(&name) {
+                    Some(&transform) => self.set_transform(transform, &location),
+                    None => self.warn(&location, format!("unknown coordinate system \"{}\" ignored", name)),
+                }
+            }
+            "ReverseOrientation" => self.state.reverse_orientation = !self.state.reverse_orientation,
+            "TransformTimes" => {
+                parser.numbers(2)?;
+            }
+            "ActiveTransform" => match parser.next() {
+                Some(Token::Word(which)) => self.motion_end = which == "EndTime",
+                _ => return Err(parser.error("expected StartTime, EndTime or All")),
+            },
+            "Camera" => {
+                let kind = parser.string()?;
+                let params = parser.params()?;
+                // The current transform goes from the world to the camera.
+                let camera_to_pbrt_world = current.inverse().ok_or_else(|| format!("{}: camera transform cannot be inverted", location))?;
+                self.world_mirror = if current.determinant() < 0.0 { Matrix::IDENTITY } else { MIRROR };
+                let camera_to_world = self.world_mirror.multiply(&camera_to_pbrt_world);
+                self.coordinate_systems.insert(String::from("camera"), camera_to_world);
+                self.camera = Some((kind, params, camera_to_world));
+            }
+            "Film" => {
+                parser.string()?;
+                self.film = parser.params()?;
+            }
+            "Sampler" => {
+                let kind = parser.string()?;
+                self.sampler = Some((kind, parser.params()?));
+            }
+            "Integrator" => {
+                parser.string()?;
+                let params = parser.params()?;
+                // PBRT counts bounces, this renderer counts the rays of a path.
+                self.scene.max_depth = Some(params.float("maxdepth").unwrap_or(5.0) as i32 + 1);
+            }
+            "PixelFilter" | "Accelerator" | "ColorSpace" => {
+                parser.string()?;
+                parser.params()?;
+            }
+            "Option" => {
+                parser.params()?;
+            }
+            "MakeNamedMedium" | "MediumInterface" => {
+                while let Some(Token::Text(text)) = parser.peek() {
+                    if text.contains(' ') {
+                        break;
+                    }
+                    parser.position += 1;
+                }
+                parser.params()?;
+                self.warn(&location, String::from("participating media are not supported"));
+            }
+            "WorldBegin" => {
+                self.in_world = true;
+                self.state.transform = self.world_mirror;
+                self.coordinate_systems.insert(String::from("world"), self.world_mirror);
+            }
+            "WorldEnd" => {}
+            "AttributeBegin" | "TransformBegin" => self.stack.push((self.state.clone(), directive == "TransformBegin")),
+            "AttributeEnd" | "TransformEnd" => match self.stack.pop() {
+                Some((state, transform_only)) => {
+                    if transform_only != (directive == "TransformEnd") {
+                        self.warn(&location, format!("{} does not match the last begin", directive));
+                    }
+                    if transform_only {
+                        self.state.transform = state.transform;
+                    } else {
+                        self.state = state;
+                    }
+                }
+                None => self.warn(&location, format!("unmatched {} ignored", directive)),
+            },
+            "Attribute" => {
+                parser.string()?;
+                parser.params()?;
+                self.warn(&location, String::from("Attribute defaults are not supported"));
+            }
+            "Shape" => {
+                let name = parser.string()?;
+                let params = parser.params()?;
+                match &mut self.object {
+                    Some((_, calls)) => calls.push(ShapeCall { name, params, state: self.state.clone(), location }),
+                    None => {
+                        let state = self.state.clone();
+                        self.shape(&name, &params, &state, &location)?;
+                    }
+                }
+            }
+            "ObjectBegin" => {
+                let name = parser.string()?;
+                self.stack.push((self.state.clone(), false));
+                self.object = Some((name, Vec::new()));
+            }
+            "ObjectEnd" => {
+                if let Some((name, calls)) = self.object.take() {
+                    self.objects.insert(name, calls);
+                }
+                if let Some((state, _)) = self.stack.pop() {
+                    self.state = state;
+                }
+            }
+            "ObjectInstance" => {
+                let name = parser.string()?;
+                let calls = self.objects.remove(&name).ok_or_else(|| format!("{}: unknown object \"{}\"", location, name))?;
+                // The shapes were placed in the world when defined; the instance moves them on,
+                // from PBRT's world rather than the mirrored one.
+                let placement = current.multiply(&self.world_mirror);
+                for call in &calls {
+                    let mut state = call.state.clone();
+                    state.transform = placement.multiply(&call.state.transform);
+                    self.shape(&call.name, &call.params, &state, &call.location)?;
+                }
+                self.objects.insert(name, calls);
+            }
+            "Material" => {
+                let kind = parser.string()?;
+                let params = parser.params()?;
+                self.state.material = self.material(&kind, &params, &location);
+            }
+            "MakeNamedMaterial" => {
+                let name = parser.string()?;
+                let params = parser.params()?;
+                let kind = params.string("type").unwrap_or("").to_string();
+                let material = self.material(&kind, &params, &location);
+                self.named_materials.insert(name, material);
+            }
+            "NamedMaterial" => {
+                let name = parser.string()?;
+                match self.named_materials.get(&name) {
+                    Some(material) => self.state.material = material.clone(),
+                    None => self.warn(&location, format!("unknown material \"{}\" ignored", name)),
+                }
+            }
+            "Texture" => {
+                let name = parser.string()?;
+                let kind = parser.string()?;
+                let class = parser.string()?;
+                let params = parser.params()?;
+                self.define_texture(name, &kind, &class, &params, &location);
+            }
+            "LightSource" => {
+                let kind = parser.string()?;
+                let params = parser.params()?;
+                self.light(&kind, &params, &location);
+            }
+            "AreaLightSource" => {
+                let kind = parser.string()?;
+                let params = parser.params()?;
+                if kind != "diffuse" {
+                    self.warn(&location, format!("unsupported area light \"{}\" skipped", kind));
+                    return Ok(());
+                }
+                let radiance = self.color(&params, "L", &location).unwrap_or(gray(1.0));
+                let scale = self.color(&params, "scale", &location).unwrap_or(gray(1.0));
+                let two_sided = params.bool("twosided").unwrap_or(false);
+                self.state.area_light = Some(Rc::new(RefCell::new(DiffuseLight { emit: scale * radiance, two_sided })));
+            }
+            _ => {
+                self.warn(&location, format!("unsupported directive {} ignored", directive));
+                while parser.peek().is_some_and(|token| !matches!(token, Token::Word(_))) {
+                    parser.position += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Turns the camera, film and sampler settings into the scene's view and render settings.
+    fn finish(&mut self) {
+        let location = String::from("camera");
+        let width = self.film.float("xresolution").unwrap_or(1280.0);
+        let height = self.film.float("yresolution").unwrap_or(720.0);
+        let aspect_ratio = width / height;
+        self.scene.image_width = Some(width);
+
+        if let Some((kind, params)) = self.sampler.take() {
+            let samples = match kind.as_str() {
+                "stratified" => params.float("xsamples").unwrap_or(4.0) * params.float("ysamples").unwrap_or(4.0),
+                _ => params.float("pixelsamples").unwrap_or(16.0),
+            };
+            self.scene.samples_per_pixel = Some(samples as i32);
+            let name = match kind.as_str() {
+                "random" | "independent" => "independent",
+                "stratified" => "stratified",
+                "halton" => "halton",
+                "sobol" | "zsobol" | "paddedsobol" | "pmj02bn" | "02sequence" | "lowdiscrepancy" | "maxmindist" => "sobol",
+                _ => {
+                    self.warn(&location, format!("unknown sampler \"{}\" replaced by sobol", kind));
+                    "sobol"
+                }
+            };
+            self.scene.sampler_name = Some(String::from(name));
+        }
+
+        let (kind, params, camera_to_world) = self.camera.take().unwrap_or((String::from("perspective"), Params::default(), MIRROR));
+        let look_from = camera_to_world.point(Point3::new(0.0, 0.0, 0.0));
+        let mut view = View {
+            look_from,
+            look_at: look_from + camera_to_world.vector(Vec3::new(0.0, 0.0, 1.0)),
+            vup: camera_to_world.vector(Vec3::new(0.0, 1.0, 0.0)),
+            vfov: 90.0,
+            aspect_ratio: Some(aspect_ratio),
+            projection: Projection::Perspective,
+            defocus_angle: 0.0,
+            focus_distance: None,
+        };
+        if params.get("screenwindow").is_some() {
+            self.warn(&location, String::from("camera screen windows are ignored"));
+        }
+        match kind.as_str() {
+            "perspective" | "realistic" => {
+                if kind == "realistic" {
+                    self.warn(&location, String::from("realistic cameras are rendered as perspective ones"));
+                }
+                // The field of view spans the shorter side of the image.
+                let fov = degrees_to_radians(params.float("fov").unwrap_or(90.0));
+                view.vfov = if aspect_ratio >= 1.0 { fov } else { 2.0 * ((fov / 2.0).tan() / aspect_ratio).atan() } * 180.0 / PI;
+                let lens_radius = params.float("lensradius").unwrap_or(0.0);
+                if lens_radius > 0.0 {
+                    let focus_distance = params.float("focaldistance").unwrap_or(1e6);
+                    view.defocus_angle = 2.0 * (lens_radius / focus_distance).atan() * 180.0 / PI;
+                    view.focus_distance = Some(focus_distance);
+                }
+            }
+            "orthographic" => {
+                let scale = camera_to_world.vector(Vec3::new(1.0, 0.0, 0.0)).length();
+                view.projection = Projection::Orthographic { width: 2.0 * aspect_ratio.max(1.0) * scale };
+            }
+            "spherical" => view.projection = Projection::Equirectangular,
+            _ => self.warn(&location, format!("unsupported camera \"{}\" rendered as a perspective one", kind)),
+        }
+        self.scene.views.push(view);
+    }
+}
+
+// Loads the subset of a PBRT v3 or v4 scene this renderer can show: spheres, triangle and PLY
+// meshes, diffuse, conductor, dielectric and coated materials with image and checkerboard
+// textures, point, spot, distant, uniform infinite and diffuse area lights, and the camera, film
+// and sampler settings. Anything else is reported once and skipped.
+pub(crate) fn load_pbrt(path: &str) -> io::Result<Scene> {
+    let directory = Path::new(path).parent().unwrap_or(Path::new(".")).to_path_buf();
+    let (mut files, mut tokens) = (Vec::new(), Vec::new());
+    tokenize(Path::new(path), &directory, &mut files, &mut tokens).map_err(invalid_data)?;
+    let mut parser = Parser { tokens, files, position: 0 };
+    let mut loader = Loader {
+        directory,
+        scene: Scene::new(),
+        state: GraphicsState { transform: Matrix::IDENTITY, material: Some(Loader::default_material()), area_light: None, reverse_orientation: false },
+        stack: Vec::new(),
+        in_world: false,
+        world_mirror: MIRROR,
+        motion_end: false,
+        coordinate_systems: HashMap::new(),
+        named_materials: HashMap::new(),
+        textures: HashMap::new(),
+        float_textures: HashSet::new(),
+        objects: HashMap::new(),
+        object: None,
+        camera: None,
+        film: Params::default(),
+        sampler: None,
+        warned: HashSet::new(),
+    };
+    while let Some(token) = parser.next() {
+        let Token::Word(directive) = token else {
+            return Err(invalid_data(parser.error("expected a directive")));
+        };
+        loader.directive(&mut parser, &directive).map_err(invalid_data)?;
+    }
+    loader.finish();
+    Ok(loader.scene)
+}
@@ -0,0 +1,61 @@
+// Photographic description of a camera. When set on a `Camera` it derives the field of view, the
+// lens aperture and the exposure instead of `vfov`, `defocus_angle` and the film exposure.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PhysicalCamera {
+    pub(crate) sensor_width: f64,
+    // Sensor width in millimeters (36 for full frame)
+    pub(crate) sensor_height: f64,
+    // Sensor height in millimeters (24 for full frame)
+    pub(crate) focal_length: f64,
+    // Lens focal length in millimeters
+    pub(crate) f_number: f64,
+    // Ratio of focal length over aperture diameter
+    pub(crate) iso: f64,
+    // Sensor sensitivity
+    pub(crate) shutter_seconds: f64,
+    // Exposure time; the shutter interval of the scene only sets the motion blur
+    pub(crate) exposure_compensation: f64,
+    // Extra exposure in EV stops
+    pub(crate) meters_per_unit: f64, // Size of one world unit, to express the aperture in world units
+}
+
+impl PhysicalCamera {
+    pub(crate) fn new() -> Self {
+        PhysicalCamera {
+            sensor_width: 36.0,
+            sensor_height: 24.0,
+            focal_length: 50.0,
+            f_number: 8.0,
+            iso: 100.0,
+            shutter_seconds: 1.0 / 125.0,
+            exposure_compensation: 0.0,
+            meters_per_unit: 1.0,
+        }
+    }
+
+    // Vertical field of view in degrees for an image of the given aspect ratio. The image is fit
+    // inside the sensor, cropping whichever sensor dimension does not match the aspect ratio.
+    pub(crate) fn vertical_fov(&self, aspect_ratio: f64) -> f64 {
+        let sensor_aspect = self.sensor_width / self.sensor_height;
+        let image_height = if aspect_ratio >= sensor_aspect { self.sensor_width / aspect_ratio } else { self.sensor_height };
+        2.0 * (image_height / (2.0 * self.focal_length)).atan().to_degrees()
+    }
+
+    // Radius of the entrance pupil in world units.
+    pub(crate) fn aperture_radius(&self) -> f64 {
+        let diameter_mm = self.focal_length / self.f_number;
+        diameter_mm / 2.0 / 1000.0 / self.meters_per_unit
+    }
+
+    // Exposure value of the settings at ISO 100.
+    fn ev100(&self) -> f64 {
+        (self.f_number * self.f_number / self.shutter_seconds.max(1e-9) * 100.0 / self.iso).log2()
+    }
+
+    // Film exposure adjustment in EV stops. Scene radiance is taken as metered for the default
+    // settings, f/8 at 1/125 s and ISO 100, which render as bright as a camera without physical
+    // settings; every stop of EV100 above them halves the image.
+    pub(crate) fn exposure(&self) -> f64 {
+        self.exposure_compensation - (self.ev100() - PhysicalCamera::new().ev100())
+    }
+}
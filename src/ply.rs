@@ -0,0 +1,261 @@
+use std::fs;
+use std::io;
+
+use crate::color::Color;
+use crate::image::srgb_to_linear;
+use crate::mesh::invalid_data;
+use crate::vec3::Vec3;
+
+// Storage type of a PLY property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PlyType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl PlyType {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "char" | "int8" => Some(PlyType::Int8),
+            "uchar" | "uint8" => Some(PlyType::UInt8),
+            "short" | "int16" => Some(PlyType::Int16),
+            "ushort" | "uint16" => Some(PlyType::UInt16),
+            "int" | "int32" => Some(PlyType::Int32),
+            "uint" | "uint32" => Some(PlyType::UInt32),
+            "float" | "float32" => Some(PlyType::Float32),
+            "double" | "float64" => Some(PlyType::Float64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyType::Int8 | PlyType::UInt8 => 1,
+            PlyType::Int16 | PlyType::UInt16 => 2,
+            PlyType::Int32 | PlyType::UInt32 | PlyType::Float32 => 4,
+            PlyType::Float64 => 8,
+        }
+    }
+
+    // Largest value of the unsigned types colors are stored in, by which they are divided.
+    pub(crate) fn integer_max(self) -> Option<f64> {
+        match self {
+            PlyType::UInt8 => Some(255.0),
+            PlyType::UInt16 => Some(65535.0),
+            _ => None,
+        }
+    }
+
+    fn decode(self, bytes: &[u8], big_endian: bool) -> f64 {
+        macro_rules! read {
+            ($t:ty) => {{
+                let array = bytes.try_into().unwrap();
+                (if big_endian { <$t>::from_be_bytes(array) } else { <$t>::from_le_bytes(array) }) as f64
+            }};
+        }
+        match self {
+            PlyType::Int8 => bytes[0] as i8 as f64,
+            PlyType::UInt8 => bytes[0] as f64,
+            PlyType::Int16 => read!(i16),
+            PlyType::UInt16 => read!(u16),
+            PlyType::Int32 => read!(i32),
+            PlyType::UInt32 => read!(u32),
+            PlyType::Float32 => read!(f32),
+            PlyType::Float64 => read!(f64),
+        }
+    }
+}
+
+enum PlyColumn {
+    Scalar(Vec<f64>),
+    // All the lists one after the other, with where each one ends
+    List { values: Vec<f64>, ends: Vec<usize> },
+}
+
+struct PlyProperty {
+    name: String,
+    kind: PlyType,
+    count_kind: Option<PlyType>, // Type of the length of list properties
+    column: PlyColumn,
+}
+
+// The values of one element (vertex, face, ...) by property.
+pub(crate) struct PlyElement {
+    pub(crate) name: String,
+    pub(crate) count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+impl PlyElement {
+    fn property(&self, name: &str) -> Option<&PlyProperty> {
+        self.properties.iter().find(|property| property.name == name)
+    }
+
+    pub(crate) fn scalar(&self, name: &str) -> Option<&[f64]> {
+        match &self.property(name)?.column {
+            PlyColumn::Scalar(values) => Some(values),
+            PlyColumn::List { .. } => None,
+        }
+    }
+
+    // The list of each instance of the element.
+    pub(crate) fn lists(&self, name: &str) -> Option<Vec<&[f64]>> {
+        match &self.property(name)?.column {
+            PlyColumn::List { values, ends } => {
+                Some(ends.iter().scan(0, |start, &end| Some(&values[std::mem::replace(start, end)..end])).collect())
+            }
+            PlyColumn::Scalar(_) => None,
+        }
+    }
+
+    // Three scalar properties taken together, such as nx, ny and nz.
+    pub(crate) fn vectors(&self, names: [&str; 3]) -> Option<Vec<Vec3>> {
+        let (x, y, z) = (self.scalar(names[0])?, self.scalar(names[1])?, self.scalar(names[2])?);
+        Some((0..self.count).map(|i| Vec3::new(x[i], y[i], z[i])).collect())
+    }
+
+    // Colors from red, green and blue (or r, g and b). Integer colors are taken as sRGB and
+    // floating point ones as linear.
+    pub(crate) fn colors(&self) -> Option<Vec<Color>> {
+        let channel = |names: [&str; 2]| {
+            names.iter().find_map(|&name| {
+                let values = self.scalar(name)?;
+                Some(match self.property(name)?.kind.integer_max() {
+                    Some(max) => values.iter().map(|&value| srgb_to_linear(value / max)).collect(),
+                    None => values.to_vec(),
+                })
+            })
+        };
+        let (r, g, b): (Vec<f64>, Vec<f64>, Vec<f64>) = (channel(["red", "r"])?, channel(["green", "g"])?, channel(["blue", "b"])?);
+        Some((0..self.count).map(|i| Color::new(r[i], g[i], b[i])).collect())
+    }
+
+}
+
+// A Stanford PLY file, ASCII or binary of either byte order.
+pub(crate) struct Ply {
+    pub(crate) elements: Vec<PlyElement>,
+}
+
+impl Ply {
+    pub(crate) fn load(path: &str) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        Ply::parse(&data).map_err(|message| invalid_data(format!("{}: {}", path, message)))
+    }
+
+    pub(crate) fn element(&self, name: &str) -> Option<&PlyElement> {
+        self.elements.iter().find(|element| element.name == name)
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, String> {
+        // The data starts after the line break that ends the end_header line.
+        let header_end = data.windows(10).position(|window| window == b"end_header").ok_or("no end_header line")?;
+        let header = String::from_utf8_lossy(&data[..header_end]);
+        let body = header_end + data[header_end..].iter().position(|&b| b == b'\n').ok_or("no data after the header")? + 1;
+
+        let mut lines = header.lines();
+        if lines.next().map(str::trim) != Some("ply") {
+            return Err(String::from("not a PLY file"));
+        }
+        let mut format = None;
+        let mut elements: Vec<PlyElement> = Vec::new();
+        for line in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["format", name, _version] => format = Some(name.to_string()),
+                ["element", name, count] => elements.push(PlyElement {
+                    name: name.to_string(),
+                    count: count.parse().map_err(|_| format!("invalid count of element {}", name))?,
+                    properties: Vec::new(),
+                }),
+                ["property", "list", count_type, item_type, name] => {
+                    let element = elements.last_mut().ok_or("property before any element")?;
+                    element.properties.push(PlyProperty {
+                        name: name.to_string(),
+                        kind: PlyType::from_name(item_type).ok_or_else(|| format!("unknown type {}", item_type))?,
+                        count_kind: Some(PlyType::from_name(count_type).ok_or_else(|| format!("unknown type {}", count_type))?),
+                        column: PlyColumn::List { values: Vec::new(), ends: Vec::new() },
+                    });
+                }
+                ["property", kind, name] => {
+                    let element = elements.last_mut().ok_or("property before any element")?;
+                    element.properties.push(PlyProperty {
+                        name: name.to_string(),
+                        kind: PlyType::from_name(kind).ok_or_else(|| format!("unknown type {}", kind))?,
+                        count_kind: None,
+                        column: PlyColumn::Scalar(Vec::new()),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        match format.as_deref() {
+            Some("ascii") => Ply::read_ascii(&data[body..], &mut elements, header.lines().count() + 2)?,
+            Some("binary_little_endian") => Ply::read_binary(&data[body..], &mut elements, false)?,
+            Some("binary_big_endian") => Ply::read_binary(&data[body..], &mut elements, true)?,
+            _ => return Err(String::from("missing or unknown format")),
+        }
+        Ok(Ply { elements })
+    }
+
+    fn read_ascii(data: &[u8], elements: &mut [PlyElement], first_line: usize) -> Result<(), String> {
+        let text = String::from_utf8_lossy(data);
+        let mut tokens = text.lines().enumerate().flat_map(|(line, text)| text.split_whitespace().map(move |token| (first_line + line, token)));
+        for element in elements {
+            for index in 0..element.count {
+                let mut next = || -> Result<f64, String> {
+                    let (line, token) = tokens.next().ok_or_else(|| format!("data ends in {} {} of {}", element.name, index, element.count))?;
+                    token.parse().map_err(|_| format!("line {}: invalid number {}", line, token))
+                };
+                for property in &mut element.properties {
+                    match &mut property.column {
+                        PlyColumn::Scalar(values) => values.push(next()?),
+                        PlyColumn::List { values, ends } => {
+                            let length = next()? as usize;
+                            for _ in 0..length {
+                                values.push(next()?);
+                            }
+                            ends.push(values.len());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_binary(data: &[u8], elements: &mut [PlyElement], big_endian: bool) -> Result<(), String> {
+        let mut position = 0;
+        for element in elements {
+            for index in 0..element.count {
+                let mut read = |kind: PlyType| -> Result<f64, String> {
+                    let bytes = data
+                        .get(position..position + kind.size())
+                        .ok_or_else(|| format!("data ends in {} {} of {}", element.name, index, element.count))?;
+                    position += kind.size();
+                    Ok(kind.decode(bytes, big_endian))
+                };
+                for property in &mut element.properties {
+                    match &mut property.column {
+                        PlyColumn::Scalar(values) => values.push(read(property.kind)?),
+                        PlyColumn::List { values, ends } => {
+                            let length = read(property.count_kind.unwrap_or(PlyType::UInt8))? as usize;
+                            for _ in 0..length {
+                                values.push(read(property.kind)?);
+                            }
+                            ends.push(values.len());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::deflate::{zlib_compress, zlib_decompress};
+
+// Writes an 8-bit RGB PNG. `rgb` holds the rows top to bottom, three bytes per pixel.
+pub(crate) fn write_png(path: &str, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Bit depth 8, color type 2 (RGB), deflate, adaptive filtering, no interlace.
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut out, b"IHDR", &header)?;
+
+    write_chunk(&mut out, b"IDAT", &zlib_compress(&filter_rows(width, height, rgb)))?;
+    write_chunk(&mut out, b"IEND", &[])?;
+    out.flush()
+}
+
+fn write_chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data.iter()).copied());
+    out.write_all(&crc.to_be_bytes())
+}
+
+// Prefixes every row with the filter that gives the smallest sum of absolute residuals, the
+// usual heuristic for photographic images.
+fn filter_rows(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    const BPP: usize = 3;
+    let stride = width * BPP;
+    let mut filtered = Vec::with_capacity((stride + 1) * height);
+    let zero_row = vec![0u8; stride];
+    let mut candidate = vec![0u8; stride];
+    let mut best = vec![0u8; stride];
+
+    for y in 0..height {
+        let row = &rgb[y * stride..(y + 1) * stride];
+        let previous = if y > 0 { &rgb[(y - 1) * stride..y * stride] } else { &zero_row[..] };
+        let mut best_filter = 0;
+        let mut best_cost = u64::MAX;
+
+        for filter in 0..5u8 {
+            for i in 0..stride {
+                let a = if i >= BPP { row[i - BPP] } else { 0 };
+                let b = previous[i];
+                let c = if i >= BPP { previous[i - BPP] } else { 0 };
+                let predictor = match filter {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    _ => paeth(a, b, c),
+                };
+                candidate[i] = row[i].wrapping_sub(predictor);
+            }
+            let cost: u64 = candidate.iter().map(|&v| (v as i8).unsigned_abs() as u64).sum();
+            if cost < best_cost {
+                best_cost = cost;
+                best_filter = filter;
+                best.copy_from_slice(&candidate);
+            }
+        }
+
+        filtered.push(best_filter);
+        filtered.extend_from_slice(&best);
+    }
+
+    filtered
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Starting column and row and the steps between pixels of the seven Adam7 passes.
+const ADAM7: [(usize, usize, usize, usize); 7] = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+
+// Decodes a PNG of any color type and bit depth, interlaced or not. Returns the width, the height
+// and the pixels top to bottom as red, green, blue and alpha in [0, 1], still encoded as stored.
+pub(crate) fn read_png(data: &[u8]) -> Result<(usize, usize, Vec<[f64; 4]>), String> {
+    if !data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Err(String::from("not a PNG file"));
+    }
+    let mut header = None;
+    let mut palette: Vec<[f64; 4]> = Vec::new();
+    let mut transparent: Option<Vec<u8>> = None;
+    let mut compressed = Vec::new();
+    let mut position = 8;
+    while position + 8 <= data.len() {
+        let length = u32::from_be_bytes(data[position..position + 4].try_into().unwrap()) as usize;
+        let kind = &data[position + 4..position + 8];
+        let body = data.get(position + 8..position + 8 + length).ok_or("truncated chunk")?;
+        match kind {
+            b"IHDR" if length == 13 => header = Some(body.to_vec()),
+            b"PLTE" => palette = body.chunks_exact(3).map(|c| [c[0] as f64 / 255.0, c[1] as f64 / 255.0, c[2] as f64 / 255.0, 1.0]).collect(),
+            b"tRNS" => transparent = Some(body.to_vec()),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        position += 12 + length;
+    }
+
+    let header = header.ok_or("missing IHDR chunk")?;
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+    let (depth, color_type, interlaced) = (header[8] as usize, header[9], header[12] == 1);
+    let channels = match color_type {
+        0 => 1,
+        2 => 3,
+        3 => 1,
+        4 => 2,
+        6 => 4,
+        _ => return Err(format!("invalid color type {}", color_type)),
+    };
+    if ![1, 2, 4, 8, 16].contains(&depth) || (depth < 8 && channels > 1) || (depth == 16 && color_type == 3) {
+        return Err(format!("invalid bit depth {} for color type {}", depth, color_type));
+    }
+    // tRNS gives the alpha of palette entries, or else the one color that is fully transparent.
+    let mut transparent_color = None;
+    if let Some(values) = &transparent {
+        if color_type == 3 {
+            for (entry, &a) in palette.iter_mut().zip(values) {
+                entry[3] = a as f64 / 255.0;
+            }
+        } else if values.len() == 2 * channels {
+            let mut key = [0u32; 4];
+            for (k, pair) in values.chunks_exact(2).enumerate() {
+                key[k] = u16::from_be_bytes([pair[0], pair[1]]) as u32;
+            }
+            transparent_color = Some(key);
+        }
+    }
+    let raw = zlib_decompress(&compressed)?;
+
+    let max = ((1u32 << depth) - 1) as f64;
+    let bits_per_pixel = depth * channels;
+    let bpp = bits_per_pixel.div_ceil(8); // Byte distance for the filters
+    let mut pixels = vec![[0.0, 0.0, 0.0, 1.0]; width * height];
+    let mut offset = 0;
+    let passes: &[(usize, usize, usize, usize)] = if interlaced { &ADAM7 } else { &[(0, 0, 1, 1)] };
+    for &(x0, y0, dx, dy) in passes {
+        let pass_width = (width + dx - 1 - x0.min(width)) / dx;
+        let pass_height = (height + dy - 1 - y0.min(height)) / dy;
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let stride = (pass_width * bits_per_pixel).div_ceil(8);
+        let mut previous = vec![0u8; stride];
+        for row in 0..pass_height {
+            let filter = *raw.get(offset).ok_or("image data ends early")?;
+            let mut line = raw.get(offset + 1..offset + 1 + stride).ok_or("image data ends early")?.to_vec();
+            offset += 1 + stride;
+            for i in 0..stride {
+                let a = if i >= bpp { line[i - bpp] } else { 0 };
+                let (b, c) = (previous[i], if i >= bpp { previous[i - bpp] } else { 0 });
+                let predictor = match filter {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    4 => paeth(a, b, c),
+                    _ => return Err(format!("invalid filter type {}", filter)),
+                };
+                line[i] = line[i].wrapping_add(predictor);
+            }
+
+            let sample = |index: usize| -> u32 {
+                match depth {
+                    16 => u16::from_be_bytes([line[2 * index], line[2 * index + 1]]) as u32,
+                    8 => line[index] as u32,
+                    _ => {
+                        let bit = index * depth;
+                        (line[bit / 8] as u32 >> (8 - depth - bit % 8)) & ((1 << depth) - 1)
+                    }
+                }
+            };
+            for column in 0..pass_width {
+                let mut values = [0u32; 4];
+                for (k, value) in values.iter_mut().enumerate().take(channels) {
+                    *value = sample(column * channels + k);
+                }
+                let pixel = match color_type {
+                    3 => *palette.get(values[0] as usize).ok_or("palette index out of range")?,
+                    _ => {
+                        let v = |k: usize| values[k] as f64 / max;
+                        let mut pixel = match channels {
+                            1 => [v(0), v(0), v(0), 1.0],
+                            2 => [v(0), v(0), v(0), v(1)],
+                            3 => [v(0), v(1), v(2), 1.0],
+                            _ => [v(0), v(1), v(2), v(3)],
+                        };
+                        if transparent_color == Some(values) {
+                            pixel[3] = 0.0;
+                        }
+                        pixel
+                    }
+                };
+                pixels[(y0 + row * dy) * width + x0 + column * dx] = pixel;
+            }
+            previous = line;
+        }
+    }
+
+    Ok((width, height, pixels))
+}
+
+fn crc32(bytes: impl Iterator<Item = u8>) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+use crate::aabb::Aabb;
+use crate::color::Color;
+use crate::hittable::{HitRecord, Hittable, SceneHasher};
+use crate::interval::Interval;
+use crate::json::Json;
+use crate::material::MaterialTrait;
+use crate::mesh::invalid_data;
+use crate::ply::Ply;
+use crate::ray::Ray;
+use crate::scene_file::{flatten, SceneWriter};
+use crate::transform::Keyframe;
+use crate::vec3::{dot, unit_vector, Point3, Vec3};
+
+// Points per leaf of the hierarchy.
+const LEAF_SIZE: usize = 4;
+
+// What each point is drawn as.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum PointShape {
+    Sphere,
+    // Disk square to the point's normal, or facing the ray when the cloud has no normals
+    Disk,
+}
+
+impl PointShape {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sphere" => Some(PointShape::Sphere),
+            "disk" => Some(PointShape::Disk),
+            _ => None,
+        }
+    }
+}
+
+// Positions, colors and optional normals of a cloud of points.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PointData {
+    pub(crate) positions: Vec<Point3>,
+    pub(crate) colors: Vec<Color>,
+    pub(crate) normals: Vec<Vec3>,
+}
+
+impl PointData {
+    // Reads the vertices of a PLY file: x, y and z, with colors and normals (nx, ny and nz) when
+    // present.
+    pub(crate) fn load_ply(path: &str) -> io::Result<Self> {
+        let ply = Ply::load(path)?;
+        let vertices = ply.element("vertex").ok_or_else(|| invalid_data(format!("{}: no vertex element", path)))?;
+        Ok(PointData {
+            positions: vertices.vectors(["x", "y", "z"]).ok_or_else(|| invalid_data(format!("{}: vertices have no x, y and z", path)))?,
+            colors: vertices.colors().unwrap_or_default(),
+            normals: vertices.vectors(["nx", "ny", "nz"]).unwrap_or_default(),
+        })
+    }
+
+    // Bakes a transform into the points.
+    pub(crate) fn transform(&mut self, keyframe: &Keyframe) {
+        for p in &mut self.positions {
+            *p = keyframe.apply_point(*p);
+        }
+        for n in &mut self.normals {
+            if n.length_squared() > 0.0 {
+                *n = unit_vector(keyframe.apply_normal(*n));
+            }
+        }
+    }
+}
+
+// A node of the flattened hierarchy; leaves hold a range of points, interior nodes keep their
+// first child right after themselves.
+struct PointNode {
+    bbox: Aabb,
+    second_child: usize,
+    first_point: usize,
+    point_count: usize,
+}
+
+// Points drawn as small spheres or disks of one radius, each tinting the material with its color.
+// The points live in one array, reordered for a hierarchy built just for them, so millions of
+// them take far less memory and time than as separate objects.
+pub(crate) struct PointCloud {
+    points: PointData,
+    radius: f64,
+    shape: PointShape,
+    material_ptr: Rc<RefCell<dyn MaterialTrait>>,
+    nodes: Vec<PointNode>,
+}
+
+impl PointCloud {
+    pub(crate) fn new(points: PointData, radius: f64, shape: PointShape, material_ptr: Rc<RefCell<dyn MaterialTrait>>) -> Self {
+        let count = points.positions.len();
+        let mut order: Vec<usize> = (0..count).collect();
+        let mut nodes = Vec::with_capacity(2 * count / LEAF_SIZE + 1);
+        if count > 0 {
+            PointCloud::build(&points.positions, radius, &mut order, 0, count, &mut nodes);
+        }
+
+        // Store the points in the order of the leaves.
+        let reorder = |values: &[Vec3]| if values.is_empty() { Vec::new() } else { order.iter().map(|&i| values[i]).collect() };
+        let points = PointData { positions: reorder(&points.positions), colors: reorder(&points.colors), normals: reorder(&points.normals) };
+
+        PointCloud { points, radius, shape, material_ptr, nodes }
+    }
+
+    fn build(positions: &[Point3], radius: f64, order: &mut [usize], start: usize, end: usize, nodes: &mut Vec<PointNode>) -> usize {
+        let centers = order[start..end].iter().fold(Aabb::EMPTY, |bounds, &i| bounds.include(positions[i]));
+        let r = Vec3::new(radius, radius, radius);
+        let bbox = Aabb::from_points(centers.min() - r, centers.max() + r);
+        let index = nodes.len();
+        nodes.push(PointNode { bbox, second_child: 0, first_point: start, point_count: end - start });
+        if end - start <= LEAF_SIZE {
+            return index;
+        }
+
+        // Split at the median along the widest axis; selecting it is linear, unlike sorting.
+        let axis = centers.longest_axis();
+        let middle = start + (end - start) / 2;
+        order[start..end].select_nth_unstable_by(middle - start, |&a, &b| positions[a][axis].total_cmp(&positions[b][axis]));
+
+        PointCloud::build(positions, radius, order, start, middle, nodes);
+        let second_child = PointCloud::build(positions, radius, order, middle, end, nodes);
+        nodes[index].second_child = second_child;
+        nodes[index].point_count = 0;
+        index
+    }
+
+    // Distance to point `i` along the ray within `ray_t`, with the outward normal there.
+    fn hit_point(&self, i: usize, ray: &Ray, ray_t: Interval) -> Option<(f64, Vec3)> {
+        let center = self.points.positions[i];
+        match self.shape {
+            PointShape::Sphere => {
+                let oc = ray.origin() - center;
+                let a = ray.direction().length_squared();
+                let half_b = dot(oc, ray.direction());
+                let c = oc.length_squared() - self.radius * self.radius;
+                let discriminant = half_b * half_b - a * c;
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let sqrtd = discriminant.sqrt();
+                let t = [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a].into_iter().find(|&t| ray_t.surrounds(t))?;
+                Some((t, (ray.at(t) - center) / self.radius))
+            }
+            PointShape::Disk => {
+                let normal = match self.points.normals.get(i) {
+                    Some(&normal) if normal.length_squared() > 0.0 => unit_vector(normal),
+                    _ => -unit_vector(ray.direction()),
+                };
+                let denominator = dot(ray.direction(), normal);
+                if denominator.abs() < 1e-12 {
+                    return None;
+                }
+                let t = dot(center - ray.origin(), normal) / denominator;
+                if !ray_t.surrounds(t) || (ray.at(t) - center).length_squared() > self.radius * self.radius {
+                    return None;
+                }
+                Some((t, normal))
+            }
+        }
+    }
+}
+
+impl Hittable for PointCloud {
+    fn hit(&self, ray: &Ray, ray_t: Interval, record: &mut HitRecord) -> bool {
+        let mut closest = ray_t.max;
+        let mut nearest: Option<(usize, Vec3)> = None;
+        let mut stack = Vec::with_capacity(64);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.bbox.hit(ray, Interval::with_bounds(ray_t.min, closest)) {
+                continue;
+            }
+            if node.point_count == 0 {
+                stack.push(node.second_child);
+                stack.push(index + 1);
+                continue;
+            }
+            for i in node.first_point..node.first_point + node.point_count {
+                if let Some((t, normal)) = self.hit_point(i, ray, Interval::with_bounds(ray_t.min, closest)) {
+                    closest = t;
+                    nearest = Some((i, normal));
+                }
+            }
+        }
+
+        let Some((i, outward_normal)) = nearest else {
+            return false;
+        };
+        record.t = closest;
+        record.point = ray.at(closest);
+        record.set_face_normal(ray, outward_normal);
+        record.set_material(&self.material_ptr);
+        record.tint = self.points.colors.get(i).copied();
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |node| node.bbox)
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        let shape = match self.shape {
+            PointShape::Sphere => "sphere",
+            PointShape::Disk => "disk",
+        };
+        Some(Json::object(vec![
+            ("type", "point_cloud".into()),
+            ("positions", flatten(&self.points.positions)),
+            ("colors", flatten(&self.points.colors)),
+            ("normals", flatten(&self.points.normals)),
+            ("radius", self.radius.into()),
+            ("shape", shape.into()),
+            ("material", scene.material(&self.material_ptr)),
+        ]))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add(self.points.positions.len() as u64);
+        hasher.add_box(&self.bounding_box());
+        hasher.add_material(&self.material_ptr);
+    }
+}
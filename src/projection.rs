@@ -0,0 +1,69 @@
+use crate::utils::{degrees_to_radians, PI};
+use crate::vec3::Vec3;
+
+// How the camera maps positions on the image to ray directions. All of them use the camera basis
+// (`u` right, `v` up, `-w` forward) set up from `look_from`, `look_at` and `vup`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Projection {
+    // Thin lens perspective driven by `vfov`
+    Perspective,
+    // Parallel rays; `width` is the extent of the image in world units
+    Orthographic { width: f64 },
+    // Fisheye with image radius proportional to the angle from the view axis; `fov` in degrees
+    // covers the circle inscribed in the image height
+    FisheyeEquidistant { fov: f64 },
+    // Fisheye that preserves solid angle (equal area); `fov` as for the equidistant fisheye
+    FisheyeEquisolid { fov: f64 },
+    // Full 360x180 degree latitude-longitude panorama, centered on the view direction
+    Equirectangular,
+}
+
+impl Projection {
+    pub(crate) fn from_name(name: &str, fov: f64, ortho_width: f64) -> Option<Self> {
+        match name {
+            "perspective" => Some(Projection::Perspective),
+            "orthographic" => Some(Projection::Orthographic { width: ortho_width }),
+            "fisheye" | "equidistant" => Some(Projection::FisheyeEquidistant { fov }),
+            "equisolid" => Some(Projection::FisheyeEquisolid { fov }),
+            "equirectangular" => Some(Projection::Equirectangular),
+            _ => None,
+        }
+    }
+
+    // Direction in camera space (x right, y up, z backwards) for the image position (s, t) in
+    // [0, 1]^2, with t growing downwards. Only used by the panoramic projections; returns None
+    // outside the image circle of a fisheye.
+    pub(crate) fn camera_direction(&self, s: f64, t: f64, aspect_ratio: f64) -> Option<Vec3> {
+        match *self {
+            Projection::Perspective | Projection::Orthographic { .. } => None,
+            Projection::FisheyeEquidistant { fov } | Projection::FisheyeEquisolid { fov } => {
+                // Image coordinates with the unit circle inscribed in the image height.
+                let x = (2.0 * s - 1.0) * aspect_ratio;
+                let y = 1.0 - 2.0 * t;
+                let radius = (x * x + y * y).sqrt();
+                let half_fov = degrees_to_radians(fov) / 2.0;
+
+                let theta = if let Projection::FisheyeEquisolid { .. } = self {
+                    let sine = radius * (half_fov / 2.0).sin();
+                    if sine > 1.0 {
+                        return None;
+                    }
+                    2.0 * sine.asin()
+                } else {
+                    radius * half_fov
+                };
+                if theta > PI || radius > 1.0 {
+                    return None;
+                }
+
+                let phi = y.atan2(x);
+                Some(Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), -theta.cos()))
+            }
+            Projection::Equirectangular => {
+                let phi = 2.0 * PI * (s - 0.5);
+                let theta = PI * t;
+                Some(Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos()))
+            }
+        }
+    }
+}
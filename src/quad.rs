@@ -0,0 +1,80 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SceneHasher};
+use crate::interval::Interval;
+use crate::json::Json;
+use crate::material::MaterialTrait;
+use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
+use crate::vec3::{cross, dot, unit_vector, Point3, Vec3};
+
+// A parallelogram with one corner at `q` and its sides along `u` and `v`. The normal is
+// u × v, and the surface coordinates run along the two sides.
+pub(crate) struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    material_ptr: Rc<RefCell<dyn MaterialTrait>>,
+    normal: Vec3,
+    offset: f64, // Of the plane, along the normal
+    w: Vec3, // Turns a point of the plane into its coordinates along the sides
+    bbox: Aabb,
+}
+
+impl Quad {
+    pub(crate) fn new(q: Point3, u: Vec3, v: Vec3, material_ptr: Rc<RefCell<dyn MaterialTrait>>) -> Self {
+        let n = cross(u, v);
+        let normal = unit_vector(n);
+        let bbox = Aabb::surrounding(&Aabb::from_points(q, q + u + v), &Aabb::from_points(q + u, q + v));
+        Quad { q, u, v, material_ptr, normal, offset: dot(normal, q), w: n / dot(n, n), bbox }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, interval: Interval, record: &mut HitRecord) -> bool {
+        let denominator = dot(self.normal, ray.direction());
+        if denominator.abs() < 1e-8 {
+            return false;
+        }
+        let t = (self.offset - dot(self.normal, ray.origin())) / denominator;
+        if !interval.surrounds(t) {
+            return false;
+        }
+
+        let point = ray.at(t);
+        let planar = point - self.q;
+        let alpha = dot(self.w, cross(planar, self.v));
+        let beta = dot(self.w, cross(self.u, planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        record.t = t;
+        record.point = point;
+        record.set_face_normal(ray, self.normal);
+        record.set_material(&self.material_ptr);
+        (record.u, record.v) = (alpha, beta);
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![
+            ("type", "quad".into()),
+            ("q", self.q.into()),
+            ("u", self.u.into()),
+            ("v", self.v.into()),
+            ("material", scene.material(&self.material_ptr)),
+        ]))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add_box(&self.bounding_box());
+        hasher.add_material(&self.material_ptr);
+    }
+}
@@ -0,0 +1,318 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SceneHasher};
+use crate::interval::Interval;
+use crate::json::Json;
+use crate::material::MaterialTrait;
+use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
+use crate::utils::{degrees_to_radians, PI};
+use crate::vec3::{dot, unit_vector, Point3, Vec3};
+
+// Bisection steps when refining a polynomial root; enough to reach the precision of an f64.
+const ROOT_ITERATIONS: usize = 80;
+
+// Surfaces of revolution around the y axis, in their own space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum QuadricShape {
+    Cylinder { radius: f64 },
+    // Base of `radius` at y = 0 narrowing to the apex at y = `height`
+    Cone { radius: f64, height: f64 },
+    // Tip at the origin, opening upwards to `radius` at y = `height`
+    Paraboloid { radius: f64, height: f64 },
+    // One sheet whose radius at height y is sqrt(waist_radius² + (slope * y)²)
+    Hyperboloid { waist_radius: f64, slope: f64 },
+    // Tube of `minor_radius` around a ring of `major_radius` in the xz plane
+    Torus { major_radius: f64, minor_radius: f64 },
+}
+
+impl QuadricShape {
+    // Squared distance of the surface from the axis at height `y`. Not used for the torus.
+    pub(crate) fn radius_squared(&self, y: f64) -> f64 {
+        match *self {
+            QuadricShape::Cylinder { radius } => radius * radius,
+            QuadricShape::Cone { radius, height } => (radius / height * (height - y)).powi(2),
+            QuadricShape::Paraboloid { radius, height } => radius * radius * y / height,
+            QuadricShape::Hyperboloid { waist_radius, slope } => waist_radius * waist_radius + (slope * y).powi(2),
+            QuadricShape::Torus { .. } => 0.0,
+        }
+    }
+
+    // Parameters along the ray from `origin` in `direction` (both relative to the shape) at
+    // which it crosses the whole untrimmed surface, in increasing order.
+    fn intersections(&self, origin: Point3, direction: Vec3) -> Vec<f64> {
+        let (o, d) = (origin, direction);
+        let radial_a = d.x() * d.x() + d.z() * d.z();
+        let radial_b = 2.0 * (o.x() * d.x() + o.z() * d.z());
+        let radial_c = o.x() * o.x() + o.z() * o.z();
+
+        // Each surface of revolution is x² + z² = r²(y); subtract r²(y) along the ray.
+        let (a, b, c) = match *self {
+            QuadricShape::Cylinder { radius } => (radial_a, radial_b, radial_c - radius * radius),
+            QuadricShape::Cone { radius, height } => {
+                let k = (radius / height).powi(2);
+                let h = height - o.y();
+                (radial_a - k * d.y() * d.y(), radial_b + 2.0 * k * d.y() * h, radial_c - k * h * h)
+            }
+            QuadricShape::Paraboloid { radius, height } => {
+                let k = radius * radius / height;
+                (radial_a, radial_b - k * d.y(), radial_c - k * o.y())
+            }
+            QuadricShape::Hyperboloid { waist_radius, slope } => {
+                let k = slope * slope;
+                (radial_a - k * d.y() * d.y(), radial_b - 2.0 * k * o.y() * d.y(), radial_c - k * o.y() * o.y() - waist_radius * waist_radius)
+            }
+            QuadricShape::Torus { major_radius, minor_radius } => return torus_intersections(o, d, major_radius, minor_radius),
+        };
+        quadratic_roots(a, b, c)
+    }
+
+    fn outward_normal(&self, p: Point3) -> Vec3 {
+        // Gradient of x² + z² - r²(y), or the direction away from the ring for the torus.
+        let normal = match *self {
+            QuadricShape::Cylinder { .. } => Vec3::new(p.x(), 0.0, p.z()),
+            QuadricShape::Cone { radius, height } => Vec3::new(p.x(), (radius / height).powi(2) * (height - p.y()), p.z()),
+            QuadricShape::Paraboloid { radius, height } => Vec3::new(p.x(), -0.5 * radius * radius / height, p.z()),
+            QuadricShape::Hyperboloid { slope, .. } => Vec3::new(p.x(), -slope * slope * p.y(), p.z()),
+            QuadricShape::Torus { major_radius, .. } => {
+                let ring = unit_vector(Vec3::new(p.x(), 0.0, p.z()));
+                p - major_radius * ring
+            }
+        };
+        unit_vector(normal)
+    }
+}
+
+// A quadric or torus around a vertical axis through `center`, trimmed to heights from `y_min`
+// to `y_max` above the center and to `phi_max` degrees around the axis, starting from +x
+// towards +z.
+pub(crate) struct Quadric {
+    shape: QuadricShape,
+    center: Point3,
+    y_min: f64,
+    y_max: f64,
+    material_ptr: Rc<RefCell<dyn MaterialTrait>>,
+    pub(crate) phi_max: f64,
+    pub(crate) caps: bool, // Closes the ends with disks; the torus has none
+}
+
+impl Quadric {
+    pub(crate) fn new(shape: QuadricShape, center: Point3, y_min: f64, y_max: f64, material_ptr: Rc<RefCell<dyn MaterialTrait>>) -> Self {
+        Quadric { shape, center, y_min, y_max, material_ptr, phi_max: 360.0, caps: false }
+    }
+
+    // Angle around the axis in [0, 2π), or None when it lies outside the sweep.
+    fn swept_phi(&self, p: Point3) -> Option<f64> {
+        let mut phi = p.z().atan2(p.x());
+        if phi < 0.0 {
+            phi += 2.0 * PI;
+        }
+        (phi <= degrees_to_radians(self.phi_max)).then_some(phi)
+    }
+
+    fn surface_uv(&self, p: Point3, phi: f64) -> (f64, f64) {
+        let u = phi / degrees_to_radians(self.phi_max);
+        let v = match self.shape {
+            QuadricShape::Torus { major_radius, .. } => {
+                // Angle around the tube, from the outer equator upwards.
+                let rho = (p.x() * p.x() + p.z() * p.z()).sqrt();
+                let theta = p.y().atan2(rho - major_radius);
+                (if theta < 0.0 { theta + 2.0 * PI } else { theta }) / (2.0 * PI)
+            }
+            _ => (p.y() - self.y_min) / (self.y_max - self.y_min),
+        };
+        (u, v)
+    }
+}
+
+impl Hittable for Quadric {
+    fn hit(&self, ray: &Ray, ray_t: Interval, record: &mut HitRecord) -> bool {
+        let origin = ray.origin() - self.center;
+        let direction = ray.direction();
+        let mut closest = ray_t.max;
+        // Local hit point, outward normal and surface coordinates of the nearest hit so far
+        let mut nearest: Option<(Point3, Vec3, (f64, f64))> = None;
+
+        for t in self.shape.intersections(origin, direction) {
+            if !Interval::with_bounds(ray_t.min, closest).surrounds(t) {
+                continue;
+            }
+            let p = origin + t * direction;
+            if p.y() < self.y_min || p.y() > self.y_max {
+                continue;
+            }
+            let Some(phi) = self.swept_phi(p) else {
+                continue;
+            };
+            closest = t;
+            nearest = Some((p, self.shape.outward_normal(p), self.surface_uv(p, phi)));
+            break;
+        }
+
+        let is_torus = matches!(self.shape, QuadricShape::Torus { .. });
+        if self.caps && !is_torus && direction.y() != 0.0 {
+            for (y, normal_y) in [(self.y_min, -1.0), (self.y_max, 1.0)] {
+                let t = (y - origin.y()) / direction.y();
+                if !Interval::with_bounds(ray_t.min, closest).surrounds(t) {
+                    continue;
+                }
+                let p = origin + t * direction;
+                let radius_squared = self.shape.radius_squared(y);
+                let rho_squared = p.x() * p.x() + p.z() * p.z();
+                if rho_squared > radius_squared {
+                    continue;
+                }
+                let Some(phi) = self.swept_phi(p) else {
+                    continue;
+                };
+                // Caps map u around the axis and v from the rim in to the axis.
+                let v = 1.0 - (rho_squared / radius_squared).sqrt();
+                closest = t;
+                nearest = Some((p, Vec3::new(0.0, normal_y, 0.0), (phi / degrees_to_radians(self.phi_max), v)));
+            }
+        }
+
+        let Some((p, outward_normal, (u, v))) = nearest else {
+            return false;
+        };
+        record.t = closest;
+        record.point = p + self.center;
+        record.set_face_normal(ray, outward_normal);
+        record.set_material(&self.material_ptr);
+        (record.u, record.v) = (u, v);
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let (radius, y_min, y_max) = match self.shape {
+            QuadricShape::Torus { major_radius, minor_radius } => {
+                (major_radius + minor_radius, self.y_min.max(-minor_radius), self.y_max.min(minor_radius))
+            }
+            // Every other profile is widest at one of its ends.
+            _ => {
+                let radius_squared = self.shape.radius_squared(self.y_min).max(self.shape.radius_squared(self.y_max));
+                (radius_squared.max(0.0).sqrt(), self.y_min, self.y_max)
+            }
+        };
+        Aabb::from_points(self.center + Vec3::new(-radius, y_min, -radius), self.center + Vec3::new(radius, y_max, radius))
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        let mut members: Vec<(&str, Json)> = match self.shape {
+            QuadricShape::Cylinder { radius } => vec![("shape", "cylinder".into()), ("radius", radius.into())],
+            QuadricShape::Cone { radius, height } => vec![("shape", "cone".into()), ("radius", radius.into()), ("height", height.into())],
+            QuadricShape::Paraboloid { radius, height } => vec![("shape", "paraboloid".into()), ("radius", radius.into()), ("height", height.into())],
+            QuadricShape::Hyperboloid { waist_radius, slope } => {
+                vec![("shape", "hyperboloid".into()), ("waist_radius", waist_radius.into()), ("slope", slope.into())]
+            }
+            QuadricShape::Torus { major_radius, minor_radius } => {
+                vec![("shape", "torus".into()), ("major_radius", major_radius.into()), ("minor_radius", minor_radius.into())]
+            }
+        };
+        members.insert(0, ("type", "quadric".into()));
+        members.extend([
+            ("center", self.center.into()),
+            ("y_min", self.y_min.into()),
+            ("y_max", self.y_max.into()),
+            ("phi_max", self.phi_max.into()),
+            ("caps", self.caps.into()),
+            ("material", scene.material(&self.material_ptr)),
+        ]);
+        Some(Json::object(members))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add_box(&self.bounding_box());
+        hasher.add_material(&self.material_ptr);
+    }
+}
+
+// Real roots of a t² + b t + c in increasing order, avoiding cancellation between b and the
+// square root of the discriminant.
+fn quadratic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return if b == 0.0 { Vec::new() } else { vec![-c / b] };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q == 0.0 {
+        return vec![0.0];
+    }
+    let (t0, t1) = (q / a, c / q);
+    if t0 < t1 { vec![t0, t1] } else { vec![t1, t0] }
+}
+
+fn torus_intersections(origin: Point3, direction: Vec3, major_radius: f64, minor_radius: f64) -> Vec<f64> {
+    // Solve along a unit direction from the point of the ray closest to the center, which keeps
+    // the quartic's coefficients small and well conditioned.
+    let length = direction.length();
+    let d = direction / length;
+    let shift = -dot(origin, d);
+    let o = origin + shift * d;
+
+    // (|p|² + R² - r²)² = 4R²(x² + z²) with p = o + s d
+    let r2 = major_radius * major_radius;
+    let f = dot(o, d);
+    let e = o.length_squared() + r2 - minor_radius * minor_radius;
+    let coefficients = [
+        e * e - 4.0 * r2 * (o.x() * o.x() + o.z() * o.z()),
+        4.0 * f * e - 8.0 * r2 * (o.x() * d.x() + o.z() * d.z()),
+        2.0 * e + 4.0 * f * f - 4.0 * r2 * (d.x() * d.x() + d.z() * d.z()),
+        4.0 * f,
+        1.0,
+    ];
+    polynomial_roots(&coefficients).into_iter().map(|s| (s + shift) / length).collect()
+}
+
+// Real roots, in increasing order, of the polynomial with the given coefficients (constant term
+// first, last one nonzero). The roots of the derivative split the line into stretches where the
+// polynomial is monotonic, and each stretch whose ends differ in sign holds one root, found by
+// bisection. Double roots, where the polynomial only touches zero, are missed.
+fn polynomial_roots(coefficients: &[f64]) -> Vec<f64> {
+    let degree = coefficients.len() - 1;
+    let leading = coefficients[degree];
+    if degree == 1 {
+        return vec![-coefficients[0] / leading];
+    }
+
+    let evaluate = |x: f64| coefficients.iter().rev().fold(0.0, |sum, c| sum * x + c);
+    let derivative: Vec<f64> = coefficients.iter().enumerate().skip(1).map(|(i, c)| i as f64 * c).collect();
+
+    // Cauchy's bound on the size of any root
+    let bound = 1.0 + coefficients[..degree].iter().fold(0.0_f64, |max, c| max.max((c / leading).abs()));
+    let mut ends = vec![-bound];
+    ends.extend(polynomial_roots(&derivative).into_iter().filter(|x| x.abs() < bound));
+    ends.push(bound);
+
+    let mut roots = Vec::new();
+    for pair in ends.windows(2) {
+        let (mut low, mut high) = (pair[0], pair[1]);
+        let (f_low, f_high) = (evaluate(low), evaluate(high));
+        if f_low == 0.0 {
+            roots.push(low);
+            continue;
+        }
+        if f_low * f_high > 0.0 {
+            continue;
+        }
+        let rising = f_high > f_low;
+        for _ in 0..ROOT_ITERATIONS {
+            let middle = 0.5 * (low + high);
+            if middle <= low || middle >= high {
+                break;
+            }
+            if (evaluate(middle) < 0.0) == rising {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        roots.push(0.5 * (low + high));
+    }
+    roots
+}
@@ -0,0 +1,414 @@
+use crate::utils::{hash_values, mix_bits};
+
+// Largest f64 strictly below one, used to keep samples inside [0, 1).
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// Bases for the Halton sequence; dimensions beyond the table wrap around with a fresh scramble.
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+    137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223,
+    227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+pub(crate) trait Sampler {
+    // Prepares the sampler to generate sample `sample_index` of `pixel`, starting at dimension 0.
+    fn start_pixel_sample(&mut self, pixel: (i32, i32), sample_index: u32);
+
+    // Jumps to `dimension` of the current pixel sample. Consumers that draw a variable amount of
+    // samples use this to keep later dimensions aligned between pixel samples.
+    fn set_dimension(&mut self, dimension: u32);
+
+    fn get_1d(&mut self) -> f64;
+
+    fn get_2d(&mut self) -> (f64, f64);
+
+    // Name the sampler is chosen by in `sampler_from_name`.
+    fn name(&self) -> &'static str;
+
+    // Seed that decorrelates this sampler from other renders of the same scene.
+    fn seed(&self) -> u64;
+
+    fn set_seed(&mut self, seed: u64);
+}
+
+// Builds one of the samplers by name, as given on the command line.
+pub(crate) fn sampler_from_name(name: &str, samples_per_pixel: i32, seed: u64) -> Option<Box<dyn Sampler>> {
+    match name {
+        "independent" => Some(Box::new(IndependentSampler::new(seed))),
+        "stratified" => {
+            let x_samples = (samples_per_pixel as f64).sqrt().floor().max(1.0) as u32;
+            let y_samples = (samples_per_pixel as u32).div_ceil(x_samples).max(1);
+            Some(Box::new(StratifiedSampler::new(x_samples, y_samples, true, seed)))
+        }
+        "halton" => Some(Box::new(HaltonSampler::new(seed))),
+        "sobol" => Some(Box::new(SobolSampler::new(seed))),
+        _ => None,
+    }
+}
+
+// Converts the high bits of a hash to a uniformly distributed float in [0, 1).
+fn hash_to_float(hash: u64) -> f64 {
+    (hash >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+fn pixel_hash(pixel: (i32, i32), dimension: u32, seed: u64) -> u64 {
+    hash_values(&[pixel.0 as u64, pixel.1 as u64, dimension as u64, seed])
+}
+
+// Returns the `index`-th element of a pseudo-random permutation of [0, length) selected by
+// `seed` (Kensler, "Correlated Multi-Jittered Sampling").
+fn permutation_element(mut index: u32, length: u32, seed: u32) -> u32 {
+    let mut mask = length.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dcb303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e501cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860a3df);
+        index &= mask;
+        index ^= index >> 5;
+
+        if index < length {
+            return index.wrapping_add(seed) % length;
+        }
+    }
+}
+
+// Hash based Owen scrambling of the bits of `value` (Burley, "Practical Hash-based Owen Scrambling").
+fn nested_uniform_scramble(value: u32, seed: u32) -> u32 {
+    let mut x = value.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+// First dimension of the Sobol sequence (the van der Corput sequence in base two).
+fn sobol_0(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+// Second dimension of the Sobol sequence, whose generator matrix is the Pascal matrix mod 2.
+fn sobol_1(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut direction = 1u32 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    result
+}
+
+// Radical inverse of `index` in `base` with every digit permuted depending on the digits that
+// precede it, which gives an Owen scrambled Halton sequence.
+fn owen_scrambled_radical_inverse(base: u64, mut index: u64, hash: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed_digits: u64 = 0;
+
+    while 1.0 - inv_base_m < 1.0 {
+        let next = index / base;
+        let digit = index - next * base;
+        let digit_hash = mix_bits(hash ^ reversed_digits) as u32;
+        let digit = permutation_element(digit as u32, base as u32, digit_hash) as u64;
+        reversed_digits = reversed_digits * base + digit;
+        inv_base_m *= inv_base;
+        index = next;
+    }
+
+    f64::min(inv_base_m * reversed_digits as f64, ONE_MINUS_EPSILON)
+}
+
+// Uniform random samples; the baseline every other sampler is measured against.
+pub(crate) struct IndependentSampler {
+    seed: u64,
+    pixel: (i32, i32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl IndependentSampler {
+    pub(crate) fn new(seed: u64) -> Self {
+        IndependentSampler { seed, pixel: (0, 0), sample_index: 0, dimension: 0 }
+    }
+
+    fn next(&mut self) -> f64 {
+        let hash = hash_values(&[
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.sample_index as u64,
+            self.dimension as u64,
+            self.seed,
+        ]);
+        self.dimension += 1;
+        hash_to_float(hash)
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel: (i32, i32), sample_index: u32) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn name(&self) -> &'static str {
+        "independent"
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.next(), self.next())
+    }
+}
+
+// Jittered stratified samples. Each dimension is split into `x_samples * y_samples` strata which
+// are visited in a per-pixel, per-dimension random order so dimensions stay uncorrelated.
+// Camera samples per pixel should match `x_samples * y_samples`; extra samples reuse the strata.
+pub(crate) struct StratifiedSampler {
+    x_samples: u32,
+    y_samples: u32,
+    jitter: bool,
+    seed: u64,
+    pixel: (i32, i32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub(crate) fn new(x_samples: u32, y_samples: u32, jitter: bool, seed: u64) -> Self {
+        StratifiedSampler {
+            x_samples: x_samples.max(1),
+            y_samples: y_samples.max(1),
+            jitter,
+            seed,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn samples_per_pixel(&self) -> u32 {
+        self.x_samples * self.y_samples
+    }
+
+    fn stratum(&self) -> u32 {
+        let hash = pixel_hash(self.pixel, self.dimension, self.seed);
+        let count = self.samples_per_pixel();
+        permutation_element(self.sample_index % count, count, hash as u32)
+    }
+
+    fn offset(&self, dimension: u32) -> f64 {
+        if !self.jitter {
+            return 0.5;
+        }
+        hash_to_float(hash_values(&[
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.sample_index as u64,
+            dimension as u64,
+            self.seed,
+        ]))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: (i32, i32), sample_index: u32) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn name(&self) -> &'static str {
+        "stratified"
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let stratum = self.stratum();
+        let delta = self.offset(self.dimension);
+        self.dimension += 1;
+        f64::min((stratum as f64 + delta) / self.samples_per_pixel() as f64, ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let stratum = self.stratum();
+        let x = stratum % self.x_samples;
+        let y = stratum / self.x_samples;
+        let dx = self.offset(self.dimension);
+        let dy = self.offset(self.dimension + 1);
+        self.dimension += 2;
+        (
+            f64::min((x as f64 + dx) / self.x_samples as f64, ONE_MINUS_EPSILON),
+            f64::min((y as f64 + dy) / self.y_samples as f64, ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+// Owen scrambled Halton sequence, one prime base per dimension and an independent scramble per
+// pixel.
+pub(crate) struct HaltonSampler {
+    seed: u64,
+    pixel: (i32, i32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl HaltonSampler {
+    pub(crate) fn new(seed: u64) -> Self {
+        HaltonSampler { seed, pixel: (0, 0), sample_index: 0, dimension: 0 }
+    }
+
+    fn next(&mut self) -> f64 {
+        let base = PRIMES[self.dimension as usize % PRIMES.len()];
+        let hash = pixel_hash(self.pixel, self.dimension, self.seed);
+        self.dimension += 1;
+        owen_scrambled_radical_inverse(base, self.sample_index as u64, hash)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: (i32, i32), sample_index: u32) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn name(&self) -> &'static str {
+        "halton"
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.next(), self.next())
+    }
+}
+
+// Owen scrambled Sobol samples. Every 1D or 2D request is taken from the first two Sobol
+// dimensions with its own index shuffle and scramble ("padding"), which keeps the excellent 2D
+// stratification of the sequence without needing a table of direction numbers.
+pub(crate) struct SobolSampler {
+    seed: u64,
+    pixel: (i32, i32),
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub(crate) fn new(seed: u64) -> Self {
+        SobolSampler { seed, pixel: (0, 0), sample_index: 0, dimension: 0 }
+    }
+
+    // Returns the shuffled sample index and the scramble seeds for the current dimension.
+    fn scramble(&mut self) -> (u32, u64) {
+        let hash = pixel_hash(self.pixel, self.dimension, self.seed);
+        let index = nested_uniform_scramble(self.sample_index, hash as u32);
+        (index, mix_bits(hash))
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: (i32, i32), sample_index: u32) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn name(&self) -> &'static str {
+        "sobol"
+    }
+
+    fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (index, hash) = self.scramble();
+        self.dimension += 1;
+        let x = nested_uniform_scramble(sobol_0(index), hash as u32);
+        f64::min(x as f64 * (1.0 / 4294967296.0), ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (index, hash) = self.scramble();
+        self.dimension += 2;
+        let x = nested_uniform_scramble(sobol_0(index), hash as u32);
+        let y = nested_uniform_scramble(sobol_1(index), (hash >> 32) as u32);
+        (
+            f64::min(x as f64 * (1.0 / 4294967296.0), ONE_MINUS_EPSILON),
+            f64::min(y as f64 * (1.0 / 4294967296.0), ONE_MINUS_EPSILON),
+        )
+    }
+}
@@ -0,0 +1,80 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::aperture::Aperture;
+use crate::color::{Color, PostProcess};
+use crate::hittable::Hittable;
+use crate::light::PunctualLight;
+use crate::material::MaterialTrait;
+use crate::physical_camera::PhysicalCamera;
+use crate::projection::Projection;
+use crate::stereo::Stereo;
+use crate::transform::Keyframe;
+use crate::vec3::{Point3, Vec3};
+
+// A camera of an imported scene, in world space.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct View {
+    pub(crate) look_from: Point3,
+    pub(crate) look_at: Point3,
+    pub(crate) vup: Vec3,
+    pub(crate) vfov: f64, // Degrees; unused by orthographic cameras
+    pub(crate) aspect_ratio: Option<f64>,
+    pub(crate) projection: Projection,
+    pub(crate) defocus_angle: f64,
+    pub(crate) focus_distance: Option<f64>, // None keeps the renderer's own
+}
+
+// Everything an imported scene holds that the renderer can use: the objects with the material of
+// each, the punctual lights and the cameras, all in world space, and the render settings the
+// file asks for, which the command line may still override.
+pub(crate) struct Scene {
+    pub(crate) objects: Vec<Box<dyn Hittable>>,
+    pub(crate) materials: Vec<Rc<RefCell<dyn MaterialTrait>>>,
+    pub(crate) lights: Vec<PunctualLight>,
+    pub(crate) views: Vec<View>,
+    pub(crate) background: Option<Color>, // Uniform light from all directions instead of the sky
+    pub(crate) image_width: Option<f64>,
+    pub(crate) samples_per_pixel: Option<i32>,
+    pub(crate) sampler_name: Option<String>,
+    pub(crate) max_depth: Option<i32>,
+    pub(crate) seed: Option<u64>,
+    pub(crate) shutter: Option<(f64, f64)>,
+    pub(crate) shutter_curve: Vec<f64>, // Empty for an ideal box shutter
+    pub(crate) camera_keyframes: Vec<Keyframe>,
+    pub(crate) post_process: Option<PostProcess>,
+    pub(crate) physical: Option<PhysicalCamera>,
+    pub(crate) aperture: Option<Aperture>,
+    pub(crate) cat_eye: Option<f64>,
+    pub(crate) stereo: Option<Stereo>,
+}
+
+impl Scene {
+    pub(crate) fn new() -> Self {
+        Scene {
+            objects: Vec::new(),
+            materials: Vec::new(),
+            lights: Vec::new(),
+            views: Vec::new(),
+            background: None,
+            image_width: None,
+            samples_per_pixel: None,
+            sampler_name: None,
+            max_depth: None,
+            seed: None,
+            shutter: None,
+            shutter_curve: Vec::new(),
+            camera_keyframes: Vec::new(),
+            post_process: None,
+            physical: None,
+            aperture: None,
+            cat_eye: None,
+            stereo: None,
+        }
+    }
+
+    pub(crate) fn add(&mut self, object: Box<dyn Hittable>, material: Rc<RefCell<dyn MaterialTrait>>) {
+        self.objects.push(object);
+        self.materials.push(material);
+    }
+}
@@ -0,0 +1,999 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::aperture::Aperture;
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::color::{Color, PostProcess, ToneMapper};
+use crate::csg::{Csg, CsgOperation};
+use crate::cuboid::Cuboid;
+use crate::curve::{Curve, CurveType};
+use crate::hair::HairMaterial;
+use crate::heightfield::Heightfield;
+use crate::hittable::Hittable;
+use crate::hittables::HittableList;
+use crate::image::Image;
+use crate::instance::Instance;
+use crate::json::{base64_decode, base64_encode, Json};
+use crate::light::{LightKind, PunctualLight};
+use crate::material::{Dielectric, DiffuseLight, Isotropic, Lambertian, MaterialTrait, Metal, TexturedLambertian};
+use crate::medium::ConstantMedium;
+use crate::mesh::{invalid_data, Triangle, TriangleMesh};
+use crate::pbr::PbrMaterial;
+use crate::physical_camera::PhysicalCamera;
+use crate::point_cloud::{PointCloud, PointData, PointShape};
+use crate::projection::Projection;
+use crate::quad::Quad;
+use crate::quadric::{Quadric, QuadricShape};
+use crate::scene::{Scene, View};
+use crate::sdf::{Sdf, SdfObject};
+use crate::sphere::Sphere;
+use crate::stereo::{Stereo, StereoLayout, StereoMode};
+use crate::texture::Texture;
+use crate::transform::{AnimatedTransform, Keyframe, Quaternion};
+use crate::utils::PI;
+use crate::vec3::{cross, unit_vector, Point3, Vec3};
+
+// Name and version at the top of every scene file.
+const FORMAT: &str = "rtiow-scene";
+const VERSION: f64 = 2.0;
+
+// Segments around and along the spheres and quadrics of an OBJ export.
+const OBJ_SEGMENTS: usize = 32;
+
+impl From<Vec3> for Json {
+    fn from(v: Vec3) -> Self {
+        Json::from(vec![v.x(), v.y(), v.z()])
+    }
+}
+
+// Vectors as one array of numbers, three after three.
+pub(crate) fn flatten(vectors: &[Vec3]) -> Json {
+    Json::from(vectors.iter().flat_map(|v| [v.x(), v.y(), v.z()]).collect::<Vec<f64>>())
+}
+
+// Gathers what objects share while they are saved: every material, mesh and image is written
+// once, however many objects use it, and referred to by its index in the file.
+pub(crate) struct SceneWriter {
+    materials: Vec<Json>,
+    material_ids: HashMap<*const (), usize>,
+    meshes: Vec<Json>,
+    mesh_ids: HashMap<*const TriangleMesh, usize>,
+    images: Vec<Json>,
+    image_ids: HashMap<*const Image, usize>,
+}
+
+impl SceneWriter {
+    fn new() -> Self {
+        SceneWriter {
+            materials: Vec::new(),
+            material_ids: HashMap::new(),
+            meshes: Vec::new(),
+            mesh_ids: HashMap::new(),
+            images: Vec::new(),
+            image_ids: HashMap::new(),
+        }
+    }
+
+    // Index of the material in the file, adding it the first time. Materials that refer to
+    // others are added after them, so a reader can build them in order.
+    pub(crate) fn material(&mut self, material: &Rc<RefCell<dyn MaterialTrait>>) -> Json {
+        let key = Rc::as_ptr(material) as *const ();
+        if let Some(&id) = self.material_ids.get(&key) {
+            return Json::from(id);
+        }
+        let saved = material.borrow().save(self).unwrap_or_else(|| {
+            eprintln!("Saving material {} as gray: its kind cannot be written to a scene file", self.materials.len());
+            Json::object(vec![("type", "lambertian".into()), ("albedo", Color::new(0.5, 0.5, 0.5).into())])
+        });
+        self.materials.push(saved);
+        self.material_ids.insert(key, self.materials.len() - 1);
+        Json::from(self.materials.len() - 1)
+    }
+
+    pub(crate) fn mesh(&mut self, mesh: &Rc<TriangleMesh>) -> Json {
+        let key = Rc::as_ptr(mesh);
+        if let Some(&id) = self.mesh_ids.get(&key) {
+            return Json::from(id);
+        }
+        let uvs: Vec<f64> = mesh.uvs.iter().flat_map(|&(u, v)| [u, v]).collect();
+        let triangles: Vec<f64> = mesh.triangles.iter().flatten().map(|&index| index as f64).collect();
+        self.meshes.push(Json::object(vec![
+            ("positions", flatten(&mesh.positions)),
+            ("normals", flatten(&mesh.normals)),
+            ("uvs", uvs.into()),
+            ("colors", flatten(&mesh.colors)),
+            ("triangles", triangles.into()),
+        ]));
+        self.mesh_ids.insert(key, self.meshes.len() - 1);
+        Json::from(self.meshes.len() - 1)
+    }
+
+    // Images loaded from files are referred to by their absolute path; others are written out
+    // as base64 of their little-endian f64 channels.
+    pub(crate) fn image(&mut self, image: &Rc<Image>) -> Json {
+        let key = Rc::as_ptr(image);
+        if let Some(&id) = self.image_ids.get(&key) {
+            return Json::from(id);
+        }
+        let saved = match &image.source {
+            Some((path, linear)) => {
+                let path = fs::canonicalize(path).map_or_else(|_| path.clone(), |path| path.to_string_lossy().into_owned());
+                Json::object(vec![("path", path.as_str().into()), ("linear", (*linear).into())])
+            }
+            None => {
+                let bytes: Vec<u8> = image.pixels().iter().flat_map(|p| [p.x(), p.y(), p.z()]).flat_map(f64::to_le_bytes).collect();
+                Json::object(vec![
+                    ("width", image.width.into()),
+                    ("height", image.height.into()),
+                    ("pixels", base64_encode(&bytes).as_str().into()),
+                ])
+            }
+        };
+        self.images.push(saved);
+        self.image_ids.insert(key, self.images.len() - 1);
+        Json::from(self.images.len() - 1)
+    }
+
+    // Each top-level object with the material it is animated through, or an error naming the
+    // first object that cannot be written.
+    fn objects(&mut self, objects: &[Box<dyn Hittable>], materials: &[Rc<RefCell<dyn MaterialTrait>>]) -> Result<Vec<Json>, String> {
+        let mut saved = Vec::with_capacity(objects.len());
+        for (index, object) in objects.iter().enumerate() {
+            let json = object.save(self).ok_or_else(|| format!("object {} cannot be written to a scene file", index + 1))?;
+            let material = materials.get(index).map_or(Json::Null, |material| self.material(material));
+            saved.push(Json::object(vec![("material", material), ("object", json)]));
+        }
+        Ok(saved)
+    }
+}
+
+fn projection_json(projection: &Projection) -> Json {
+    match *projection {
+        Projection::Perspective => Json::object(vec![("type", "perspective".into())]),
+        Projection::Orthographic { width } => Json::object(vec![("type", "orthographic".into()), ("width", width.into())]),
+        Projection::FisheyeEquidistant { fov } => Json::object(vec![("type", "fisheye".into()), ("fov", fov.into())]),
+        Projection::FisheyeEquisolid { fov } => Json::object(vec![("type", "equisolid".into()), ("fov", fov.into())]),
+        Projection::Equirectangular => Json::object(vec![("type", "equirectangular".into())]),
+    }
+}
+
+fn aperture_json(aperture: &Aperture) -> Option<Json> {
+    match *aperture {
+        Aperture::Circular => Some(Json::object(vec![("type", "circular".into())])),
+        Aperture::Polygon { blades, rotation } => {
+            Some(Json::object(vec![("type", "polygon".into()), ("blades", (blades as usize).into()), ("rotation", rotation.into())]))
+        }
+        // Only the sampling tables of the image are kept.
+        Aperture::Mask(_) => None,
+    }
+}
+
+fn physical_json(physical: &PhysicalCamera) -> Json {
+    Json::object(vec![
+        ("sensor_width", physical.sensor_width.into()),
+        ("sensor_height", physical.sensor_height.into()),
+        ("focal_length", physical.focal_length.into()),
+        ("f_number", physical.f_number.into()),
+        ("iso", physical.iso.into()),
+        ("shutter_seconds", physical.shutter_seconds.into()),
+        ("exposure_compensation", physical.exposure_compensation.into()),
+        ("meters_per_unit", physical.meters_per_unit.into()),
+    ])
+}
+
+fn stereo_json(stereo: &Stereo) -> Json {
+    Json::object(vec![
+        ("mode", stereo.mode.name().into()),
+        ("layout", stereo.layout.name().into()),
+        ("interpupillary_distance", stereo.interpupillary_distance.into()),
+    ])
+}
+
+pub(crate) fn keyframes_json(keyframes: &[Keyframe]) -> Json {
+    let keyframes: Vec<Json> = keyframes
+        .iter()
+        .map(|keyframe| {
+            Json::object(vec![
+                ("time", keyframe.time.into()),
+                ("translation", keyframe.translation.into()),
+                ("rotation", keyframe.rotation.to_array().to_vec().into()),
+                ("scale", keyframe.scale.into()),
+            ])
+        })
+        .collect();
+    keyframes.into()
+}
+
+fn light_json(light: &PunctualLight) -> Json {
+    let mut members = match light.kind {
+        LightKind::Point => vec![("type", "point".into())],
+        LightKind::Spot { direction, inner_angle, outer_angle } => vec![
+            ("type", "spot".into()),
+            ("direction", direction.into()),
+            ("inner_angle", inner_angle.into()),
+            ("outer_angle", outer_angle.into()),
+        ],
+        LightKind::Directional { direction } => vec![("type", "directional".into()), ("direction", direction.into())],
+    };
+    members.extend([("position", light.position.into()), ("intensity", light.intensity.into()), ("range", light.range.into())]);
+    Json::object(members)
+}
+
+// Writes the objects, with the material of each top-level one, and the camera with its lights
+// and render settings to a scene file that `load_scene` reads back into the same render. Scenes
+// holding anything a scene file cannot describe, such as a lens system or an aperture mask, are
+// not written at all.
+pub(crate) fn save_scene(
+    path: &str,
+    objects: &[Box<dyn Hittable>],
+    materials: &[Rc<RefCell<dyn MaterialTrait>>],
+    camera: &Camera,
+    sampler_name: &str,
+    seed: u64,
+) -> io::Result<()> {
+    let unsupported = |what: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("{} cannot be written to a scene file", what));
+    if camera.lens_system.is_some() {
+        return Err(unsupported("the lens system"));
+    }
+    let aperture = aperture_json(&camera.aperture).ok_or_else(|| unsupported("the aperture mask"))?;
+    let mut writer = SceneWriter::new();
+    let saved_objects = writer.objects(objects, materials).map_err(|message| io::Error::new(io::ErrorKind::InvalidInput, message))?;
+    let post_process = Json::object(vec![
+        ("exposure", camera.post_process.exposure.into()),
+        ("tone_mapper", camera.post_process.tone_mapper.name().into()),
+        ("white_balance", camera.post_process.white_balance.into()),
+    ]);
+    let camera_keyframes = camera.camera_motion.as_ref().map_or(&[][..], |motion| motion.keyframes());
+    let camera_json = Json::object(vec![
+        ("look_from", camera.look_from.into()),
+        ("look_at", camera.look_at.into()),
+        ("vup", camera.vup.into()),
+        ("vfov", camera.vfov.into()),
+        ("aspect_ratio", camera.aspect_ratio.into()),
+        ("image_width", camera.image_width.into()),
+        ("samples_per_pixel", (camera.samples_per_pixel as f64).into()),
+        ("max_depth", (camera.max_depth as f64).into()),
+        ("defocus_angle", camera.defocus_angle.into()),
+        ("focus_distance", camera.focus_distance.into()),
+        ("projection", projection_json(&camera.projection)),
+        ("shutter", vec![camera.shutter_open, camera.shutter_close].into()),
+        ("shutter_curve", camera.shutter_curve.values().to_vec().into()),
+        ("keyframes", keyframes_json(camera_keyframes)),
+        ("post_process", post_process),
+        ("physical", camera.physical.as_ref().map_or(Json::Null, physical_json)),
+        ("aperture", aperture),
+        ("cat_eye", camera.cat_eye.into()),
+        ("stereo", camera.stereo.as_ref().map_or(Json::Null, stereo_json)),
+        ("sampler", sampler_name.into()),
+        // As text, since numbers in JSON only hold 53 bits exactly.
+        ("seed", seed.to_string().as_str().into()),
+    ]);
+    let scene = Json::object(vec![
+        ("format", FORMAT.into()),
+        ("version", VERSION.into()),
+        ("camera", camera_json),
+        ("lights", camera.lights.iter().map(light_json).collect::<Vec<Json>>().into()),
+        ("background", camera.background.into()),
+        ("images", writer.images.into()),
+        ("meshes", writer.meshes.into()),
+        ("materials", writer.materials.into()),
+        ("objects", saved_objects.into()),
+    ]);
+
+    let mut text = String::new();
+    scene.write(&mut text, 0);
+    text.push('\n');
+    fs::write(path, text)
+}
+
+fn field<'a>(json: &'a Json, name: &str) -> Result<&'a Json, String> {
+    json.get(name).ok_or_else(|| format!("missing {}", name))
+}
+
+fn number(json: &Json, name: &str) -> Result<f64, String> {
+    field(json, name)?.as_f64().ok_or_else(|| format!("invalid {}", name))
+}
+
+fn count(json: &Json, name: &str) -> Result<usize, String> {
+    field(json, name)?.as_usize().ok_or_else(|| format!("invalid {}", name))
+}
+
+fn flag(json: &Json, name: &str) -> Result<bool, String> {
+    field(json, name)?.as_bool().ok_or_else(|| format!("invalid {}", name))
+}
+
+fn text<'a>(json: &'a Json, name: &str) -> Result<&'a str, String> {
+    field(json, name)?.as_str().ok_or_else(|| format!("invalid {}", name))
+}
+
+// An array of numbers, of exactly `length` of them if given.
+fn numbers(json: &Json, name: &str, length: Option<usize>) -> Result<Vec<f64>, String> {
+    match field(json, name)?.numbers() {
+        Some(values) if length.is_none_or(|length| values.len() == length) => Ok(values),
+        _ => Err(format!("invalid {}", name)),
+    }
+}
+
+fn vector(json: &Json, name: &str) -> Result<Vec3, String> {
+    let values = numbers(json, name, Some(3))?;
+    Ok(Vec3::new(values[0], values[1], values[2]))
+}
+
+fn vectors(json: &Json, name: &str) -> Result<Vec<Vec3>, String> {
+    let values = numbers(json, name, None)?;
+    if values.len() % 3 != 0 {
+        return Err(format!("{} must hold three numbers per vector", name));
+    }
+    Ok(values.chunks_exact(3).map(|v| Vec3::new(v[0], v[1], v[2])).collect())
+}
+
+fn pair(json: &Json, name: &str) -> Result<(f64, f64), String> {
+    let values = numbers(json, name, Some(2))?;
+    Ok((values[0], values[1]))
+}
+
+// The item a field refers to by index.
+fn reference<T: Clone>(items: &[T], json: &Json, name: &str) -> Result<T, String> {
+    let index = count(json, name)?;
+    items.get(index).cloned().ok_or_else(|| format!("{} {} does not exist", name, index))
+}
+
+fn optional_reference<T: Clone>(items: &[T], json: &Json, name: &str) -> Result<Option<T>, String> {
+    match json.get(name) {
+        None | Some(Json::Null) => Ok(None),
+        Some(_) => reference(items, json, name).map(Some),
+    }
+}
+
+fn projection(json: &Json) -> Result<Projection, String> {
+    match text(json, "type")? {
+        "perspective" => Ok(Projection::Perspective),
+        "orthographic" => Ok(Projection::Orthographic { width: number(json, "width")? }),
+        "fisheye" => Ok(Projection::FisheyeEquidistant { fov: number(json, "fov")? }),
+        "equisolid" => Ok(Projection::FisheyeEquisolid { fov: number(json, "fov")? }),
+        "equirectangular" => Ok(Projection::Equirectangular),
+        other => Err(format!("unknown projection {}", other)),
+    }
+}
+
+fn light(json: &Json) -> Result<PunctualLight, String> {
+    let kind = match text(json, "type")? {
+        "point" => LightKind::Point,
+        "spot" => LightKind::Spot {
+            direction: vector(json, "direction")?,
+            inner_angle: number(json, "inner_angle")?,
+            outer_angle: number(json, "outer_angle")?,
+        },
+        "directional" => LightKind::Directional { direction: vector(json, "direction")? },
+        other => return Err(format!("unknown light type {}", other)),
+    };
+    let range = match json.get("range") {
+        None | Some(Json::Null) => None,
+        Some(_) => Some(number(json, "range")?),
+    };
+    Ok(PunctualLight { kind, position: vector(json, "position")?, intensity: vector(json, "intensity")?, range })
+}
+
+fn post_process(json: &Json) -> Result<PostProcess, String> {
+    let tone_mapper = text(json, "tone_mapper")?;
+    let white_balance = match json.get("white_balance") {
+        None | Some(Json::Null) => None,
+        Some(_) => Some(number(json, "white_balance")?),
+    };
+    Ok(PostProcess {
+        exposure: number(json, "exposure")?,
+        tone_mapper: ToneMapper::from_name(tone_mapper).ok_or_else(|| format!("unknown tone mapper {}", tone_mapper))?,
+        white_balance,
+    })
+}
+
+fn physical(json: &Json) -> Result<PhysicalCamera, String> {
+    Ok(PhysicalCamera {
+        sensor_width: number(json, "sensor_width")?,
+        sensor_height: number(json, "sensor_height")?,
+        focal_length: number(json, "focal_length")?,
+        f_number: number(json, "f_number")?,
+        iso: number(json, "iso")?,
+        shutter_seconds: number(json, "shutter_seconds")?,
+        exposure_compensation: number(json, "exposure_compensation")?,
+        meters_per_unit: number(json, "meters_per_unit")?,
+    })
+}
+
+fn aperture(json: &Json) -> Result<Aperture, String> {
+    match text(json, "type")? {
+        "circular" => Ok(Aperture::Circular),
+        "polygon" => Ok(Aperture::Polygon { blades: count(json, "blades")? as u32, rotation: number(json, "rotation")? }),
+        other => Err(format!("unknown aperture type {}", other)),
+    }
+}
+
+fn stereo(json: &Json) -> Result<Stereo, String> {
+    let (mode, layout) = (text(json, "mode")?, text(json, "layout")?);
+    Ok(Stereo {
+        mode: StereoMode::from_name(mode).ok_or_else(|| format!("unknown stereo mode {}", mode))?,
+        layout: StereoLayout::from_name(layout).ok_or_else(|| format!("unknown stereo layout {}", layout))?,
+        interpupillary_distance: number(json, "interpupillary_distance")?,
+    })
+}
+
+fn quadric_shape(json: &Json) -> Result<QuadricShape, String> {
+    match text(json, "shape")? {
+        "cylinder" => Ok(QuadricShape::Cylinder { radius: number(json, "radius")? }),
+        "cone" => Ok(QuadricShape::Cone { radius: number(json, "radius")?, height: number(json, "height")? }),
+        "paraboloid" => Ok(QuadricShape::Paraboloid { radius: number(json, "radius")?, height: number(json, "height")? }),
+        "hyperboloid" => Ok(QuadricShape::Hyperboloid { waist_radius: number(json, "waist_radius")?, slope: number(json, "slope")? }),
+        "torus" => Ok(QuadricShape::Torus { major_radius: number(json, "major_radius")?, minor_radius: number(json, "minor_radius")? }),
+        other => Err(format!("unknown quadric shape {}", other)),
+    }
+}
+
+fn keyframes(json: &Json) -> Result<Vec<Keyframe>, String> {
+    field(json, "keyframes")?
+        .elements()
+        .iter()
+        .map(|keyframe| {
+            let rotation = numbers(keyframe, "rotation", Some(4))?;
+            Ok(Keyframe::new(
+                number(keyframe, "time")?,
+                vector(keyframe, "translation")?,
+                Quaternion::from_array([rotation[0], rotation[1], rotation[2], rotation[3]]),
+                vector(keyframe, "scale")?,
+            ))
+        })
+        .collect()
+}
+
+fn sdf(json: &Json) -> Result<Sdf, String> {
+    let child = |name: &str| sdf(field(json, name)?).map(Box::new);
+    let iterations = |json: &Json| count(json, "iterations").map(|iterations| iterations as u32);
+    Ok(match text(json, "type")? {
+        "sphere" => Sdf::Sphere { radius: number(json, "radius")? },
+        "box" => Sdf::Box { half_extents: vector(json, "half_extents")? },
+        "round_box" => Sdf::RoundBox { half_extents: vector(json, "half_extents")?, radius: number(json, "radius")? },
+        "torus" => Sdf::Torus { major_radius: number(json, "major_radius")?, minor_radius: number(json, "minor_radius")? },
+        "capsule" => Sdf::Capsule { a: vector(json, "a")?, b: vector(json, "b")?, radius: number(json, "radius")? },
+        "cylinder" => Sdf::Cylinder { radius: number(json, "radius")?, half_height: number(json, "half_height")? },
+        "mandelbulb" => Sdf::Mandelbulb { power: number(json, "power")?, iterations: iterations(json)? },
+        "menger_sponge" => Sdf::MengerSponge { iterations: iterations(json)? },
+        "translate" => Sdf::Translate { offset: vector(json, "offset")?, sdf: child("sdf")? },
+        "scale" => Sdf::Scale { factor: number(json, "factor")?, sdf: child("sdf")? },
+        "smooth_union" => Sdf::SmoothUnion { k: number(json, "k")?, a: child("a")?, b: child("b")? },
+        "smooth_subtract" => Sdf::SmoothSubtract { k: number(json, "k")?, a: child("a")?, b: child("b")? },
+        "repeat" => {
+            let count = numbers(json, "count", Some(3))?;
+            let count = [count[0] as u32, count[1] as u32, count[2] as u32];
+            Sdf::Repeat { period: vector(json, "period")?, count, sdf: child("sdf")? }
+        }
+        "twist" => Sdf::Twist { rate: number(json, "rate")?, sdf: child("sdf")? },
+        other => return Err(format!("unknown distance function {}", other)),
+    })
+}
+
+fn mesh(json: &Json) -> Result<TriangleMesh, String> {
+    let positions = vectors(json, "positions")?;
+    let uvs: Vec<(f64, f64)> = numbers(json, "uvs", None)?.chunks_exact(2).map(|uv| (uv[0], uv[1])).collect();
+    let mesh = TriangleMesh {
+        normals: vectors(json, "normals")?,
+        colors: vectors(json, "colors")?,
+        triangles: numbers(json, "triangles", None)?.chunks_exact(3).map(|t| [t[0] as usize, t[1] as usize, t[2] as usize]).collect(),
+        positions,
+        uvs,
+    };
+    let count = mesh.positions.len();
+    if [mesh.normals.len(), mesh.uvs.len(), mesh.colors.len()].iter().any(|&length| length != 0 && length != count) {
+        return Err(String::from("normals, uvs and colors must be empty or one per position"));
+    }
+    if mesh.triangles.iter().flatten().any(|&index| index >= count) {
+        return Err(String::from("triangle refers to a missing vertex"));
+    }
+    Ok(mesh)
+}
+
+fn image(json: &Json) -> Result<Image, String> {
+    if json.get("path").is_some() {
+        return Image::load(text(json, "path")?, flag(json, "linear")?).map_err(|error| error.to_string());
+    }
+    let bytes = base64_decode(text(json, "pixels")?)?;
+    let pixels = bytes
+        .chunks_exact(24)
+        .map(|pixel| {
+            let channel = |i: usize| f64::from_le_bytes(pixel[8 * i..8 * i + 8].try_into().unwrap_or_default());
+            Color::new(channel(0), channel(1), channel(2))
+        })
+        .collect();
+    Image::from_pixels(count(json, "width")?, count(json, "height")?, pixels)
+}
+
+// What objects of a scene file refer to by index, built in the order of the file.
+struct SceneReader {
+    images: Vec<Rc<Image>>,
+    meshes: Vec<Rc<TriangleMesh>>,
+    materials: Vec<Rc<RefCell<dyn MaterialTrait>>>,
+}
+
+impl SceneReader {
+    fn texture(&self, json: &Json) -> Result<Texture, String> {
+        match text(json, "type")? {
+            "solid" => Ok(Texture::Solid(vector(json, "color")?)),
+            "image" => Ok(Texture::Image { image: reference(&self.images, json, "image")?, scale: pair(json, "scale")?, offset: pair(json, "offset")? }),
+            "checker" => Ok(Texture::Checker { even: vector(json, "even")?, odd: vector(json, "odd")?, scale: pair(json, "scale")? }),
+            "solid_checker" => Ok(Texture::SolidChecker { even: vector(json, "even")?, odd: vector(json, "odd")?, size: number(json, "size")? }),
+            "noise" => {
+                let seed = text(json, "seed")?.parse().map_err(|_| String::from("invalid noise seed"))?;
+                Ok(Texture::noise(seed, number(json, "scale")?))
+            }
+            other => Err(format!("unknown texture type {}", other)),
+        }
+    }
+
+    fn material(&self, json: &Json) -> Result<Rc<RefCell<dyn MaterialTrait>>, String> {
+        Ok(match text(json, "type")? {
+            "lambertian" => Rc::new(RefCell::new(Lambertian { albedo: vector(json, "albedo")? })),
+            "textured_lambertian" => Rc::new(RefCell::new(TexturedLambertian { texture: Rc::new(self.texture(field(json, "texture")?)?) })),
+            "metal" => Rc::new(RefCell::new(Metal { albedo: vector(json, "albedo")?, fuzz: number(json, "fuzz")? })),
+            "dielectric" => Rc::new(RefCell::new(Dielectric { refraction_index: number(json, "refraction_index")? })),
+            "diffuse_light" => Rc::new(RefCell::new(DiffuseLight { emit: vector(json, "emit")?, two_sided: flag(json, "two_sided")? })),
+            "isotropic" => Rc::new(RefCell::new(Isotropic { albedo: vector(json, "albedo")? })),
+            "pbr" => {
+                let mut material = PbrMaterial::new(vector(json, "base_color")?, number(json, "metallic")?, number(json, "roughness")?);
+                material.base_color_texture = optional_reference(&self.images, json, "base_color_texture")?;
+                material.metallic_roughness_texture = optional_reference(&self.images, json, "metallic_roughness_texture")?;
+                material.emissive = vector(json, "emissive")?;
+                material.emissive_texture = optional_reference(&self.images, json, "emissive_texture")?;
+                Rc::new(RefCell::new(material))
+            }
+            "hair" => Rc::new(RefCell::new(HairMaterial::new(
+                vector(json, "sigma_a")?,
+                number(json, "eta")?,
+                number(json, "beta_m")?,
+                number(json, "beta_n")?,
+                number(json, "alpha")?,
+            ))),
+            other => return Err(format!("unknown material type {}", other)),
+        })
+    }
+
+    fn objects(&self, json: &Json) -> Result<Vec<Box<dyn Hittable>>, String> {
+        field(json, "objects")?.elements().iter().map(|object| self.object(object)).collect()
+    }
+
+    fn object(&self, json: &Json) -> Result<Box<dyn Hittable>, String> {
+        let material = || reference(&self.materials, json, "material");
+        Ok(match text(json, "type")? {
+            "sphere" => {
+                let (center, center_1) = (vector(json, "center")?, json.get("center_1").map(|_| vector(json, "center_1")).transpose()?);
+                Box::new(Sphere::new(center, number(json, "radius")?, material()?, center_1.unwrap_or(center), center_1.is_some()))
+            }
+            "cuboid" => Box::new(Cuboid::new(vector(json, "min")?, vector(json, "max")?, material()?)),
+            "quad" => Box::new(Quad::new(vector(json, "q")?, vector(json, "u")?, vector(json, "v")?, material()?)),
+            "quadric" => {
+                let mut quadric = Quadric::new(quadric_shape(json)?, vector(json, "center")?, number(json, "y_min")?, number(json, "y_max")?, material()?);
+                quadric.phi_max = number(json, "phi_max")?;
+                quadric.caps = flag(json, "caps")?;
+                Box::new(quadric)
+            }
+            "csg" => {
+                let operation = match text(json, "operation")? {
+                    "union" => CsgOperation::Union,
+                    "intersection" => CsgOperation::Intersection,
+                    "difference" => CsgOperation::Difference,
+                    other => return Err(format!("unknown CSG operation {}", other)),
+                };
+                Box::new(Csg::new(operation, self.object(field(json, "left")?)?, self.object(field(json, "right")?)?)?)
+            }
+            "instance" => {
+                let transform = AnimatedTransform::new(keyframes(json)?);
+                Box::new(Instance::new(self.object(field(json, "object")?)?, transform, vector(json, "pivot")?))
+            }
+            "bvh" => Box::new(Bvh::new(self.objects(json)?)),
+            "list" => {
+                let mut list = HittableList::new();
+                for object in self.objects(json)? {
+                    list.add(object);
+                }
+                Box::new(list)
+            }
+            "mesh" => Box::new(TriangleMesh::shared_hittable(&reference(&self.meshes, json, "mesh")?, material()?)),
+            "triangle" => {
+                let mesh = reference(&self.meshes, json, "mesh")?;
+                let index = count(json, "index")?;
+                if index >= mesh.triangles.len() {
+                    return Err(format!("triangle {} does not exist", index));
+                }
+                Box::new(Triangle::new(mesh, index, material()?))
+            }
+            "curve" => {
+                let control = vectors(json, "control")?;
+                let control: [Point3; 4] = control.try_into().map_err(|_| String::from("a curve needs four control points"))?;
+                let kind = match text(json, "kind")? {
+                    "ribbon" => {
+                        let normals = numbers(json, "normals", Some(6))?;
+                        CurveType::Ribbon(Vec3::new(normals[0], normals[1], normals[2]), Vec3::new(normals[3], normals[4], normals[5]))
+                    }
+                    name => CurveType::from_name(name).ok_or_else(|| format!("unknown curve kind {}", name))?,
+                };
+                Box::new(Curve::new(control, pair(json, "widths")?, kind, material()?))
+            }
+            "heightfield" => {
+                let (columns, rows) = (count(json, "columns")?, count(json, "rows")?);
+                let heights = numbers(json, "heights", Some(columns * rows))?;
+                Box::new(Heightfield::from_heights(heights, columns, rows, vector(json, "corner")?, pair(json, "cell_size")?, material()?)?)
+            }
+            "point_cloud" => {
+                let points = PointData { positions: vectors(json, "positions")?, colors: vectors(json, "colors")?, normals: vectors(json, "normals")? };
+                let shape = PointShape::from_name(text(json, "shape")?).ok_or_else(|| String::from("unknown point shape"))?;
+                Box::new(PointCloud::new(points, number(json, "radius")?, shape, material()?))
+            }
+            "sdf" => Box::new(SdfObject::new(sdf(field(json, "sdf")?)?, material()?)),
+            "medium" => Box::new(ConstantMedium::new(self.object(field(json, "boundary")?)?, number(json, "density")?, material()?)),
+            other => return Err(format!("unknown object type {}", other)),
+        })
+    }
+}
+
+fn parse_scene(json: &Json) -> Result<Scene, String> {
+    if json.get("format").and_then(Json::as_str) != Some(FORMAT) {
+        return Err(String::from("not a scene file"));
+    }
+    if number(json, "version")? != VERSION {
+        return Err(format!("unsupported version {}", number(json, "version")?));
+    }
+
+    let mut reader = SceneReader { images: Vec::new(), meshes: Vec::new(), materials: Vec::new() };
+    for (index, image_json) in field(json, "images")?.elements().iter().enumerate() {
+        let loaded = image(image_json).map_err(|message| format!("image {}: {}", index, message))?;
+        reader.images.push(Rc::new(loaded));
+    }
+    for (index, mesh_json) in field(json, "meshes")?.elements().iter().enumerate() {
+        let loaded = mesh(mesh_json).map_err(|message| format!("mesh {}: {}", index, message))?;
+        reader.meshes.push(Rc::new(loaded));
+    }
+    for (index, material_json) in field(json, "materials")?.elements().iter().enumerate() {
+        let material = reader.material(material_json).map_err(|message| format!("material {}: {}", index, message))?;
+        reader.materials.push(material);
+    }
+
+    let mut scene = Scene::new();
+    let white: Rc<RefCell<dyn MaterialTrait>> = Rc::new(RefCell::new(Lambertian { albedo: Color::new(1.0, 1.0, 1.0) }));
+    for (index, entry) in field(json, "objects")?.elements().iter().enumerate() {
+        let context = |message: String| format!("object {}: {}", index + 1, message);
+        let object = reader.object(field(entry, "object").map_err(context)?).map_err(context)?;
+        let material = match entry.get("material") {
+            None | Some(Json::Null) => white.clone(),
+            Some(_) => reference(&reader.materials, entry, "material").map_err(context)?,
+        };
+        scene.add(object, material);
+    }
+
+    let camera = field(json, "camera")?;
+    let context = |message: String| format!("camera: {}", message);
+    let shutter = pair(camera, "shutter").map_err(context)?;
+    scene.views.push(View {
+        look_from: vector(camera, "look_from").map_err(context)?,
+        look_at: vector(camera, "look_at").map_err(context)?,
+        vup: vector(camera, "vup").map_err(context)?,
+        vfov: number(camera, "vfov").map_err(context)?,
+        aspect_ratio: Some(number(camera, "aspect_ratio").map_err(context)?),
+        projection: projection(field(camera, "projection").map_err(context)?).map_err(context)?,
+        defocus_angle: number(camera, "defocus_angle").map_err(context)?,
+        focus_distance: Some(number(camera, "focus_distance").map_err(context)?),
+    });
+    scene.image_width = Some(number(camera, "image_width").map_err(context)?);
+    scene.samples_per_pixel = Some(count(camera, "samples_per_pixel").map_err(context)? as i32);
+    scene.max_depth = Some(count(camera, "max_depth").map_err(context)? as i32);
+    scene.sampler_name = Some(text(camera, "sampler").map_err(context)?.to_string());
+    scene.seed = Some(text(camera, "seed").map_err(context)?.parse().map_err(|_| context(String::from("invalid seed")))?);
+    scene.shutter = Some(shutter);
+    scene.shutter_curve = numbers(camera, "shutter_curve", None).map_err(context)?;
+    scene.camera_keyframes = keyframes(camera).map_err(context)?;
+    scene.post_process = Some(post_process(field(camera, "post_process").map_err(context)?).map_err(context)?);
+    scene.physical = match camera.get("physical") {
+        None | Some(Json::Null) => None,
+        Some(json) => Some(physical(json).map_err(context)?),
+    };
+    scene.aperture = Some(aperture(field(camera, "aperture").map_err(context)?).map_err(context)?);
+    scene.cat_eye = Some(number(camera, "cat_eye").map_err(context)?);
+    scene.stereo = match camera.get("stereo") {
+        None | Some(Json::Null) => None,
+        Some(json) => Some(stereo(json).map_err(context)?),
+    };
+
+    for (index, light_json) in field(json, "lights")?.elements().iter().enumerate() {
+        scene.lights.push(light(light_json).map_err(|message| format!("light {}: {}", index, message))?);
+    }
+    scene.background = match json.get("background") {
+        None | Some(Json::Null) => None,
+        Some(_) => Some(vector(json, "background")?),
+    };
+    Ok(scene)
+}
+
+// Reads a scene file written by `save_scene`, with its one camera as the only view.
+pub(crate) fn load_scene(path: &str) -> io::Result<Scene> {
+    let text = fs::read_to_string(path)?;
+    let json = Json::parse(&text).map_err(|message| invalid_data(format!("{}: {}", path, message)))?;
+    parse_scene(&json).map_err(|message| invalid_data(format!("{}: {}", path, message)))
+}
+
+// Position, optional normal and optional texture coordinates of a vertex.
+type ObjVertex = (Point3, Option<Vec3>, Option<(f64, f64)>);
+
+// A surface to write: its vertices and triangles of vertex indices.
+struct ObjSurface {
+    vertices: Vec<ObjVertex>,
+    triangles: Vec<[usize; 3]>,
+}
+
+// Writes surfaces as OBJ text, numbering vertices across the whole file.
+struct ObjWriter {
+    text: String,
+    positions: usize,
+    normals: usize,
+    uvs: usize,
+    meshes: Vec<TriangleMesh>,
+    warnings: Vec<String>,
+}
+
+impl ObjWriter {
+    fn warn(&mut self, message: &str) {
+        if !self.warnings.iter().any(|warning| warning == message) {
+            self.warnings.push(message.to_string());
+        }
+    }
+
+    // Writes the surface moved by `placement`, innermost transform first.
+    fn surface(&mut self, surface: &ObjSurface, placement: &[(Keyframe, Point3)]) {
+        let mut references = Vec::with_capacity(surface.vertices.len());
+        for &(mut position, mut normal, uv) in &surface.vertices {
+            for (keyframe, pivot) in placement {
+                position = keyframe.apply_point(position - *pivot) + *pivot;
+                normal = normal.map(|normal| keyframe.apply_normal(normal));
+            }
+            self.text.push_str(&format!("v {} {} {}\n", position.x(), position.y(), position.z()));
+            self.positions += 1;
+            let mut reference = self.positions.to_string();
+            if let Some((u, v)) = uv {
+                self.text.push_str(&format!("vt {} {}\n", u, v));
+                self.uvs += 1;
+                reference.push_str(&format!("/{}", self.uvs));
+            }
+            if let Some(normal) = normal.filter(|normal| normal.length_squared() > 0.0).map(unit_vector) {
+                self.text.push_str(&format!("vn {} {} {}\n", normal.x(), normal.y(), normal.z()));
+                self.normals += 1;
+                reference.push_str(&format!("{}/{}", if uv.is_some() { "" } else { "/" }, self.normals));
+            }
+            references.push(reference);
+        }
+        for &[a, b, c] in &surface.triangles {
+            self.text.push_str(&format!("f {} {} {}\n", references[a], references[b], references[c]));
+        }
+    }
+
+    fn use_material(&mut self, json: &Json) {
+        if let Some(material) = json.get("material").and_then(Json::as_usize) {
+            self.text.push_str(&format!("usemtl material_{}\n", material));
+        }
+    }
+
+    fn object(&mut self, json: &Json, placement: &[(Keyframe, Point3)]) -> Result<(), String> {
+        match text(json, "type")? {
+            "sphere" => {
+                self.use_material(json);
+                let (center, radius) = (vector(json, "center")?, number(json, "radius")?);
+                let rings = OBJ_SEGMENTS / 2;
+                let surface = grid(OBJ_SEGMENTS, rings, |i, j| {
+                    let (s, t) = (i as f64 / OBJ_SEGMENTS as f64, j as f64 / rings as f64);
+                    let (phi, theta) = (2.0 * PI * s, PI * t);
+                    let normal = Vec3::new(-theta.sin() * phi.cos(), -theta.cos(), theta.sin() * phi.sin());
+                    (center + radius * normal, Some(normal), Some((s, t)))
+                });
+                self.surface(&surface, placement);
+            }
+            "cuboid" => {
+                self.use_material(json);
+                let (min, max) = (vector(json, "min")?, vector(json, "max")?);
+                let mut surface = ObjSurface { vertices: Vec::new(), triangles: Vec::new() };
+                for axis in 0..3 {
+                    for side in [-1.0, 1.0] {
+                        // The face with this outward normal, wound counterclockwise seen from outside.
+                        let mut normal = Vec3::new(0.0, 0.0, 0.0);
+                        normal[axis] = side;
+                        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+                        let first = surface.vertices.len();
+                        for (s, t) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                            let mut p = Point3::new(0.0, 0.0, 0.0);
+                            p[axis] = if side > 0.0 { max[axis] } else { min[axis] };
+                            p[a] = min[a] + s * (max[a] - min[a]);
+                            p[b] = min[b] + t * (max[b] - min[b]);
+                            surface.vertices.push((p, Some(normal), Some((s, t))));
+                        }
+                        if side > 0.0 {
+                            surface.triangles.extend([[first, first + 1, first + 2], [first, first + 2, first + 3]]);
+                        } else {
+                            surface.triangles.extend([[first, first + 2, first + 1], [first, first + 3, first + 2]]);
+                        }
+                    }
+                }
+                self.surface(&surface, placement);
+            }
+            "quad" => {
+                self.use_material(json);
+                let (q, u, v) = (vector(json, "q")?, vector(json, "u")?, vector(json, "v")?);
+                let normal = unit_vector(cross(u, v));
+                let surface = grid(1, 1, |i, j| (q + i as f64 * u + j as f64 * v, Some(normal), Some((i as f64, j as f64))));
+                self.surface(&surface, placement);
+            }
+            "quadric" => {
+                self.use_material(json);
+                let shape = quadric_shape(json)?;
+                let (center, y_min, y_max) = (vector(json, "center")?, number(json, "y_min")?, number(json, "y_max")?);
+                let phi_max = number(json, "phi_max")?.to_radians();
+                let rings = OBJ_SEGMENTS / 2;
+                let rim = |y: f64, phi: f64| {
+                    let rho = shape.radius_squared(y).max(0.0).sqrt();
+                    center + Point3::new(rho * phi.cos(), y, rho * phi.sin())
+                };
+                // Rows run downwards, which winds the triangles to face out.
+                let mut surface = grid(OBJ_SEGMENTS, rings, |i, j| {
+                    let (s, t) = (i as f64 / OBJ_SEGMENTS as f64, 1.0 - j as f64 / rings as f64);
+                    let phi = phi_max * s;
+                    let p = match shape {
+                        QuadricShape::Torus { major_radius, minor_radius } => {
+                            let theta = 2.0 * PI * t;
+                            let rho = major_radius + minor_radius * theta.cos();
+                            center + Point3::new(rho * phi.cos(), minor_radius * theta.sin(), rho * phi.sin())
+                        }
+                        _ => rim(y_min + t * (y_max - y_min), phi),
+                    };
+                    (p, None, Some((s, t)))
+                });
+                if flag(json, "caps")? && !matches!(shape, QuadricShape::Torus { .. }) {
+                    for y in [y_min, y_max] {
+                        let first = surface.vertices.len();
+                        surface.vertices.push((center + Point3::new(0.0, y, 0.0), None, None));
+                        surface.vertices.extend((0..=OBJ_SEGMENTS).map(|i| (rim(y, phi_max * i as f64 / OBJ_SEGMENTS as f64), None, None)));
+                        for i in 1..=OBJ_SEGMENTS {
+                            // The bottom cap faces down and the top one up.
+                            let (a, b) = if y == y_min { (first + i, first + i + 1) } else { (first + i + 1, first + i) };
+                            surface.triangles.push([first, a, b]);
+                        }
+                    }
+                }
+                self.surface(&surface, placement);
+            }
+            "heightfield" => {
+                self.use_material(json);
+                let (columns, rows) = (count(json, "columns")?, count(json, "rows")?);
+                let heights = numbers(json, "heights", Some(columns * rows))?;
+                let (corner, (cell_x, cell_z)) = (vector(json, "corner")?, pair(json, "cell_size")?);
+                // Columns of the grid run along z, rows along x, so the triangles face up.
+                let surface = grid(rows - 1, columns - 1, |j, i| {
+                    let position = Point3::new(corner.x() + i as f64 * cell_x, heights[j * columns + i], corner.z() + j as f64 * cell_z);
+                    (position, None, Some((i as f64 / (columns - 1) as f64, j as f64 / (rows - 1) as f64)))
+                });
+                self.surface(&surface, placement);
+            }
+            "mesh" | "triangle" => {
+                self.use_material(json);
+                let index = count(json, "mesh")?;
+                let mesh = self.meshes.get(index).ok_or_else(|| format!("mesh {} does not exist", index))?;
+                let vertex = |i: usize| (mesh.positions[i], mesh.normals.get(i).copied(), mesh.uvs.get(i).copied());
+                let surface = match json.get("index").and_then(Json::as_usize) {
+                    Some(triangle) => {
+                        let [a, b, c] = *mesh.triangles.get(triangle).ok_or_else(|| format!("triangle {} does not exist", triangle))?;
+                        ObjSurface { vertices: vec![vertex(a), vertex(b), vertex(c)], triangles: vec![[0, 1, 2]] }
+                    }
+                    None => ObjSurface { vertices: (0..mesh.positions.len()).map(vertex).collect(), triangles: mesh.triangles.clone() },
+                };
+                self.surface(&surface, placement);
+            }
+            "instance" => {
+                let transform = AnimatedTransform::new(keyframes(json)?);
+                let mut inner = vec![(transform.at(0.0), vector(json, "pivot")?)];
+                inner.extend_from_slice(placement);
+                self.object(field(json, "object")?, &inner)?;
+            }
+            "bvh" | "list" => {
+                for object in field(json, "objects")?.elements() {
+                    self.object(object, placement)?;
+                }
+            }
+            "csg" => {
+                self.warn("CSG objects are written as their two operands");
+                self.object(field(json, "left")?, placement)?;
+                self.object(field(json, "right")?, placement)?;
+            }
+            "curve" => self.warn("Curves are left out"),
+            "point_cloud" => self.warn("Point clouds are left out"),
+            "sdf" => self.warn("Distance functions are left out"),
+            "medium" => self.warn("Volumes are left out"),
+            other => return Err(format!("unknown object type {}", other)),
+        }
+        Ok(())
+    }
+}
+
+// A grid of `columns` × `rows` cells split into triangles, with `point(i, j)` giving the vertex
+// in column i and row j; cells are wound counterclockwise seen with i to the right and j up.
+fn grid(columns: usize, rows: usize, point: impl Fn(usize, usize) -> ObjVertex) -> ObjSurface {
+    let vertices = (0..=rows).flat_map(|j| (0..=columns).map(move |i| (i, j))).map(|(i, j)| point(i, j)).collect();
+    let index = |i: usize, j: usize| j * (columns + 1) + i;
+    let mut triangles = Vec::with_capacity(2 * columns * rows);
+    for j in 0..rows {
+        for i in 0..columns {
+            triangles.push([index(i, j), index(i + 1, j), index(i + 1, j + 1)]);
+            triangles.push([index(i, j), index(i + 1, j + 1), index(i, j + 1)]);
+        }
+    }
+    ObjSurface { vertices, triangles }
+}
+
+// Material library entry with the nearest OBJ equivalents of a saved material.
+fn mtl_entry(id: usize, json: &Json, images: &[Json]) -> String {
+    let color = |name: &str| vector(json, name).unwrap_or(Color::new(0.8, 0.8, 0.8));
+    let line = |key: &str, c: Color| format!("{} {} {} {}\n", key, c.x(), c.y(), c.z());
+    let image_path = |name: &str| {
+        let index = json.get(name).and_then(Json::as_usize)?;
+        images.get(index)?.get("path").and_then(Json::as_str).map(str::to_string)
+    };
+    let mut entry = format!("newmtl material_{}\n", id);
+    match json.get("type").and_then(Json::as_str).unwrap_or_default() {
+        "metal" => entry += &(line("Kd", color("albedo")) + &line("Ks", color("albedo")) + "illum 3\n"),
+        "dielectric" => entry += &format!("Kd 1 1 1\nNi {}\nd 1\nillum 7\n", number(json, "refraction_index").unwrap_or(1.5)),
+        "diffuse_light" => entry += &(line("Kd", Color::new(0.0, 0.0, 0.0)) + &line("Ke", color("emit"))),
+        "hair" => {
+            let sigma_a = color("sigma_a");
+            entry += &line("Kd", Color::new((-sigma_a.x()).exp(), (-sigma_a.y()).exp(), (-sigma_a.z()).exp()));
+        }
+        "pbr" => {
+            entry += &(line("Kd", color("base_color")) + &line("Ke", color("emissive")));
+            entry += &format!("Pm {}\nPr {}\n", number(json, "metallic").unwrap_or(0.0), number(json, "roughness").unwrap_or(1.0));
+            if let Some(path) = image_path("base_color_texture") {
+                entry += &format!("map_Kd {}\n", path);
+            }
+        }
+        "textured_lambertian" => {
+            let texture = json.get("texture").unwrap_or(&Json::Null);
+            let kd = vector(texture, "color").or_else(|_| vector(texture, "even")).unwrap_or(Color::new(0.8, 0.8, 0.8));
+            entry += &line("Kd", kd);
+            let path = texture.get("image").and_then(Json::as_usize).and_then(|index| images.get(index)?.get("path")?.as_str());
+            if let Some(path) = path {
+                entry += &format!("map_Kd {}\n", path);
+            }
+        }
+        _ => entry += &line("Kd", color("albedo")),
+    }
+    entry
+}
+
+// Writes the surfaces of the objects as a Wavefront OBJ file for other tools, with their
+// materials approximated in a material library next to it. Shapes are tessellated, moving
+// objects are placed as at time 0, and objects without a surface of triangles to give (curves,
+// point clouds, distance functions and volumes) are left out with a warning.
+pub(crate) fn export_obj(path: &str, objects: &[Box<dyn Hittable>], materials: &[Rc<RefCell<dyn MaterialTrait>>]) -> io::Result<()> {
+    let mut scene = SceneWriter::new();
+    let saved = scene.objects(objects, materials).map_err(|message| io::Error::new(io::ErrorKind::InvalidInput, message))?;
+    let meshes = scene.meshes.iter().map(mesh).collect::<Result<Vec<_>, _>>().map_err(invalid_data)?;
+
+    let library = Path::new(path).with_extension("mtl");
+    let library_name = library.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut writer = ObjWriter { text: format!("mtllib {}\n", library_name), positions: 0, normals: 0, uvs: 0, meshes, warnings: Vec::new() };
+    for (index, entry) in saved.iter().enumerate() {
+        writer.text.push_str(&format!("o object_{}\n", index + 1));
+        writer.object(entry.get("object").unwrap_or(&Json::Null), &[]).map_err(invalid_data)?;
+    }
+    for warning in &writer.warnings {
+        eprintln!("{}", warning);
+    }
+
+    let materials: String = scene.materials.iter().enumerate().map(|(id, json)| mtl_entry(id, json, &scene.images)).collect();
+    fs::write(&library, materials)?;
+    fs::write(path, writer.text)
+}
+
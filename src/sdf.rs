@@ -0,0 +1,349 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SceneHasher};
+use crate::interval::Interval;
+use crate::json::Json;
+use crate::material::MaterialTrait;
+use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
+use crate::vec3::{dot, unit_vector, Point3, Vec3};
+
+// Limit on marching steps per ray; rays that run out of steps count as misses.
+const MAX_STEPS: usize = 1024;
+// Distance at which the march counts as on the surface, growing slowly with the distance travelled.
+const SURFACE_EPSILON: f64 = 1e-4;
+// Offset of the samples for the gradient normal.
+const GRADIENT_STEP: f64 = 1e-5;
+
+// A signed distance function: negative inside, positive outside, and never more than the
+// distance to the surface (scaled by `Sdf::lipschitz`), so sphere tracing can step by it.
+#[derive(Clone)]
+pub(crate) enum Sdf {
+    Sphere { radius: f64 },
+    Box { half_extents: Vec3 },
+    RoundBox { half_extents: Vec3, radius: f64 },
+    // Ring around the y axis
+    Torus { major_radius: f64, minor_radius: f64 },
+    // Segment from `a` to `b` swept by a sphere
+    Capsule { a: Point3, b: Point3, radius: f64 },
+    // Capped cylinder along the y axis
+    Cylinder { radius: f64, half_height: f64 },
+    // Power 8 gives the classic bulb, inside a sphere of radius about 1.2
+    Mandelbulb { power: f64, iterations: u32 },
+    // Menger sponge filling the cube [-1, 1]^3
+    MengerSponge { iterations: u32 },
+    Translate { offset: Vec3, sdf: Box<Sdf> },
+    Scale { factor: f64, sdf: Box<Sdf> },
+    // Union blended over a distance of about `k`; 0 gives the sharp union
+    SmoothUnion { k: f64, a: Box<Sdf>, b: Box<Sdf> },
+    // `a` with `b` carved out, blended over about `k`
+    SmoothSubtract { k: f64, a: Box<Sdf>, b: Box<Sdf> },
+    // Copies every `period` along each axis, from -`count` to `count` copies away from the origin
+    Repeat { period: Vec3, count: [u32; 3], sdf: Box<Sdf> },
+    // Rotation around the y axis by `rate` radians per unit of height
+    Twist { rate: f64, sdf: Box<Sdf> },
+}
+
+impl Sdf {
+    pub(crate) fn distance(&self, p: Point3) -> f64 {
+        match self {
+            Sdf::Sphere { radius } => p.length() - radius,
+            Sdf::Box { half_extents } => box_distance(p, *half_extents),
+            Sdf::RoundBox { half_extents, radius } => {
+                box_distance(p, *half_extents - Vec3::new(*radius, *radius, *radius)) - radius
+            }
+            Sdf::Torus { major_radius, minor_radius } => {
+                let ring = (p.x() * p.x() + p.z() * p.z()).sqrt() - major_radius;
+                (ring * ring + p.y() * p.y()).sqrt() - minor_radius
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let pa = p - *a;
+                let ba = *b - *a;
+                let h = (dot(pa, ba) / dot(ba, ba)).clamp(0.0, 1.0);
+                (pa - h * ba).length() - radius
+            }
+            Sdf::Cylinder { radius, half_height } => {
+                let dx = (p.x() * p.x() + p.z() * p.z()).sqrt() - radius;
+                let dy = p.y().abs() - half_height;
+                dx.max(dy).min(0.0) + (dx.max(0.0).powi(2) + dy.max(0.0).powi(2)).sqrt()
+            }
+            Sdf::Mandelbulb { power, iterations } => mandelbulb_distance(p, *power, *iterations),
+            Sdf::MengerSponge { iterations } => menger_distance(p, *iterations),
+            Sdf::Translate { offset, sdf } => sdf.distance(p - *offset),
+            Sdf::Scale { factor, sdf } => sdf.distance(p / *factor) * factor,
+            Sdf::SmoothUnion { k, a, b } => {
+                let (da, db) = (a.distance(p), b.distance(p));
+                if *k <= 0.0 {
+                    return da.min(db);
+                }
+                let h = (0.5 + 0.5 * (db - da) / k).clamp(0.0, 1.0);
+                db + h * (da - db) - k * h * (1.0 - h)
+            }
+            Sdf::SmoothSubtract { k, a, b } => {
+                let (da, db) = (a.distance(p), b.distance(p));
+                if *k <= 0.0 {
+                    return da.max(-db);
+                }
+                let h = (0.5 - 0.5 * (da + db) / k).clamp(0.0, 1.0);
+                da + h * (-db - da) + k * h * (1.0 - h)
+            }
+            Sdf::Repeat { period, count, sdf } => {
+                // Fold p into the nearest copy, staying within the allowed copies.
+                let mut local = p;
+                for axis in 0..3 {
+                    if period[axis] > 0.0 {
+                        let limit = count[axis] as f64;
+                        let copy = (p[axis] / period[axis]).round().clamp(-limit, limit);
+                        local[axis] = p[axis] - period[axis] * copy;
+                    }
+                }
+                sdf.distance(local)
+            }
+            Sdf::Twist { rate, sdf } => {
+                let angle = -rate * p.y();
+                let (sin, cos) = angle.sin_cos();
+                sdf.distance(Point3::new(cos * p.x() - sin * p.z(), p.y(), sin * p.x() + cos * p.z()))
+            }
+        }
+    }
+
+    // Box containing the surface.
+    pub(crate) fn bounds(&self) -> Aabb {
+        let symmetric = |x: f64, y: f64, z: f64| Aabb::from_points(Point3::new(-x, -y, -z), Point3::new(x, y, z));
+        match self {
+            Sdf::Sphere { radius } => symmetric(*radius, *radius, *radius),
+            Sdf::Box { half_extents } | Sdf::RoundBox { half_extents, .. } => {
+                symmetric(half_extents.x(), half_extents.y(), half_extents.z())
+            }
+            Sdf::Torus { major_radius, minor_radius } => {
+                symmetric(major_radius + minor_radius, *minor_radius, major_radius + minor_radius)
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let r = Vec3::new(*radius, *radius, *radius);
+                Aabb::surrounding(&Aabb::from_points(*a - r, *a + r), &Aabb::from_points(*b - r, *b + r))
+            }
+            Sdf::Cylinder { radius, half_height } => symmetric(*radius, *half_height, *radius),
+            Sdf::Mandelbulb { .. } => symmetric(1.25, 1.25, 1.25),
+            Sdf::MengerSponge { .. } => symmetric(1.0, 1.0, 1.0),
+            Sdf::Translate { offset, sdf } => {
+                let inner = sdf.bounds();
+                Aabb::from_points(inner.min() + *offset, inner.max() + *offset)
+            }
+            Sdf::Scale { factor, sdf } => {
+                let inner = sdf.bounds();
+                Aabb::from_points(inner.min() * *factor, inner.max() * *factor)
+            }
+            Sdf::SmoothUnion { k, a, b } => {
+                // Blending can bulge out by up to k / 4 on every side; `expand` adds half of its
+                // argument at each end.
+                let union = Aabb::surrounding(&a.bounds(), &b.bounds());
+                let bulge = k.max(0.0) / 4.0;
+                let (x, y, z) = (union.x.expand(2.0 * bulge), union.y.expand(2.0 * bulge), union.z.expand(2.0 * bulge));
+                Aabb::new(x, y, z)
+            }
+            Sdf::SmoothSubtract { a, .. } => a.bounds(),
+            Sdf::Repeat { period, count, sdf } => {
+                let inner = sdf.bounds();
+                let reach = Vec3::new(period.x() * count[0] as f64, period.y() * count[1] as f64, period.z() * count[2] as f64);
+                Aabb::from_points(inner.min() - reach, inner.max() + reach)
+            }
+            Sdf::Twist { sdf, .. } => {
+                let inner = sdf.bounds();
+                let x = inner.x.min.abs().max(inner.x.max.abs());
+                let z = inner.z.min.abs().max(inner.z.max.abs());
+                let radius = (x * x + z * z).sqrt();
+                Aabb::from_points(Point3::new(-radius, inner.y.min, -radius), Point3::new(radius, inner.y.max, radius))
+            }
+        }
+    }
+
+    // How much faster than the true distance the function may change. Marching divides its
+    // steps by this so they never overshoot the surface.
+    pub(crate) fn lipschitz(&self) -> f64 {
+        match self {
+            Sdf::Translate { sdf, .. } | Sdf::Scale { sdf, .. } | Sdf::Repeat { sdf, .. } => sdf.lipschitz(),
+            Sdf::SmoothUnion { a, b, .. } | Sdf::SmoothSubtract { a, b, .. } => a.lipschitz().max(b.lipschitz()),
+            Sdf::Twist { rate, sdf } => {
+                // Twisting stretches the domain most at the largest distance from the axis.
+                let inner = sdf.bounds();
+                let x = inner.x.min.abs().max(inner.x.max.abs());
+                let z = inner.z.min.abs().max(inner.z.max.abs());
+                let radius = (x * x + z * z).sqrt();
+                sdf.lipschitz() * (1.0 + (rate * radius).powi(2)).sqrt()
+            }
+            _ => 1.0,
+        }
+    }
+
+    // Description of the function for a scene file.
+    pub(crate) fn save(&self) -> Json {
+        let node = |name: &str, mut members: Vec<(&str, Json)>| {
+            members.insert(0, ("type", name.into()));
+            Json::object(members)
+        };
+        match self {
+            Sdf::Sphere { radius } => node("sphere", vec![("radius", (*radius).into())]),
+            Sdf::Box { half_extents } => node("box", vec![("half_extents", (*half_extents).into())]),
+            Sdf::RoundBox { half_extents, radius } => node("round_box", vec![("half_extents", (*half_extents).into()), ("radius", (*radius).into())]),
+            Sdf::Torus { major_radius, minor_radius } => {
+                node("torus", vec![("major_radius", (*major_radius).into()), ("minor_radius", (*minor_radius).into())])
+            }
+            Sdf::Capsule { a, b, radius } => node("capsule", vec![("a", (*a).into()), ("b", (*b).into()), ("radius", (*radius).into())]),
+            Sdf::Cylinder { radius, half_height } => node("cylinder", vec![("radius", (*radius).into()), ("half_height", (*half_height).into())]),
+            Sdf::Mandelbulb { power, iterations } => node("mandelbulb", vec![("power", (*power).into()), ("iterations", (*iterations as f64).into())]),
+            Sdf::MengerSponge { iterations } => node("menger_sponge", vec![("iterations", (*iterations as f64).into())]),
+            Sdf::Translate { offset, sdf } => node("translate", vec![("offset", (*offset).into()), ("sdf", sdf.save())]),
+            Sdf::Scale { factor, sdf } => node("scale", vec![("factor", (*factor).into()), ("sdf", sdf.save())]),
+            Sdf::SmoothUnion { k, a, b } => node("smooth_union", vec![("k", (*k).into()), ("a", a.save()), ("b", b.save())]),
+            Sdf::SmoothSubtract { k, a, b } => node("smooth_subtract", vec![("k", (*k).into()), ("a", a.save()), ("b", b.save())]),
+            Sdf::Repeat { period, count, sdf } => {
+                let count: Vec<f64> = count.iter().map(|&count| count as f64).collect();
+                node("repeat", vec![("period", (*period).into()), ("count", count.into()), ("sdf", sdf.save())])
+            }
+            Sdf::Twist { rate, sdf } => node("twist", vec![("rate", (*rate).into()), ("sdf", sdf.save())]),
+        }
+    }
+}
+
+fn box_distance(p: Point3, half_extents: Vec3) -> f64 {
+    let q = Vec3::new(p.x().abs(), p.y().abs(), p.z().abs()) - half_extents;
+    let outside = Vec3::new(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0)).length();
+    outside + q.x().max(q.y().max(q.z())).min(0.0)
+}
+
+// Distance estimate of the Mandelbulb from the running derivative of the iteration.
+fn mandelbulb_distance(p: Point3, power: f64, iterations: u32) -> f64 {
+    let mut z = p;
+    let mut dr = 1.0;
+    let mut r = z.length();
+
+    for _ in 0..iterations {
+        r = z.length();
+        if r > 2.0 {
+            break;
+        }
+        let theta = (z.z() / r).clamp(-1.0, 1.0).acos() * power;
+        let phi = z.y().atan2(z.x()) * power;
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+        let zr = r.powf(power);
+        z = zr * Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) + p;
+    }
+
+    if r <= 0.0 {
+        return 0.0;
+    }
+    0.5 * r.ln() * r / dr
+}
+
+// Distance to the Menger sponge: the cube with crosses carved out at every level.
+fn menger_distance(p: Point3, iterations: u32) -> f64 {
+    let mut distance = box_distance(p, Vec3::new(1.0, 1.0, 1.0));
+    let mut scale = 1.0;
+
+    for _ in 0..iterations {
+        let a = Vec3::new(
+            (p.x() * scale).rem_euclid(2.0) - 1.0,
+            (p.y() * scale).rem_euclid(2.0) - 1.0,
+            (p.z() * scale).rem_euclid(2.0) - 1.0,
+        );
+        scale *= 3.0;
+        let r = Vec3::new((1.0 - 3.0 * a.x().abs()).abs(), (1.0 - 3.0 * a.y().abs()).abs(), (1.0 - 3.0 * a.z().abs()).abs());
+        let da = r.x().max(r.y());
+        let db = r.y().max(r.z());
+        let dc = r.z().max(r.x());
+        let cross = (da.min(db.min(dc)) - 1.0) / scale;
+        distance = distance.max(cross);
+    }
+
+    distance
+}
+
+// A surface given by a signed distance function, found by sphere tracing.
+pub(crate) struct SdfObject {
+    sdf: Sdf,
+    material_ptr: Rc<RefCell<dyn MaterialTrait>>,
+    bbox: Aabb,
+    step_scale: f64,
+}
+
+impl SdfObject {
+    pub(crate) fn new(sdf: Sdf, material_ptr: Rc<RefCell<dyn MaterialTrait>>) -> Self {
+        let bbox = sdf.bounds();
+        let step_scale = 1.0 / sdf.lipschitz().max(1.0);
+        SdfObject { sdf, material_ptr, bbox, step_scale }
+    }
+
+    // Normal from the gradient, sampled at the corners of a tetrahedron.
+    fn gradient_normal(&self, p: Point3) -> Vec3 {
+        let offsets = [Vec3::new(1.0, -1.0, -1.0), Vec3::new(-1.0, -1.0, 1.0), Vec3::new(-1.0, 1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)];
+        let gradient = offsets
+            .iter()
+            .fold(Vec3::new(0.0, 0.0, 0.0), |sum, &offset| sum + offset * self.sdf.distance(p + offset * GRADIENT_STEP));
+        unit_vector(gradient)
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, ray: &Ray, ray_t: Interval, record: &mut HitRecord) -> bool {
+        // March only across the part of the ray inside the bounds.
+        let origin = ray.origin();
+        let direction = ray.direction();
+        let mut t_range = ray_t;
+        for axis in 0..3 {
+            let interval = self.bbox.axis_interval(axis);
+            let inverse = 1.0 / direction[axis];
+            let t0 = (interval.min - origin[axis]) * inverse;
+            let t1 = (interval.max - origin[axis]) * inverse;
+            let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            t_range.min = t_range.min.max(near);
+            t_range.max = t_range.max.min(far);
+        }
+        if t_range.max <= t_range.min {
+            return false;
+        }
+
+        // Distances are measured along the ray in units of its direction's length. Rays that
+        // start inside (refracted ones) march on the negated distance to find the way out.
+        let speed = direction.length();
+        let mut t = t_range.min.max(ray_t.min);
+        let side = if self.sdf.distance(ray.at(t)) < 0.0 { -1.0 } else { 1.0 };
+
+        for _ in 0..MAX_STEPS {
+            let distance = side * self.sdf.distance(ray.at(t));
+            let epsilon = SURFACE_EPSILON * (1.0 + t * speed * 0.01);
+            if distance < epsilon {
+                if !ray_t.surrounds(t) {
+                    return false;
+                }
+                record.t = t;
+                record.point = ray.at(t);
+                let outward_normal = self.gradient_normal(record.point);
+                record.set_face_normal(ray, outward_normal);
+                record.set_material(&self.material_ptr);
+                return true;
+            }
+
+            t += distance * self.step_scale / speed;
+            if t > t_range.max {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        Some(Json::object(vec![("type", "sdf".into()), ("sdf", self.sdf.save()), ("material", scene.material(&self.material_ptr))]))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add_box(&self.bbox);
+        hasher.add_material(&self.material_ptr);
+    }
+}
@@ -0,0 +1,51 @@
+// Efficiency of the shutter over its open interval, as evenly spaced values from opening to
+// closing that are linearly interpolated. Real shutters take time to open and close, so the ends
+// of the interval contribute less light and motion trails fade out instead of stopping sharply.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ShutterCurve {
+    efficiency: Vec<f64>,
+}
+
+impl ShutterCurve {
+    // A curve with fewer than two values is an ideal box shutter.
+    pub(crate) fn new(efficiency: Vec<f64>) -> Self {
+        ShutterCurve { efficiency: efficiency.into_iter().map(|e| e.max(0.0)).collect() }
+    }
+
+    pub(crate) fn values(&self) -> &[f64] {
+        &self.efficiency
+    }
+
+    // Maps a uniform sample to a position in [0, 1] over the open interval, distributed
+    // proportionally to the efficiency.
+    pub(crate) fn sample(&self, u: f64) -> f64 {
+        let segments = self.efficiency.len().saturating_sub(1);
+        let areas: Vec<f64> = self.efficiency.windows(2).map(|pair| 0.5 * (pair[0] + pair[1])).collect();
+        let total: f64 = areas.iter().sum();
+        if segments == 0 || total <= 0.0 {
+            return u;
+        }
+
+        // Pick the segment, then invert the CDF of the linear density a + (b - a) x inside it.
+        let mut target = u * total;
+        let mut segment = segments - 1;
+        for (i, &area) in areas.iter().enumerate() {
+            if target < area {
+                segment = i;
+                break;
+            }
+            target -= area;
+        }
+        let (a, b) = (self.efficiency[segment], self.efficiency[segment + 1]);
+        let target = target.min(areas[segment]);
+
+        let x = if (b - a).abs() < 1e-12 {
+            if a > 0.0 { target / a } else { 0.5 }
+        } else {
+            let discriminant = (a * a + 2.0 * (b - a) * target).max(0.0);
+            (discriminant.sqrt() - a) / (b - a)
+        };
+
+        (segment as f64 + x.clamp(0.0, 1.0)) / segments as f64
+    }
+}
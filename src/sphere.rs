@@ -1,10 +1,14 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use crate::hittable::{HitRecord, Hittable};
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable, SceneHasher, Span};
 use crate::interval::Interval;
+use crate::json::Json;
 use crate::material::MaterialTrait;
 use crate::ray::Ray;
+use crate::scene_file::SceneWriter;
+use crate::utils::PI;
 use crate::vec3::{dot, Point3, Vec3};
 
 pub(crate) struct Sphere {
@@ -12,17 +16,20 @@ pub(crate) struct Sphere {
     radius: f64,
     material_ptr: Rc<RefCell<dyn MaterialTrait>>,
     is_moving: bool,
+    center_1: Point3, // Kept for saving; the motion uses `center_vec`
     center_vec: Vec3,
 }
 
 impl Sphere {
     pub(crate) fn new(center: Point3, radius: f64, material_ptr: Rc<RefCell<dyn MaterialTrait>>, center_1: Point3, is_moving: bool) -> Self {
-        Sphere { center, radius, material_ptr, is_moving, center_vec: center_1 - center }
+        Sphere { center, radius, material_ptr, is_moving, center_1, center_vec: center_1 - center }
     }
 
     pub(crate) fn center(&self, time: f64) -> Point3 {
+        // Moves from `center` at time 0 to `center_1` at time 1 and rests there outside that span,
+        // like the keyframes of an `Instance`.
         if self.is_moving {
-            return self.center + time * self.center_vec;
+            return self.center + time.clamp(0.0, 1.0) * self.center_vec;
         }
         self.center
     }
@@ -59,10 +66,68 @@ impl Hittable for Sphere {
         record.t = root;
         record.point = ray.at(record.t);
 
-        let outward_normal: Vec3 = (record.point - self.center) / self.radius;
+        let outward_normal: Vec3 = (record.point - center) / self.radius;
         record.set_face_normal(ray, outward_normal);
-        record.material_ptr = Rc::clone(&self.material_ptr);
+        record.set_material(&self.material_ptr);
+        (record.u, record.v) = sphere_uv(outward_normal);
 
-        return true;
+        true
     }
-}
\ No newline at end of file
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let center = self.center(ray.time());
+        let oc: Point3 = ray.origin() - center;
+        let a: f64 = ray.direction().length_squared();
+        let half_b: f64 = dot(oc, ray.direction());
+        let c: f64 = oc.length_squared() - self.radius * self.radius;
+        let discriminant: f64 = half_b * half_b - a * c;
+        if discriminant <= 0.0 {
+            return Some(Vec::new());
+        }
+        let sqrtd: f64 = discriminant.sqrt();
+
+        let record = |t: f64| {
+            let point = ray.at(t);
+            let mut record = HitRecord::new(point, (point - center) / self.radius, t, false);
+            record.set_material(&self.material_ptr);
+            (record.u, record.v) = sphere_uv(record.normal);
+            record
+        };
+        Some(vec![Span { enter: record((-half_b - sqrtd) / a), exit: record((-half_b + sqrtd) / a) }])
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // A moving sphere is bounded by the boxes at both ends of its linear path.
+        let radius_vec = Vec3::new(self.radius, self.radius, self.radius);
+        let start = Aabb::from_points(self.center - radius_vec, self.center + radius_vec);
+        let end_center = self.center(1.0);
+        let end = Aabb::from_points(end_center - radius_vec, end_center + radius_vec);
+        Aabb::surrounding(&start, &end)
+    }
+
+    fn save(&self, scene: &mut SceneWriter) -> Option<Json> {
+        let mut members = vec![
+            ("type", "sphere".into()),
+            ("center", self.center.into()),
+            ("radius", self.radius.into()),
+            ("material", scene.material(&self.material_ptr)),
+        ];
+        if self.is_moving {
+            members.push(("center_1", self.center_1.into()));
+        }
+        Some(Json::object(members))
+    }
+
+    fn hash(&self, hasher: &mut SceneHasher) {
+        hasher.add_box(&self.bounding_box());
+        hasher.add_material(&self.material_ptr);
+    }
+}
+
+// Longitude and latitude of a point on the unit sphere, with u running around the y axis from
+// x = -1 and v from the bottom pole to the top one.
+fn sphere_uv(p: Point3) -> (f64, f64) {
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
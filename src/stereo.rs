@@ -0,0 +1,78 @@
+// Stereo rendering for headsets. Both eyes are rendered in one pass into a single packed image;
+// with the equirectangular projection the eyes use omni-directional stereo (ODS) instead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Stereo {
+    pub(crate) mode: StereoMode,
+    pub(crate) layout: StereoLayout,
+    pub(crate) interpupillary_distance: f64, // Distance between the eyes in world units
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum StereoMode {
+    // Both eyes look straight ahead; objects at infinity have zero parallax
+    Parallel,
+    // Off-axis frustums that meet on the focus plane, which then has zero parallax without the
+    // vertical parallax of toed-in cameras
+    Converged,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum StereoLayout {
+    // Left eye in the left half
+    SideBySide,
+    // Left eye in the top half
+    TopBottom,
+}
+
+impl StereoMode {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "parallel" => Some(StereoMode::Parallel),
+            "converged" => Some(StereoMode::Converged),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            StereoMode::Parallel => "parallel",
+            StereoMode::Converged => "converged",
+        }
+    }
+}
+
+impl StereoLayout {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "side-by-side" | "sbs" => Some(StereoLayout::SideBySide),
+            "top-bottom" | "tb" => Some(StereoLayout::TopBottom),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            StereoLayout::SideBySide => "side-by-side",
+            StereoLayout::TopBottom => "top-bottom",
+        }
+    }
+}
+
+impl Stereo {
+    // Size of the packed image holding both eyes of the given size.
+    pub(crate) fn packed_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self.layout {
+            StereoLayout::SideBySide => (2 * width, height),
+            StereoLayout::TopBottom => (width, 2 * height),
+        }
+    }
+
+    // Splits a packed pixel into the eye (-1 left, 1 right) and the pixel within that eye's image.
+    pub(crate) fn eye_pixel(&self, x: i32, y: i32, width: i32, height: i32) -> (f64, i32, i32) {
+        match self.layout {
+            StereoLayout::SideBySide if x >= width => (1.0, x - width, y),
+            StereoLayout::TopBottom if y >= height => (1.0, x, y - height),
+            _ => (-1.0, x, y),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use crate::mesh::PolygonMesh;
+use crate::vec3::{Point3, Vec3};
+
+// Applies `levels` rounds of Catmull-Clark subdivision. Every face becomes quads, one per corner,
+// and the surface converges to a smooth limit. Boundary edges follow the cubic B-spline rules,
+// so open meshes keep their outline.
+pub(crate) fn catmull_clark(mesh: &PolygonMesh, levels: u32) -> PolygonMesh {
+    let mut mesh = mesh.clone();
+    for _ in 0..levels {
+        mesh = subdivide_once(&mesh);
+    }
+    mesh
+}
+
+fn edge_key(a: usize, b: usize) -> (usize, usize) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+fn average(points: impl Iterator<Item = Point3>) -> Point3 {
+    let (sum, count) = points.fold((Vec3::new(0.0, 0.0, 0.0), 0), |(sum, count), p| (sum + p, count + 1));
+    sum / count.max(1) as f64
+}
+
+fn subdivide_once(mesh: &PolygonMesh) -> PolygonMesh {
+    let positions = &mesh.positions;
+    let face_points: Vec<Point3> = mesh.faces.iter().map(|face| average(face.iter().map(|&v| positions[v]))).collect();
+
+    // Each edge once, with the faces on either side.
+    let mut edge_index: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut edges: Vec<((usize, usize), Vec<usize>)> = Vec::new();
+    for (f, face) in mesh.faces.iter().enumerate() {
+        for i in 0..face.len() {
+            let key = edge_key(face[i], face[(i + 1) % face.len()]);
+            let index = *edge_index.entry(key).or_insert_with(|| {
+                edges.push((key, Vec::new()));
+                edges.len() - 1
+            });
+            edges[index].1.push(f);
+        }
+    }
+
+    // Interior edges move to the average of their ends and the two face points; boundary (and
+    // non-manifold) edges stay at their midpoints.
+    let edge_points: Vec<Point3> = edges
+        .iter()
+        .map(|&((a, b), ref faces)| {
+            if faces.len() == 2 {
+                (positions[a] + positions[b] + face_points[faces[0]] + face_points[faces[1]]) / 4.0
+            } else {
+                (positions[a] + positions[b]) / 2.0
+            }
+        })
+        .collect();
+
+    // Faces and edges around each original vertex.
+    let mut vertex_faces: Vec<Vec<usize>> = vec![Vec::new(); positions.len()];
+    for (f, face) in mesh.faces.iter().enumerate() {
+        for &v in face {
+            vertex_faces[v].push(f);
+        }
+    }
+    let mut vertex_edges: Vec<Vec<usize>> = vec![Vec::new(); positions.len()];
+    for (e, &((a, b), _)) in edges.iter().enumerate() {
+        vertex_edges[a].push(e);
+        vertex_edges[b].push(e);
+    }
+
+    let vertex_points: Vec<Point3> = (0..positions.len())
+        .map(|v| {
+            let p = positions[v];
+            let boundary: Vec<usize> = vertex_edges[v].iter().copied().filter(|&e| edges[e].1.len() != 2).collect();
+            if boundary.is_empty() {
+                if vertex_faces[v].is_empty() {
+                    return p;
+                }
+                // (F + 2R + (n - 3)P) / n, with F the average of the surrounding face points and
+                // R the average of the surrounding edge midpoints
+                let n = vertex_faces[v].len() as f64;
+                let f = average(vertex_faces[v].iter().map(|&f| face_points[f]));
+                let r = average(vertex_edges[v].iter().map(|&e| {
+                    let (a, b) = edges[e].0;
+                    (positions[a] + positions[b]) / 2.0
+                }));
+                (f + 2.0 * r + (n - 3.0) * p) / n
+            } else if boundary.len() == 2 {
+                // Cubic B-spline along the boundary curve
+                let neighbor = |e: usize| {
+                    let (a, b) = edges[e].0;
+                    positions[if a == v { b } else { a }]
+                };
+                (neighbor(boundary[0]) + 6.0 * p + neighbor(boundary[1])) / 8.0
+            } else {
+                // A corner where the boundary meets itself stays put.
+                p
+            }
+        })
+        .collect();
+
+    // New vertices: moved originals, then edge points, then face points.
+    let edge_base = vertex_points.len();
+    let face_base = edge_base + edge_points.len();
+    let mut subdivided = PolygonMesh { positions: vertex_points, faces: Vec::new() };
+    subdivided.positions.extend(edge_points);
+    subdivided.positions.extend(face_points);
+
+    for (f, face) in mesh.faces.iter().enumerate() {
+        let n = face.len();
+        for i in 0..n {
+            let (previous, current, next) = (face[(i + n - 1) % n], face[i], face[(i + 1) % n]);
+            subdivided.faces.push(vec![
+                current,
+                edge_base + edge_index[&edge_key(current, next)],
+                face_base + f,
+                edge_base + edge_index[&edge_key(previous, current)],
+            ]);
+        }
+    }
+
+    subdivided
+}
@@ -0,0 +1,82 @@
+use std::rc::Rc;
+
+use crate::color::Color;
+use crate::image::Image;
+use crate::json::Json;
+use crate::noise::Perlin;
+use crate::scene_file::SceneWriter;
+use crate::vec3::Point3;
+
+// Color varying over a surface, looked up with the surface coordinates of a hit or, for solid
+// textures, its point in space.
+pub(crate) enum Texture {
+    Solid(Color),
+    // An image repeated `scale` times across the surface and shifted by `offset`; v = 0 is the
+    // bottom of the image.
+    Image { image: Rc<Image>, scale: (f64, f64), offset: (f64, f64) },
+    // Squares of two colors, `scale` of them along u and v.
+    Checker { even: Color, odd: Color, scale: (f64, f64) },
+    // Cubes of two colors filling space, `size` on a side, which cover curved surfaces evenly.
+    SolidChecker { even: Color, odd: Color, size: f64 },
+    // Gray marble: stripes along z, `scale` of them per 2π units, bent by Perlin turbulence.
+    Noise { perlin: Perlin, seed: u64, scale: f64 },
+}
+
+impl Texture {
+    pub(crate) fn noise(seed: u64, scale: f64) -> Self {
+        Texture::Noise { perlin: Perlin::new(seed), seed, scale }
+    }
+
+    pub(crate) fn value(&self, u: f64, v: f64, point: Point3) -> Color {
+        match self {
+            Texture::Solid(color) => *color,
+            Texture::Image { image, scale, offset } => image.sample(u * scale.0 + offset.0, 1.0 - (v * scale.1 + offset.1)),
+            Texture::Checker { even, odd, scale } => {
+                if ((u * scale.0).floor() + (v * scale.1).floor()).rem_euclid(2.0) == 0.0 {
+                    *even
+                } else {
+                    *odd
+                }
+            }
+            Texture::SolidChecker { even, odd, size } => {
+                let cell = (point.x() / size).floor() + (point.y() / size).floor() + (point.z() / size).floor();
+                if cell.rem_euclid(2.0) == 0.0 {
+                    *even
+                } else {
+                    *odd
+                }
+            }
+            Texture::Noise { perlin, scale, .. } => {
+                let turbulence = perlin.fbm(point, 7).abs();
+                0.5 * (1.0 + (scale * point.z() + 10.0 * turbulence).sin()) * Color::new(1.0, 1.0, 1.0)
+            }
+        }
+    }
+
+    pub(crate) fn save(&self, scene: &mut SceneWriter) -> Json {
+        match self {
+            Texture::Solid(color) => Json::object(vec![("type", "solid".into()), ("color", (*color).into())]),
+            Texture::Image { image, scale, offset } => Json::object(vec![
+                ("type", "image".into()),
+                ("image", scene.image(image)),
+                ("scale", vec![scale.0, scale.1].into()),
+                ("offset", vec![offset.0, offset.1].into()),
+            ]),
+            Texture::Checker { even, odd, scale } => Json::object(vec![
+                ("type", "checker".into()),
+                ("even", (*even).into()),
+                ("odd", (*odd).into()),
+                ("scale", vec![scale.0, scale.1].into()),
+            ]),
+            Texture::SolidChecker { even, odd, size } => Json::object(vec![
+                ("type", "solid_checker".into()),
+                ("even", (*even).into()),
+                ("odd", (*odd).into()),
+                ("size", (*size).into()),
+            ]),
+            Texture::Noise { seed, scale, .. } => {
+                Json::object(vec![("type", "noise".into()), ("seed", seed.to_string().as_str().into()), ("scale", (*scale).into())])
+            }
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use crate::aabb::Aabb;
+use crate::utils::degrees_to_radians;
+use crate::vec3::{cross, dot, unit_vector, Point3, Vec3};
+
+// Steps per keyframe segment when bounding the volume swept by a moving box.
+const SWEEP_STEPS: usize = 16;
+
+// Unit quaternion representing a rotation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Quaternion {
+    w: f64,
+    v: Vec3,
+}
+
+impl Quaternion {
+    pub(crate) const IDENTITY: Quaternion = Quaternion { w: 1.0, v: Vec3::new(0.0, 0.0, 0.0) };
+
+    // Rotation of `degrees` counterclockwise around `axis` (looking down the axis).
+    pub(crate) fn from_axis_angle(axis: Vec3, degrees: f64) -> Self {
+        let half = degrees_to_radians(degrees) / 2.0;
+        let length = axis.length();
+        if length == 0.0 {
+            return Quaternion::IDENTITY;
+        }
+        Quaternion { w: half.cos(), v: axis * (half.sin() / length) }
+    }
+
+    // Rotation around the x, then the y, then the z axis, in degrees.
+    pub(crate) fn from_euler(x: f64, y: f64, z: f64) -> Self {
+        let qx = Quaternion::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), x);
+        let qy = Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), y);
+        let qz = Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), z);
+        qz.mul(qy).mul(qx)
+    }
+
+    // Components as [w, x, y, z].
+    pub(crate) fn to_array(self) -> [f64; 4] {
+        [self.w, self.v.x(), self.v.y(), self.v.z()]
+    }
+
+    // The quaternion with components [w, x, y, z], as `to_array` gives them.
+    pub(crate) fn from_array([w, x, y, z]: [f64; 4]) -> Self {
+        Quaternion { w, v: Vec3::new(x, y, z) }
+    }
+
+    fn mul(self, other: Quaternion) -> Self {
+        Quaternion { w: self.w * other.w - dot(self.v, other.v), v: self.w * other.v + other.w * self.v + cross(self.v, other.v) }
+    }
+
+    fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + dot(self.v, other.v)
+    }
+
+    fn scaled(&self, s: f64) -> Self {
+        Quaternion { w: self.w * s, v: self.v * s }
+    }
+
+    fn add(&self, other: &Quaternion) -> Self {
+        Quaternion { w: self.w + other.w, v: self.v + other.v }
+    }
+
+    fn normalized(&self) -> Self {
+        self.scaled(1.0 / self.dot(self).sqrt())
+    }
+
+    fn conjugate(&self) -> Self {
+        Quaternion { w: self.w, v: -self.v }
+    }
+
+    pub(crate) fn rotate(&self, p: Vec3) -> Vec3 {
+        let t = 2.0 * cross(self.v, p);
+        p + self.w * t + cross(self.v, t)
+    }
+
+    // Angle in radians of the rotation taking `self` to `other` along the shorter arc.
+    fn angle_to(&self, other: &Quaternion) -> f64 {
+        2.0 * self.dot(other).abs().min(1.0).acos()
+    }
+
+    // Spherical linear interpolation along the shorter arc.
+    pub(crate) fn slerp(&self, other: &Quaternion, t: f64) -> Self {
+        let mut cos_theta = self.dot(other);
+        let mut target = *other;
+        if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            target = target.scaled(-1.0);
+        }
+
+        if cos_theta > 0.9995 {
+            // Nearly parallel: normalized linear interpolation is accurate and avoids dividing by
+            // a vanishing sine.
+            return self.scaled(1.0 - t).add(&target.scaled(t)).normalized();
+        }
+
+        let theta = cos_theta.acos();
+        let sin_theta = theta.sin();
+        let a = ((1.0 - t) * theta).sin() / sin_theta;
+        let b = (t * theta).sin() / sin_theta;
+        self.scaled(a).add(&target.scaled(b))
+    }
+}
+
+// A transform at one point in time: scale, then rotation, then translation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Keyframe {
+    pub(crate) time: f64,
+    pub(crate) translation: Vec3,
+    pub(crate) rotation: Quaternion,
+    pub(crate) scale: Vec3,
+}
+
+impl Keyframe {
+    pub(crate) fn new(time: f64, translation: Vec3, rotation: Quaternion, scale: Vec3) -> Self {
+        Keyframe { time, translation, rotation, scale }
+    }
+
+    pub(crate) fn apply_point(&self, p: Point3) -> Point3 {
+        self.rotation.rotate(self.scale * p) + self.translation
+    }
+
+    pub(crate) fn apply_vector(&self, v: Vec3) -> Vec3 {
+        self.rotation.rotate(self.scale * v)
+    }
+
+    // Normals transform with the inverse transpose, which divides by the scale instead.
+    pub(crate) fn apply_normal(&self, n: Vec3) -> Vec3 {
+        self.rotation.rotate(Vec3::new(n.x() / self.scale.x(), n.y() / self.scale.y(), n.z() / self.scale.z()))
+    }
+
+    pub(crate) fn inverse_point(&self, p: Point3) -> Point3 {
+        self.inverse_vector(p - self.translation)
+    }
+
+    pub(crate) fn inverse_vector(&self, v: Vec3) -> Vec3 {
+        let r = self.rotation.conjugate().rotate(v);
+        Vec3::new(r.x() / self.scale.x(), r.y() / self.scale.y(), r.z() / self.scale.z())
+    }
+
+    fn interpolate(&self, other: &Keyframe, time: f64) -> Keyframe {
+        let t = (time - self.time) / (other.time - self.time);
+        Keyframe {
+            time,
+            translation: (1.0 - t) * self.translation + t * other.translation,
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale: (1.0 - t) * self.scale + t * other.scale,
+        }
+    }
+}
+
+// A transform that changes over time through keyframes, interpolated linearly for translation
+// and scale and with slerp for rotation. It holds the first and last keyframes outside their span.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct AnimatedTransform {
+    keyframes: Vec<Keyframe>,
+}
+
+impl AnimatedTransform {
+    pub(crate) fn new(mut keyframes: Vec<Keyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        AnimatedTransform { keyframes }
+    }
+
+    pub(crate) fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub(crate) fn at(&self, time: f64) -> Keyframe {
+        let Some(first) = self.keyframes.first() else {
+            return Keyframe::new(time, Vec3::new(0.0, 0.0, 0.0), Quaternion::IDENTITY, Vec3::new(1.0, 1.0, 1.0));
+        };
+        if time <= first.time {
+            return *first;
+        }
+
+        for pair in self.keyframes.windows(2) {
+            if time < pair[1].time {
+                return pair[0].interpolate(&pair[1], time);
+            }
+        }
+        *self.keyframes.last().unwrap()
+    }
+
+    // Box enclosing `bbox` transformed at any time. Each segment between keyframes is sampled
+    // and padded by how far a rotating corner can bulge out between two samples.
+    pub(crate) fn motion_bounds(&self, bbox: &Aabb) -> Aabb {
+        let corners = bbox.corners();
+        let transform_box = |keyframe: &Keyframe| {
+            corners.iter().fold(Aabb::EMPTY, |bounds, &corner| bounds.include(keyframe.apply_point(corner)))
+        };
+
+        if self.keyframes.len() < 2 {
+            return transform_box(&self.at(0.0));
+        }
+
+        let mut bounds = Aabb::EMPTY;
+        for pair in self.keyframes.windows(2) {
+            let step_angle = pair[0].rotation.angle_to(&pair[1].rotation) / SWEEP_STEPS as f64;
+            let mut segment = Aabb::EMPTY;
+            let mut radius: f64 = 0.0;
+
+            for step in 0..=SWEEP_STEPS {
+                let time = pair[0].time + (pair[1].time - pair[0].time) * step as f64 / SWEEP_STEPS as f64;
+                let keyframe = if pair[1].time > pair[0].time { pair[0].interpolate(&pair[1], time) } else { pair[1] };
+                segment = Aabb::surrounding(&segment, &transform_box(&keyframe));
+                for &corner in &corners {
+                    radius = radius.max((keyframe.scale * corner).length());
+                }
+            }
+
+            let pad = 2.0 * radius * (1.0 - (step_angle / 2.0).cos());
+            segment = Aabb::new(segment.x.expand(pad), segment.y.expand(pad), segment.z.expand(pad));
+            bounds = Aabb::surrounding(&bounds, &segment);
+        }
+
+        bounds
+    }
+}
+
+// Affine transform as a row-major 4x4 matrix.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Matrix(pub(crate) [[f64; 4]; 4]);
+
+impl Matrix {
+    pub(crate) const IDENTITY: Matrix = Matrix([[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+
+    // From 16 values listing the columns one after the other, as glTF and PBRT write matrices.
+    pub(crate) fn from_columns(values: &[f64]) -> Matrix {
+        let mut m = [[0.0; 4]; 4];
+        for (i, &value) in values.iter().enumerate().take(16) {
+            m[i % 4][i / 4] = value;
+        }
+        Matrix(m)
+    }
+
+    // Scales, then rotates by the unit quaternion (x, y, z, w), then translates.
+    pub(crate) fn from_trs(t: &[f64], r: &[f64], s: &[f64]) -> Matrix {
+        let (x, y, z, w) = (r[0], r[1], r[2], r[3]);
+        let rotation = [
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w)],
+            [2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w)],
+            [2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y)],
+        ];
+        let mut m = Matrix::IDENTITY.0;
+        for row in 0..3 {
+            for column in 0..3 {
+                m[row][column] = rotation[row][column] * s[column];
+            }
+            m[row][3] = t[row];
+        }
+        Matrix(m)
+    }
+
+    pub(crate) fn translation(offset: Vec3) -> Matrix {
+        let mut m = Matrix::IDENTITY.0;
+        for row in 0..3 {
+            m[row][3] = offset[row];
+        }
+        Matrix(m)
+    }
+
+    pub(crate) fn scaling(scale: Vec3) -> Matrix {
+        let mut m = Matrix::IDENTITY.0;
+        for row in 0..3 {
+            m[row][row] = scale[row];
+        }
+        Matrix(m)
+    }
+
+    // Rotation of `degrees` counterclockwise around `axis`.
+    pub(crate) fn rotation(axis: Vec3, degrees: f64) -> Matrix {
+        let [w, x, y, z] = Quaternion::from_axis_angle(axis, degrees).to_array();
+        Matrix::from_trs(&[0.0; 3], &[x, y, z, w], &[1.0; 3])
+    }
+
+    pub(crate) fn multiply(&self, other: &Matrix) -> Matrix {
+        let mut m = [[0.0; 4]; 4];
+        for (row, values) in m.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.0[row][k] * other.0[k][column]).sum();
+            }
+        }
+        Matrix(m)
+    }
+
+    // Inverse by Gauss-Jordan elimination with partial pivoting, or None for a singular matrix.
+    pub(crate) fn inverse(&self) -> Option<Matrix> {
+        let (mut m, mut inverse) = (self.0, Matrix::IDENTITY.0);
+        for column in 0..4 {
+            let pivot = (column..4).max_by(|&a, &b| m[a][column].abs().total_cmp(&m[b][column].abs()))?;
+            if m[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            m.swap(column, pivot);
+            inverse.swap(column, pivot);
+            let scale = 1.0 / m[column][column];
+            for k in 0..4 {
+                m[column][k] *= scale;
+                inverse[column][k] *= scale;
+            }
+            for row in (0..4).filter(|&row| row != column) {
+                let factor = m[row][column];
+                for k in 0..4 {
+                    m[row][k] -= factor * m[column][k];
+                    inverse[row][k] -= factor * inverse[column][k];
+                }
+            }
+        }
+        Some(Matrix(inverse))
+    }
+
+    pub(crate) fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    pub(crate) fn point(&self, p: Point3) -> Point3 {
+        self.vector(p) + Vec3::new(self.0[0][3], self.0[1][3], self.0[2][3])
+    }
+
+    pub(crate) fn determinant(&self) -> f64 {
+        let m = &self.0;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // Normals go through the inverse transpose, which is the cofactor matrix up to a scale;
+    // the sign of the determinant keeps them pointing out of mirrored objects.
+    pub(crate) fn normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.0;
+        let columns = [Vec3::new(m[0][0], m[1][0], m[2][0]), Vec3::new(m[0][1], m[1][1], m[2][1]), Vec3::new(m[0][2], m[1][2], m[2][2])];
+        let cofactor = cross(columns[1], columns[2]) * n.x() + cross(columns[2], columns[0]) * n.y() + cross(columns[0], columns[1]) * n.z();
+        let transformed = self.determinant().signum() * cofactor;
+        if transformed.length_squared() > 0.0 { unit_vector(transformed) } else { transformed }
+    }
+}
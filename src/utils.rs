@@ -1,15 +1,37 @@
-use rand::Rng;
+use std::cell::RefCell;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 pub const PI: f64 = std::f64::consts::PI;
 
+thread_local! {
+    // Generator behind `random_float`; seeded so procedurally built scenes can be rebuilt exactly.
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::seed_from_u64(0));
+}
+
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * PI / 180.0
 }
 
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
 pub fn random_float() -> f64 {
-    return  rand::thread_rng().gen_range(0.0..1.0);
+    RNG.with(|rng| rng.borrow_mut().gen_range(0.0..1.0))
+}
+
+// Finalizer of MurmurHash3 style 64-bit mixing; scatters nearby inputs across the whole range.
+pub fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
 }
 
-pub fn random_float_range(min: f64, max: f64) -> f64 {
-    return  rand::thread_rng().gen_range(min..max);
-}
\ No newline at end of file
+pub fn hash_values(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |hash, &value| mix_bits(hash ^ value).wrapping_add(0x9e3779b97f4a7c15))
+}
@@ -1,7 +1,7 @@
 use std::fmt;
 use std::ops::{Add, Div, Index, IndexMut, Mul, Neg, Sub};
 
-use crate::utils::random_float_range;
+use crate::utils::PI;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub(crate) struct Vec3 {
@@ -9,7 +9,7 @@ pub(crate) struct Vec3 {
 }
 
 impl Vec3 {
-    pub(crate) fn new(e0: f64, e1: f64, e2: f64) -> Self {
+    pub(crate) const fn new(e0: f64, e1: f64, e2: f64) -> Self {
         Vec3 { elements: [e0, e1, e2] }
     }
 
@@ -153,36 +153,29 @@ pub(crate) fn unit_vector(v: Vec3) -> Vec3 {
     v / v.length()
 }
 
-pub(crate) fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3::new(random_float_range(-1.0, 1.0), random_float_range(-1.0, 1.0), 0.0);
-        if p.length_squared() < 1.0 {
-            return p;
-        }
+pub(crate) fn sample_unit_disk(u: (f64, f64)) -> Vec3 {
+    // Maps a point of the unit square to the unit disk with Shirley's concentric mapping, which
+    // preserves the stratification of the input sample.
+    let offset_x = 2.0 * u.0 - 1.0;
+    let offset_y = 2.0 * u.1 - 1.0;
+    if offset_x == 0.0 && offset_y == 0.0 {
+        return Vec3::new(0.0, 0.0, 0.0);
     }
-}
-
-pub(crate) fn random_in_unit_sphere() -> Vec3 {
-    loop {
-        let p = random_range(-1.0, 1.0);
-        if p.length_squared() < 1.0 {
-            continue;
-        }
-        return p;
-    }
-}
 
-pub(crate) fn random_unit_vector() -> Vec3 {
-    unit_vector(random_in_unit_sphere())
+    let (r, theta) = if offset_x.abs() > offset_y.abs() {
+        (offset_x, PI / 4.0 * (offset_y / offset_x))
+    } else {
+        (offset_y, PI / 2.0 - PI / 4.0 * (offset_x / offset_y))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
 }
 
-
-pub(crate) fn random_range(min: f64, max: f64) -> Vec3 {
-    Vec3::new(
-        random_float_range(min, max),
-        random_float_range(min, max),
-        random_float_range(min, max),
-    )
+pub(crate) fn sample_unit_vector(u: (f64, f64)) -> Vec3 {
+    // Maps a point of the unit square to a uniformly distributed direction.
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
 }
 
 // Alias for geometric clarity